@@ -0,0 +1,44 @@
+//! Generates the gRPC messages of `proto/nssm_exec.proto` into `OUT_DIR`, without
+//! requiring `protoc`. The service itself is defined by hand in `src/grpc_server.rs`, since the
+//! pure parser does not read service definitions.
+
+extern crate protobuf_codegen;
+extern crate protobuf_codegen_pure;
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+const PROTO_DIR: &str = "proto";
+const PROTO_PATH: &str = "proto/nssm_exec.proto";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", PROTO_PATH);
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+
+    let parsed = protobuf_codegen_pure::parse_and_typecheck(&[PROTO_DIR], &[PROTO_PATH])
+        .expect("Unable to parse the proto file");
+
+    let results = protobuf_codegen::gen(
+        &parsed.file_descriptors,
+        &parsed.relative_paths,
+        &Default::default(),
+    );
+
+    for result in results {
+        // the inner attributes and doc comments are not allowed in files included with
+        // `include!`
+        let content: String = String::from_utf8(result.content)
+            .expect("Generated code is UTF-8")
+            .lines()
+            .filter(|line| !line.starts_with("#![") && !line.starts_with("//!"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        File::create(Path::new(&out_dir).join(&result.name))
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .expect("Unable to write the generated code");
+    }
+}
@@ -0,0 +1,76 @@
+//! Runs nssm_exec itself as a service with nssm, which periodically reconciles the installed
+//! services against the configuration like the `update` subcommand.
+
+use config::{Args, Backend, FileConfig, OtherConfig, Service};
+use errors::*;
+use std::env;
+use std::time::Duration;
+
+/// Name of the agent service when no other name is configured.
+pub const DEFAULT_SERVICE_NAME: &str = "nssm_exec_agent";
+
+/// Interval in seconds between the reconciliations when no other interval is configured.
+pub const DEFAULT_INTERVAL_SECS: u64 = 300;
+
+/// Returns the configured name of the agent service, with the name prefix of the services.
+pub fn service_name(file_config: &FileConfig) -> String {
+    let service_name = file_config
+        .agent
+        .as_ref()
+        .and_then(|agent| agent.service_name.as_ref())
+        .map_or(DEFAULT_SERVICE_NAME, |service_name| service_name.as_str());
+
+    format!(
+        "{}{}",
+        file_config.name_prefix.as_ref().map_or("", |name_prefix| name_prefix.as_str()),
+        service_name
+    )
+}
+
+/// Returns the configured interval between the reconciliations.
+pub fn interval(file_config: &FileConfig) -> Duration {
+    Duration::from_secs(
+        file_config
+            .agent
+            .as_ref()
+            .and_then(|agent| agent.interval_secs)
+            .unwrap_or(DEFAULT_INTERVAL_SECS),
+    )
+}
+
+/// Returns a copy of the configuration holding only the agent service on the local
+/// machine, which runs the current executable with the given arguments in the current
+/// working directory. The agent service is always managed by nssm.
+pub fn agent_config(file_config: &FileConfig, args: Vec<String>) -> Result<FileConfig> {
+    let exe_path = env::current_exe()
+        .chain_err(|| "Unable to get the path of the current executable")?;
+
+    let working_dir = env::current_dir().chain_err(|| "Unable to get the working directory")?;
+
+    let mut agent_config = file_config.for_host(None);
+    let service_name = service_name(file_config);
+
+    agent_config.global = None;
+    agent_config.registry_direct = None;
+    agent_config.services = vec![
+        Service {
+            display_name: Some(service_name.clone()),
+            name: service_name,
+            path: exe_path,
+            startup_dir: Some(working_dir),
+            args: Some(Args::List(args)),
+            description: Some(
+                "Reconciles the installed services against the nssm_exec configuration"
+                    .to_owned(),
+            ),
+            other: Some(OtherConfig {
+                start_on_create: Some(true),
+                backend: Some(Backend::Nssm),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    ];
+
+    Ok(agent_config)
+}
@@ -0,0 +1,316 @@
+//! Renders the services into an Ansible playbook of `win_nssm` and `win_service` tasks, so
+//! that the services can be managed by Ansible from the same configuration.
+
+use cmdline;
+use config::{FileConfig, Protocol, RecoveryAction, Service};
+use errors::*;
+use file;
+use firewall;
+use nssm;
+use params::{self, ServiceParam};
+use recovery;
+use serde_yaml::{self, Mapping, Value};
+use std::path::Path;
+use super::dependency_order;
+
+/// Returns the `win_nssm` option of the nssm parameter, if the module supports it.
+fn win_nssm_option(param_name: &str) -> Option<&'static str> {
+    match param_name {
+        "Application" => Some("application"),
+        "AppDirectory" => Some("working_directory"),
+        "AppParameters" => Some("arguments"),
+        "DisplayName" => Some("display_name"),
+        "Description" => Some("description"),
+        "AppStdout" => Some("stdout_file"),
+        "AppStderr" => Some("stderr_file"),
+        "AppRotateOnline" => Some("app_rotate_online"),
+        "AppRotateSeconds" => Some("app_rotate_seconds"),
+        "AppRotateBytes" => Some("app_rotate_bytes"),
+        "AppThrottle" => Some("app_throttle"),
+        "AppStopMethodSkip" => Some("app_stop_method_skip"),
+        "AppStopMethodConsole" => Some("app_stop_method_console"),
+        "AppStopMethodWindow" => Some("app_stop_method_window"),
+        "AppStopMethodThreads" => Some("app_stop_method_threads"),
+        _ => None,
+    }
+}
+
+fn string<S: Into<String>>(value: S) -> Value {
+    Value::String(value.into())
+}
+
+fn mapping(entries: Vec<(&str, Value)>) -> Value {
+    let mut mapping = Mapping::new();
+
+    for (key, value) in entries {
+        mapping.insert(string(key), value);
+    }
+
+    Value::Mapping(mapping)
+}
+
+/// Returns the task with the name running the module with the arguments, only on the hosts
+/// of the service if it has any.
+fn task(name: String, module: &str, args: Value, service: &Service) -> Value {
+    let mut entries = vec![("name", string(name)), (module, args)];
+
+    if let Some(ref hosts) = service.hosts {
+        let hosts: Vec<String> = hosts.iter().map(|host| format!("'{}'", host)).collect();
+        entries.push(("when", string(format!("inventory_hostname in [{}]", hosts.join(", ")))));
+    }
+
+    mapping(entries)
+}
+
+fn hook_task(service: &Service, hook: &str, cmd_line: Option<&String>) -> Option<Value> {
+    cmd_line.map(|cmd_line| {
+        task(
+            format!("Run {} hook of service '{}'", hook, service.name),
+            "win_command",
+            string(cmd_line.as_str()),
+            service,
+        )
+    })
+}
+
+/// Returns the variable holding the password of the user, adding it if new, which is
+/// expected to be defined with Ansible Vault rather than in the playbook.
+fn password_var(user: &str, password_users: &mut Vec<String>) -> String {
+    let index = match password_users.iter().position(|existing| existing == user) {
+        Some(index) => index,
+        None => {
+            password_users.push(user.to_owned());
+            password_users.len() - 1
+        }
+    };
+
+    format!("nssm_exec_password_{}", index + 1)
+}
+
+fn nssm_tasks(
+    service: &Service,
+    file_config: &FileConfig,
+    password_users: &mut Vec<String>,
+) -> Vec<Value> {
+    let nssm_path = file_config.nssm_path.to_string_lossy().into_owned();
+
+    let mut entries = vec![
+        ("name", string(service.name.as_str())),
+        ("executable", string(nssm_path.as_str())),
+    ];
+
+    let mut set_params: Vec<ServiceParam> = Vec::new();
+
+    for param in params::desired_params(service, file_config) {
+        match (param.name, win_nssm_option(param.name)) {
+            (_, Some(option)) if param.sub.is_none() => {
+                entries.push((option, string(param.value())));
+            }
+
+            ("DependOnService", _) => {
+                let deps = param.values.iter().map(|dep| string(dep.as_str())).collect();
+                entries.push(("dependencies", Value::Sequence(deps)));
+            }
+
+            ("ObjectName", _) => {
+                entries.push(("username", string(param.value())));
+
+                if param.secret.is_some() {
+                    let var = password_var(&param.value(), password_users);
+                    entries.push(("password", string(format!("{{{{ {} }}}}", var))));
+                }
+            }
+
+            _ => set_params.push(param),
+        }
+    }
+
+    entries.push(("state", string("present")));
+
+    let mut tasks = vec![
+        task(
+            format!("Install service '{}'", service.name),
+            "win_nssm",
+            mapping(entries),
+            service,
+        ),
+    ];
+
+    // the parameters that win_nssm does not support are set with nssm itself
+    for param in set_params {
+        tasks.push(task(
+            format!("Set {} of service '{}'", param.key(), service.name),
+            "win_command",
+            string(cmdline::to_cmd_line(&nssm_path, &nssm::set(&service.name, &param))),
+            service,
+        ));
+    }
+
+    tasks
+}
+
+fn win_service_task(service: &Service, file_config: &FileConfig) -> Option<Value> {
+    let mut entries = vec![("name", string(service.name.as_str()))];
+
+    if let Some(ref recovery) = service.recovery {
+        let delay_ms = recovery
+            .restart_delay_ms
+            .unwrap_or(recovery::DEFAULT_RESTART_DELAY_MS);
+
+        let actions = vec![recovery.first, recovery.second, recovery.subsequent]
+            .into_iter()
+            .map(|action| {
+                let action_type = match action.unwrap_or(RecoveryAction::None) {
+                    RecoveryAction::None => "none",
+                    RecoveryAction::Restart => "restart",
+                    RecoveryAction::Reboot => "reboot",
+                };
+
+                mapping(vec![
+                    ("type", string(action_type)),
+                    ("delay_ms", Value::Number(delay_ms.into())),
+                ])
+            })
+            .collect();
+
+        entries.push(("failure_actions", Value::Sequence(actions)));
+
+        entries.push((
+            "failure_reset_period_sec",
+            Value::Number(recovery.reset_secs.unwrap_or(recovery::DEFAULT_RESET_SECS).into()),
+        ));
+
+        entries.push((
+            "failure_actions_on_non_crash_failure",
+            Value::Bool(recovery.non_crash_failures.unwrap_or(false)),
+        ));
+    }
+
+    if let Some(&true) = file_config.merged_other(service).start_on_create {
+        entries.push(("state", string("started")));
+    }
+
+    if entries.len() == 1 {
+        return None;
+    }
+
+    Some(task(
+        format!("Configure service '{}'", service.name),
+        "win_service",
+        mapping(entries),
+        service,
+    ))
+}
+
+fn firewall_task(service: &Service) -> Option<Value> {
+    service.firewall.as_ref().map(|firewall| {
+        let ports: Vec<String> = firewall.ports.iter().map(|port| port.to_string()).collect();
+
+        let protocol = match firewall.protocol {
+            Some(Protocol::Udp) => "udp",
+            Some(Protocol::Tcp) | None => "tcp",
+        };
+
+        let mut entries = vec![
+            ("name", string(firewall::rule_name(&service.name, firewall))),
+            ("localport", string(ports.join(","))),
+            ("action", string("allow")),
+            ("direction", string("in")),
+            ("protocol", string(protocol)),
+        ];
+
+        match firewall.profile {
+            Some(ref profile) if profile != "any" => {
+                let profiles = profile
+                    .split(',')
+                    .map(|profile| string(profile.trim()))
+                    .collect();
+
+                entries.push(("profiles", Value::Sequence(profiles)));
+            }
+
+            _ => (),
+        }
+
+        entries.push(("state", string("present")));
+        entries.push(("enabled", Value::Bool(true)));
+
+        task(
+            format!("Allow the ports of service '{}'", service.name),
+            "win_firewall_rule",
+            mapping(entries),
+            service,
+        )
+    })
+}
+
+fn service_tasks(
+    service: &Service,
+    file_config: &FileConfig,
+    password_users: &mut Vec<String>,
+) -> Vec<Value> {
+    let other = file_config.merged_other(service);
+    let mut tasks = Vec::new();
+
+    if service.artifact.is_some() {
+        warn!("Artifact of service '{}' is not exported", service.name);
+    }
+
+    if service.health.is_some() {
+        warn!("Health probes of service '{}' are not exported", service.name);
+    }
+
+    if other.pre_stop.is_some() || other.post_start.is_some() {
+        warn!(
+            "Only the pre_install and post_install hooks of service '{}' are exported",
+            service.name
+        );
+    }
+
+    tasks.extend(hook_task(service, "pre_install", other.pre_install));
+    tasks.extend(nssm_tasks(service, file_config, password_users));
+    tasks.extend(firewall_task(service));
+    tasks.extend(hook_task(service, "post_install", other.post_install));
+    tasks.extend(win_service_task(service, file_config));
+    tasks
+}
+
+/// Generates the playbook with the tasks of every service in the dependency order, run on
+/// the configured hosts, or on all the hosts of the inventory if there is none. The
+/// passwords of the accounts are referenced as the `nssm_exec_password_<n>` variables.
+pub fn generate(file_config: &FileConfig) -> Result<String> {
+    let mut password_users = Vec::new();
+    let mut tasks = Vec::new();
+
+    for index in dependency_order(file_config) {
+        let service = &file_config.services[index];
+        tasks.extend(service_tasks(service, file_config, &mut password_users));
+    }
+
+    let hosts: Vec<String> = file_config.hosts().into_iter().flatten().collect();
+
+    let hosts = if hosts.is_empty() {
+        "all".to_owned()
+    } else {
+        hosts.join(":")
+    };
+
+    let playbook = Value::Sequence(vec![
+        mapping(vec![
+            ("name", string("Manage the services with nssm")),
+            ("hosts", string(hosts)),
+            ("tasks", Value::Sequence(tasks)),
+        ]),
+    ]);
+
+    serde_yaml::to_string(&playbook).chain_err(|| "Unable to serialize playbook into YAML")
+}
+
+/// Generates the playbook and writes it into the file at the path.
+pub fn write<P: AsRef<Path>>(file_config: &FileConfig, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let playbook = generate(file_config)?;
+
+    file::put_text(path, playbook)
+        .chain_err(|| format!("Unable to write playbook at '{}'", path.to_string_lossy()))
+}
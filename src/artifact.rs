@@ -0,0 +1,507 @@
+//! Fetches the artifacts of the services, e.g. their executables, from their URLs to their
+//! destination paths before the services are installed, verifying their SHA-256 checksums,
+//! extracts the artifacts that are zip archives into the install directories, and switches
+//! the directory junctions of versioned deployments to the install directories.
+
+use config::Artifact;
+use download;
+use errors::*;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::ZipArchive;
+
+/// Name of the file in the extract directory holding the checksum of the extracted archive.
+const EXTRACTED_MARKER: &str = ".nssm_exec_artifact";
+
+/// Returns true if the file at the destination path does not need to be downloaded, i.e.
+/// it has the configured checksum or there is no URL to download it from.
+pub fn is_downloaded(artifact: &Artifact) -> bool {
+    match (artifact.url.as_ref(), artifact.sha256.as_ref()) {
+        (Some(_), Some(sha256)) => {
+            artifact.dest.is_file() && download::verify_sha256(&artifact.dest, sha256).is_ok()
+        }
+        (Some(_), None) => false,
+        (None, _) => true,
+    }
+}
+
+/// Returns true if the archive at the destination path does not need to be extracted, i.e.
+/// it has been extracted into the extract directory or there is no extract directory.
+pub fn is_extracted(artifact: &Artifact) -> bool {
+    let extract_dir = match artifact.extract_dir {
+        Some(ref extract_dir) => extract_dir,
+        None => return true,
+    };
+
+    let mut marker = String::new();
+
+    let has_marker = File::open(extract_dir.join(EXTRACTED_MARKER))
+        .and_then(|mut marker_file| marker_file.read_to_string(&mut marker))
+        .is_ok();
+
+    has_marker && download::sha256(&artifact.dest).ok().as_ref() == Some(&marker)
+}
+
+/// Removes the verbatim prefixes of the path for comparison, since the junction targets are
+/// read back with them, and ignores the letter case on Windows.
+fn normalize_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+
+    let path = if path.starts_with("\\\\?\\") || path.starts_with("\\??\\") {
+        &path[4..]
+    } else {
+        &path[..]
+    };
+
+    let path = path.trim_end_matches(['/', '\\']);
+
+    if cfg!(windows) {
+        path.to_lowercase()
+    } else {
+        path.to_owned()
+    }
+}
+
+/// Returns true if the junction points at the extract directory, or there is no junction.
+pub fn is_switched(artifact: &Artifact) -> bool {
+    match (artifact.junction.as_ref(), artifact.extract_dir.as_ref()) {
+        (Some(junction), Some(extract_dir)) => fs::read_link(junction)
+            .map(|target| normalize_path(&target) == normalize_path(extract_dir))
+            .unwrap_or(false),
+        _ => true,
+    }
+}
+
+/// Returns true if the artifact is neither to be downloaded, extracted nor switched to.
+pub fn is_up_to_date(artifact: &Artifact) -> bool {
+    is_downloaded(artifact) && is_extracted(artifact) && is_switched(artifact)
+}
+
+/// Path that the artifact is downloaded to before replacing the destination file, so that
+/// a failed download leaves the destination file intact.
+fn partial_path(artifact: &Artifact) -> PathBuf {
+    let mut partial_path = OsString::from(artifact.dest.as_os_str());
+    partial_path.push(".download");
+    PathBuf::from(partial_path)
+}
+
+/// Downloads the artifact and verifies its checksum, before replacing the destination file.
+pub fn fetch(artifact: &Artifact) -> Result<()> {
+    let (url, sha256) = match (artifact.url.as_ref(), artifact.sha256.as_ref()) {
+        (Some(url), Some(sha256)) => (url, sha256),
+        (Some(url), None) => bail!("Unable to verify artifact at '{}' without sha256", url),
+        (None, _) => bail!("Unable to download artifact without url"),
+    };
+
+    if let Some(parent) = artifact.dest.parent() {
+        fs::create_dir_all(parent).chain_err(|| {
+            format!(
+                "Unable to create artifact directory at '{}'",
+                parent.to_string_lossy()
+            )
+        })?;
+    }
+
+    let partial_path = partial_path(artifact);
+
+    let res = download::download(url, &partial_path)
+        .and_then(|_| download::verify_sha256(&partial_path, sha256))
+        .and_then(|_| {
+            fs::rename(&partial_path, &artifact.dest).chain_err(|| {
+                format!(
+                    "Unable to replace artifact at '{}'",
+                    artifact.dest.to_string_lossy()
+                )
+            })
+        });
+
+    if res.is_err() && partial_path.exists() {
+        if let Err(e) = fs::remove_file(&partial_path) {
+            warn!(
+                "Unable to remove downloaded artifact at '{}': {}",
+                partial_path.to_string_lossy(),
+                e
+            );
+        }
+    }
+
+    res
+}
+
+/// Returns the relative path of the archive entry, or `None` if the entry would be extracted
+/// outside of the extract directory.
+fn entry_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => (),
+            ".." => return None,
+            part => path.push(part),
+        }
+    }
+
+    let is_relative = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+
+    if is_relative {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Extracts the archive at the destination path into the extract directory, after removing
+/// the previous contents of the directory if configured, and marks the archive as extracted.
+pub fn extract(artifact: &Artifact) -> Result<()> {
+    let extract_dir = match artifact.extract_dir {
+        Some(ref extract_dir) => extract_dir,
+        None => return Ok(()),
+    };
+
+    if let Some(sha256) = artifact.sha256.as_ref() {
+        download::verify_sha256(&artifact.dest, sha256)?;
+    }
+
+    if artifact.clean.unwrap_or(false) && extract_dir.exists() {
+        fs::remove_dir_all(extract_dir).chain_err(|| {
+            format!(
+                "Unable to remove previous contents of '{}'",
+                extract_dir.to_string_lossy()
+            )
+        })?;
+    }
+
+    extract_zip(&artifact.dest, extract_dir)?;
+
+    let sha256 = download::sha256(&artifact.dest)?;
+
+    File::create(extract_dir.join(EXTRACTED_MARKER))
+        .and_then(|mut marker_file| marker_file.write_all(sha256.as_bytes()))
+        .chain_err(|| {
+            format!(
+                "Unable to mark archive as extracted in '{}'",
+                extract_dir.to_string_lossy()
+            )
+        })
+}
+
+fn extract_zip(archive_path: &Path, extract_dir: &Path) -> Result<()> {
+    let archive_file = File::open(archive_path).chain_err(|| {
+        format!(
+            "Unable to open archive at '{}'",
+            archive_path.to_string_lossy()
+        )
+    })?;
+
+    let mut archive = ZipArchive::new(archive_file).chain_err(|| {
+        format!(
+            "Unable to read archive at '{}' as zip",
+            archive_path.to_string_lossy()
+        )
+    })?;
+
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .chain_err(|| format!("Unable to read entry #{} of archive", index))?;
+
+        let entry_path = match entry_path(entry.name()) {
+            Some(entry_path) => extract_dir.join(entry_path),
+            None => bail!(
+                "Unable to extract entry '{}' outside of '{}'",
+                entry.name(),
+                extract_dir.to_string_lossy()
+            ),
+        };
+
+        // directories are stored as entries ending with a separator
+        let (dir, is_dir) = if entry.name().ends_with('/') || entry.name().ends_with('\\') {
+            (Some(entry_path.as_path()), true)
+        } else {
+            (entry_path.parent(), false)
+        };
+
+        if let Some(dir) = dir {
+            fs::create_dir_all(dir).chain_err(|| {
+                format!("Unable to create directory at '{}'", dir.to_string_lossy())
+            })?;
+        }
+
+        if is_dir {
+            continue;
+        }
+
+        let mut entry_file = File::create(&entry_path).chain_err(|| {
+            format!(
+                "Unable to create file at '{}'",
+                entry_path.to_string_lossy()
+            )
+        })?;
+
+        io::copy(&mut entry, &mut entry_file).chain_err(|| {
+            format!(
+                "Unable to extract '{}' to '{}'",
+                entry.name(),
+                entry_path.to_string_lossy()
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Points the junction at the extract directory, replacing any existing junction, so that
+/// switching back to a previously extracted version rolls the deployment back.
+pub fn switch(artifact: &Artifact) -> Result<()> {
+    let (junction, extract_dir) = match (artifact.junction.as_ref(), artifact.extract_dir.as_ref())
+    {
+        (Some(junction), Some(extract_dir)) => (junction, extract_dir),
+        _ => return Ok(()),
+    };
+
+    if let Ok(metadata) = fs::symlink_metadata(junction) {
+        if !metadata.file_type().is_symlink() {
+            bail!(
+                "Unable to replace '{}' with a junction since it is not a junction",
+                junction.to_string_lossy()
+            );
+        }
+
+        imp::remove_junction(junction).chain_err(|| {
+            format!(
+                "Unable to remove previous junction at '{}'",
+                junction.to_string_lossy()
+            )
+        })?;
+    }
+
+    if let Some(parent) = junction.parent() {
+        fs::create_dir_all(parent).chain_err(|| {
+            format!("Unable to create directory at '{}'", parent.to_string_lossy())
+        })?;
+    }
+
+    imp::create_junction(junction, extract_dir).chain_err(|| {
+        format!(
+            "Unable to point junction at '{}' to '{}'",
+            junction.to_string_lossy(),
+            extract_dir.to_string_lossy()
+        )
+    })
+}
+
+/// Size of the header of `REPARSE_DATA_BUFFER`, i.e. the tag, data length and reserved fields.
+const REPARSE_HEADER_SIZE: usize = 8;
+
+/// Size of the offset and length fields of the names of a mount point reparse buffer.
+const MOUNT_POINT_HEADER_SIZE: usize = 8;
+
+/// Reparse tag of the mount points, which directory junctions are.
+const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+
+/// Returns the `REPARSE_DATA_BUFFER` that makes a directory a junction to the absolute target
+/// directory, whose substitute name is the NT path of the target and whose print name is the
+/// target as given, both terminated by null.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn mount_point_buffer(target: &str) -> Vec<u8> {
+    let target = target.trim_start_matches(r"\\?\");
+    let wide = |s: &str| -> Vec<u16> { s.encode_utf16().collect() };
+
+    let substitute_name = wide(&format!(r"\??\{}", target));
+    let print_name = wide(target);
+
+    let substitute_len = substitute_name.len() * 2;
+    let print_len = print_name.len() * 2;
+    let data_len = MOUNT_POINT_HEADER_SIZE + substitute_len + 2 + print_len + 2;
+
+    let mut buffer = Vec::with_capacity(REPARSE_HEADER_SIZE + data_len);
+
+    fn push_u16(buffer: &mut Vec<u8>, value: usize) {
+        buffer.push(value as u8);
+        buffer.push((value >> 8) as u8);
+    }
+
+    buffer.extend_from_slice(&[
+        IO_REPARSE_TAG_MOUNT_POINT as u8,
+        (IO_REPARSE_TAG_MOUNT_POINT >> 8) as u8,
+        (IO_REPARSE_TAG_MOUNT_POINT >> 16) as u8,
+        (IO_REPARSE_TAG_MOUNT_POINT >> 24) as u8,
+    ]);
+
+    push_u16(&mut buffer, data_len);
+    push_u16(&mut buffer, 0);
+
+    // the print name follows the substitute name and its terminating null
+    push_u16(&mut buffer, 0);
+    push_u16(&mut buffer, substitute_len);
+    push_u16(&mut buffer, substitute_len + 2);
+    push_u16(&mut buffer, print_len);
+
+    for unit in substitute_name.iter().chain(&[0]).chain(&print_name).chain(&[0]) {
+        push_u16(&mut buffer, *unit as usize);
+    }
+
+    buffer
+}
+
+#[cfg(windows)]
+mod imp {
+    use errors::*;
+    use std::env;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+    use super::mount_point_buffer;
+    use winapi::shared::minwindef::DWORD;
+    use winapi::um::fileapi::{CreateFileW, OPEN_EXISTING};
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::ioapiset::DeviceIoControl;
+    use winapi::um::winbase::{FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT};
+    use winapi::um::winioctl::FSCTL_SET_REPARSE_POINT;
+    use winapi::um::winnt::{GENERIC_WRITE, HANDLE};
+
+    struct FileHandle(HANDLE);
+
+    impl Drop for FileHandle {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+
+    pub fn remove_junction(junction: &Path) -> Result<()> {
+        // removing the junction as a directory leaves its target intact
+        fs::remove_dir(junction).chain_err(|| "Unable to remove junction directory")
+    }
+
+    /// Sets the mount point reparse data on the directory, without going through any shell
+    /// that would interpret the characters in the paths.
+    fn set_mount_point(junction: &Path, target: &Path) -> Result<()> {
+        let target = if target.is_absolute() {
+            target.to_owned()
+        } else {
+            env::current_dir()
+                .chain_err(|| "Unable to get current directory")?
+                .join(target)
+        };
+
+        let buffer = mount_point_buffer(&target.to_string_lossy());
+
+        let wide_junction: Vec<u16> = OsStr::new(junction)
+            .encode_wide()
+            .chain(Some(0))
+            .collect();
+
+        let handle = unsafe {
+            CreateFileW(
+                wide_junction.as_ptr(),
+                GENERIC_WRITE,
+                0,
+                ptr::null_mut(),
+                OPEN_EXISTING,
+                FILE_FLAG_OPEN_REPARSE_POINT | FILE_FLAG_BACKUP_SEMANTICS,
+                ptr::null_mut(),
+            )
+        };
+
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error())
+                .chain_err(|| "Unable to open junction directory");
+        }
+
+        let handle = FileHandle(handle);
+        let mut returned: DWORD = 0;
+
+        let res = unsafe {
+            DeviceIoControl(
+                handle.0,
+                FSCTL_SET_REPARSE_POINT,
+                buffer.as_ptr() as *mut _,
+                buffer.len() as DWORD,
+                ptr::null_mut(),
+                0,
+                &mut returned,
+                ptr::null_mut(),
+            )
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error())
+                .chain_err(|| "Unable to set reparse point of junction directory");
+        }
+
+        Ok(())
+    }
+
+    pub fn create_junction(junction: &Path, target: &Path) -> Result<()> {
+        fs::create_dir(junction).chain_err(|| "Unable to create junction directory")?;
+
+        set_mount_point(junction, target).inspect_err(|_| {
+            let _ = fs::remove_dir(junction);
+        })
+    }
+}
+
+/// Junctions are only available on Windows, so symbolic links stand in for them elsewhere.
+#[cfg(not(windows))]
+mod imp {
+    use errors::*;
+    use std::fs;
+    use std::os::unix;
+    use std::path::Path;
+
+    pub fn remove_junction(junction: &Path) -> Result<()> {
+        fs::remove_file(junction).chain_err(|| "Unable to remove symbolic link")
+    }
+
+    pub fn create_junction(junction: &Path, target: &Path) -> Result<()> {
+        unix::fs::symlink(target, junction).chain_err(|| "Unable to create symbolic link")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(buffer: &[u8], offset: usize) -> usize {
+        buffer[offset] as usize | (buffer[offset + 1] as usize) << 8
+    }
+
+    fn wide_at(buffer: &[u8], offset: usize, len: usize) -> String {
+        let units: Vec<u16> = buffer[offset..offset + len]
+            .chunks(2)
+            .map(|pair| u16::from(pair[0]) | u16::from(pair[1]) << 8)
+            .collect();
+
+        String::from_utf16(&units).unwrap()
+    }
+
+    #[test]
+    fn mount_point_buffer_keeps_shell_characters_in_target() {
+        let target = r"C:\apps\a&b^c|d%PATH%";
+        let buffer = mount_point_buffer(&format!(r"\\?\{}", target));
+
+        assert_eq!(&buffer[..4], &[0x03, 0x00, 0x00, 0xA0]);
+        assert_eq!(u16_at(&buffer, 4), buffer.len() - REPARSE_HEADER_SIZE);
+
+        let names = REPARSE_HEADER_SIZE + MOUNT_POINT_HEADER_SIZE;
+        let (substitute_offset, substitute_len) = (u16_at(&buffer, 8), u16_at(&buffer, 10));
+        let (print_offset, print_len) = (u16_at(&buffer, 12), u16_at(&buffer, 14));
+
+        assert_eq!(
+            wide_at(&buffer, names + substitute_offset, substitute_len),
+            format!(r"\??\{}", target)
+        );
+
+        assert_eq!(wide_at(&buffer, names + print_offset, print_len), target);
+        assert_eq!(u16_at(&buffer, names + print_offset + print_len), 0);
+    }
+}
@@ -0,0 +1,208 @@
+//! Appends every command that is run to query or control the services to an audit log, one
+//! JSON entry per line with the secrets replaced. Every entry holds the SHA-256 hash of the
+//! entry before it, so that any entry changed, removed or inserted afterwards before the
+//! last entry breaks the chain, which `verify` detects.
+
+use chrono::Utc;
+use cmdline;
+use errors::*;
+use notify;
+use serde_json;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use transcript;
+
+/// Hash that the first entry of the audit log refers to as the entry before it.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Holds a command that has been run.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AuditEntry {
+    /// UTC time at which the command has completed in RFC 3339.
+    pub timestamp: String,
+
+    /// User that has run the command.
+    pub user: String,
+
+    /// Host that the command has been run on.
+    pub host: String,
+
+    /// Exit code of the command. `None` if the command could not be run, timed out or was
+    /// terminated by a signal.
+    pub exit_code: Option<i32>,
+
+    /// Command line with the secrets replaced.
+    pub command: String,
+
+    /// SHA-256 hash in hex of the line of the entry before this one.
+    pub prev_hash: String,
+}
+
+/// Appends the commands to the audit log file, which may be shared across concurrent
+/// workers.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    secrets: Vec<String>,
+
+    /// Hash of the last entry in the file, which the next entry refers to.
+    last_hash: Mutex<String>,
+}
+
+fn hash_line(line: &str) -> String {
+    let mut hasher = Sha256::default();
+    hasher.input(line.as_bytes());
+    format!("{:x}", hasher.result())
+}
+
+/// Returns the name of the user running this process, or `unknown` if unknown.
+fn user_name() -> String {
+    env::var("USERNAME")
+        .or_else(|_| env::var("USER"))
+        .unwrap_or_else(|_| "unknown".to_owned())
+}
+
+/// Returns the lines of the audit log file in order, or no line if the file does not exist.
+fn read_lines(path: &Path) -> Result<Vec<String>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let file = File::open(path).chain_err(|| {
+        format!("Unable to open audit log at '{}'", path.to_string_lossy())
+    })?;
+
+    BufReader::new(file)
+        .lines()
+        .collect::<::std::io::Result<Vec<_>>>()
+        .chain_err(|| format!("Unable to read audit log at '{}'", path.to_string_lossy()))
+}
+
+impl AuditLog {
+    /// Opens the audit log file at the path, creating it on the first entry if it does not
+    /// exist, and replacing every occurrence of the given secrets in the commands.
+    pub fn open<P: AsRef<Path>>(path: P, secrets: Vec<String>) -> Result<AuditLog> {
+        let path = path.as_ref();
+
+        let last_hash = match read_lines(path)?.last() {
+            Some(line) => hash_line(line),
+            None => GENESIS_HASH.to_owned(),
+        };
+
+        Ok(AuditLog {
+            path: path.to_owned(),
+            secrets: secrets
+                .into_iter()
+                .filter(|secret| !secret.is_empty())
+                .collect(),
+            last_hash: Mutex::new(last_hash),
+        })
+    }
+
+    /// Appends the command run on the given remote host, or on the local machine if no host
+    /// is given, with its exit code. The secrets are replaced in the arguments before they
+    /// are quoted into the command line.
+    pub fn append<S: AsRef<str>>(
+        &self,
+        program: &str,
+        args: &[S],
+        host: Option<&str>,
+        exit_code: Option<i32>,
+    ) -> Result<()> {
+        let command =
+            cmdline::to_cmd_line(program, &transcript::redact_args(args, &self.secrets));
+
+        // held until the entry is written, so that concurrent entries chain in order
+        let mut last_hash = self.last_hash.lock().unwrap();
+
+        let entry = AuditEntry {
+            timestamp: Utc::now().to_rfc3339(),
+            user: user_name(),
+            host: host.map_or_else(notify::machine_name, |host| host.to_owned()),
+            exit_code,
+            command,
+            prev_hash: last_hash.clone(),
+        };
+
+        let line =
+            serde_json::to_string(&entry).chain_err(|| "Unable to serialize audit entry")?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .chain_err(|| {
+                format!("Unable to open audit log at '{}'", self.path.to_string_lossy())
+            })?;
+
+        writeln!(file, "{}", line).chain_err(|| {
+            format!("Unable to append to audit log at '{}'", self.path.to_string_lossy())
+        })?;
+
+        *last_hash = hash_line(&line);
+        Ok(())
+    }
+}
+
+/// Verifies that every entry of the audit log file at the path refers to the hash of the
+/// entry before it, and returns the number of entries.
+pub fn verify<P: AsRef<Path>>(path: P) -> Result<usize> {
+    let path = path.as_ref();
+
+    if !path.is_file() {
+        bail!("Unable to find audit log at '{}'", path.to_string_lossy());
+    }
+
+    let lines = read_lines(path)?;
+    let mut prev_hash = GENESIS_HASH.to_owned();
+
+    for (index, line) in lines.iter().enumerate() {
+        let entry: AuditEntry = serde_json::from_str(line).chain_err(|| {
+            format!("Unable to interpret entry {} of the audit log", index + 1)
+        })?;
+
+        if entry.prev_hash != prev_hash {
+            bail!(
+                "Entry {} of the audit log does not match the hash of the entry before it, \
+                 the audit log has been tampered with",
+                index + 1
+            );
+        }
+
+        prev_hash = hash_line(line);
+    }
+
+    Ok(lines.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn redacts_secret_that_quoting_escapes() {
+        let secret = r#"pa"ss\"#;
+
+        let path = env::temp_dir().join(format!(
+            "nssm_exec_audit_test_{}.log",
+            ::std::process::id()
+        ));
+
+        let _ = fs::remove_file(&path);
+
+        let audit_log = AuditLog::open(&path, vec![secret.to_owned()]).unwrap();
+        let args = ["set", "app", "ObjectName", "app", secret];
+        audit_log.append("nssm.exe", &args, None, Some(0)).unwrap();
+
+        let lines = read_lines(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let entry: AuditEntry = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(entry.command, "nssm.exe set app ObjectName app <secret>");
+    }
+}
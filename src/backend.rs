@@ -0,0 +1,163 @@
+//! Abstracts the tool that installs and configures the services, so that services managed by
+//! nssm, WinSW and shawl can be mixed in the same configuration, and the same services can be
+//! managed by systemd on Linux.
+
+use config::{Backend, FileConfig};
+use errors::*;
+use mock::Mock;
+use nssm;
+use output;
+use params::ServiceParam;
+use scm;
+use shawl::Shawl;
+use state::ServiceState;
+use std::path::Path;
+use systemd::Systemd;
+use winsw::Winsw;
+use super::{run_nssm_cmd, run_nssm_modify_cmd};
+
+/// Operations that every tool managing the services has to support.
+/// Operations that modify the services only log what they would do if dry run is enabled.
+pub trait ServiceBackend: Sync {
+    /// Installs the service with the given executable path.
+    fn install(&self, service_name: &str, path: &Path, file_config: &FileConfig) -> Result<()>;
+
+    /// Sets the parameter of the installed service.
+    fn set_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<()>;
+
+    /// Returns the installed value of the parameter of the service, where multiple values
+    /// are on separate lines.
+    fn get_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<String>;
+
+    /// Queries the current state of the service.
+    /// Returns error if the service does not exist.
+    fn status(&self, service_name: &str, file_config: &FileConfig) -> Result<ServiceState>;
+
+    /// Starts the service without waiting for it to be running.
+    fn start(&self, service_name: &str, file_config: &FileConfig) -> Result<()>;
+
+    /// Stops the service without necessarily waiting for it to stop.
+    fn stop(&self, service_name: &str, file_config: &FileConfig) -> Result<()>;
+
+    /// Forcibly terminates the service together with every process that it has started,
+    /// for services that do not stop by themselves.
+    fn kill_tree(&self, service_name: &str, file_config: &FileConfig) -> Result<()>;
+
+    /// Removes the service, which should have been stopped.
+    fn remove(&self, service_name: &str, file_config: &FileConfig) -> Result<()>;
+}
+
+/// Manages the services with the configured nssm executable, while controlling them
+/// through the Service Control Manager directly.
+pub struct Nssm;
+
+impl ServiceBackend for Nssm {
+    fn install(&self, service_name: &str, path: &Path, file_config: &FileConfig) -> Result<()> {
+        run_nssm_modify_cmd(&nssm::install(service_name, path), file_config)
+    }
+
+    fn set_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<()> {
+        run_nssm_modify_cmd(&nssm::set(service_name, param), file_config)
+    }
+
+    fn get_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<String> {
+        let output = run_nssm_cmd(&nssm::get(service_name, param), file_config)?;
+        let value = output::decode(&output.stdout);
+        Ok(value.trim().to_owned())
+    }
+
+    fn status(&self, service_name: &str, file_config: &FileConfig) -> Result<ServiceState> {
+        scm::query_state(service_name, file_config)
+    }
+
+    fn start(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        scm::start(service_name, file_config)
+    }
+
+    fn stop(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        scm::stop(service_name, file_config)
+    }
+
+    fn kill_tree(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        scm::kill_tree(service_name, file_config)
+    }
+
+    fn remove(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_nssm_modify_cmd(&nssm::remove(service_name), file_config)
+    }
+}
+
+/// Returns the backend configured for the service with the given name.
+pub fn of(service_name: &str, file_config: &FileConfig) -> &'static dyn ServiceBackend {
+    match file_config.backend(service_name) {
+        Backend::Nssm => &Nssm,
+        Backend::Winsw => &Winsw,
+        Backend::Shawl => &Shawl,
+        Backend::Systemd => &Systemd,
+        Backend::Mock => &Mock,
+    }
+}
+
+/// Installs the service with its configured backend.
+pub fn install(service_name: &str, path: &Path, file_config: &FileConfig) -> Result<()> {
+    of(service_name, file_config).install(service_name, path, file_config)
+}
+
+/// Sets the parameter of the service with its configured backend.
+pub fn set_param(service_name: &str, param: &ServiceParam, file_config: &FileConfig) -> Result<()> {
+    of(service_name, file_config).set_param(service_name, param, file_config)
+}
+
+/// Returns the installed value of the parameter of the service with its configured backend.
+pub fn get_param(
+    service_name: &str,
+    param: &ServiceParam,
+    file_config: &FileConfig,
+) -> Result<String> {
+    of(service_name, file_config).get_param(service_name, param, file_config)
+}
+
+/// Queries the current state of the service with its configured backend.
+pub fn status(service_name: &str, file_config: &FileConfig) -> Result<ServiceState> {
+    of(service_name, file_config).status(service_name, file_config)
+}
+
+/// Starts the service with its configured backend.
+pub fn start(service_name: &str, file_config: &FileConfig) -> Result<()> {
+    of(service_name, file_config).start(service_name, file_config)
+}
+
+/// Stops the service with its configured backend.
+pub fn stop(service_name: &str, file_config: &FileConfig) -> Result<()> {
+    of(service_name, file_config).stop(service_name, file_config)
+}
+
+/// Terminates the process tree of the service with its configured backend.
+pub fn kill_tree(service_name: &str, file_config: &FileConfig) -> Result<()> {
+    of(service_name, file_config).kill_tree(service_name, file_config)
+}
+
+/// Removes the service with its configured backend.
+pub fn remove(service_name: &str, file_config: &FileConfig) -> Result<()> {
+    of(service_name, file_config).remove(service_name, file_config)
+}
@@ -1,139 +1,175 @@
-#[macro_use]
-extern crate derive_error_chain;
-#[macro_use]
-extern crate error_chain;
-extern crate file;
-extern crate itertools;
-#[macro_use]
-extern crate lazy_static;
+extern crate chrono;
+extern crate crossbeam;
 #[macro_use]
 extern crate log;
 extern crate log4rs;
-extern crate serde;
-#[macro_use]
-extern crate serde_derive;
+extern crate nssm_exec;
+extern crate rpassword;
+extern crate serde_yaml;
 extern crate simple_logger;
 extern crate structopt;
 #[macro_use]
 extern crate structopt_derive;
-extern crate toml;
 
-use std::collections::HashMap;
-use std::fmt::Display;
-use std::iter::{Map, Zip};
+use nssm_exec::{AuditLog, Backend, FileConfig, NssmVersion, RunReport, ServiceDiff,
+                ServiceListing, ServiceReport, ServiceStatus, Session, Transcript};
+use nssm_exec::explain::Explanation;
+use nssm_exec::import::ImportedService;
+use log::LogLevelFilter;
+use log4rs::file::{Deserializers, RawConfig};
+use nssm_exec::errors::*;
+use std::cmp;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
-use std::path::PathBuf;
-use std::process::{self, Command, Output};
-use std::slice::Iter;
-use std::time::Duration;
+use std::time::Instant;
 use structopt::StructOpt;
 
-struct OtherConfigRef<'a, 'b, 'c> {
-    deps: Option<&'a String>,
-    start_on_create: Option<&'b bool>,
-    account: Option<&'c Account>,
-}
-
-/// Groups the Windows account settings for running a service.
-#[derive(Deserialize)]
-struct Account {
-    /// Windows account username.
-    user: String,
-
-    /// Password corresponding to the username.
-    /// May be left as empty string if username does not require password.
-    password: String,
-}
-
-/// Groups the extra configurations required for configuring the service.
-/// May be used on every service or in a global context.
-#[derive(Deserialize)]
-struct OtherConfig {
-    /// List of other service names to depend on before starting this service.
-    /// Multiple service names are space delimited.
-    deps: Option<String>,
-
-    /// States whether to immediately start the created service.
-    /// Defaults to false.
-    start_on_create: Option<bool>,
-
-    /// Holds the account configuration to run the service.
-    account: Option<Account>,
-}
-
-/// Groups the configurations required for a service.
-#[derive(Deserialize)]
-struct Service {
-    /// Name of service.
-    name: String,
-
-    /// Service executable file path.
-    path: PathBuf,
-
-    /// Service startup directory path. Leaving empty should use the directory path
-    /// containing the executable.
-    startup_dir: Option<PathBuf>,
-
-    /// Arguments to be passed into the executable. Multiple arguments are space delimited and
-    /// arguments may be wrapped around double quotes like in cmd.
-    args: Option<String>,
-
-    /// Description string of service.
-    description: Option<String>,
-
-    /// Holds the extra configurations.
-    /// Any specific extra configurations will always override the global ones.
-    other: Option<OtherConfig>,
-}
-
-/// Represents the TOML nssm_exec configuration.
-#[derive(Deserialize)]
-struct FileConfig {
-    /// NSSM executable file path
-    nssm_path: PathBuf,
-
-    /// Interval in milliseconds before retrying to check if the service has stopped.
-    /// Default is 500. Only applicable if there is any running existing service.
-    pending_stop_poll_ms: Option<u64>,
+/// Exit code if the run has succeeded.
+const EXIT_SUCCESS: i32 = 0;
 
-    /// Number of retries to check if the service has stopped.
-    /// Default is 5. Only applicable if there is any running existing service.
-    pending_stop_poll_count: Option<u64>,
+/// Exit code for any failure without a more specific exit code.
+const EXIT_FAILURE: i32 = 1;
 
-    /// Interval in milliseconds before retrying to check if the service has started.
-    /// Default is 500. Only applicable if there is any running existing service.
-    pending_start_poll_ms: Option<u64>,
+/// Exit code if the configuration cannot be parsed, loaded or validated.
+const EXIT_CONFIG: i32 = 2;
 
-    /// Number of retries to check if the service has started.
-    /// Default is 5. Only applicable if there is any running existing service.
-    pending_start_poll_count: Option<u64>,
+/// Exit code if the nssm executable is missing or does not support the configuration.
+const EXIT_NSSM: i32 = 3;
 
-    /// Holds the global extra configurations.
-    /// Any specific extra configurations will always override the global ones.
-    global: Option<OtherConfig>,
+/// Exit code if some, but not all, of the services or hosts have failed.
+const EXIT_PARTIAL_FAILURE: i32 = 4;
 
-    /// Holds the service configurations.
-    services: Vec<Service>,
-}
+/// Exit code if all the services or hosts have failed.
+const EXIT_TOTAL_FAILURE: i32 = 5;
 
-#[derive(StructOpt, Debug)]
+#[derive(StructOpt, Clone, Debug)]
 #[structopt(name = "NSSM Executor")]
 /// Program to facilitate easy adding of nssm services.
 struct MainConfig {
-    #[structopt(short = "c", long = "conf", default_value = "config/nssm_exec.toml")]
-    /// TOML configuration to set up NSSM
-    config_path: String,
+    #[structopt(short = "c", long = "conf", default_value = "config/nssm_exec.toml",
+                number_of_values = 1)]
+    /// TOML configuration to set up NSSM, or YAML if the file has .yml or .yaml extension,
+    /// or a directory of TOML configurations, may be repeated to merge every file over the
+    /// files before it
+    config_paths: Vec<String>,
 
     #[structopt(short = "l", long = "log", default_value = "config/logging_nssm_exec.yml")]
     /// Logging configuration file path
     log_config_path: Option<String>,
 
+    #[structopt(short = "v", long = "verbose")]
+    /// Increases the logging verbosity, -v for debug and -vv for trace messages
+    verbose: u64,
+
+    #[structopt(short = "q", long = "quiet")]
+    /// Only logs warnings and errors, taking precedence over --verbose
+    quiet: bool,
+
+    #[structopt(long = "profile")]
+    /// Name of the profile in the TOML configuration to overlay over the base configuration
+    profile: Option<String>,
+
+    #[structopt(long = "prefix")]
+    /// Prefix to prepend to the name of every service, overriding the TOML configuration, so
+    /// that the same configuration may be installed several times side by side
+    prefix: Option<String>,
+
+    #[structopt(long = "secrets")]
+    /// TOML or YAML file with the account settings to merge over the configured ones, e.g.
+    /// [services.<name>.other.account], so that they may be kept out of the configuration
+    secrets_path: Option<String>,
+
+    #[structopt(long = "prompt-passwords")]
+    /// Prompts for the password of every account whose password is not in the configuration
+    prompt_passwords: bool,
+
+    #[structopt(long = "dry-run")]
+    /// Only logs the nssm commands that modify the services without running them
+    dry_run: bool,
+
+    #[structopt(long = "transcript")]
+    /// Writes the commands logged by the dry run in order into the given file, with the
+    /// passwords replaced by placeholders
+    transcript: Option<String>,
+
+    #[structopt(long = "record")]
+    /// Writes every command run with its exit code and output in order into the given JSON
+    /// file, with the passwords replaced by placeholders, to be replayed elsewhere
+    record: Option<String>,
+
+    #[structopt(long = "emit-script")]
+    /// Writes a standalone .bat, .cmd or .ps1 script that recreates the services with nssm
+    /// instead of processing them, e.g. to copy over to air-gapped hosts
+    emit_script: Option<String>,
+
+    #[structopt(long = "only")]
+    /// Only processes the service with the given name, may be repeated for multiple services
+    only: Vec<String>,
+
+    #[structopt(long = "fail-fast")]
+    /// Skips the remaining services after the first service that has failed
+    fail_fast: bool,
+
+    #[structopt(long = "blue-green")]
+    /// Recreates the existing services only after a copy of each service with the -green
+    /// suffix is installed and started, removing the copy afterwards
+    blue_green: bool,
+
+    #[structopt(long = "canary")]
+    /// Processes the first service alone, and the remaining services only if it succeeds
+    canary: bool,
+
+    #[structopt(long = "skip-existing")]
+    /// Leaves the services that are already installed untouched, and only installs the
+    /// services that do not exist yet
+    skip_existing: bool,
+
+    #[structopt(long = "report")]
+    /// Writes a JSON summary of the actions taken and the outcome of every service
+    report: Option<String>,
+
+    #[structopt(long = "registry-direct")]
+    /// Writes the application parameters of the nssm services directly into the registry
+    /// instead of running nssm once per parameter
+    registry_direct: bool,
+
+    #[structopt(long = "backend")]
+    /// Manages every service with the given backend instead of the configured ones, e.g.
+    /// mock to only simulate the services in memory
+    backend: Option<Backend>,
+
+    #[structopt(long = "elevate")]
+    /// Relaunches the program elevated through the UAC prompt if it lacks the administrator
+    /// privileges needed to manage the services
+    elevate: bool,
+
+    #[structopt(long = "working-dir")]
+    /// Changes the working directory before reading any configuration, which is used to keep
+    /// the working directory of an elevated relaunch
+    working_dir: Option<String>,
+
+    #[structopt(long = "parallelism")]
+    /// Number of services to process concurrently, at most 16, or 0 for the maximum,
+    /// overriding the TOML configuration
+    parallelism: Option<usize>,
+
+    #[structopt(long = "host-parallelism")]
+    /// Number of remote hosts to process concurrently, at most 16, or 0 for the maximum,
+    /// overriding the TOML configuration
+    host_parallelism: Option<usize>,
+
     #[structopt(subcommand)]
     /// Possible other specialized commands to use
     cmd: Option<CustomCmd>,
 }
 
-#[derive(StructOpt, Debug)]
+#[derive(StructOpt, Clone, Debug)]
 enum CustomCmd {
     #[structopt(name = "stop")]
     /// Only stops the services in the TOML configuration
@@ -142,603 +178,1316 @@ enum CustomCmd {
     #[structopt(name = "remove")]
     /// Only stops and removes the services in the TOML configuration.
     Remove,
+
+    #[structopt(name = "purge")]
+    /// Only stops and removes every service in the TOML configuration on every host, after
+    /// confirming on the console.
+    Purge {
+        #[structopt(short = "y", long = "yes")]
+        /// Skips the confirmation
+        yes: bool,
+    },
+
+    #[structopt(name = "restart")]
+    /// Only stops and then starts the services in the TOML configuration.
+    Restart,
+
+    #[structopt(name = "update")]
+    /// Only sets the changed parameters of the services in the TOML configuration without
+    /// reinstalling them, and creates the services that do not exist yet.
+    Update,
+
+    #[structopt(name = "status")]
+    /// Only prints the status of the services in the TOML configuration.
+    Status,
+
+    #[structopt(name = "list")]
+    /// Only prints the services in the TOML configuration with their status and whether they
+    /// are managed by nssm, followed by the installed nssm services that are not in the TOML
+    /// configuration.
+    List,
+
+    #[structopt(name = "diff")]
+    /// Only prints the differences between the TOML configuration and the installed
+    /// parameters of the services.
+    Diff,
+
+    #[structopt(name = "explain")]
+    /// Only prints the extra configurations of the service after merging its own over the
+    /// global ones with where each value comes from, and the nssm commands that set its
+    /// parameters.
+    Explain {
+        /// Name of the service in the TOML configuration
+        service_name: String,
+    },
+
+    #[structopt(name = "backup")]
+    /// Only writes the installed parameters of the services in the TOML configuration into
+    /// a backup file.
+    Backup {
+        #[structopt(short = "o", long = "output")]
+        /// Backup file path, defaults to nssm_exec_backup_<timestamp>.toml
+        output: Option<String>,
+    },
+
+    #[structopt(name = "restore")]
+    /// Only reinstalls the services in the backup file with their backed up parameters.
+    Restore {
+        /// Backup file path to restore from
+        backup_path: String,
+    },
+
+    #[structopt(name = "export-dsc")]
+    /// Only writes a PowerShell DSC configuration that recreates the services in the TOML
+    /// configuration with nssm whenever they differ from it.
+    ExportDsc {
+        #[structopt(short = "o", long = "output")]
+        /// DSC configuration file path, defaults to NssmExec.ps1
+        output: Option<String>,
+    },
+
+    #[structopt(name = "export-ansible")]
+    /// Only writes an Ansible playbook with win_nssm and win_service tasks for the services in
+    /// the TOML configuration.
+    ExportAnsible {
+        #[structopt(short = "o", long = "output")]
+        /// Playbook file path, defaults to nssm_exec.yml
+        output: Option<String>,
+    },
+
+    #[structopt(name = "validate")]
+    /// Only checks the TOML configuration, the nssm executable and the service executables
+    /// for problems, without touching the services.
+    Validate,
+
+    #[structopt(name = "verify-audit")]
+    /// Only verifies that the entries of the configured audit log have not been tampered
+    /// with, without touching the services.
+    VerifyAudit,
+
+    #[structopt(name = "bootstrap")]
+    /// Only downloads the configured nssm release, verifies its checksum and extracts the
+    /// nssm executable for this machine to the nssm path in the TOML configuration.
+    Bootstrap,
+
+    #[structopt(name = "agent")]
+    /// Only reconciles the services in the TOML configuration like update at every interval
+    /// of the agent settings, reloading the configuration each time, until stopped.
+    Agent {
+        #[structopt(long = "install")]
+        /// Installs and starts this program as a service running the agent with the same
+        /// configuration instead
+        install: bool,
+
+        #[structopt(long = "uninstall")]
+        /// Stops and removes the service running the agent instead
+        uninstall: bool,
+    },
+
+    #[structopt(name = "serve")]
+    /// Only serves an HTTP API to list, query, start, stop and restart the services in the
+    /// TOML configuration and apply the configuration, until stopped.
+    Serve {
+        #[structopt(long = "address")]
+        /// Address to listen on, overriding the TOML configuration
+        address: Option<String>,
+    },
+
+    #[structopt(name = "grpc")]
+    /// Only serves a gRPC interface to query the status of the services in the TOML
+    /// configuration, and to apply the configuration or stop the services while streaming
+    /// their results, until stopped.
+    Grpc {
+        #[structopt(long = "address")]
+        /// Address to listen on, overriding the TOML configuration
+        address: Option<String>,
+    },
+
+    #[structopt(name = "tui")]
+    /// Only shows the services in the TOML configuration with their live status in the
+    /// terminal, with keys to start, stop, restart or reapply the selected service, until
+    /// quit.
+    Tui,
+
+    #[structopt(name = "init")]
+    /// Only prompts for the nssm path and the services, and writes them into the TOML
+    /// configuration, with the default logging configuration at the logging configuration
+    /// path.
+    Init {
+        #[structopt(long = "force")]
+        /// Overwrites the configuration files if they exist
+        force: bool,
+    },
+
+    #[structopt(name = "import-systemd")]
+    /// Only prints the services translated from the systemd unit files as TOML configuration,
+    /// to be reviewed and added to the TOML configuration.
+    ImportSystemd {
+        /// Paths of the systemd .service unit files
+        unit_paths: Vec<String>,
+
+        #[structopt(short = "o", long = "output")]
+        /// Writes the TOML into the file path instead of printing it
+        output: Option<String>,
+    },
+
+    #[structopt(name = "import-compose")]
+    /// Only prints the services translated from the docker-compose file as TOML configuration,
+    /// to be reviewed and added to the TOML configuration.
+    ImportCompose {
+        /// Path of the docker-compose file
+        compose_path: String,
+
+        #[structopt(short = "o", long = "output")]
+        /// Writes the TOML into the file path instead of printing it
+        output: Option<String>,
+    },
+
+    #[structopt(name = "replay")]
+    /// Only runs the commands of a session recorded with --record in order on this machine,
+    /// and reports every command whose exit code or output differs from the recording.
+    Replay {
+        /// Path of the recorded session file
+        session_path: String,
+    },
+
+    #[structopt(name = "encrypt-password")]
+    /// Prompts for a password and prints it protected with DPAPI for the current user, to be
+    /// used as encrypted_password in the TOML configuration on this machine.
+    EncryptPassword,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum ServiceState {
-    /// SERVICE_CONTINUE_PENDING (0x00000005)
-    /// The service continue is pending.
-    ContinuePending,
+fn print_diffs(diffs: &[ServiceDiff]) {
+    for diff in diffs {
+        if !diff.installed {
+            println!("Service '{}' is not installed", diff.name);
+            continue;
+        }
+
+        if diff.params.is_empty() {
+            println!("Service '{}' is up to date", diff.name);
+            continue;
+        }
 
-    /// SERVICE_PAUSE_PENDING (0x00000006)
-    /// The service pause is pending.
-    PausePending,
+        println!("Service '{}' differs:", diff.name);
+
+        for param_diff in &diff.params {
+            // multiple installed values are on separate lines
+            let installed = param_diff
+                .installed
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            println!(
+                "  {}: '{}' -> '{}'",
+                param_diff.param.key(),
+                installed,
+                param_diff.param.value()
+            );
+        }
+    }
+}
 
-    /// SERVICE_PAUSED (0x00000007)
-    /// The service is paused.
-    Paused,
+/// Lists the services to purge and asks on the console whether to proceed.
+fn confirm_purge(file_config: &FileConfig) -> Result<bool> {
+    eprintln!("The following services will be stopped and removed:");
 
-    /// SERVICE_RUNNING (0x00000004)
-    /// The service is running.
-    Running,
+    for service in &file_config.services {
+        match service.hosts {
+            Some(ref hosts) if !hosts.is_empty() => {
+                eprintln!("  {} (on {})", service.name, hosts.join(", "))
+            }
+            _ => eprintln!("  {}", service.name),
+        }
+    }
 
-    /// SERVICE_START_PENDING (0x00000002)
-    /// The service is starting.
-    StartPending,
+    eprint!("Proceed? [y/N] ");
 
-    /// SERVICE_STOP_PENDING (0x00000003)
-    /// The service is stopping.
-    StopPending,
+    let mut answer = String::new();
 
-    /// SERVICE_STOPPED (0x00000001)
-    /// The service is not running.
-    Stopped,
-}
+    io::stdin()
+        .read_line(&mut answer)
+        .chain_err(|| "Unable to read the confirmation")?;
 
-lazy_static! {
-    static ref STATE_MAP: HashMap<&'static str, ServiceState> = {
-        let mut m = HashMap::new();
-        m.insert("SERVICE_CONTINUE_PENDING", ServiceState::ContinuePending);
-        m.insert("SERVICE_PAUSE_PENDING", ServiceState::PausePending);
-        m.insert("SERVICE_PAUSED", ServiceState::Paused);
-        m.insert("SERVICE_RUNNING", ServiceState::Running);
-        m.insert("SERVICE_START_PENDING", ServiceState::StartPending);
-        m.insert("SERVICE_STOP_PENDING", ServiceState::StopPending);
-        m.insert("SERVICE_STOPPED", ServiceState::Stopped);
-        m
-    };
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
 }
 
-mod errors {
-    #[derive(Debug, ErrorChain)]
-    pub enum ErrorKind {
-        Msg(String),
+fn encrypt_password() -> Result<()> {
+    let password = rpassword::prompt_password_stderr("Password: ")
+        .chain_err(|| "Unable to prompt for password")?;
+
+    let confirmed_password = rpassword::prompt_password_stderr("Confirm password: ")
+        .chain_err(|| "Unable to prompt for password confirmation")?;
+
+    if password != confirmed_password {
+        return Err("Unable to encrypt password since the passwords do not match".into());
     }
+
+    println!("{}", nssm_exec::dpapi::protect(&password)?);
+    Ok(())
 }
 
-use errors::*;
+/// Runs the wizard writing the first configuration path, logging with the default logger
+/// since the logging configuration does not exist yet.
+fn init(config: &MainConfig, force: bool) -> Result<()> {
+    simple_logger::init().chain_err(|| "Unable to initialize default logger")?;
 
-const PENDING_POLL_DEFAULT_MS: u64 = 500;
-const PENDING_POLL_DEFAULT_COUNT: u64 = 5;
+    let config_path = config
+        .config_paths
+        .first()
+        .ok_or_else(|| Error::from("Unable to initialize without a configuration path"))?;
 
-trait ChainService<T> {
-    fn chain_service_msg(self, description: &str, service_name: &str) -> Result<T>;
-}
+    let log_config_path = config
+        .log_config_path
+        .as_ref()
+        .ok_or_else(|| Error::from("Unable to initialize without a logging configuration path"))?;
 
-impl<T, E> ChainService<T> for std::result::Result<T, E>
-where
-    E: std::error::Error + Send + 'static,
-{
-    fn chain_service_msg(self, description: &str, service_name: &str) -> Result<T> {
-        self.chain_err(|| format!("{} service '{}'", description, service_name))
-    }
-}
+    nssm_exec::init::init(config_path, log_config_path, force)?;
 
-fn state_from_str(status: &str) -> Result<ServiceState> {
-    let state = STATE_MAP
-        .get(status)
-        .map(|state| state.clone())
-        .ok_or_else(|| {
-            format!(
-                "Unable to obtain valid state from status string '{}'",
-                status
-            )
-        })?;
+    info!(
+        "Configuration written into '{}' and logging configuration into '{}', check it with \
+         the validate subcommand",
+        config_path, log_config_path
+    );
 
-    Ok(state)
+    Ok(())
 }
 
-fn run_cmd(cmd: &str) -> Result<Output> {
-    debug!("{}", cmd);
+fn print_imported(services: &[ImportedService], output: &Option<String>) -> Result<()> {
+    match *output {
+        Some(ref output) => {
+            nssm_exec::import::write(services, output)?;
+            info!("Imported services written into '{}'", output);
+        }
 
-    let output = if cfg!(target_os = "windows") {
-        Command::new("cmd").args(&["/C", &cmd]).output()
-    } else {
-        Command::new("sh").args(&["-c", &cmd]).output()
-    }.chain_err(|| format!("Unable to create command '{}'", cmd))?;
-
-    if !output.status.success() {
-        // nssm always generates 2 bytes char point
-        // need to remove all the '\0' bytes
-        bail!(
-            r#"{} {{ exit code: {}, stdout: "{}", stderr: "{}" }}"#,
-            cmd,
-            match output.status.code() {
-                Some(code) => format!("{}", code),
-                None => "NIL".to_owned(),
-            },
-            String::from_utf8_lossy(&remove_zeros(&output.stdout)).trim(),
-            String::from_utf8_lossy(&remove_zeros(&output.stderr)).trim()
-        );
+        None => print!("{}", nssm_exec::import::to_toml(services)?),
     }
 
-    Ok(output)
+    Ok(())
 }
 
-fn run_nssm_cmd(cmd: &str, file_config: &FileConfig) -> Result<Output> {
-    run_cmd(&format!(
-        "{} {}",
-        file_config.nssm_path.to_string_lossy(),
-        cmd
-    ))
-}
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| {
+            rows.iter()
+                .map(|row| row[index].len())
+                .chain(Some(header.len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let format_row = |cells: Vec<&str>| {
+        let last_index = cells.len() - 1;
+
+        cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                // the last column is not padded to avoid trailing whitespace
+                if index == last_index {
+                    cell.to_string()
+                } else {
+                    format!("{:<width$}", cell, width = widths[index])
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+
+    println!("{}", format_row(headers.to_vec()));
 
-fn run_nssm_set_cmd(cmd: &str, file_config: &FileConfig) -> Result<Output> {
-    run_nssm_cmd(&format!("set {}", cmd), file_config)
+    for row in rows {
+        println!(
+            "{}",
+            format_row(row.iter().map(|cell| cell.as_str()).collect())
+        );
+    }
 }
 
-fn run_nssm_set_cmd_if_some<T>(
-    service_name: &str,
-    field_name: &str,
-    param: &Option<T>,
-    file_config: &FileConfig,
-) -> Result<()>
-where
-    T: Display,
-{
-    if let Some(ref param) = *param {
-        let param_cmd = &format!("{} {} {}", service_name, field_name, param);
-
-        run_nssm_set_cmd(param_cmd, file_config).chain_service_msg(
-            &format!(
-                "Unable to set '{}' for",
-                field_name
-            ),
-            service_name,
-        )?;
+fn print_explanation(explanation: &Explanation) {
+    println!(
+        "Service '{}' with backend {:?}:",
+        explanation.name, explanation.backend
+    );
+
+    let rows: Vec<Vec<String>> = explanation
+        .settings
+        .iter()
+        .map(|setting| {
+            vec![
+                setting.name.clone(),
+                setting.value.clone().unwrap_or_else(|| "-".to_owned()),
+                setting.source.to_owned(),
+            ]
+        })
+        .collect();
+
+    print_table(&["SETTING", "VALUE", "SOURCE"], &rows);
+
+    if explanation.backend != Backend::Nssm {
+        return;
     }
 
-    Ok(())
-}
+    println!();
+    println!("nssm commands:");
 
-fn run_nssm_status_cmd(cmd: &str, file_config: &FileConfig) -> Result<Output> {
-    run_nssm_cmd(&format!("status {}", cmd), file_config)
+    for nssm_cmd in &explanation.nssm_cmds {
+        println!("  {}", nssm_cmd);
+    }
 }
 
-fn run_nssm_status_cmd_extract_status(cmd: &str, file_config: &FileConfig) -> Result<ServiceState> {
-    run_nssm_status_cmd(cmd, file_config).and_then(|output| {
-        let stdout = remove_zeros(&output.stdout);
-
-        let status = std::str::from_utf8(&stdout)
-            .chain_err(|| {
-                format!(
-                    "Unable to get convert from utf8 '{:?}' into status string",
-                    stdout
-                )
-            })?
-            .trim();
+fn print_status_table(statuses: &[ServiceStatus]) {
+    let rows: Vec<Vec<String>> = statuses
+        .iter()
+        .map(|status| {
+            vec![
+                status.name.to_owned(),
+                match status.state {
+                    Some(state) => format!("{:?}", state),
+                    None => "-".to_owned(),
+                },
+                if status.is_installed() { "yes" } else { "no" }.to_owned(),
+            ]
+        })
+        .collect();
+
+    print_table(&["NAME", "STATE", "INSTALLED"], &rows);
+}
 
-        state_from_str(&status)
-    })
+fn print_list_table(listings: &[ServiceListing]) {
+    let rows: Vec<Vec<String>> = listings
+        .iter()
+        .map(|listing| {
+            vec![
+                listing.name.clone(),
+                if listing.configured { "yes" } else { "no" }.to_owned(),
+                match listing.state {
+                    Some(state) => format!("{:?}", state),
+                    None => "-".to_owned(),
+                },
+                match listing.nssm_managed {
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                    None => "-",
+                }.to_owned(),
+            ]
+        })
+        .collect();
+
+    print_table(&["NAME", "CONFIGURED", "STATE", "NSSM"], &rows);
 }
 
-fn poll_service_state_until(
-    service_name: &str,
-    file_config: &FileConfig,
-    poll_interval: &Duration,
-    poll_count: u64,
-    expected_state: ServiceState,
-) -> Result<()> {
-
-    let status_check_iter = (0..poll_count).map(|_| {
-        run_nssm_status_cmd_extract_status(service_name, file_config)
-            .map(|status| status == expected_state)
-            .unwrap_or(false)
-    });
+/// Summarizes the actions, collapsing consecutive parameter settings into their count.
+fn summarize_actions(actions: &[String]) -> String {
+    let mut summary: Vec<String> = Vec::new();
+    let mut set_count = 0;
 
-    // starts from 1 to reduce the count by 1 and prevent underflow
-    let between_delay_iter = (1..poll_count).map(|_| {
-        info!(
-            "Service '{}' is still not in state {:?}, waiting...",
-            service_name,
-            expected_state
-        );
+    for action in actions {
+        if action.starts_with("set ") {
+            set_count += 1;
+            continue;
+        }
 
-        thread::sleep(poll_interval.clone());
-        false
-    });
+        if set_count > 0 {
+            summary.push(format!("set {} param(s)", set_count));
+            set_count = 0;
+        }
 
-    let state_reached = itertools::interleave(status_check_iter, between_delay_iter)
-        .any(|reached| reached);
+        summary.push(action.clone());
+    }
 
-    if !state_reached {
-        bail!(
-            "Timeout waiting for service name '{}' to be in state {:?}",
-            service_name,
-            expected_state
-        );
+    if set_count > 0 {
+        summary.push(format!("set {} param(s)", set_count));
     }
 
-    Ok(())
+    if summary.is_empty() {
+        "-".to_owned()
+    } else {
+        summary.join(", ")
+    }
 }
 
-fn merge_other_conf<'a, F, R>(
-    lhs: &'a Option<OtherConfig>,
-    rhs: &'a Option<OtherConfig>,
-    chooser: F,
-) -> Option<&'a R>
-where
-    F: Fn(&'a OtherConfig) -> Option<&'a R>,
-{
-    lhs.as_ref().and_then(&chooser).or(rhs.as_ref().and_then(
-        &chooser,
-    ))
-}
+fn print_summary_table(service_reports: &[ServiceReport]) {
+    // the host column is only shown if any service is on a remote host
+    let has_hosts = service_reports
+        .iter()
+        .any(|service_report| service_report.host.is_some());
 
-fn remove_zeros(bytes: &[u8]) -> Vec<u8> {
-    bytes
+    let rows: Vec<Vec<String>> = service_reports
         .iter()
-        .filter(|&c| *c != 0)
-        .map(|c| c.clone())
-        .collect()
+        .map(|service_report| {
+            let mut row = vec![
+                service_report.name.clone(),
+                summarize_actions(&service_report.actions),
+                format!("{:?}", service_report.outcome).to_uppercase(),
+                format!("{:.2}s", service_report.duration_ms as f64 / 1000.0),
+            ];
+
+            if has_hosts {
+                row.insert(0, host_label(service_report.host.as_ref()).to_owned());
+            }
+
+            row
+        })
+        .collect();
+
+    if has_hosts {
+        print_table(&["HOST", "SERVICE", "ACTIONS", "RESULT", "ELAPSED"], &rows);
+    } else {
+        print_table(&["SERVICE", "ACTIONS", "RESULT", "ELAPSED"], &rows);
+    }
 }
 
-fn do_service_stop(
-    service_name: &str,
-    file_config: &FileConfig,
-    state: ServiceState,
-    pending_stop_poll_interval: &Duration,
-    pending_stop_poll_count: u64,
-) -> Result<()> {
-    if state != ServiceState::Stopped {
-        let stop_cmd = &format!("stop {}", service_name);
-
-        // sometimes the error message happens
-        // "Unexpected status SERVICE_STOP_PENDING in response to STOP control"
-        // even though the service will eventually stop
-        // so allow for this to happen
-
-        let stop_res = run_nssm_cmd(stop_cmd, file_config).chain_service_msg(
-            "Service stopping returned error, temporarily allowing this for",
-            service_name,
-        );
+fn host_label(host: Option<&String>) -> &str {
+    host.map_or("localhost", |host| host.as_str())
+}
 
-        if let Err(e) = stop_res {
-            print_recursive_warning(&e);
+fn log_level_override(config: &MainConfig) -> Option<LogLevelFilter> {
+    // quiet takes precedence since the logger is not available yet to report the conflict
+    if config.quiet {
+        Some(LogLevelFilter::Warn)
+    } else {
+        match config.verbose {
+            0 => None,
+            1 => Some(LogLevelFilter::Debug),
+            _ => Some(LogLevelFilter::Trace),
         }
+    }
+}
+
+fn init_log4rs_with_level(log_config_path: &str, level: LogLevelFilter) -> Result<()> {
+    let log_config_file = File::open(log_config_path).chain_err(|| {
+        format!("Unable to open log config file at '{}'", log_config_path)
+    })?;
+
+    let raw_config: RawConfig = serde_yaml::from_reader(log_config_file).chain_err(|| {
+        format!("Unable to parse log config file at '{}'", log_config_path)
+    })?;
+
+    let (appenders, errors) = raw_config.appenders_lossy(&Deserializers::default());
 
-        // sometimes it takes a while to stop the service so wait for it
-        poll_service_state_until(
-            service_name,
-            file_config,
-            &pending_stop_poll_interval,
-            pending_stop_poll_count,
-            ServiceState::Stopped,
-        )?;
+    for error in &errors {
+        eprintln!("log4rs: {}", error);
     }
 
-    Ok(())
-}
+    // the root and every logger take the overriding level, and the config is not
+    // watched for changes since a reload would discard the overriding level
+    let root = log4rs::config::Root::builder()
+        .appenders(raw_config.root().appenders().iter().cloned())
+        .build(level);
 
-fn do_service_remove(service_name: &str, file_config: &FileConfig) -> Result<()> {
-    let remove_cmd = &format!("remove {} confirm", service_name);
+    let loggers: Vec<_> = raw_config
+        .loggers()
+        .iter()
+        .map(|logger| {
+            log4rs::config::Logger::builder()
+                .appenders(logger.appenders().iter().cloned())
+                .additive(logger.additive())
+                .build(logger.name(), level)
+        })
+        .collect();
+
+    let log_config = log4rs::config::Config::builder()
+        .appenders(appenders)
+        .loggers(loggers)
+        .build(root)
+        .chain_err(|| {
+            format!("Unable to build log config from file at '{}'", log_config_path)
+        })?;
 
-    run_nssm_cmd(remove_cmd, file_config).chain_service_msg(
-        "Unable to remove",
-        service_name,
-    )?;
+    log4rs::init_config(log_config)
+        .chain_err(|| "Unable to initialize log4rs logger")?;
 
     Ok(())
 }
 
-fn log_service_status<'a, I>(log_names: I)
-where
-    I: Iterator<Item = (Result<()>, &'a str)>,
-{
+fn init_logger(config: &MainConfig) -> Result<()> {
+    let level = log_level_override(config);
 
-    for (log, name) in log_names {
-        match log {
-            Ok(_) => info!("Service '{}' [OK]", name),
-            Err(e) => {
-                error!("Service '{}' [FAILED]", name);
-                print_recursive_err(&e);
-            }
+    match (config.log_config_path.as_ref(), level) {
+        (Some(log_config_path), Some(level)) => {
+            init_log4rs_with_level(log_config_path, level)?;
         }
-    }
-}
 
-fn print_recursive_warning(e: &Error) {
-    warn!("WARNING: {}", e);
+        (Some(log_config_path), None) => {
+            log4rs::init_file(log_config_path, Default::default())
+                .chain_err(|| {
+                    format!(
+                        "Unable to initialize log4rs logger with the given config file at '{}'",
+                        log_config_path
+                    )
+                })?;
+        }
+
+        (None, Some(level)) => {
+            let level = level.to_log_level().unwrap_or(log::LogLevel::Error);
+
+            simple_logger::init_with_level(level).chain_err(
+                || "Unable to initialize default logger",
+            )?;
+        }
 
-    for e in e.iter().skip(1) {
-        warn!("> Caused by: {}", e);
+        (None, None) => {
+            simple_logger::init().chain_err(
+                || "Unable to initialize default logger",
+            )?;
+        }
     }
+
+    Ok(())
 }
 
-fn print_recursive_err(e: &Error) {
-    error!("ERROR: {}", e);
+/// Detects the nssm version, and returns error if any configured parameter is not
+/// supported by it. Only warns if the version cannot be detected.
+fn check_nssm_version(file_config: &FileConfig) -> Result<Option<NssmVersion>> {
+    let nssm_version = match nssm_exec::version::detect(file_config) {
+        Ok(nssm_version) => nssm_version,
+
+        // nssm is only needed locally if no service is installed on a remote host
+        Err(e) => if e.category() == ErrorCategory::CommandUnavailable
+            && file_config.hosts().iter().all(|host| host.is_none())
+        {
+            return Err(e).chain_err(|| {
+                ErrorKind::NssmUnavailable(format!(
+                    "Unable to run nssm at '{}'",
+                    file_config.nssm_path.to_string_lossy()
+                ))
+            });
+        } else {
+            warn!("Unable to detect nssm version, skipping the parameter checks: {}", e);
+            return Ok(None);
+        },
+    };
+
+    info!("Detected nssm {}", nssm_version);
+    let unsupported = nssm_exec::version::unsupported(file_config, nssm_version);
+
+    for message in &unsupported {
+        error!("{}", message);
+    }
 
-    for e in e.iter().skip(1) {
-        error!("> Caused by: {}", e);
+    if !unsupported.is_empty() {
+        return Err(ErrorKind::NssmUnavailable(format!(
+            "nssm {} does not support the configuration, found {} problem(s)",
+            nssm_version,
+            unsupported.len()
+        )).into());
     }
+
+    Ok(Some(nssm_version))
 }
 
-fn name_from_service(service: &Service) -> &str {
-    service.name.as_str()
+/// States whether the command modifies the services and so needs administrator privileges.
+fn needs_admin(cmd: &Option<CustomCmd>) -> bool {
+    matches!(
+        *cmd,
+        None
+            | Some(CustomCmd::Stop)
+            | Some(CustomCmd::Remove)
+            | Some(CustomCmd::Purge { .. })
+            | Some(CustomCmd::Restart)
+            | Some(CustomCmd::Update)
+            | Some(CustomCmd::Restore { .. })
+            | Some(CustomCmd::Agent { .. })
+            | Some(CustomCmd::Serve { .. })
+            | Some(CustomCmd::Grpc { .. })
+            | Some(CustomCmd::Tui)
+    )
 }
 
-fn nssm_exec_wrap<'a, F>(
-    file_config: &'a FileConfig,
-    f: F,
-) -> Zip<Map<Iter<'a, Service>, F>, Map<Iter<'a, Service>, fn(&'a Service) -> &'a str>>
-where
-    F: Fn(&'a Service) -> Result<()>,
-{
-    let name_from_service = name_from_service as fn(&'a Service) -> &'a str;
-
-    file_config.services.iter().map(f).zip(
-        file_config.services.iter().map(name_from_service),
+/// States whether the command processes the services as a run whose outcome is notified.
+fn notifies(cmd: &Option<CustomCmd>) -> bool {
+    matches!(
+        *cmd,
+        None
+            | Some(CustomCmd::Stop)
+            | Some(CustomCmd::Remove)
+            | Some(CustomCmd::Purge { .. })
+            | Some(CustomCmd::Restart)
+            | Some(CustomCmd::Update)
+            | Some(CustomCmd::Restore { .. })
     )
 }
 
-fn nssm_exec_stop(
-    file_config: &FileConfig,
-    pending_stop_poll_interval: &Duration,
-    pending_stop_poll_count: u64,
-) -> Result<()> {
-    let log_names = nssm_exec_wrap(file_config, |service| {
-        if let Ok(state) = run_nssm_status_cmd_extract_status(&service.name, file_config) {
-            debug!(
-                "Service '{}' exists, attempting to stop service...",
-                service.name
-            );
+/// Relaunches the program elevated with the same arguments in the same working directory,
+/// and returns the exit code of the elevated process.
+fn relaunch_elevated() -> Result<i32> {
+    let working_dir = env::current_dir().chain_err(|| "Unable to get the working directory")?;
+
+    // the elevated process otherwise starts in the system directory
+    let mut args = vec![
+        "--working-dir".to_owned(),
+        working_dir.to_string_lossy().into_owned(),
+    ];
+
+    args.extend(env::args().skip(1).filter(|arg| arg != "--elevate"));
+
+    info!("Relaunching elevated, the output continues in the new window...");
+    nssm_exec::elevation::relaunch_elevated(&args)
+}
+
+/// Runs the command that operates on the configured services, i.e. the default command,
+/// `stop`, `remove`, `purge`, `restart`, `update`, `status`, `list` or `diff`.
+fn run_services_cmd(cmd: &Option<CustomCmd>, file_config: &FileConfig) -> Result<()> {
+    let pending_stop_poll_interval = file_config.pending_stop_poll_interval();
+    let pending_stop_poll_count = file_config.pending_stop_poll_count();
 
-            do_service_stop(
-                &service.name,
+    match *cmd {
+        Some(CustomCmd::Stop) => {
+            nssm_exec::nssm_exec_stop(
                 file_config,
-                state,
                 &pending_stop_poll_interval,
                 pending_stop_poll_count,
-            )?;
+            ).chain_err(|| "Unable to complete all nssm stop operations")
         }
 
-        Ok(())
-    });
-
-    log_service_status(log_names);
-    Ok(())
-}
+        Some(CustomCmd::Remove) | Some(CustomCmd::Purge { .. }) => {
+            nssm_exec::nssm_exec_remove(
+                file_config,
+                &pending_stop_poll_interval,
+                pending_stop_poll_count,
+            ).chain_err(|| "Unable to complete all nssm remove operations")
+        }
 
-fn nssm_exec_remove(
-    file_config: &FileConfig,
-    pending_stop_poll_interval: &Duration,
-    pending_stop_poll_count: u64,
-) -> Result<()> {
-    let log_names = nssm_exec_wrap(file_config, |service| {
-        if let Ok(state) = run_nssm_status_cmd_extract_status(&service.name, file_config) {
-            debug!(
-                "Service '{}' exists, attempting to stop service first...",
-                service.name
-            );
+        Some(CustomCmd::Restart) => {
+            nssm_exec::nssm_exec_restart(
+                file_config,
+                &pending_stop_poll_interval,
+                pending_stop_poll_count,
+                &file_config.pending_start_poll_interval(),
+                file_config.pending_start_poll_count(),
+            ).chain_err(|| "Unable to complete all nssm restart operations")
+        }
 
-            do_service_stop(
-                &service.name,
+        Some(CustomCmd::Update) => {
+            nssm_exec::nssm_exec_update(
                 file_config,
-                state,
                 &pending_stop_poll_interval,
                 pending_stop_poll_count,
-            )?;
+                &file_config.pending_start_poll_interval(),
+                file_config.pending_start_poll_count(),
+            ).chain_err(|| "Unable to complete all nssm update operations")
+        }
 
-            debug!("Next attempting to remove service '{}'...", service.name);
-            do_service_remove(&service.name, file_config)?;
+        Some(CustomCmd::Status) => {
+            let statuses = nssm_exec::nssm_exec_status(file_config)
+                .chain_err(|| "Unable to complete all nssm status operations")?;
+
+            print_status_table(&statuses);
+            Ok(())
         }
 
-        Ok(())
-    });
+        Some(CustomCmd::List) => {
+            let listings = nssm_exec::nssm_exec_list(file_config)
+                .chain_err(|| "Unable to complete all nssm list operations")?;
 
-    log_service_status(log_names);
-    Ok(())
-}
+            print_list_table(&listings);
+            Ok(())
+        }
 
-fn nssm_exec(
-    file_config: &FileConfig,
-    pending_stop_poll_interval: &Duration,
-    pending_stop_poll_count: u64,
-    pending_start_poll_interval: &Duration,
-    pending_start_poll_count: u64,
-) -> Result<()> {
-    let log_names = nssm_exec_wrap(file_config, |service| {
-        info!("Creating service '{}'...", service.name);
-
-        // ignore if cannot get status, which probably means that the service does not exist yet
-        if let Ok(state) = run_nssm_status_cmd_extract_status(&service.name, file_config) {
-            debug!(
-                "Service '{}' exists, attempting to stop service first...",
-                service.name
-            );
+        Some(CustomCmd::Diff) => {
+            let diffs = nssm_exec::nssm_exec_diff(file_config)
+                .chain_err(|| "Unable to complete all nssm diff operations")?;
 
-            do_service_stop(
-                &service.name,
+            print_diffs(&diffs);
+            Ok(())
+        }
+
+        None => {
+            nssm_exec::nssm_exec(
                 file_config,
-                state,
                 &pending_stop_poll_interval,
                 pending_stop_poll_count,
-            )?;
-
-            debug!("Next attempting to remove service '{}'...", service.name);
-            do_service_remove(&service.name, file_config)?;
+                &file_config.pending_start_poll_interval(),
+                file_config.pending_start_poll_count(),
+            ).chain_err(|| "Unable to complete all nssm operations")
         }
 
-        // install service first
-        // note that the service path is relative from nssm.exe
-        let install_cmd = &format!(
-            "install {} {}",
-            service.name,
-            service.path.to_string_lossy(),
+        _ => unreachable!(),
+    }
+}
+
+/// Runs the service command on every host of the configured services, collecting the
+/// service reports of every host into the run report of the configuration.
+fn run_on_hosts(cmd: &Option<CustomCmd>, file_config: &FileConfig) -> Result<()> {
+    let hosts = file_config.hosts();
+
+    if hosts.iter().all(|host| host.is_none()) {
+        return run_services_cmd(cmd, file_config);
+    }
+
+    // the printed tables of the hosts would otherwise interleave
+    let prints_tables = matches!(
+        *cmd,
+        Some(CustomCmd::Status) | Some(CustomCmd::List) | Some(CustomCmd::Diff)
+    );
+
+    let host_parallelism = if prints_tables {
+        1
+    } else {
+        file_config.host_parallelism()
+    };
+
+    let next_host_index = AtomicUsize::new(0);
+    let failed_count = AtomicUsize::new(0);
+
+    let exec_host = |host: Option<&String>| {
+        let host_config = file_config.for_host(host.map(|host| host.as_str()));
+
+        info!(
+            "Processing {} service(s) on host '{}'...",
+            host_config.services.len(),
+            host_label(host)
         );
 
-        run_nssm_cmd(install_cmd, file_config).chain_service_msg(
-            "Unable to install",
-            &service.name,
-        )?;
-
-        // then set the rest of the parameters
-        if let Some(ref startup_dir) = service.startup_dir {
-            // app directory is also relative from nssm.exe
-            let app_dir_cmd = &format!(
-                "{} AppDirectory {}",
-                service.name,
-                startup_dir.to_string_lossy()
-            );
+        if prints_tables {
+            println!("Host '{}':", host_label(host));
+        }
 
-            run_nssm_set_cmd(app_dir_cmd, file_config)
-                .chain_service_msg("Unable to set startup directory for", &service.name)?;
-        }
-
-        run_nssm_set_cmd_if_some(&service.name, "AppParameters", &service.args, file_config)?;
-
-        run_nssm_set_cmd_if_some(
-            &service.name,
-            "Description",
-            &service.description,
-            file_config,
-        )?;
-
-        // merges the options, prioritizing the local ones if available individually
-        let merged_other = OtherConfigRef {
-            deps: merge_other_conf(
-                &service.other,
-                &file_config.global,
-                |other| other.deps.as_ref(),
-            ),
-            start_on_create: merge_other_conf(&service.other, &file_config.global, |other| {
-                other.start_on_create.as_ref()
-            }),
-            account: merge_other_conf(&service.other, &file_config.global, |other| {
-                other.account.as_ref()
-            }),
-        };
+        let res = run_services_cmd(cmd, &host_config);
 
-        run_nssm_set_cmd_if_some(
-            &service.name,
-            "DependOnService",
-            &merged_other.deps,
-            file_config,
-        )?;
-
-        if let Some(account) = merged_other.account {
-            let acct_cmd = &format!(
-                "{} ObjectName {} {}",
-                service.name,
-                account.user,
-                if !account.password.is_empty() {
-                    &account.password
-                } else {
-                    r#""""#
-                }
-            );
+        if let Err(ref e) = res {
+            nssm_exec::print_recursive_err(e);
+            failed_count.fetch_add(1, Ordering::SeqCst);
+        }
 
-            run_nssm_set_cmd(acct_cmd, file_config).chain_service_msg(
-                "Unable to set the username and password for",
-                &service.name,
-            )?;
+        if let (Some(run_report), Some(host_report)) =
+            (file_config.run_report.as_ref(), host_config.run_report)
+        {
+            run_report.append(host_report, host.map(|host| host.as_str()));
+        }
+    };
+
+    crossbeam::scope(|scope| {
+        for _ in 0..cmp::min(host_parallelism, hosts.len()) {
+            scope.spawn(|| {
+                // each worker keeps taking the next unprocessed host until none is left
+                while let Some(host) = hosts.get(next_host_index.fetch_add(1, Ordering::SeqCst)) {
+                    exec_host(host.as_ref());
+                }
+            });
         }
+    });
 
-        if let Some(&true) = merged_other.start_on_create {
-            let start_cmd = &format!("start {}", service.name);
+    let failed_count = failed_count.load(Ordering::SeqCst);
 
-            let start_res = run_nssm_cmd(start_cmd, file_config).chain_service_msg(
-                "Service starting returned error, temporarily allowing this for",
-                &service.name,
+    if failed_count > 0 {
+        return Err(ErrorKind::HostsFailed {
+            failed: failed_count,
+            total: hosts.len(),
+        }.into());
+    }
+
+    Ok(())
+}
+
+/// Reads the configuration files and applies the command line overrides over it.
+fn load_file_config(config: &MainConfig) -> Result<FileConfig> {
+    let mut file_config = FileConfig::from_paths_with_secrets(
+        &config.config_paths,
+        config.profile.as_deref(),
+        config.secrets_path.as_ref().map(Path::new),
+        config.prefix.as_deref(),
+    )?;
+
+    if !config.only.is_empty() {
+        file_config.retain_services(&config.only)?;
+    }
+
+    if config.prompt_passwords {
+        file_config.prompt_passwords()?;
+    }
+
+    if let Some(parallelism) = config.parallelism {
+        file_config.parallelism = Some(parallelism);
+    }
+
+    if let Some(host_parallelism) = config.host_parallelism {
+        file_config.host_parallelism = Some(host_parallelism);
+    }
+
+    if config.fail_fast {
+        file_config.fail_fast = Some(true);
+    }
+
+    if config.registry_direct {
+        file_config.registry_direct = Some(true);
+    }
+
+    if let Some(backend) = config.backend {
+        file_config.backend = Some(backend);
+    }
+
+    if config.blue_green {
+        file_config.blue_green = Some(true);
+    }
+
+    if config.canary {
+        file_config.canary = Some(true);
+    }
+
+    if config.skip_existing {
+        file_config.skip_existing = Some(true);
+    }
+
+    if config.dry_run {
+        file_config.dry_run = Some(true);
+    }
+
+    if let Some(audit_log) = file_config.audit_log.clone() {
+        let audit = AuditLog::open(&audit_log, file_config.passwords()).chain_err(|| {
+            format!("Unable to open audit log at '{}'", audit_log.to_string_lossy())
+        })?;
+
+        file_config.audit = Some(Arc::new(audit));
+    }
+
+    Ok(file_config)
+}
+
+/// Returns the arguments of the agent service, which runs the agent with the same
+/// configuration as this run.
+fn agent_args(config: &MainConfig) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for config_path in &config.config_paths {
+        args.extend(vec!["-c".to_owned(), config_path.clone()]);
+    }
+
+    if let Some(ref log_config_path) = config.log_config_path {
+        args.extend(vec!["-l".to_owned(), log_config_path.clone()]);
+    }
+
+    if let Some(ref profile) = config.profile {
+        args.extend(vec!["--profile".to_owned(), profile.clone()]);
+    }
+
+    if let Some(ref prefix) = config.prefix {
+        args.extend(vec!["--prefix".to_owned(), prefix.clone()]);
+    }
+
+    if let Some(ref secrets_path) = config.secrets_path {
+        args.extend(vec!["--secrets".to_owned(), secrets_path.clone()]);
+    }
+
+    for name in &config.only {
+        args.extend(vec!["--only".to_owned(), name.clone()]);
+    }
+
+    if let Some(ref backend) = config.backend {
+        args.extend(vec!["--backend".to_owned(), format!("{:?}", backend).to_lowercase()]);
+    }
+
+    args.push("agent".to_owned());
+    args
+}
+
+/// Reconciles the services at every interval until the process is stopped, reloading the
+/// configuration before each reconciliation to pick up its changes.
+fn run_agent(config: &MainConfig, file_config: &FileConfig) -> Result<()> {
+    let mut reloaded_config: Option<FileConfig> = None;
+
+    loop {
+        let interval = {
+            let file_config = reloaded_config.as_ref().unwrap_or(file_config);
+
+            info!(
+                "Reconciling {} service(s)...",
+                file_config.services.len()
             );
 
-            if let Err(e) = start_res {
-                print_recursive_warning(&e);
+            if let Err(e) = run_on_hosts(&Some(CustomCmd::Update), file_config) {
+                nssm_exec::print_recursive_err(&e);
             }
 
-            // may take some time to start the service
-            poll_service_state_until(
-                &service.name,
-                file_config,
-                &pending_start_poll_interval,
-                pending_start_poll_count,
-                ServiceState::Running,
-            )?;
-        }
+            nssm_exec::agent::interval(file_config)
+        };
 
-        Ok(())
-    });
 
-    log_service_status(log_names);
-    Ok(())
+        debug!("Next reconciling in {}s...", interval.as_secs());
+        thread::sleep(interval);
+
+        let reloaded = load_file_config(config).and_then(|mut reloaded| {
+            if reloaded.uses_backend(Backend::Nssm) {
+                reloaded.nssm_version = check_nssm_version(&reloaded)?;
+            }
+
+            Ok(reloaded)
+        });
+
+        // the reloaded configuration does not collect the service reports, which would
+        // otherwise grow indefinitely
+        match reloaded {
+            Ok(reloaded) => reloaded_config = Some(reloaded),
+            Err(e) => {
+                nssm_exec::print_recursive_err(&e);
+                warn!("Keeping the previous configuration...");
+            }
+        }
+    }
 }
 
 fn run() -> Result<()> {
+    let run_start_time = Instant::now();
     let config = MainConfig::from_args();
 
-    if let Some(ref log_config_path) = config.log_config_path {
-        log4rs::init_file(log_config_path, Default::default())
-            .chain_err(|| {
-                format!(
-                    "Unable to initialize log4rs logger with the given config file at '{}'",
-                    log_config_path
-                )
-            })?;
-    } else {
-        simple_logger::init().chain_err(
-            || "Unable to initialize default logger",
-        )?;
+    if let Some(ref working_dir) = config.working_dir {
+        env::set_current_dir(working_dir).chain_err(|| {
+            format!("Unable to change the working directory to '{}'", working_dir)
+        })?;
     }
 
-    let file_config_buf = file::get(&config.config_path).chain_err(|| {
-        format!(
-            "Unable to read TOML configuration file path at '{}'",
-            config.config_path
-        )
-    })?;
+    if let Some(CustomCmd::Init { force }) = config.cmd {
+        return init(&config, force);
+    }
 
-    let file_config_str = String::from_utf8(file_config_buf).chain_err(
-        || "Unable to convert TOML configuration file content into Rust String",
-    )?;
+    init_logger(&config)?;
 
-    let file_config: FileConfig = toml::from_str(&file_config_str).chain_err(
-        || "Unable to interpret configuration file content as TOML",
-    )?;
+    if let Some(CustomCmd::EncryptPassword) = config.cmd {
+        return encrypt_password();
+    }
 
-    let pending_stop_poll_interval =
-        Duration::from_millis(file_config.pending_stop_poll_ms.unwrap_or(
-            PENDING_POLL_DEFAULT_MS,
-        ));
+    if let Some(CustomCmd::Replay { ref session_path }) = config.cmd {
+        return nssm_exec::session::replay(session_path, config.dry_run);
+    }
 
-    let pending_stop_poll_count = file_config.pending_stop_poll_count.unwrap_or(
-        PENDING_POLL_DEFAULT_COUNT,
-    );
+    if let Some(CustomCmd::ImportSystemd {
+        ref unit_paths,
+        ref output,
+    }) = config.cmd
+    {
+        let services = nssm_exec::import::from_systemd_units(unit_paths)?;
+        return print_imported(&services, output);
+    }
 
-    match config.cmd {
-        Some(CustomCmd::Stop) => {
-            nssm_exec_stop(
-                &file_config,
-                &pending_stop_poll_interval,
-                pending_stop_poll_count,
-            ).chain_err(|| "Unable to complete all nssm stop operations")
+    if let Some(CustomCmd::ImportCompose {
+        ref compose_path,
+        ref output,
+    }) = config.cmd
+    {
+        let services = nssm_exec::import::from_compose_file(compose_path)?;
+        return print_imported(&services, output);
+    }
+
+    let mut file_config = load_file_config(&config)
+        .chain_err(|| ErrorKind::ConfigInvalid("Unable to load the configuration".to_owned()))?;
+
+    if let Some(CustomCmd::Explain { ref service_name }) = config.cmd {
+        let explanation = nssm_exec::explain::explain(&file_config, service_name)?;
+        print_explanation(&explanation);
+        return Ok(());
+    }
+
+    if let Some(ref script_path) = config.emit_script {
+        if config.cmd.is_some() {
+            return Err("Unable to emit a script for any subcommand".into());
         }
 
-        Some(CustomCmd::Remove) => {
-            nssm_exec_remove(
-                &file_config,
-                &pending_stop_poll_interval,
-                pending_stop_poll_count,
-            ).chain_err(|| "Unable to complete all nssm remove operations")
+        nssm_exec::script::write(&file_config, script_path)?;
+        info!("Script written into '{}'", script_path);
+        return Ok(());
+    }
+
+    if config.transcript.is_some() {
+        if !file_config.is_dry_run() {
+            return Err("Unable to write a transcript without --dry-run".into());
         }
 
-        None => {
-            let pending_start_poll_interval =
-                Duration::from_millis(file_config.pending_start_poll_ms.unwrap_or(
-                    PENDING_POLL_DEFAULT_MS,
-                ));
+        file_config.transcript = Some(Arc::new(Transcript::new(file_config.passwords())));
+    }
 
-            let pending_start_poll_count = file_config.pending_start_poll_count.unwrap_or(
-                PENDING_POLL_DEFAULT_COUNT,
+    if config.record.is_some() {
+        file_config.session = Some(Arc::new(Session::new(file_config.passwords())));
+    }
+
+    if file_config.is_dry_run() {
+        info!("Dry run enabled, only logging the planned nssm commands...");
+    } else if needs_admin(&config.cmd) && file_config.backend != Some(Backend::Mock)
+        && !nssm_exec::elevation::is_elevated()
+    {
+        if !config.elevate {
+            return Err(
+                "Administrator privileges are needed to manage the services, run from an \
+                 elevated prompt or with --elevate"
+                    .into(),
+            );
+        }
+
+        let exit_code = relaunch_elevated()?;
+        info!("Elevated process exited with code {}", exit_code);
+        process::exit(exit_code);
+    }
+
+    match config.cmd {
+        Some(CustomCmd::Bootstrap)
+        | Some(CustomCmd::Validate)
+        | Some(CustomCmd::VerifyAudit)
+        | Some(CustomCmd::ExportDsc { .. })
+        | Some(CustomCmd::ExportAnsible { .. }) => (),
+        _ => if file_config.uses_backend(Backend::Nssm) {
+            file_config.nssm_version = check_nssm_version(&file_config)?;
+        },
+    }
+
+    // always collected for the summary table
+    file_config.run_report = Some(RunReport::default());
+
+    let res = match config.cmd {
+        Some(CustomCmd::Bootstrap) => {
+            nssm_exec::bootstrap::bootstrap(&file_config)
+                .chain_err(|| "Unable to bootstrap nssm")
+        }
+
+        Some(CustomCmd::Backup { ref output }) => {
+            let backup_path = output.clone().unwrap_or_else(|| {
+                format!(
+                    "nssm_exec_backup_{}.toml",
+                    chrono::Local::now().format("%Y%m%d_%H%M%S")
+                )
+            });
+
+            if file_config.hosts().iter().any(|host| host.is_some()) {
+                warn!("Only backing up the services on the local machine...");
+            }
+
+            let local_config = file_config.for_host(None);
+
+            let snapshots = nssm_exec::nssm_exec_backup(&local_config)
+                .chain_err(|| "Unable to complete all nssm backup operations")?;
+
+            nssm_exec::snapshot::write_backup(&backup_path, &snapshots)?;
+            info!(
+                "Backed up {} service(s) into '{}'",
+                snapshots.len(),
+                backup_path
             );
 
-            nssm_exec(
+            Ok(())
+        }
+
+        Some(CustomCmd::Restore { ref backup_path }) => {
+            let snapshots = nssm_exec::snapshot::read_backup(backup_path)?;
+
+            nssm_exec::nssm_exec_restore(
                 &file_config,
-                &pending_stop_poll_interval,
-                pending_stop_poll_count,
-                &pending_start_poll_interval,
-                pending_start_poll_count,
-            ).chain_err(|| "Unable to complete all nssm operations")
+                &snapshots,
+                &file_config.pending_stop_poll_interval(),
+                file_config.pending_stop_poll_count(),
+                &file_config.pending_start_poll_interval(),
+                file_config.pending_start_poll_count(),
+            ).chain_err(|| "Unable to complete all nssm restore operations")
+        }
+
+        Some(CustomCmd::ExportDsc { ref output }) => {
+            let output = output.clone().unwrap_or_else(|| {
+                format!("{}.ps1", nssm_exec::script::DSC_CONFIGURATION_NAME)
+            });
+
+            nssm_exec::script::write_dsc(&file_config, &output)?;
+            info!("DSC configuration written into '{}'", output);
+            Ok(())
+        }
+
+        Some(CustomCmd::ExportAnsible { ref output }) => {
+            let output = output.clone().unwrap_or_else(|| "nssm_exec.yml".to_owned());
+
+            nssm_exec::ansible::write(&file_config, &output)?;
+            info!("Ansible playbook written into '{}'", output);
+            Ok(())
+        }
+
+        Some(CustomCmd::VerifyAudit) => {
+            let audit_log = file_config.audit_log.as_ref().ok_or_else(|| {
+                Error::from("Unable to verify the audit log without audit_log in the configuration")
+            })?;
+
+            let count = nssm_exec::audit::verify(audit_log)?;
+            info!("Audit log is intact with {} entries", count);
+            Ok(())
         }
+
+        Some(CustomCmd::Validate) => {
+            let validation = nssm_exec::validate::validate(&file_config);
+
+            for warning in &validation.warnings {
+                warn!("{}", warning);
+            }
+
+            for error in &validation.errors {
+                error!("{}", error);
+            }
+
+            if !validation.is_valid() {
+                return Err(ErrorKind::ConfigInvalid(format!(
+                    "Configuration has {} error(s)",
+                    validation.errors.len()
+                )).into());
+            }
+
+            info!("Configuration is valid");
+            Ok(())
+        }
+
+        Some(CustomCmd::Agent { install: true, uninstall: true }) => {
+            Err("Unable to both install and uninstall the agent".into())
+        }
+
+        Some(CustomCmd::Agent { install: true, .. }) => {
+            let agent_config = nssm_exec::agent::agent_config(&file_config, agent_args(&config))?;
+
+            let res = nssm_exec::nssm_exec(
+                &agent_config,
+                &agent_config.pending_stop_poll_interval(),
+                agent_config.pending_stop_poll_count(),
+                &agent_config.pending_start_poll_interval(),
+                agent_config.pending_start_poll_count(),
+            ).chain_err(|| "Unable to install the agent service");
+
+            if let (Some(run_report), Some(agent_report)) =
+                (file_config.run_report.as_ref(), agent_config.run_report)
+            {
+                run_report.append(agent_report, None);
+            }
+
+            res
+        }
+
+        Some(CustomCmd::Agent { uninstall: true, .. }) => {
+            let agent_config = nssm_exec::agent::agent_config(&file_config, Vec::new())?;
+
+            let res = nssm_exec::nssm_exec_remove(
+                &agent_config,
+                &agent_config.pending_stop_poll_interval(),
+                agent_config.pending_stop_poll_count(),
+            ).chain_err(|| "Unable to uninstall the agent service");
+
+            if let (Some(run_report), Some(agent_report)) =
+                (file_config.run_report.as_ref(), agent_config.run_report)
+            {
+                run_report.append(agent_report, None);
+            }
+
+            res
+        }
+
+        Some(CustomCmd::Agent { .. }) => run_agent(&config, &file_config),
+
+        Some(CustomCmd::Serve { ref address }) => {
+            let address = address
+                .clone()
+                .unwrap_or_else(|| nssm_exec::serve::address(&file_config).to_owned());
+
+            let token = nssm_exec::serve::token(&file_config)?;
+            nssm_exec::serve::serve(&address, &token, || load_file_config(&config))
+        }
+
+        Some(CustomCmd::Tui) => nssm_exec::tui::run(|| load_file_config(&config)),
+
+        Some(CustomCmd::Grpc { ref address }) => {
+            let address = address
+                .clone()
+                .unwrap_or_else(|| nssm_exec::grpc_server::address(&file_config).to_owned());
+
+            let token = nssm_exec::serve::token(&file_config)?;
+            let config = config.clone();
+            nssm_exec::grpc_server::serve(&address, &token, move || load_file_config(&config))
+        }
+
+        Some(CustomCmd::Purge { yes }) => {
+            if yes || file_config.is_dry_run() || confirm_purge(&file_config)? {
+                run_on_hosts(&config.cmd, &file_config)
+            } else {
+                info!("Purge cancelled, no service is removed");
+                Ok(())
+            }
+        }
+
+        Some(CustomCmd::EncryptPassword)
+        | Some(CustomCmd::ImportSystemd { .. })
+        | Some(CustomCmd::ImportCompose { .. })
+        | Some(CustomCmd::Replay { .. })
+        | Some(CustomCmd::Explain { .. })
+        | Some(CustomCmd::Init { .. }) => unreachable!(),
+
+        _ => run_on_hosts(&config.cmd, &file_config),
+    };
+
+    let run_report = file_config.run_report.take().unwrap_or_default();
+    let service_reports = run_report.services();
+
+    if !service_reports.is_empty() {
+        print_summary_table(&service_reports);
+    }
+
+    if notifies(&config.cmd) {
+        nssm_exec::notify::notify(&file_config, &service_reports, &res);
+
+        // a failed export does not change the outcome of the run either
+        let export_res = nssm_exec::metrics::export(
+            &file_config,
+            &service_reports,
+            res.is_ok(),
+            &run_start_time.elapsed(),
+        );
+
+        if let Err(e) = export_res {
+            nssm_exec::print_recursive_err(&e);
+        }
+    }
+
+    if let Some(report_path) = config.report {
+        let write_res = run_report.write(&report_path);
+
+        // the run error takes precedence over the report error
+        match (&res, write_res) {
+            (&Ok(_), Err(e)) => return Err(e),
+            (&Err(_), Err(e)) => nssm_exec::print_recursive_err(&e),
+            (_, Ok(_)) => info!("Run report written into '{}'", report_path),
+        }
+    }
+
+    if let (Some(transcript_path), Some(transcript)) =
+        (config.transcript, file_config.transcript)
+    {
+        let write_res = transcript.write(&transcript_path);
+
+        match (&res, write_res) {
+            (&Ok(_), Err(e)) => return Err(e),
+            (&Err(_), Err(e)) => nssm_exec::print_recursive_err(&e),
+            (_, Ok(_)) => info!("Transcript written into '{}'", transcript_path),
+        }
+    }
+
+    if let (Some(record_path), Some(session)) = (config.record, file_config.session) {
+        let write_res = session.write(&record_path);
+
+        match (&res, write_res) {
+            (&Ok(_), Err(e)) => return Err(e),
+            (&Err(_), Err(e)) => nssm_exec::print_recursive_err(&e),
+            (_, Ok(_)) => info!("Session recorded into '{}'", record_path),
+        }
+    }
+
+    res
+}
+
+/// Exit codes of the process by the category of its error, which are kept stable so that
+/// scripts can tell e.g. a bad configuration that is not worth retrying from transient
+/// failures of the services.
+fn exit_code(e: &Error) -> i32 {
+    match e.category() {
+        ErrorCategory::ConfigParse | ErrorCategory::ConfigInvalid => EXIT_CONFIG,
+        ErrorCategory::NssmUnavailable => EXIT_NSSM,
+        ErrorCategory::PartialFailure => EXIT_PARTIAL_FAILURE,
+        ErrorCategory::TotalFailure => EXIT_TOTAL_FAILURE,
+        _ => EXIT_FAILURE,
     }
 }
 
@@ -746,12 +1495,12 @@ fn main() {
     match run() {
         Ok(_) => {
             info!("Program completed!");
-            process::exit(0)
+            process::exit(EXIT_SUCCESS)
         }
 
         Err(ref e) => {
-            print_recursive_err(e);
-            process::exit(1);
+            nssm_exec::print_recursive_err(e);
+            process::exit(exit_code(e));
         }
     }
 }
@@ -0,0 +1,211 @@
+//! Downloads a pinned nssm release, verifies its checksum and extracts the nssm executable
+//! matching the architecture of the machine to the configured nssm path.
+
+use config::FileConfig;
+use download;
+use errors::*;
+use log_dry_run;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::ZipArchive;
+
+/// URL of the nssm release downloaded when no other release is configured.
+pub const DEFAULT_URL: &str = "https://nssm.cc/release/nssm-2.24.zip";
+
+/// SHA-256 checksum of the release at `DEFAULT_URL`.
+pub const DEFAULT_SHA256: &str =
+    "727d1e42275c605e0f04aba98095c38a8e1e46def453cdffce42869428aa6743";
+
+/// Downloads the configured nssm release and extracts its nssm executable to the
+/// configured nssm path, replacing any existing executable there.
+pub fn bootstrap(file_config: &FileConfig) -> Result<()> {
+    let (url, sha256) = release(file_config)?;
+    let nssm_path = &file_config.nssm_path;
+    let arch_dir = arch_dir();
+
+    if file_config.is_dry_run() {
+        log_dry_run(
+            file_config,
+            &format!(
+                "Download '{}' and extract {}/nssm.exe to '{}'",
+                url,
+                arch_dir,
+                nssm_path.to_string_lossy(),
+            ),
+        );
+
+        return Ok(());
+    }
+
+    let download_dir = create_download_dir()?;
+    let archive_path = download_dir.join("nssm.zip");
+
+    info!("Downloading nssm from '{}'...", url);
+
+    let res = download::download(url, &archive_path)
+        .and_then(|_| download::verify_sha256(&archive_path, sha256))
+        .and_then(|_| extract_nssm(&archive_path, arch_dir, nssm_path));
+
+    if let Err(e) = fs::remove_dir_all(&download_dir) {
+        warn!(
+            "Unable to remove download directory at '{}': {}",
+            download_dir.to_string_lossy(),
+            e
+        );
+    }
+
+    res?;
+
+    info!(
+        "Installed {}/nssm.exe at '{}'",
+        arch_dir,
+        nssm_path.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+/// Creates a new directory in the temporary directory to download the release into, which
+/// no other process can have created beforehand, e.g. to swap the archive.
+fn create_download_dir() -> Result<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+
+    for attempt in 0..100 {
+        let dir = env::temp_dir().join(format!(
+            "nssm_exec_bootstrap.{}.{}.{}",
+            process::id(),
+            nanos,
+            attempt
+        ));
+
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).chain_err(|| {
+                    format!(
+                        "Unable to create download directory at '{}'",
+                        dir.to_string_lossy()
+                    )
+                })
+            }
+        }
+    }
+
+    bail!("Unable to create a new download directory in the temporary directory")
+}
+
+fn release(file_config: &FileConfig) -> Result<(&str, &str)> {
+    let bootstrap = match file_config.bootstrap {
+        Some(ref bootstrap) => bootstrap,
+        None => return Ok((DEFAULT_URL, DEFAULT_SHA256)),
+    };
+
+    match (bootstrap.url.as_ref(), bootstrap.sha256.as_ref()) {
+        (Some(url), Some(sha256)) => Ok((url, sha256)),
+        (Some(_), None) => bail!("Unable to verify the configured bootstrap url without sha256"),
+        (None, Some(_)) => bail!("Unable to use the configured bootstrap sha256 without url"),
+        (None, None) => Ok((DEFAULT_URL, DEFAULT_SHA256)),
+    }
+}
+
+/// Returns the directory in the nssm release archive holding the executable for the
+/// architecture of the machine, which is not necessarily the one of this program.
+fn arch_dir() -> &'static str {
+    // PROCESSOR_ARCHITEW6432 is only set for 32-bit processes on 64-bit Windows
+    let arch = env::var("PROCESSOR_ARCHITEW6432")
+        .or_else(|_| env::var("PROCESSOR_ARCHITECTURE"))
+        .unwrap_or_default();
+
+    if arch.contains("64") {
+        "win64"
+    } else {
+        "win32"
+    }
+}
+
+fn extract_nssm(archive_path: &Path, arch_dir: &str, nssm_path: &Path) -> Result<()> {
+    let archive_file = File::open(archive_path).chain_err(|| {
+        format!(
+            "Unable to open downloaded archive at '{}'",
+            archive_path.to_string_lossy()
+        )
+    })?;
+
+    let mut archive = ZipArchive::new(archive_file).chain_err(|| {
+        format!(
+            "Unable to read downloaded archive at '{}' as zip",
+            archive_path.to_string_lossy()
+        )
+    })?;
+
+    let entry_suffix = format!("{}/nssm.exe", arch_dir);
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).chain_err(|| {
+            format!("Unable to read entry #{} of downloaded archive", index)
+        })?;
+
+        if !entry.name().ends_with(&entry_suffix) {
+            continue;
+        }
+
+        if let Some(parent) = nssm_path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent).chain_err(|| {
+                    format!(
+                        "Unable to create directory at '{}'",
+                        parent.to_string_lossy()
+                    )
+                })?;
+            }
+        }
+
+        let mut nssm_file = File::create(nssm_path).chain_err(|| {
+            format!(
+                "Unable to create nssm executable at '{}'",
+                nssm_path.to_string_lossy()
+            )
+        })?;
+
+        io::copy(&mut entry, &mut nssm_file).chain_err(|| {
+            format!(
+                "Unable to extract '{}' to '{}'",
+                entry.name(),
+                nssm_path.to_string_lossy()
+            )
+        })?;
+
+        return Ok(());
+    }
+
+    bail!(
+        "Unable to find '{}' in downloaded archive",
+        entry_suffix
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn download_dirs_are_new() {
+        let first_dir = create_download_dir().unwrap();
+        let second_dir = create_download_dir().unwrap();
+
+        assert!(first_dir.is_dir());
+        assert!(second_dir.is_dir());
+        assert_ne!(first_dir, second_dir);
+
+        fs::remove_dir_all(first_dir).unwrap();
+        fs::remove_dir_all(second_dir).unwrap();
+    }
+}
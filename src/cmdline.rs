@@ -0,0 +1,165 @@
+//! Builds Windows command lines from arguments, quoting them as needed so that they are
+//! parsed back into the same arguments by the receiving program, and splits them back.
+
+use std::borrow::Cow;
+
+/// Quotes the argument if it is empty or contains any whitespace or double quote, escaping
+/// the double quotes and the backslashes preceding them.
+pub fn quote_arg(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return Cow::Borrowed(arg);
+    }
+
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    let mut backslashes = 0;
+
+    quoted.push('"');
+
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+
+        // backslashes only need escaping if they precede a double quote
+        let escaped_backslashes = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+
+        quoted.push_str(&"\\".repeat(escaped_backslashes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+
+    // trailing backslashes precede the closing double quote
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+
+    Cow::Owned(quoted)
+}
+
+/// Joins the arguments into a single command line, quoting each argument as needed.
+pub fn join_args<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    args.into_iter()
+        .map(|arg| quote_arg(arg.as_ref()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats the program and its arguments as a single command line, for display purposes.
+pub fn to_cmd_line<S: AsRef<str>>(program: &str, args: &[S]) -> String {
+    let mut cmd_line = quote_arg(program).into_owned();
+
+    for arg in args {
+        cmd_line.push(' ');
+        cmd_line.push_str(&quote_arg(arg.as_ref()));
+    }
+
+    cmd_line
+}
+
+/// Splits the command line into its arguments with the same rules as `CommandLineToArgvW`,
+/// which is the inverse of `join_args`.
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut in_quotes = false;
+    let mut backslashes = 0;
+
+    for c in line.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            in_arg = true;
+            continue;
+        }
+
+        if c == '"' {
+            // an odd backslash escapes the double quote, the rest are halved
+            arg.push_str(&"\\".repeat(backslashes / 2));
+
+            if backslashes % 2 == 1 {
+                arg.push('"');
+            } else {
+                in_quotes = !in_quotes;
+            }
+
+            backslashes = 0;
+            in_arg = true;
+            continue;
+        }
+
+        arg.push_str(&"\\".repeat(backslashes));
+        backslashes = 0;
+
+        if (c == ' ' || c == '\t') && !in_quotes {
+            if in_arg {
+                args.push(arg.clone());
+                arg.clear();
+                in_arg = false;
+            }
+        } else {
+            arg.push(c);
+            in_arg = true;
+        }
+    }
+
+    arg.push_str(&"\\".repeat(backslashes));
+
+    if in_arg {
+        args.push(arg);
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_arg_keeps_plain_args() {
+        assert_eq!(quote_arg(r"C:\app\app.exe"), r"C:\app\app.exe");
+        assert_eq!(quote_arg(r"a\\b"), r"a\\b");
+    }
+
+    #[test]
+    fn quote_arg_quotes_empty_args() {
+        assert_eq!(quote_arg(""), r#""""#);
+        assert_eq!(split_args(r#"a "" b"#), vec!["a", "", "b"]);
+    }
+
+    #[test]
+    fn quote_arg_escapes_embedded_quotes() {
+        assert_eq!(quote_arg(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_arg(r#"a\"b"#), r#""a\\\"b""#);
+    }
+
+    #[test]
+    fn quote_arg_doubles_trailing_backslashes() {
+        assert_eq!(quote_arg(r"C:\Program Files\"), r#""C:\Program Files\\""#);
+        assert_eq!(quote_arg(r"a b\\"), r#""a b\\\\""#);
+    }
+
+    #[test]
+    fn split_args_reverses_join_args() {
+        let args = vec![
+            "",
+            "plain",
+            "with space",
+            r#"say "hi""#,
+            r"trailing\",
+            r"trailing space\ ",
+            r#"back\"quote"#,
+            r"a\\b",
+        ];
+
+        assert_eq!(split_args(&join_args(&args)), args);
+    }
+}
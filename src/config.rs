@@ -0,0 +1,2940 @@
+use audit::AuditLog;
+use cmdline;
+use credman;
+use dpapi;
+use errors::*;
+use file;
+use interp;
+use report::RunReport;
+use session::Session;
+use rpassword;
+use glob;
+use serde::de::DeserializeOwned;
+use strict;
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use transcript::Transcript;
+use version::NssmVersion;
+
+/// Default interval in milliseconds between each service state poll.
+pub const PENDING_POLL_DEFAULT_MS: u64 = 500;
+
+/// Default number of service state polls before timing out.
+pub const PENDING_POLL_DEFAULT_COUNT: u64 = 5;
+
+/// Default delay in milliseconds before retrying a failed nssm command.
+pub const COMMAND_RETRY_DELAY_DEFAULT_MS: u64 = 1000;
+
+/// Default interval in milliseconds before running the drain check command again.
+pub const DRAIN_POLL_DEFAULT_MS: u64 = 1000;
+
+/// Default number of times to run the drain check command before giving up.
+pub const DRAIN_POLL_DEFAULT_COUNT: u64 = 60;
+
+/// Maximum number of worker threads processing the services or the hosts concurrently,
+/// which is also used when the parallelism is configured as 0.
+pub const MAX_PARALLELISM: usize = 16;
+
+/// Name of the variable holding the name of the service, which may be referenced as
+/// `{{ service_name }}` in the values of the service, including the ones taken from the
+/// extra configurations, unless a variable of the same name is configured.
+pub const SERVICE_NAME_VARIABLE: &str = "service_name";
+
+/// Placeholder of the name of the service in its display name, description and log paths,
+/// which is replaced after the variables are rendered.
+pub const NAME_PLACEHOLDER: &str = "{name}";
+
+/// Represents the arguments to be passed into the executable.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Args {
+    /// Arguments in a single string, where multiple arguments are space delimited and
+    /// arguments may be wrapped around double quotes like in cmd.
+    Line(String),
+
+    /// Arguments in an array, where each argument is quoted as needed.
+    List(Vec<String>),
+}
+
+impl Args {
+    /// Returns the arguments as a single command line string.
+    pub fn to_line(&self) -> Cow<'_, str> {
+        match *self {
+            Args::Line(ref line) => Cow::Borrowed(line),
+            Args::List(ref list) => Cow::Owned(cmdline::join_args(list)),
+        }
+    }
+}
+
+/// Represents a list of names.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum NameList {
+    /// Names in a single string, where multiple names are space delimited.
+    Line(String),
+
+    /// Names in an array.
+    List(Vec<String>),
+}
+
+impl NameList {
+    /// Returns the names in the list.
+    pub fn names(&self) -> Vec<&str> {
+        match *self {
+            NameList::Line(ref line) => line.split_whitespace().collect(),
+            NameList::List(ref list) => list.iter().map(|name| name.as_str()).collect(),
+        }
+    }
+
+    /// Prepends the prefix to the names in the list that are among the given names.
+    fn prefix(&mut self, prefix: &str, names: &HashSet<String>) {
+        let prefixed = |name: &str| if names.contains(name) {
+            format!("{}{}", prefix, name)
+        } else {
+            name.to_owned()
+        };
+
+        *self = match *self {
+            NameList::Line(ref line) => NameList::Line(
+                line.split_whitespace()
+                    .map(&prefixed)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            NameList::List(ref list) => {
+                NameList::List(list.iter().map(|name| prefixed(name)).collect())
+            }
+        };
+    }
+}
+
+/// Groups the Windows account settings for running a service.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Account {
+    /// Windows account username.
+    /// May be left empty if the username is read from `credential_target`.
+    /// Set to `virtual` to run the service under its own virtual account
+    /// `NT SERVICE\<name>`, which takes no password.
+    #[serde(default)]
+    pub user: String,
+
+    /// Built-in account to run the service under instead of the username, which takes no
+    /// password.
+    pub builtin: Option<BuiltinAccount>,
+
+    /// Password corresponding to the username.
+    /// May be left empty if username does not require password.
+    /// Only `${NAME}` environment variable references are expanded in the password, so
+    /// that `{{` is kept as is.
+    #[serde(default)]
+    pub password: String,
+
+    /// Name of the environment variable to read the password from when the configuration
+    /// is loaded, so that the password does not need to be stored in the configuration.
+    pub password_env: Option<String>,
+
+    /// DPAPI protected password, as produced by the `encrypt-password` subcommand on the
+    /// target machine by the same user that runs nssm_exec.
+    pub encrypted_password: Option<String>,
+
+    /// Target name of the generic credential in the Windows Credential Manager to read the
+    /// password from when the configuration is loaded, together with the username if it is
+    /// left empty.
+    pub credential_target: Option<String>,
+}
+
+/// Represents the built-in accounts that services may run under.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinAccount {
+    /// The `LocalSystem` account with full privileges on the machine.
+    LocalSystem,
+
+    /// The `NT AUTHORITY\LocalService` account with minimal privileges.
+    LocalService,
+
+    /// The `NT AUTHORITY\NetworkService` account with minimal privileges, which
+    /// authenticates on the network as the machine.
+    NetworkService,
+}
+
+impl BuiltinAccount {
+    /// Returns the account name as expected by the Service Control Manager.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BuiltinAccount::LocalSystem => "LocalSystem",
+            BuiltinAccount::LocalService => "NT AUTHORITY\\LocalService",
+            BuiltinAccount::NetworkService => "NT AUTHORITY\\NetworkService",
+        }
+    }
+}
+
+/// Username standing for the virtual account of the service.
+pub const VIRTUAL_USER: &str = "virtual";
+
+const VIRTUAL_DOMAIN_PREFIX: &str = "nt service\\";
+
+/// Returns true if the account name as set on a service is a built-in or virtual account,
+/// which takes no password.
+pub fn is_passwordless_account(object_name: &str) -> bool {
+    let builtins = [
+        BuiltinAccount::LocalSystem,
+        BuiltinAccount::LocalService,
+        BuiltinAccount::NetworkService,
+    ];
+
+    builtins
+        .iter()
+        .any(|builtin| builtin.name().eq_ignore_ascii_case(object_name))
+        || object_name.to_lowercase().starts_with(VIRTUAL_DOMAIN_PREFIX)
+}
+
+impl Account {
+    /// Returns true if the account is a virtual service account, i.e. `virtual` or an
+    /// explicit `NT SERVICE\<name>`, which takes no password.
+    pub fn is_virtual(&self) -> bool {
+        let user = self.user.to_lowercase();
+        user == VIRTUAL_USER || user.starts_with(VIRTUAL_DOMAIN_PREFIX)
+    }
+
+    /// Returns true if the account requires a password, i.e. it is neither a built-in nor a
+    /// virtual account.
+    pub fn takes_password(&self) -> bool {
+        self.builtin.is_none() && !self.is_virtual()
+    }
+
+    /// Returns the account name to run the service with the given name under.
+    pub fn object_name(&self, service_name: &str) -> String {
+        if let Some(builtin) = self.builtin {
+            builtin.name().to_owned()
+        } else if self.user.eq_ignore_ascii_case(VIRTUAL_USER) {
+            format!("NT SERVICE\\{}", service_name)
+        } else {
+            self.user.clone()
+        }
+    }
+
+    /// Merges the account settings of the secrets file over the own settings, replacing the
+    /// username and built-in account if set, and every password setting if any of them is
+    /// set.
+    fn merge_secret(&mut self, secret: Account) {
+        if !secret.user.is_empty() {
+            self.user = secret.user;
+        }
+
+        self.builtin = secret.builtin.or(self.builtin);
+
+        let has_password = !secret.password.is_empty() || secret.password_env.is_some()
+            || secret.encrypted_password.is_some()
+            || secret.credential_target.is_some();
+
+        if has_password {
+            self.password = secret.password;
+            self.password_env = secret.password_env;
+            self.encrypted_password = secret.encrypted_password;
+            self.credential_target = secret.credential_target;
+        }
+    }
+
+    fn expand_values<F>(&mut self, expand: &F) -> Result<()>
+    where
+        F: Fn(&str) -> Result<String>,
+    {
+        interp::expand_in(&mut self.user, expand)?;
+
+        // passwords may well contain `{{`, so variables are not rendered in them
+        self.password = interp::expand_env_vars(&self.password)?;
+        Ok(())
+    }
+
+    fn resolve_password(&mut self) -> Result<()> {
+        let source_count = [
+            !self.password.is_empty(),
+            self.password_env.is_some(),
+            self.encrypted_password.is_some(),
+            self.credential_target.is_some(),
+        ].iter()
+            .filter(|is_set| **is_set)
+            .count();
+
+        if let Some(builtin) = self.builtin {
+            if !self.user.is_empty() {
+                bail!(
+                    "Unable to use both user '{}' and builtin account '{}'",
+                    self.user,
+                    builtin.name()
+                );
+            }
+
+            if source_count > 0 {
+                bail!("Unable to use a password for builtin account '{}'", builtin.name());
+            }
+
+            return Ok(());
+        }
+
+        if self.is_virtual() && source_count > 0 {
+            bail!("Unable to use a password for virtual account '{}'", self.user);
+        }
+
+        if source_count > 1 {
+            bail!(
+                "Unable to use more than one of password, password_env, encrypted_password \
+                 and credential_target for account '{}'",
+                self.user
+            );
+        }
+
+        if let Some(ref password_env) = self.password_env {
+            self.password = env::var(password_env).chain_err(|| {
+                format!(
+                    "Unable to read password of account '{}' from environment variable '{}'",
+                    self.user,
+                    password_env
+                )
+            })?;
+        }
+
+        if let Some(ref encrypted_password) = self.encrypted_password {
+            self.password = dpapi::unprotect(encrypted_password).chain_err(|| {
+                format!("Unable to decrypt password of account '{}'", self.user)
+            })?;
+        }
+
+        if let Some(ref credential_target) = self.credential_target {
+            let credential = credman::read(credential_target)?;
+
+            if self.user.is_empty() {
+                self.user = credential.user;
+            }
+
+            self.password = credential.password;
+        }
+
+        if self.user.is_empty() {
+            bail!("Unable to find username of account in the configuration");
+        }
+
+        Ok(())
+    }
+}
+
+/// Represents the action nssm takes when the executable exits.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum ExitAction {
+    /// Restarts the executable.
+    Restart,
+
+    /// Does nothing, leaving the service running without the executable.
+    Ignore,
+
+    /// Stops the service gracefully.
+    Exit,
+
+    /// Stops the service without a graceful shutdown, which allows recovery actions to run.
+    Suicide,
+}
+
+impl ExitAction {
+    /// Returns the action name as used by nssm.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ExitAction::Restart => "Restart",
+            ExitAction::Ignore => "Ignore",
+            ExitAction::Exit => "Exit",
+            ExitAction::Suicide => "Suicide",
+        }
+    }
+}
+
+/// Represents the tool that installs and configures the service.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    Nssm,
+    Winsw,
+    Shawl,
+    Systemd,
+    Mock,
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    /// Parses the backend name as used in the configuration, e.g. `nssm`.
+    fn from_str(s: &str) -> Result<Backend> {
+        match s {
+            "nssm" => Ok(Backend::Nssm),
+            "winsw" => Ok(Backend::Winsw),
+            "shawl" => Ok(Backend::Shawl),
+            "systemd" => Ok(Backend::Systemd),
+            "mock" => Ok(Backend::Mock),
+            _ => bail!("Unable to recognize backend '{}'", s),
+        }
+    }
+}
+
+/// Represents the process priority class of the executable.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Runs the executable only when the system is idle.
+    Idle,
+
+    /// Runs the executable below the normal priority.
+    BelowNormal,
+
+    /// Runs the executable at the normal priority, which is the default.
+    Normal,
+
+    /// Runs the executable above the normal priority.
+    AboveNormal,
+
+    /// Runs the executable ahead of the normal priority processes, for time-critical tasks.
+    High,
+
+    /// Runs the executable ahead of all the other processes, including the system ones.
+    Realtime,
+}
+
+impl Priority {
+    /// Returns the priority class name as used by nssm.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Priority::Idle => "IDLE_PRIORITY_CLASS",
+            Priority::BelowNormal => "BELOW_NORMAL_PRIORITY_CLASS",
+            Priority::Normal => "NORMAL_PRIORITY_CLASS",
+            Priority::AboveNormal => "ABOVE_NORMAL_PRIORITY_CLASS",
+            Priority::High => "HIGH_PRIORITY_CLASS",
+            Priority::Realtime => "REALTIME_PRIORITY_CLASS",
+        }
+    }
+}
+
+/// Groups the command lines to run on the nssm events.
+#[derive(Clone, Default, Deserialize)]
+pub struct Hooks {
+    /// Command line to run before the executable is started.
+    #[serde(rename = "Start/Pre")]
+    pub start_pre: Option<String>,
+
+    /// Command line to run after the executable is started.
+    #[serde(rename = "Start/Post")]
+    pub start_post: Option<String>,
+
+    /// Command line to run before the executable is stopped.
+    #[serde(rename = "Stop/Pre")]
+    pub stop_pre: Option<String>,
+
+    /// Command line to run after the executable exits.
+    #[serde(rename = "Exit/Post")]
+    pub exit_post: Option<String>,
+
+    /// Command line to run before the log files are rotated.
+    #[serde(rename = "Rotate/Pre")]
+    pub rotate_pre: Option<String>,
+
+    /// Command line to run after the log files are rotated.
+    #[serde(rename = "Rotate/Post")]
+    pub rotate_post: Option<String>,
+
+    /// Command line to run when the power status of the system changes.
+    #[serde(rename = "Power/Change")]
+    pub power_change: Option<String>,
+
+    /// Command line to run when the system resumes from a suspended state.
+    #[serde(rename = "Power/Resume")]
+    pub power_resume: Option<String>,
+}
+
+impl Hooks {
+    /// Returns the pairs of nssm event name and configured command line.
+    pub fn events(&self) -> Vec<(&'static str, &str)> {
+        let events = [
+            ("Start/Pre", &self.start_pre),
+            ("Start/Post", &self.start_post),
+            ("Stop/Pre", &self.stop_pre),
+            ("Exit/Post", &self.exit_post),
+            ("Rotate/Pre", &self.rotate_pre),
+            ("Rotate/Post", &self.rotate_post),
+            ("Power/Change", &self.power_change),
+            ("Power/Resume", &self.power_resume),
+        ];
+
+        events
+            .iter()
+            .filter_map(|&(event, cmd)| cmd.as_ref().map(|cmd| (event, cmd.as_str())))
+            .collect()
+    }
+}
+
+/// Groups the exit action settings, either as a single action for all exit codes
+/// or as actions for specific exit codes.
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ExitActionConfig {
+    /// Action to take on any exit code.
+    Default(ExitAction),
+
+    /// Actions to take on specific exit codes.
+    PerCode {
+        /// Action to take on any exit code without a specific action.
+        default: Option<ExitAction>,
+
+        /// Mapping of exit code to the action to take.
+        #[serde(default)]
+        codes: BTreeMap<String, ExitAction>,
+    },
+}
+
+/// Groups the rotation settings of the redirected output log files.
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+pub struct LogRotation {
+    /// States whether to rotate the log files when the service starts.
+    pub enabled: Option<bool>,
+
+    /// States whether to also rotate the log files while the service is running.
+    pub online: Option<bool>,
+
+    /// Rotates the log files only if they are older than this number of seconds.
+    pub seconds: Option<u32>,
+
+    /// Rotates the log files only if they are larger than this number of bytes.
+    pub bytes: Option<u32>,
+}
+
+impl LogRotation {
+    /// Merges the settings over the given fallback settings,
+    /// prioritizing the own settings if available individually.
+    pub fn or(self, fallback: LogRotation) -> LogRotation {
+        LogRotation {
+            enabled: self.enabled.or(fallback.enabled),
+            online: self.online.or(fallback.online),
+            seconds: self.seconds.or(fallback.seconds),
+            bytes: self.bytes.or(fallback.bytes),
+        }
+    }
+}
+
+/// Groups the settings of how nssm stops the executable. nssm attempts each of the stop
+/// methods in order, i.e. console Ctrl-C, window close, thread quit and finally terminate,
+/// waiting for the respective timeout before attempting the next method.
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+pub struct StopMethod {
+    /// States whether to skip sending Ctrl-C to the console.
+    pub skip_console: Option<bool>,
+
+    /// States whether to skip sending WM_CLOSE to the windows.
+    pub skip_window: Option<bool>,
+
+    /// States whether to skip sending WM_QUIT to the threads.
+    pub skip_threads: Option<bool>,
+
+    /// States whether to skip terminating the process.
+    pub skip_terminate: Option<bool>,
+
+    /// Duration in milliseconds to wait for the executable to exit after Ctrl-C.
+    pub console_ms: Option<u32>,
+
+    /// Duration in milliseconds to wait for the executable to exit after WM_CLOSE.
+    pub window_ms: Option<u32>,
+
+    /// Duration in milliseconds to wait for the executable to exit after WM_QUIT.
+    pub threads_ms: Option<u32>,
+
+    /// States whether nssm terminates the processes started by the executable as well when
+    /// the service stops. Requires nssm 2.25 or later.
+    pub kill_process_tree: Option<bool>,
+
+    /// States whether nssm_exec terminates the whole process tree of the service if the
+    /// service has not stopped within the stop poll budget, instead of failing the service.
+    /// Defaults to false.
+    pub kill_tree: Option<bool>,
+}
+
+impl StopMethod {
+    /// Returns the nssm bitmask of the stop methods to skip,
+    /// or `None` if none of the skip settings is configured.
+    pub fn skip_mask(&self) -> Option<u32> {
+        let skips = [
+            (self.skip_console, 1),
+            (self.skip_window, 2),
+            (self.skip_threads, 4),
+            (self.skip_terminate, 8),
+        ];
+
+        if skips.iter().all(|&(skip, _)| skip.is_none()) {
+            return None;
+        }
+
+        Some(
+            skips
+                .iter()
+                .filter(|&&(skip, _)| skip == Some(true))
+                .map(|&(_, bit)| bit)
+                .sum(),
+        )
+    }
+
+    /// Returns true if the process tree of the service is terminated when it does not stop.
+    pub fn is_kill_tree(&self) -> bool {
+        self.kill_tree.unwrap_or(false)
+    }
+
+    /// Merges the settings over the given fallback settings,
+    /// prioritizing the own settings if available individually.
+    pub fn or(self, fallback: StopMethod) -> StopMethod {
+        StopMethod {
+            skip_console: self.skip_console.or(fallback.skip_console),
+            skip_window: self.skip_window.or(fallback.skip_window),
+            skip_threads: self.skip_threads.or(fallback.skip_threads),
+            skip_terminate: self.skip_terminate.or(fallback.skip_terminate),
+            console_ms: self.console_ms.or(fallback.console_ms),
+            window_ms: self.window_ms.or(fallback.window_ms),
+            threads_ms: self.threads_ms.or(fallback.threads_ms),
+            kill_process_tree: self.kill_process_tree.or(fallback.kill_process_tree),
+            kill_tree: self.kill_tree.or(fallback.kill_tree),
+        }
+    }
+}
+
+/// Groups the extra configurations required for configuring the service.
+/// May be used on every service or in a global context.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct OtherConfig {
+    /// List of other service names to depend on before starting this service.
+    /// Either an array of service names, or a string where multiple service names are
+    /// space delimited.
+    pub deps: Option<NameList>,
+
+    /// States whether to immediately start the created service.
+    /// Defaults to false.
+    pub start_on_create: Option<bool>,
+
+    /// Holds the account configuration to run the service.
+    pub account: Option<Account>,
+
+    /// Holds the rotation settings of the redirected output log files.
+    pub log_rotation: Option<LogRotation>,
+
+    /// Minimum duration in milliseconds the executable must run before nssm
+    /// stops throttling its restarts.
+    pub throttle_ms: Option<u32>,
+
+    /// Duration in milliseconds to wait before restarting the executable after it exits.
+    pub restart_delay_ms: Option<u32>,
+
+    /// Holds the settings of how nssm stops the executable, each of which falls back to the
+    /// global setting individually.
+    pub stop_method: Option<StopMethod>,
+
+    /// States whether to not allocate a console for the executable, e.g. for GUI applications.
+    /// Defaults to false.
+    pub no_console: Option<bool>,
+
+    /// List of load ordering group names to depend on before starting this service.
+    /// Either an array of group names, or a string where multiple group names are
+    /// space delimited.
+    pub dep_groups: Option<NameList>,
+
+    /// Tool that installs and configures the service, either `nssm`, `winsw`, `shawl`,
+    /// `systemd` or `mock`. Defaults to systemd for the local services on Linux, and to nssm
+    /// otherwise.
+    pub backend: Option<Backend>,
+
+    /// Command line that nssm_exec runs before installing the service or setting its
+    /// changed parameters, failing the service if the command fails.
+    pub pre_install: Option<String>,
+
+    /// Command line that nssm_exec runs after installing the service or setting its changed
+    /// parameters, before starting it, failing the service if the command fails.
+    pub post_install: Option<String>,
+
+    /// Command line that nssm_exec runs before stopping the running service, failing the
+    /// service if the command fails.
+    pub pre_stop: Option<String>,
+
+    /// Command line that nssm_exec runs after the started service is running and healthy,
+    /// failing the service if the command fails.
+    pub post_start: Option<String>,
+
+    /// Number of seconds after which the commands run for the service, e.g. nssm, are
+    /// killed and fail. Only the global setting applies to the commands that are not run
+    /// for any particular service, e.g. the hooks. Defaults to no timeout.
+    pub command_timeout_secs: Option<u64>,
+
+    /// Number of times to retry the nssm commands that modify the service when they fail
+    /// transiently, i.e. while the Service Control Manager is busy or the previous service
+    /// is still marked for deletion. Defaults to 0.
+    pub command_retries: Option<u32>,
+
+    /// Duration in milliseconds to wait before retrying a failed nssm command.
+    /// Defaults to 1000.
+    pub command_retry_delay_ms: Option<u64>,
+
+    /// States whether to grant the account of the service write access to the directories
+    /// of its `stdout_log` and `stderr_log` before starting it. Only applies to the Windows
+    /// services running under an account other than `LocalSystem`. Defaults to false.
+    pub grant_log_access: Option<bool>,
+
+    /// Startup directory path of the services that do not set their own `startup_dir`.
+    pub startup_dir: Option<PathBuf>,
+
+    /// Arguments of the services that do not set their own `args`.
+    pub args: Option<Args>,
+
+    /// Description of the services that do not set their own `description`.
+    pub description: Option<String>,
+
+    /// Environment variables of the services, each of which is only set if the service does
+    /// not set the same variable in its own `env`.
+    pub env: Option<BTreeMap<String, String>>,
+
+    /// File path to redirect the standard output to, for the services that do not set their
+    /// own `stdout_log`.
+    pub stdout_log: Option<PathBuf>,
+
+    /// File path to redirect the standard error to, for the services that do not set their
+    /// own `stderr_log`.
+    pub stderr_log: Option<PathBuf>,
+}
+
+impl OtherConfig {
+    fn expand_values<F>(&mut self, expand: &F) -> Result<()>
+    where
+        F: Fn(&str) -> Result<String>,
+    {
+        if let Some(ref mut account) = self.account {
+            account.expand_values(expand)?;
+        }
+
+        if let Some(ref mut pre_install) = self.pre_install {
+            interp::expand_in(pre_install, expand)?;
+        }
+
+        if let Some(ref mut post_install) = self.post_install {
+            interp::expand_in(post_install, expand)?;
+        }
+
+        if let Some(ref mut pre_stop) = self.pre_stop {
+            interp::expand_in(pre_stop, expand)?;
+        }
+
+        if let Some(ref mut post_start) = self.post_start {
+            interp::expand_in(post_start, expand)?;
+        }
+
+        Ok(())
+    }
+
+    /// Merges the extra configurations over the given fallback extra configurations,
+    /// prioritizing the own configurations if available individually.
+    pub fn or(self, fallback: OtherConfig) -> OtherConfig {
+        OtherConfig {
+            deps: self.deps.or(fallback.deps),
+            start_on_create: self.start_on_create.or(fallback.start_on_create),
+            account: self.account.or(fallback.account),
+            log_rotation: self.log_rotation.or(fallback.log_rotation),
+            throttle_ms: self.throttle_ms.or(fallback.throttle_ms),
+            restart_delay_ms: self.restart_delay_ms.or(fallback.restart_delay_ms),
+            stop_method: or_stop_method(self.stop_method, fallback.stop_method),
+            no_console: self.no_console.or(fallback.no_console),
+            dep_groups: self.dep_groups.or(fallback.dep_groups),
+            backend: self.backend.or(fallback.backend),
+            pre_install: self.pre_install.or(fallback.pre_install),
+            post_install: self.post_install.or(fallback.post_install),
+            pre_stop: self.pre_stop.or(fallback.pre_stop),
+            post_start: self.post_start.or(fallback.post_start),
+            command_timeout_secs: self.command_timeout_secs.or(fallback.command_timeout_secs),
+            command_retries: self.command_retries.or(fallback.command_retries),
+            command_retry_delay_ms: self.command_retry_delay_ms
+                .or(fallback.command_retry_delay_ms),
+            grant_log_access: self.grant_log_access.or(fallback.grant_log_access),
+            startup_dir: self.startup_dir.or(fallback.startup_dir),
+            args: self.args.or(fallback.args),
+            description: self.description.or(fallback.description),
+            env: match (fallback.env, self.env) {
+                (Some(mut env), Some(own_env)) => {
+                    env.extend(own_env);
+                    Some(env)
+                }
+                (env, own_env) => own_env.or(env),
+            },
+            stdout_log: self.stdout_log.or(fallback.stdout_log),
+            stderr_log: self.stderr_log.or(fallback.stderr_log),
+        }
+    }
+}
+
+/// Holds the extra configurations of a service after merging the specific ones over the
+/// global ones.
+pub struct OtherConfigRef<'a> {
+    /// Merged list of other service names to depend on.
+    pub deps: Option<&'a NameList>,
+
+    /// Merged state of whether to immediately start the created service.
+    pub start_on_create: Option<&'a bool>,
+
+    /// Merged account configuration to run the service.
+    pub account: Option<&'a Account>,
+
+    /// Merged minimum duration in milliseconds before throttling restarts.
+    pub throttle_ms: Option<&'a u32>,
+
+    /// Merged duration in milliseconds to wait before restarting the executable.
+    pub restart_delay_ms: Option<&'a u32>,
+
+    /// Merged settings of how nssm stops the executable, where each setting is merged
+    /// individually.
+    pub stop_method: Option<StopMethod>,
+
+    /// Merged state of whether to not allocate a console for the executable.
+    pub no_console: Option<&'a bool>,
+
+    /// Merged list of load ordering group names to depend on.
+    pub dep_groups: Option<&'a NameList>,
+
+    /// Merged tool that installs and configures the service.
+    pub backend: Option<&'a Backend>,
+
+    /// Merged command line to run before installing the service.
+    pub pre_install: Option<&'a String>,
+
+    /// Merged command line to run after installing the service.
+    pub post_install: Option<&'a String>,
+
+    /// Merged command line to run before stopping the service.
+    pub pre_stop: Option<&'a String>,
+
+    /// Merged command line to run after starting the service.
+    pub post_start: Option<&'a String>,
+
+    /// Merged number of seconds after which the commands run for the service are killed.
+    pub command_timeout_secs: Option<&'a u64>,
+
+    /// Merged number of times to retry the failed nssm commands of the service.
+    pub command_retries: Option<&'a u32>,
+
+    /// Merged duration in milliseconds to wait before retrying a failed nssm command.
+    pub command_retry_delay_ms: Option<&'a u64>,
+
+    /// Merged state of whether to grant the account write access to the log directories.
+    pub grant_log_access: Option<&'a bool>,
+}
+
+/// Holds the probes checked after the service is started, all of which must pass before
+/// the service is considered healthy. They are retried with the pending start poll settings.
+#[derive(Clone, Deserialize)]
+pub struct Health {
+    /// TCP port that must accept connections, on the host of the service.
+    pub tcp_port: Option<u16>,
+
+    /// URL that must respond to a GET request with the expected HTTP status.
+    pub http_url: Option<String>,
+
+    /// Expected HTTP status of the URL. Defaults to 200.
+    pub http_status: Option<u16>,
+
+    /// Command line that must exit with code 0, run on the host of the service.
+    pub command: Option<String>,
+}
+
+impl Health {
+    /// Returns true if no probe is configured.
+    pub fn is_empty(&self) -> bool {
+        self.tcp_port.is_none() && self.http_url.is_none() && self.command.is_none()
+    }
+
+    fn expand_values<F>(&mut self, expand: &F) -> Result<()>
+    where
+        F: Fn(&str) -> Result<String>,
+    {
+        if let Some(ref mut http_url) = self.http_url {
+            interp::expand_in(http_url, expand)?;
+        }
+
+        if let Some(ref mut command) = self.command {
+            interp::expand_in(command, expand)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Holds the settings of the copy of the service installed in blue-green mode that must
+/// differ from the service, so that the copy can run next to the service, e.g. on another
+/// port, and its health probes check the copy instead of the service.
+#[derive(Clone, Deserialize)]
+pub struct GreenCopy {
+    /// Arguments of the copy, e.g. with another port. Defaults to the arguments of the
+    /// service.
+    pub args: Option<Args>,
+
+    /// Environment variables of the copy, each of which overrides the same variable of the
+    /// service.
+    pub env: Option<BTreeMap<String, String>>,
+
+    /// File path to redirect the standard output of the copy to, which is required if the
+    /// service redirects its standard output. `{name}` is replaced with the name of the copy.
+    pub stdout_log: Option<PathBuf>,
+
+    /// File path to redirect the standard error of the copy to, which is required if the
+    /// service redirects its standard error. `{name}` is replaced with the name of the copy.
+    pub stderr_log: Option<PathBuf>,
+
+    /// Holds the probes checked after the copy is started, which are required if the
+    /// service has health probes, and must not probe the same port or URL as the service.
+    pub health: Option<Health>,
+}
+
+impl GreenCopy {
+    fn expand_values<F>(&mut self, expand: &F) -> Result<()>
+    where
+        F: Fn(&str) -> Result<String>,
+    {
+        match self.args {
+            Some(Args::Line(ref mut line)) => interp::expand_in(line, expand)?,
+            Some(Args::List(ref mut list)) => for arg in list.iter_mut() {
+                interp::expand_in(arg, expand)?;
+            },
+            None => (),
+        }
+
+        if let Some(ref mut env) = self.env {
+            for value in env.values_mut() {
+                interp::expand_in(value, expand)?;
+            }
+        }
+
+        if let Some(ref mut stdout_log) = self.stdout_log {
+            interp::expand_in_path(stdout_log, expand)?;
+        }
+
+        if let Some(ref mut stderr_log) = self.stderr_log {
+            interp::expand_in_path(stderr_log, expand)?;
+        }
+
+        if let Some(ref mut health) = self.health {
+            health.expand_values(expand)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Holds the command run repeatedly before the running service is stopped, which must exit
+/// with code 0 before the service is stopped, e.g. once it has no active connections left.
+#[derive(Clone, Deserialize)]
+pub struct DrainCheck {
+    /// Command line that exits with code 0 once the service has drained, run on the host of
+    /// the service.
+    pub command: String,
+
+    /// Interval in milliseconds before running the command again. Defaults to 1000.
+    pub poll_ms: Option<u64>,
+
+    /// Number of times to run the command before giving up, which fails the service without
+    /// stopping it. Defaults to 60.
+    pub poll_count: Option<u64>,
+}
+
+impl DrainCheck {
+    /// Returns the interval before running the command again.
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_ms.unwrap_or(DRAIN_POLL_DEFAULT_MS))
+    }
+
+    /// Returns the number of times to run the command before giving up.
+    pub fn poll_count(&self) -> u64 {
+        self.poll_count.unwrap_or(DRAIN_POLL_DEFAULT_COUNT)
+    }
+}
+
+/// Represents the protocol of the ports allowed by a firewall rule.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// Holds the inbound Windows Firewall rule allowing the ports of the service, which is
+/// created when installing the service and deleted when removing it.
+#[derive(Clone, Deserialize)]
+pub struct Firewall {
+    /// Local ports to allow.
+    pub ports: Vec<u16>,
+
+    /// Protocol of the ports, either "tcp" or "udp". Defaults to tcp.
+    pub protocol: Option<Protocol>,
+
+    /// Firewall profiles the rule applies to, e.g. "domain,private". Defaults to "any".
+    pub profile: Option<String>,
+
+    /// Name of the rule. Defaults to "nssm_exec_<service name>".
+    pub rule_name: Option<String>,
+}
+
+/// Represents the action the Service Control Manager takes when the service fails.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryAction {
+    None,
+    Restart,
+    Reboot,
+}
+
+/// Holds the recovery actions of the Service Control Manager, which apply when the service
+/// process itself, e.g. nssm, dies. They are set with `sc failure` after installation.
+#[derive(Clone, Deserialize)]
+pub struct Recovery {
+    /// Action on the first failure. Defaults to none.
+    pub first: Option<RecoveryAction>,
+
+    /// Action on the second failure. Defaults to none.
+    pub second: Option<RecoveryAction>,
+
+    /// Action on the subsequent failures. Defaults to none.
+    pub subsequent: Option<RecoveryAction>,
+
+    /// Duration in seconds without failure after which the failure count is reset to 0.
+    /// Defaults to 86400, i.e. a day.
+    pub reset_secs: Option<u32>,
+
+    /// Duration in milliseconds to wait before taking each action. Defaults to 60000.
+    pub restart_delay_ms: Option<u32>,
+
+    /// States whether the actions also apply when the service stops with an error instead of
+    /// crashing. Defaults to false.
+    pub non_crash_failures: Option<bool>,
+}
+
+/// Holds the file fetched from a URL to the machine before the service is installed, e.g.
+/// the service executable, or the zip archive extracted into the install directory of the
+/// service.
+#[derive(Clone, Deserialize)]
+pub struct Artifact {
+    /// URL to download the file from. If not set, the file is expected to be at the
+    /// destination path already.
+    pub url: Option<String>,
+
+    /// Expected SHA-256 checksum in hex of the file at the URL. The file is only downloaded
+    /// again if the file at the destination path has another checksum. Required if the URL
+    /// is set.
+    pub sha256: Option<String>,
+
+    /// Destination path of the downloaded file, replacing any existing file.
+    pub dest: PathBuf,
+
+    /// Directory to extract the file into as a zip archive, whenever the archive changes.
+    pub extract_dir: Option<PathBuf>,
+
+    /// States whether to remove the previous contents of the extract directory before
+    /// extracting. Defaults to false.
+    pub clean: Option<bool>,
+
+    /// Path of the directory junction to point at the extract directory, e.g.
+    /// `app\current` for the extract directory `app\1.2.3`, which the service path
+    /// references. The junction is switched while the service is stopped.
+    pub junction: Option<PathBuf>,
+}
+
+/// Groups the configurations required for a service.
+#[derive(Clone, Default, Deserialize)]
+pub struct Service {
+    /// Name of service.
+    pub name: String,
+
+    /// Service executable file path.
+    pub path: PathBuf,
+
+    /// Service startup directory path. Leaving empty should use the directory path
+    /// containing the executable.
+    pub startup_dir: Option<PathBuf>,
+
+    /// Arguments to be passed into the executable. Either a single string where multiple
+    /// arguments are space delimited and arguments may be wrapped around double quotes like in
+    /// cmd, or an array of arguments which are quoted automatically.
+    pub args: Option<Args>,
+
+    /// Environment variables set for the executable in addition to the inherited ones.
+    pub env: Option<BTreeMap<String, String>>,
+
+    /// Human-friendly name of service shown by the service control manager.
+    /// Defaults to the name of service.
+    pub display_name: Option<String>,
+
+    /// Description string of service.
+    pub description: Option<String>,
+
+    /// File path to redirect the standard output of the executable to.
+    pub stdout_log: Option<PathBuf>,
+
+    /// File path to redirect the standard error of the executable to.
+    pub stderr_log: Option<PathBuf>,
+
+    /// Holds the rotation settings of the redirected output log files.
+    /// Any settings here will always override the ones in the extra configurations.
+    pub log_rotation: Option<LogRotation>,
+
+    /// Action to take when the executable exits. Either one of "Restart", "Ignore", "Exit" or
+    /// "Suicide" for all exit codes, or a table with the optional `default` action and the
+    /// `codes` table mapping specific exit codes to actions.
+    pub exit_action: Option<ExitActionConfig>,
+
+    /// Holds the command lines to run on the nssm events, e.g. "Start/Pre".
+    pub hooks: Option<Hooks>,
+
+    /// Process priority class of the executable. Either one of "idle", "below_normal",
+    /// "normal", "above_normal", "high" or "realtime".
+    pub priority: Option<Priority>,
+
+    /// Holds the probes checked after the service is started, before declaring it OK.
+    pub health: Option<Health>,
+
+    /// Holds the command that must succeed before the running service is stopped.
+    pub drain_check: Option<DrainCheck>,
+
+    /// Holds the settings of the copy of the service installed in blue-green mode that
+    /// differ from the service, which is required to recreate the service blue-green.
+    pub green: Option<GreenCopy>,
+
+    /// States whether to verify that the application process launched by nssm keeps running
+    /// after the service is started, since the service may be reported as running while
+    /// nssm throttles the restarts of a crashing application. Only applicable to the nssm
+    /// backend on the local machine. Defaults to false.
+    pub verify_process: Option<bool>,
+
+    /// Holds the inbound firewall rule allowing the ports of the service.
+    pub firewall: Option<Firewall>,
+
+    /// Holds the recovery actions of the Service Control Manager when the service fails.
+    pub recovery: Option<Recovery>,
+
+    /// Holds the file to download before installing the service.
+    pub artifact: Option<Artifact>,
+
+    /// Names of the remote hosts to install the service on through PowerShell remoting,
+    /// each of which must have nssm at the same `nssm_path`. Defaults to the local machine.
+    pub hosts: Option<Vec<String>>,
+
+    /// Holds the extra configurations.
+    /// Any specific extra configurations will always override the global ones.
+    pub other: Option<OtherConfig>,
+
+    /// Dotted names of the settings that the service has taken from the global extra
+    /// configurations when loaded, e.g. `startup_dir` or `env.PATH`, which is never read from
+    /// the configuration file.
+    #[serde(skip)]
+    pub from_global: Vec<String>,
+}
+
+impl Service {
+    /// Returns true if the service is installed on the given remote host, or on the local
+    /// machine if no host is given.
+    pub fn is_on_host(&self, host: Option<&str>) -> bool {
+        let hosts = match self.hosts {
+            Some(ref hosts) if !hosts.is_empty() => hosts,
+            _ => return host.is_none(),
+        };
+
+        match host {
+            Some(host) => hosts.iter().any(|service_host| service_host == host),
+            None => false,
+        }
+    }
+
+    /// Prepends the prefix to the name and display name of the service, and to the
+    /// dependencies on the services with the given names.
+    fn prefix_names(&mut self, prefix: &str, names: &HashSet<String>) {
+        self.name = format!("{}{}", prefix, self.name);
+
+        if let Some(ref mut display_name) = self.display_name {
+            *display_name = format!("{}{}", prefix, display_name);
+        }
+
+        if let Some(deps) = self.other.as_mut().and_then(|other| other.deps.as_mut()) {
+            deps.prefix(prefix, names);
+        }
+    }
+
+    /// Replaces the name placeholder in the display name, description and log paths with
+    /// the name of the service.
+    fn render_name(&mut self) {
+        let name = self.name.clone();
+        let render_path =
+            |path: &Path| PathBuf::from(path.to_string_lossy().replace(NAME_PLACEHOLDER, &name));
+
+        if let Some(ref mut display_name) = self.display_name {
+            *display_name = display_name.replace(NAME_PLACEHOLDER, &name);
+        }
+
+        if let Some(ref mut description) = self.description {
+            *description = description.replace(NAME_PLACEHOLDER, &name);
+        }
+
+        if let Some(ref mut stdout_log) = self.stdout_log {
+            *stdout_log = render_path(stdout_log);
+        }
+
+        if let Some(ref mut stderr_log) = self.stderr_log {
+            *stderr_log = render_path(stderr_log);
+        }
+    }
+
+    /// Sets the settings that the service does not set itself from its extra configurations,
+    /// or else from the given global extra configurations.
+    fn apply_defaults(&mut self, global: &OtherConfig) {
+        let other = self.other.clone().unwrap_or_default();
+        let mut from_global = Vec::new();
+
+        if default_from(&mut self.startup_dir, &other.startup_dir, &global.startup_dir) {
+            from_global.push("startup_dir".to_owned());
+        }
+
+        if default_from(&mut self.args, &other.args, &global.args) {
+            from_global.push("args".to_owned());
+        }
+
+        if default_from(&mut self.description, &other.description, &global.description) {
+            from_global.push("description".to_owned());
+        }
+
+        if default_from(&mut self.stdout_log, &other.stdout_log, &global.stdout_log) {
+            from_global.push("stdout_log".to_owned());
+        }
+
+        if default_from(&mut self.stderr_log, &other.stderr_log, &global.stderr_log) {
+            from_global.push("stderr_log".to_owned());
+        }
+
+        if other.env.is_some() || global.env.is_some() {
+            let env = self.env.get_or_insert_with(BTreeMap::new);
+
+            for (name, value) in other.env.iter().flatten() {
+                env.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+
+            for (name, value) in global.env.iter().flatten() {
+                if !env.contains_key(name) {
+                    env.insert(name.clone(), value.clone());
+                    from_global.push(format!("env.{}", name));
+                }
+            }
+        }
+
+        self.from_global = from_global;
+    }
+
+    fn expand_values<F>(&mut self, expand: &F) -> Result<()>
+    where
+        F: Fn(&str) -> Result<String>,
+    {
+        interp::expand_in_path(&mut self.path, expand)?;
+
+        if let Some(ref mut startup_dir) = self.startup_dir {
+            interp::expand_in_path(startup_dir, expand)?;
+        }
+
+        match self.args {
+            Some(Args::Line(ref mut line)) => interp::expand_in(line, expand)?,
+            Some(Args::List(ref mut list)) => for arg in list.iter_mut() {
+                interp::expand_in(arg, expand)?;
+            },
+            None => (),
+        }
+
+        if let Some(ref mut env) = self.env {
+            for value in env.values_mut() {
+                interp::expand_in(value, expand)?;
+            }
+        }
+
+        if let Some(ref mut display_name) = self.display_name {
+            interp::expand_in(display_name, expand)?;
+        }
+
+        if let Some(ref mut description) = self.description {
+            interp::expand_in(description, expand)?;
+        }
+
+        if let Some(ref mut stdout_log) = self.stdout_log {
+            interp::expand_in_path(stdout_log, expand)?;
+        }
+
+        if let Some(ref mut stderr_log) = self.stderr_log {
+            interp::expand_in_path(stderr_log, expand)?;
+        }
+
+        if let Some(ref mut health) = self.health {
+            health.expand_values(expand)?;
+        }
+
+        if let Some(ref mut drain_check) = self.drain_check {
+            interp::expand_in(&mut drain_check.command, expand)?;
+        }
+
+        if let Some(ref mut green) = self.green {
+            green.expand_values(expand)?;
+        }
+
+        if let Some(ref mut artifact) = self.artifact {
+            if let Some(ref mut url) = artifact.url {
+                interp::expand_in(url, expand)?;
+            }
+
+            interp::expand_in_path(&mut artifact.dest, expand)?;
+
+            if let Some(ref mut extract_dir) = artifact.extract_dir {
+                interp::expand_in_path(extract_dir, expand)?;
+            }
+
+            if let Some(ref mut junction) = artifact.junction {
+                interp::expand_in_path(junction, expand)?;
+            }
+        }
+
+        if let Some(ref mut other) = self.other {
+            other.expand_values(expand)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets the unset value from the one of the extra configurations of the service, or else
+/// from the global one. Returns true if the value is taken from the global one.
+fn default_from<T: Clone>(value: &mut Option<T>, other: &Option<T>, global: &Option<T>) -> bool {
+    if value.is_some() {
+        return false;
+    }
+
+    *value = other.clone();
+
+    if value.is_none() {
+        *value = global.clone();
+        return value.is_some();
+    }
+
+    false
+}
+
+/// Holds the nssm release downloaded by the `bootstrap` subcommand.
+#[derive(Clone, Deserialize)]
+pub struct Bootstrap {
+    /// URL of the nssm release zip archive. Defaults to the nssm 2.24 release.
+    pub url: Option<String>,
+
+    /// Expected SHA-256 checksum in hex of the archive at the URL. Required if the URL
+    /// is set.
+    pub sha256: Option<String>,
+}
+
+/// Holds the settings of the HTTP API served by the `serve` subcommand, which also apply
+/// to the gRPC interface served by the `grpc` subcommand.
+#[derive(Clone, Deserialize)]
+pub struct Serve {
+    /// Address to listen on. Defaults to "127.0.0.1:8080".
+    pub address: Option<String>,
+
+    /// Address to listen on with the `grpc` subcommand. Defaults to "127.0.0.1:50051".
+    pub grpc_address: Option<String>,
+
+    /// Shared token that every request has to pass in the `X-Auth-Token` header.
+    pub token: Option<String>,
+
+    /// Name of the environment variable to read the shared token from instead.
+    pub token_env: Option<String>,
+}
+
+/// Holds the notifications sent when a run that modifies the services has completed or
+/// failed.
+#[derive(Clone, Deserialize)]
+pub struct Notify {
+    /// Holds the settings of the summary email.
+    pub email: Option<EmailNotify>,
+
+    /// Holds the settings of the webhook message, e.g. to a Slack or Teams channel.
+    pub webhook: Option<WebhookNotify>,
+}
+
+/// Holds the settings of the summary email with the outcome of every service, which is sent
+/// through PowerShell's `Send-MailMessage`.
+#[derive(Clone, Deserialize)]
+pub struct EmailNotify {
+    /// Host name or address of the SMTP server.
+    pub smtp_server: String,
+
+    /// Port of the SMTP server. Defaults to 25.
+    pub smtp_port: Option<u16>,
+
+    /// Uses STARTTLS to connect to the SMTP server if true. Defaults to false.
+    pub use_tls: Option<bool>,
+
+    /// Sender address.
+    pub from: String,
+
+    /// Recipient addresses.
+    pub to: Vec<String>,
+
+    /// User name to authenticate to the SMTP server with. Sent without authentication if
+    /// not set.
+    pub username: Option<String>,
+
+    /// Password to authenticate to the SMTP server with.
+    pub password: Option<String>,
+
+    /// Name of the environment variable to read the password from instead.
+    pub password_env: Option<String>,
+
+    /// Only sends the email if the run has failed. Defaults to false.
+    pub only_on_failure: Option<bool>,
+}
+
+/// Holds the settings of the webhook message summarizing the run, which is posted as JSON.
+#[derive(Clone, Deserialize)]
+pub struct WebhookNotify {
+    /// URL to post the message to, e.g. of a Slack or Teams incoming webhook.
+    pub url: Option<String>,
+
+    /// Name of the environment variable to read the URL from instead.
+    pub url_env: Option<String>,
+
+    /// JSON template of the message, which may reference the variables `status`,
+    /// `machine`, `summary`, `installed`, `started`, `stopped`, `removed`, `failed` and
+    /// `skipped` as `{{ name }}`. Defaults to `{"text": "{{ summary }}"}`.
+    pub template: Option<String>,
+
+    /// Only posts the message if the run has failed. Defaults to false.
+    pub only_on_failure: Option<bool>,
+}
+
+/// Holds the settings of exporting the Prometheus metrics of a run that modifies the
+/// services, either by pushing them to a Pushgateway or by serving them for a while.
+#[derive(Clone, Deserialize)]
+pub struct Metrics {
+    /// URL of the Pushgateway to push the metrics to at the end of the run, e.g.
+    /// "http://pushgateway:9091".
+    pub pushgateway_url: Option<String>,
+
+    /// Job label of the pushed metrics. Defaults to "nssm_exec".
+    pub job: Option<String>,
+
+    /// Address to serve the metrics on at `/metrics` at the end of the run, e.g.
+    /// "0.0.0.0:9184".
+    pub listen_address: Option<String>,
+
+    /// Number of seconds to serve the metrics for before exiting. Defaults to 60.
+    pub listen_secs: Option<u64>,
+}
+
+/// Holds the settings of the agent that runs nssm_exec itself as a service.
+#[derive(Clone, Deserialize)]
+pub struct Agent {
+    /// Name of the agent service. Defaults to "nssm_exec_agent".
+    pub service_name: Option<String>,
+
+    /// Interval in seconds between reconciling the installed services against the
+    /// configuration. Defaults to 300.
+    pub interval_secs: Option<u64>,
+}
+
+/// Holds the values of a profile that override the ones in the base configuration when the
+/// profile is selected.
+#[derive(Deserialize)]
+pub struct Profile {
+    /// Overrides the NSSM executable file path.
+    pub nssm_path: Option<PathBuf>,
+
+    /// Overrides the interval in milliseconds before retrying to check if the service
+    /// has stopped.
+    pub pending_stop_poll_ms: Option<u64>,
+
+    /// Overrides the number of retries to check if the service has stopped.
+    pub pending_stop_poll_count: Option<u64>,
+
+    /// Overrides the interval in milliseconds before retrying to check if the service
+    /// has started.
+    pub pending_start_poll_ms: Option<u64>,
+
+    /// Overrides the number of retries to check if the service has started.
+    pub pending_start_poll_count: Option<u64>,
+
+    /// Overrides the number of services to process concurrently.
+    pub parallelism: Option<usize>,
+
+    /// Overrides the individual variables, keeping the other variables.
+    pub variables: Option<BTreeMap<String, String>>,
+
+    /// Overrides the individual global extra configurations, keeping the other ones.
+    pub global: Option<OtherConfig>,
+
+    /// Overrides the individual extra configurations of the services with the given names,
+    /// keeping the other ones.
+    pub services: Option<BTreeMap<String, OtherConfig>>,
+}
+
+/// Holds the account settings kept in a separate secrets file, which are merged over the
+/// account settings in the configuration when it is loaded.
+#[derive(Deserialize)]
+pub struct Secrets {
+    /// Account settings merged over the global account settings.
+    pub global: Option<AccountSecrets>,
+
+    /// Account settings merged over the account settings of the services with the given
+    /// names, as in `[services.<name>.other.account]`.
+    pub services: Option<BTreeMap<String, ServiceSecrets>>,
+}
+
+/// Holds the secret extra configurations of a service.
+#[derive(Deserialize)]
+pub struct ServiceSecrets {
+    /// Secret extra configurations of the service.
+    pub other: Option<AccountSecrets>,
+}
+
+/// Holds the secret account settings, which are the only extra configurations allowed in
+/// the secrets file.
+#[derive(Deserialize)]
+pub struct AccountSecrets {
+    /// Account settings to merge over the configured ones.
+    pub account: Option<Account>,
+}
+
+/// Merges the secret account settings over the account settings in the extra
+/// configurations.
+fn merge_account_secrets(other: &mut OtherConfig, secrets: AccountSecrets) {
+    if let Some(secret) = secrets.account {
+        match other.account {
+            Some(ref mut account) => account.merge_secret(secret),
+            None => other.account = Some(secret),
+        }
+    }
+}
+
+/// Represents the TOML nssm_exec configuration.
+#[derive(Deserialize)]
+pub struct FileConfig {
+    /// NSSM executable file path. Only required in the first of multiple configuration
+    /// files, which the later files may override.
+    #[serde(default)]
+    pub nssm_path: PathBuf,
+
+    /// WinSW executable file path, which is copied as the wrapper executable of every
+    /// service using the WinSW backend.
+    pub winsw_path: Option<PathBuf>,
+
+    /// Directory holding a subdirectory with the wrapper executable and the XML
+    /// configuration of every service using the WinSW backend.
+    /// Defaults to the `services` directory next to the WinSW executable.
+    pub winsw_dir: Option<PathBuf>,
+
+    /// shawl executable file path, which wraps the executable of every service using the
+    /// shawl backend.
+    pub shawl_path: Option<PathBuf>,
+
+    /// Directory holding the unit file of every service using the systemd backend.
+    /// Defaults to `/etc/systemd/system`.
+    pub systemd_unit_dir: Option<PathBuf>,
+
+    /// Backend of every service, overriding the backends of the services and the global
+    /// backend, e.g. `mock` to simulate the services without touching the machine.
+    pub backend: Option<Backend>,
+
+    /// Interval in milliseconds before retrying to check if the service has stopped.
+    /// Default is 500. Only applicable if there is any running existing service.
+    pub pending_stop_poll_ms: Option<u64>,
+
+    /// Number of retries to check if the service has stopped.
+    /// Default is 5. Only applicable if there is any running existing service.
+    pub pending_stop_poll_count: Option<u64>,
+
+    /// Interval in milliseconds before retrying to check if the service has started.
+    /// Default is 500. Only applicable if there is any running existing service.
+    pub pending_start_poll_ms: Option<u64>,
+
+    /// Number of retries to check if the service has started.
+    /// Default is 5. Only applicable if there is any running existing service.
+    pub pending_start_poll_count: Option<u64>,
+
+    /// States whether to only log the nssm commands that modify the services
+    /// without running them. Defaults to false.
+    pub dry_run: Option<bool>,
+
+    /// Number of services to process concurrently, at most 16, or 0 for the maximum of 16.
+    /// Services are always processed after the services they depend on. Defaults to 1.
+    pub parallelism: Option<usize>,
+
+    /// Number of hosts to process concurrently when the services are installed on remote
+    /// hosts, at most 16, or 0 for the maximum of 16. Defaults to 1.
+    pub host_parallelism: Option<usize>,
+
+    /// Path of the audit log file to append every command run to query or control the
+    /// services to, with the passwords replaced. Not written if not set.
+    pub audit_log: Option<PathBuf>,
+
+    /// States whether to write the application parameters of the nssm services directly
+    /// into the registry in a single pass, instead of running nssm once per parameter.
+    /// Defaults to false.
+    pub registry_direct: Option<bool>,
+
+    /// States whether to skip the remaining services after the first service that has
+    /// failed. Defaults to false.
+    pub fail_fast: Option<bool>,
+
+    /// States whether to recreate the existing services blue-green, i.e. to install and
+    /// start a copy of each service named with the `-green` suffix before replacing the
+    /// service, and to remove the copy after the service is replaced. Defaults to false.
+    pub blue_green: Option<bool>,
+
+    /// States whether to process the first service alone as a canary, e.g. the first of many
+    /// instances of the same application, and to skip the remaining services if it fails.
+    /// Defaults to false.
+    pub canary: Option<bool>,
+
+    /// States whether to leave the services that are already installed untouched, and to
+    /// only install the services that do not exist yet. Defaults to false.
+    pub skip_existing: Option<bool>,
+
+    /// Prefix prepended to the name and display name of every service, and to the
+    /// dependencies on the other services in the configuration, so that the same
+    /// configuration may be installed several times side by side, e.g. `blue-`.
+    pub name_prefix: Option<String>,
+
+    /// List of other configuration files to merge into this configuration, relative to the
+    /// directory containing this configuration file. Each entry may be a glob pattern,
+    /// e.g. `services/*.toml`. The global extra configurations of this configuration
+    /// override the included ones, and the included services are appended after the
+    /// services of this configuration.
+    pub include: Option<Vec<String>>,
+
+    /// States whether to fail on any key of the configuration files that no setting
+    /// matches, e.g. a misspelt setting, instead of only warning about it. Defaults to false.
+    pub strict: Option<bool>,
+
+    /// Holds the variables that may be referenced as `{{ name }}` in the paths, arguments,
+    /// names, descriptions and accounts of the configuration. Variables in the included
+    /// configuration files are also available, but this configuration overrides them.
+    pub variables: Option<BTreeMap<String, String>>,
+
+    /// Holds the named profiles, each of which overlays its values over this configuration
+    /// when selected.
+    pub profiles: Option<BTreeMap<String, Profile>>,
+
+    /// Holds the nssm release to download into `nssm_path` with the `bootstrap`
+    /// subcommand. Defaults to the nssm 2.24 release.
+    pub bootstrap: Option<Bootstrap>,
+
+    /// Holds the settings of the agent run by the `agent` subcommand.
+    pub agent: Option<Agent>,
+
+    /// Holds the settings of the HTTP API served by the `serve` subcommand.
+    pub serve: Option<Serve>,
+
+    /// Holds the notifications of the outcome of a run.
+    pub notify: Option<Notify>,
+
+    /// Holds the settings of exporting the Prometheus metrics of a run.
+    pub metrics: Option<Metrics>,
+
+    /// Holds the global extra configurations.
+    /// Any specific extra configurations will always override the global ones.
+    pub global: Option<OtherConfig>,
+
+    /// Holds the service configurations.
+    #[serde(default)]
+    pub services: Vec<Service>,
+
+    /// Collects the actions and outcome of every service if set, which is never read from
+    /// the configuration file.
+    #[serde(skip)]
+    pub run_report: Option<RunReport>,
+
+    /// Collects the commands of a dry run in order if set, which is never read from the
+    /// configuration file.
+    #[serde(skip)]
+    pub transcript: Option<Arc<Transcript>>,
+
+    /// Appends the commands run to the audit log if set, which is opened from `audit_log`
+    /// and never read from the configuration file.
+    #[serde(skip)]
+    pub audit: Option<Arc<AuditLog>>,
+
+    /// Records the commands run with their outputs if set, which is never read from the
+    /// configuration file.
+    #[serde(skip)]
+    pub session: Option<Arc<Session>>,
+
+    /// Version of the nssm executable detected at startup if set, which is never read from
+    /// the configuration file.
+    #[serde(skip)]
+    pub nssm_version: Option<NssmVersion>,
+
+    /// Remote host that the services are processed on if set, otherwise the local machine,
+    /// which is never read from the configuration file.
+    #[serde(skip)]
+    pub host: Option<String>,
+}
+
+/// Represents a configuration file included by another configuration file,
+/// which may only contain the global extra configurations and services.
+#[derive(Deserialize)]
+struct IncludedConfig {
+    include: Option<Vec<String>>,
+    variables: Option<BTreeMap<String, String>>,
+    global: Option<OtherConfig>,
+    #[serde(default)]
+    services: Vec<Service>,
+}
+
+impl FromStr for FileConfig {
+    type Err = Error;
+
+    /// Parses the TOML configuration content. Includes are only resolved by `from_path`.
+    fn from_str(s: &str) -> Result<FileConfig> {
+        let mut unknown_keys = Vec::new();
+
+        let mut file_config: FileConfig = strict::from_toml_str(s, &mut unknown_keys)
+            .chain_err(|| {
+                ErrorKind::ConfigParse(
+                    "Unable to interpret configuration file content as TOML".to_owned(),
+                )
+            })?;
+
+        file_config.check_unknown_keys(&describe_unknown_keys(&unknown_keys, None, Section::File))?;
+        file_config.resolve_values()?;
+        Ok(file_config)
+    }
+}
+
+impl FileConfig {
+    /// Reads and parses the configuration file at the given path, together with all the
+    /// configuration files it includes.
+    /// Files with `.yml` or `.yaml` extension are parsed as YAML, otherwise as TOML.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<FileConfig> {
+        FileConfig::from_path_with_profile(path, None)
+    }
+
+    /// Reads and parses the configuration file at the given path like `from_path`, and then
+    /// overlays the values of the given profile, if any, over the base configuration.
+    /// Returns error if the profile is not found in the configuration.
+    pub fn from_path_with_profile<P: AsRef<Path>>(
+        path: P,
+        profile: Option<&str>,
+    ) -> Result<FileConfig> {
+        FileConfig::from_path_with_secrets(path, profile, None)
+    }
+
+    /// Reads and parses the configuration file at the given path like
+    /// `from_path_with_profile`, and then merges the account settings of the secrets file at
+    /// the given path, if any, over the configured ones.
+    /// Returns error if any service of the secrets file is not found in the configuration.
+    pub fn from_path_with_secrets<P: AsRef<Path>>(
+        path: P,
+        profile: Option<&str>,
+        secrets_path: Option<&Path>,
+    ) -> Result<FileConfig> {
+        FileConfig::from_paths_with_secrets(&[path], profile, secrets_path, None)
+    }
+
+    /// Reads and parses the configuration files at the given paths like
+    /// `from_path_with_secrets`, merging every file over the files before it. The global
+    /// extra configurations are merged individually, services with the same name are
+    /// replaced, and the other values are overridden if set in the later file.
+    /// A path to a directory is read like the `*.toml` files inside in lexical order, except
+    /// that their services must have distinct names. The given name prefix, if any, overrides
+    /// the configured one.
+    /// Returns error if no path is given.
+    pub fn from_paths_with_secrets<P: AsRef<Path>>(
+        paths: &[P],
+        profile: Option<&str>,
+        secrets_path: Option<&Path>,
+        name_prefix: Option<&str>,
+    ) -> Result<FileConfig> {
+        let mut included_paths = Vec::new();
+        let mut unknown_keys = Vec::new();
+        let mut merged_config: Option<FileConfig> = None;
+
+        for path in paths {
+            let path = path.as_ref();
+
+            let file_config = if path.is_dir() {
+                FileConfig::read_dir(path, &mut included_paths, &mut unknown_keys)?
+            } else {
+                FileConfig::read_with_includes(path, &mut included_paths, &mut unknown_keys)?
+            };
+
+            merged_config = Some(match merged_config {
+                Some(merged_config) => file_config.or(merged_config),
+                None => file_config,
+            });
+        }
+
+        let mut file_config = match merged_config {
+            Some(file_config) => file_config,
+            None => bail!("No configuration file is given"),
+        };
+
+        if let Some(profile) = profile {
+            file_config.apply_profile(profile)?;
+        }
+
+        if let Some(secrets_path) = secrets_path {
+            let secrets = read_config_file(secrets_path, Section::Secrets, &mut unknown_keys)?;
+            file_config.apply_secrets(secrets)?;
+        }
+
+        let mut names = HashSet::new();
+
+        if let Some(service) = file_config.services.iter().find(|service| {
+            !names.insert(&service.name)
+        })
+        {
+            bail!(
+                "Service '{}' is configured more than once in the configuration",
+                service.name
+            );
+        }
+
+        if let Some(name_prefix) = name_prefix {
+            file_config.name_prefix = Some(name_prefix.to_owned());
+        }
+
+        file_config.check_unknown_keys(&unknown_keys)?;
+        file_config.resolve_values()?;
+        Ok(file_config)
+    }
+
+    fn read_with_includes(
+        path: &Path,
+        included_paths: &mut Vec<PathBuf>,
+        unknown_keys: &mut Vec<String>,
+    ) -> Result<FileConfig> {
+        let canonical_path = canonical_path(path)?;
+
+        if included_paths.contains(&canonical_path) {
+            bail!(
+                "Configuration file at '{}' is given more than once",
+                path.to_string_lossy()
+            );
+        }
+
+        included_paths.push(canonical_path);
+
+        let mut file_config: FileConfig = read_config_file(path, Section::File, unknown_keys)?;
+
+        if let Some(patterns) = file_config.include.take() {
+            file_config.merge_includes(path, &patterns, included_paths, unknown_keys)?;
+        }
+
+        Ok(file_config)
+    }
+
+    fn read_dir(
+        dir: &Path,
+        included_paths: &mut Vec<PathBuf>,
+        unknown_keys: &mut Vec<String>,
+    ) -> Result<FileConfig> {
+        let pattern = dir.join("*.toml");
+
+        let matches = glob::glob(&pattern.to_string_lossy()).chain_err(|| {
+            format!(
+                "Unable to list configuration files in directory at '{}'",
+                dir.to_string_lossy()
+            )
+        })?;
+
+        let mut merged_config: Option<FileConfig> = None;
+
+        for path in matches {
+            let path = path.chain_err(|| {
+                format!(
+                    "Unable to read configuration file path in directory at '{}'",
+                    dir.to_string_lossy()
+                )
+            })?;
+
+            let file_config = FileConfig::read_with_includes(&path, included_paths, unknown_keys)?;
+
+            merged_config = Some(match merged_config {
+                Some(merged_config) => {
+                    if let Some(service) = file_config.services.iter().find(|service| {
+                        merged_config
+                            .services
+                            .iter()
+                            .any(|existing| existing.name == service.name)
+                    })
+                    {
+                        bail!(
+                            "Service '{}' of configuration file at '{}' is already configured \
+                             in another file of the directory",
+                            service.name,
+                            path.to_string_lossy()
+                        );
+                    }
+
+                    file_config.or(merged_config)
+                }
+                None => file_config,
+            });
+        }
+
+        match merged_config {
+            Some(file_config) => Ok(file_config),
+            None => bail!(
+                "Unable to find any configuration file in directory at '{}'",
+                dir.to_string_lossy()
+            ),
+        }
+    }
+
+    /// Merges the configuration over the given fallback configuration. The global extra
+    /// configurations are merged individually, the variables and profiles by name, and the
+    /// services with the same name as any own service are replaced in place, while the
+    /// other own services are appended.
+    fn or(self, fallback: FileConfig) -> FileConfig {
+        let nssm_path = if self.nssm_path.as_os_str().is_empty() {
+            fallback.nssm_path
+        } else {
+            self.nssm_path
+        };
+
+        let variables = match (fallback.variables, self.variables) {
+            (Some(mut variables), Some(own_variables)) => {
+                variables.extend(own_variables);
+                Some(variables)
+            }
+            (variables, own_variables) => own_variables.or(variables),
+        };
+
+        let profiles = match (fallback.profiles, self.profiles) {
+            (Some(mut profiles), Some(own_profiles)) => {
+                profiles.extend(own_profiles);
+                Some(profiles)
+            }
+            (profiles, own_profiles) => own_profiles.or(profiles),
+        };
+
+        let mut services = fallback.services;
+
+        for own_service in self.services {
+            match services
+                .iter()
+                .position(|service| service.name == own_service.name)
+            {
+                Some(index) => services[index] = own_service,
+                None => services.push(own_service),
+            }
+        }
+
+        FileConfig {
+            nssm_path,
+            winsw_path: self.winsw_path.or(fallback.winsw_path),
+            winsw_dir: self.winsw_dir.or(fallback.winsw_dir),
+            shawl_path: self.shawl_path.or(fallback.shawl_path),
+            systemd_unit_dir: self.systemd_unit_dir.or(fallback.systemd_unit_dir),
+            backend: self.backend.or(fallback.backend),
+            pending_stop_poll_ms: self.pending_stop_poll_ms.or(fallback.pending_stop_poll_ms),
+            pending_stop_poll_count: self.pending_stop_poll_count
+                .or(fallback.pending_stop_poll_count),
+            pending_start_poll_ms: self.pending_start_poll_ms.or(fallback.pending_start_poll_ms),
+            pending_start_poll_count: self.pending_start_poll_count
+                .or(fallback.pending_start_poll_count),
+            dry_run: self.dry_run.or(fallback.dry_run),
+            parallelism: self.parallelism.or(fallback.parallelism),
+            host_parallelism: self.host_parallelism.or(fallback.host_parallelism),
+            audit_log: self.audit_log.or(fallback.audit_log),
+            registry_direct: self.registry_direct.or(fallback.registry_direct),
+            fail_fast: self.fail_fast.or(fallback.fail_fast),
+            blue_green: self.blue_green.or(fallback.blue_green),
+            canary: self.canary.or(fallback.canary),
+            skip_existing: self.skip_existing.or(fallback.skip_existing),
+            include: None,
+            name_prefix: self.name_prefix.or(fallback.name_prefix),
+            strict: self.strict.or(fallback.strict),
+            variables,
+            profiles,
+            bootstrap: self.bootstrap.or(fallback.bootstrap),
+            agent: self.agent.or(fallback.agent),
+            serve: self.serve.or(fallback.serve),
+            notify: self.notify.or(fallback.notify),
+            metrics: self.metrics.or(fallback.metrics),
+            global: or_other_conf(self.global, fallback.global),
+            services,
+            run_report: None,
+            transcript: None,
+            audit: None,
+            session: None,
+            nssm_version: None,
+            host: None,
+        }
+    }
+
+    fn apply_profile(&mut self, name: &str) -> Result<()> {
+        let profile = match self.profiles.as_mut().and_then(|profiles| profiles.remove(name)) {
+            Some(profile) => profile,
+            None => bail!("Unable to find profile '{}' in the configuration", name),
+        };
+
+        if let Some(nssm_path) = profile.nssm_path {
+            self.nssm_path = nssm_path;
+        }
+
+        self.pending_stop_poll_ms = profile.pending_stop_poll_ms.or(self.pending_stop_poll_ms);
+        self.pending_stop_poll_count = profile
+            .pending_stop_poll_count
+            .or(self.pending_stop_poll_count);
+        self.pending_start_poll_ms = profile.pending_start_poll_ms.or(self.pending_start_poll_ms);
+        self.pending_start_poll_count = profile
+            .pending_start_poll_count
+            .or(self.pending_start_poll_count);
+        self.parallelism = profile.parallelism.or(self.parallelism);
+
+        if let Some(variables) = profile.variables {
+            self.variables
+                .get_or_insert_with(BTreeMap::new)
+                .extend(variables);
+        }
+
+        self.global = or_other_conf(profile.global, self.global.take());
+
+        for (service_name, other) in profile.services.into_iter().flatten() {
+            let service = match self.services
+                .iter_mut()
+                .find(|service| service.name == service_name)
+            {
+                Some(service) => service,
+                None => bail!(
+                    "Unable to find service '{}' of profile '{}' in the configuration",
+                    service_name,
+                    name
+                ),
+            };
+
+            service.other = or_other_conf(Some(other), service.other.take());
+        }
+
+        Ok(())
+    }
+
+    fn apply_secrets(&mut self, secrets: Secrets) -> Result<()> {
+        if let Some(global_secrets) = secrets.global {
+            merge_account_secrets(
+                self.global.get_or_insert_with(OtherConfig::default),
+                global_secrets,
+            );
+        }
+
+        let services_secrets = secrets.services.into_iter().flatten();
+
+        for (service_name, service_secrets) in services_secrets {
+            let service = match self.services
+                .iter_mut()
+                .find(|service| service.name == service_name)
+            {
+                Some(service) => service,
+                None => bail!(
+                    "Unable to find service '{}' of the secrets in the configuration",
+                    service_name
+                ),
+            };
+
+            if let Some(other_secrets) = service_secrets.other {
+                merge_account_secrets(
+                    service.other.get_or_insert_with(OtherConfig::default),
+                    other_secrets,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prepends the name prefix, if any, to the names of the services and to the dependencies
+    /// on them.
+    fn apply_name_prefix(&mut self) {
+        let name_prefix = match self.name_prefix {
+            Some(ref name_prefix) if !name_prefix.is_empty() => name_prefix.clone(),
+            _ => return,
+        };
+
+        let names: HashSet<String> = self.services
+            .iter()
+            .map(|service| service.name.clone())
+            .collect();
+
+        if let Some(deps) = self.global.as_mut().and_then(|global| global.deps.as_mut()) {
+            deps.prefix(&name_prefix, &names);
+        }
+
+        for service in &mut self.services {
+            service.prefix_names(&name_prefix, &names);
+        }
+    }
+
+    fn resolve_values(&mut self) -> Result<()> {
+        if self.nssm_path.as_os_str().is_empty() {
+            bail!("NSSM executable file path is not configured");
+        }
+
+        self.apply_name_prefix();
+
+        let global = self.global.clone().unwrap_or_default();
+
+        for service in &mut self.services {
+            service.apply_defaults(&global);
+        }
+
+        self.expand_values()?;
+
+        for account in self.accounts_mut() {
+            account.resolve_password()?;
+        }
+
+        if let Some(service) = self.services.iter().find(|service| {
+            !service.is_on_host(None) && self.backend(&service.name) != Backend::Nssm
+        }) {
+            bail!(
+                "Service '{}' is installed on remote hosts, which is only supported by the nssm \
+                 backend",
+                service.name
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Prompts on the console, without echoing the input, for the password of every account
+    /// whose password is not provided in the configuration. Accounts with the same username
+    /// are only prompted once.
+    pub fn prompt_passwords(&mut self) -> Result<()> {
+        let mut passwords: BTreeMap<String, String> = BTreeMap::new();
+
+        for account in self.accounts_mut() {
+            if !account.password.is_empty() || !account.takes_password() {
+                continue;
+            }
+
+            if let Some(password) = passwords.get(&account.user) {
+                account.password = password.clone();
+                continue;
+            }
+
+            account.password =
+                rpassword::prompt_password_stderr(&format!("Password for '{}': ", account.user))
+                    .chain_err(|| {
+                        format!("Unable to prompt for password of account '{}'", account.user)
+                    })?;
+
+            passwords.insert(account.user.clone(), account.password.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the passwords of every account, e.g. to keep them out of transcripts.
+    pub fn passwords(&self) -> Vec<String> {
+        let global_accounts = self.global
+            .iter()
+            .filter_map(|global| global.account.as_ref());
+
+        let service_accounts = self.services
+            .iter()
+            .filter_map(|service| service.other.as_ref())
+            .filter_map(|other| other.account.as_ref());
+
+        global_accounts
+            .chain(service_accounts)
+            .map(|account| account.password.clone())
+            .filter(|password| !password.is_empty())
+            .collect()
+    }
+
+    fn accounts_mut(&mut self) -> Vec<&mut Account> {
+        let global_accounts = self.global
+            .iter_mut()
+            .filter_map(|global| global.account.as_mut());
+
+        let service_accounts = self.services
+            .iter_mut()
+            .filter_map(|service| service.other.as_mut())
+            .filter_map(|other| other.account.as_mut());
+
+        global_accounts.chain(service_accounts).collect()
+    }
+
+    /// Expands every `${NAME}` environment variable reference and renders every
+    /// `{{ name }}` variable reference in a single pass in the paths, arguments, names,
+    /// descriptions and accounts of the configuration. The variable values may also reference
+    /// environment variables.
+    fn expand_values(&mut self) -> Result<()> {
+        let mut variables = BTreeMap::new();
+
+        if let Some(ref config_variables) = self.variables {
+            for (name, value) in config_variables {
+                let value = interp::expand_env_vars(value).chain_err(|| {
+                    format!("Unable to expand environment variables of variable '{}'", name)
+                })?;
+
+                variables.insert(name.clone(), value);
+            }
+        }
+
+        let expand = |value: &str| interp::interpolate_env(value, &variables);
+
+        interp::expand_in_path(&mut self.nssm_path, &expand)
+            .chain_err(|| "Unable to expand variables of nssm path")?;
+
+        if let Some(ref mut winsw_path) = self.winsw_path {
+            interp::expand_in_path(winsw_path, &expand)
+                .chain_err(|| "Unable to expand variables of WinSW path")?;
+        }
+
+        if let Some(ref mut winsw_dir) = self.winsw_dir {
+            interp::expand_in_path(winsw_dir, &expand)
+                .chain_err(|| "Unable to expand variables of WinSW directory")?;
+        }
+
+        if let Some(ref mut shawl_path) = self.shawl_path {
+            interp::expand_in_path(shawl_path, &expand)
+                .chain_err(|| "Unable to expand variables of shawl path")?;
+        }
+
+        if let Some(ref mut systemd_unit_dir) = self.systemd_unit_dir {
+            interp::expand_in_path(systemd_unit_dir, &expand)
+                .chain_err(|| "Unable to expand variables of systemd unit directory")?;
+        }
+
+        if let Some(ref mut global) = self.global {
+            global.expand_values(&expand).chain_err(
+                || "Unable to expand variables of global configuration",
+            )?;
+        }
+
+        for service in &mut self.services {
+            let mut service_variables = variables.clone();
+
+            service_variables
+                .entry(SERVICE_NAME_VARIABLE.to_owned())
+                .or_insert_with(|| service.name.clone());
+
+            let expand = |value: &str| interp::interpolate_env(value, &service_variables);
+
+            service.expand_values(&expand).chain_err(|| {
+                format!("Unable to expand variables of service '{}'", service.name)
+            })?;
+
+            service.render_name();
+        }
+
+        Ok(())
+    }
+
+    fn merge_includes(
+        &mut self,
+        path: &Path,
+        patterns: &[String],
+        included_paths: &mut Vec<PathBuf>,
+        unknown_keys: &mut Vec<String>,
+    ) -> Result<()> {
+        for include_path in resolve_includes(path, patterns)? {
+            let canonical_include_path = canonical_path(&include_path)?;
+
+            if included_paths.contains(&canonical_include_path) {
+                bail!(
+                    "Configuration file at '{}' is included more than once",
+                    include_path.to_string_lossy()
+                );
+            }
+
+            included_paths.push(canonical_include_path);
+
+            let included: IncludedConfig =
+                read_config_file(&include_path, Section::Included, unknown_keys)?;
+
+            self.global = or_other_conf(self.global.take(), included.global);
+
+            if let Some(included_variables) = included.variables {
+                let variables = self.variables.get_or_insert_with(BTreeMap::new);
+
+                for (name, value) in included_variables {
+                    variables.entry(name).or_insert(value);
+                }
+            }
+
+            self.services.extend(included.services);
+
+            if let Some(patterns) = included.include {
+                self.merge_includes(&include_path, &patterns, included_paths, unknown_keys)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses the YAML configuration content. Includes are only resolved by `from_path`.
+    pub fn from_yaml_str(s: &str) -> Result<FileConfig> {
+        let mut unknown_keys = Vec::new();
+
+        let mut file_config: FileConfig = strict::from_yaml_str(s, &mut unknown_keys)
+            .chain_err(|| {
+                ErrorKind::ConfigParse(
+                    "Unable to interpret configuration file content as YAML".to_owned(),
+                )
+            })?;
+
+        file_config.check_unknown_keys(&describe_unknown_keys(&unknown_keys, None, Section::File))?;
+        file_config.resolve_values()?;
+        Ok(file_config)
+    }
+
+    /// Fails if strict parsing is enabled and any of the given descriptions of the unknown
+    /// keys of the configuration files exists, or only warns about each of them otherwise.
+    fn check_unknown_keys(&self, unknown_keys: &[String]) -> Result<()> {
+        if unknown_keys.is_empty() {
+            return Ok(());
+        }
+
+        if self.strict == Some(true) {
+            bail!(ErrorKind::ConfigParse(format!(
+                "Configuration has {} unknown key(s): {}",
+                unknown_keys.len(),
+                unknown_keys.join("; ")
+            )));
+        }
+
+        for unknown_key in unknown_keys {
+            warn!("{}", unknown_key);
+        }
+
+        Ok(())
+    }
+
+    /// Keeps only the services with the given names, in the original configuration order.
+    /// The names may also be given without the name prefix.
+    /// Returns error if any of the names is not found in the configuration.
+    pub fn retain_services(&mut self, names: &[String]) -> Result<()> {
+        let names: Vec<String> = names.iter().map(|name| self.prefixed_name(name)).collect();
+        if let Some(name) = names.iter().find(|name| {
+            !self.services.iter().any(|service| service.name == **name)
+        })
+        {
+            bail!("Unable to find service '{}' in the configuration", name);
+        }
+
+        self.services.retain(|service| names.contains(&service.name));
+        Ok(())
+    }
+
+    /// Returns the name of the configured service with the given name, which may be given
+    /// without the name prefix.
+    pub fn prefixed_name(&self, name: &str) -> String {
+        let is_configured = |name: &str| self.services.iter().any(|service| service.name == name);
+
+        match self.name_prefix {
+            Some(ref name_prefix) if !is_configured(name) => {
+                let prefixed_name = format!("{}{}", name_prefix, name);
+
+                if is_configured(&prefixed_name) {
+                    prefixed_name
+                } else {
+                    name.to_owned()
+                }
+            }
+
+            _ => name.to_owned(),
+        }
+    }
+
+    /// Merges the extra configurations of the service over the global ones,
+    /// prioritizing the specific ones if available individually.
+    pub fn merged_other<'a>(&'a self, service: &'a Service) -> OtherConfigRef<'a> {
+        OtherConfigRef {
+            deps: merge_other_conf(&service.other, &self.global, |other| other.deps.as_ref()),
+            start_on_create: merge_other_conf(&service.other, &self.global, |other| {
+                other.start_on_create.as_ref()
+            }),
+            account: merge_other_conf(&service.other, &self.global, |other| {
+                other.account.as_ref()
+            }),
+            throttle_ms: merge_other_conf(&service.other, &self.global, |other| {
+                other.throttle_ms.as_ref()
+            }),
+            restart_delay_ms: merge_other_conf(&service.other, &self.global, |other| {
+                other.restart_delay_ms.as_ref()
+            }),
+            stop_method: or_stop_method(
+                service.other.as_ref().and_then(|other| other.stop_method),
+                self.global.as_ref().and_then(|global| global.stop_method),
+            ),
+            no_console: merge_other_conf(&service.other, &self.global, |other| {
+                other.no_console.as_ref()
+            }),
+            dep_groups: merge_other_conf(&service.other, &self.global, |other| {
+                other.dep_groups.as_ref()
+            }),
+            backend: merge_other_conf(&service.other, &self.global, |other| {
+                other.backend.as_ref()
+            }),
+            pre_install: merge_other_conf(&service.other, &self.global, |other| {
+                other.pre_install.as_ref()
+            }),
+            post_install: merge_other_conf(&service.other, &self.global, |other| {
+                other.post_install.as_ref()
+            }),
+            pre_stop: merge_other_conf(&service.other, &self.global, |other| {
+                other.pre_stop.as_ref()
+            }),
+            post_start: merge_other_conf(&service.other, &self.global, |other| {
+                other.post_start.as_ref()
+            }),
+            command_timeout_secs: merge_other_conf(&service.other, &self.global, |other| {
+                other.command_timeout_secs.as_ref()
+            }),
+            command_retries: merge_other_conf(&service.other, &self.global, |other| {
+                other.command_retries.as_ref()
+            }),
+            command_retry_delay_ms: merge_other_conf(&service.other, &self.global, |other| {
+                other.command_retry_delay_ms.as_ref()
+            }),
+            grant_log_access: merge_other_conf(&service.other, &self.global, |other| {
+                other.grant_log_access.as_ref()
+            }),
+        }
+    }
+
+    /// Merges the log rotation settings of the service over the ones in its extra
+    /// configurations and then over the global ones, prioritizing the specific ones if
+    /// available individually.
+    pub fn merged_log_rotation(&self, service: &Service) -> LogRotation {
+        let other_log_rotation = |other: &Option<OtherConfig>| {
+            other
+                .as_ref()
+                .and_then(|other| other.log_rotation)
+                .unwrap_or_default()
+        };
+
+        service
+            .log_rotation
+            .unwrap_or_default()
+            .or(other_log_rotation(&service.other))
+            .or(other_log_rotation(&self.global))
+    }
+
+    /// Returns the overriding backend if set, or the backend of the service with the given
+    /// name, falling back to the global backend for services outside of the configuration,
+    /// and then to the default backend.
+    pub fn backend(&self, service_name: &str) -> Backend {
+        if let Some(backend) = self.backend {
+            return backend;
+        }
+
+        let global_backend = self.global.as_ref().and_then(|global| global.backend);
+
+        match self.services.iter().find(|service| service.name == service_name) {
+            Some(service) => self.merged_other(service)
+                .backend
+                .cloned()
+                .unwrap_or_else(|| self.default_backend(service.is_on_host(None))),
+            None => global_backend.unwrap_or_else(|| self.default_backend(true)),
+        }
+    }
+
+    /// Returns the timeout of the commands run for the service with the given name, falling
+    /// back to the global timeout for services outside of the configuration, or for commands
+    /// not run for any service. `None` if the commands never time out.
+    pub fn command_timeout(&self, service_name: Option<&str>) -> Option<Duration> {
+        self.other_setting(service_name, |other| other.command_timeout_secs.as_ref())
+            .map(Duration::from_secs)
+    }
+
+    /// Returns the number of times to retry the failed nssm commands of the service with the
+    /// given name, falling back to the global setting like `command_timeout`.
+    pub fn command_retries(&self, service_name: Option<&str>) -> u32 {
+        self.other_setting(service_name, |other| other.command_retries.as_ref())
+            .unwrap_or(0)
+    }
+
+    /// Returns the delay before retrying a failed nssm command of the service with the given
+    /// name, falling back to the global setting like `command_timeout`.
+    pub fn command_retry_delay(&self, service_name: Option<&str>) -> Duration {
+        Duration::from_millis(
+            self.other_setting(service_name, |other| other.command_retry_delay_ms.as_ref())
+                .unwrap_or(COMMAND_RETRY_DELAY_DEFAULT_MS),
+        )
+    }
+
+    fn other_setting<T, F>(&self, service_name: Option<&str>, chooser: F) -> Option<T>
+    where
+        T: Clone,
+        F: Fn(&OtherConfig) -> Option<&T>,
+    {
+        let service = service_name.and_then(|service_name| {
+            self.services
+                .iter()
+                .find(|service| service.name == service_name)
+        });
+
+        match service {
+            Some(service) => merge_other_conf(&service.other, &self.global, chooser).cloned(),
+            None => self.global.as_ref().and_then(chooser).cloned(),
+        }
+    }
+
+    /// Returns systemd for the services on the local machine when running on Linux, so that
+    /// the same configuration drives both platforms, and nssm otherwise, since the remote
+    /// hosts are reached through PowerShell remoting.
+    fn default_backend(&self, is_local: bool) -> Backend {
+        if cfg!(target_os = "linux") && is_local && self.host.is_none() {
+            Backend::Systemd
+        } else {
+            Backend::Nssm
+        }
+    }
+
+    /// Returns true if any service in the configuration uses the given backend.
+    pub fn uses_backend(&self, backend: Backend) -> bool {
+        self.services
+            .iter()
+            .any(|service| self.backend(&service.name) == backend)
+    }
+
+    /// Returns true if dry run is enabled.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.unwrap_or(false)
+    }
+
+    /// Returns true if registry direct mode is enabled.
+    pub fn is_registry_direct(&self) -> bool {
+        // the registry of remote hosts is not written directly
+        self.host.is_none() && self.registry_direct.unwrap_or(false)
+    }
+
+    /// Returns true if fail fast is enabled.
+    pub fn is_fail_fast(&self) -> bool {
+        self.fail_fast.unwrap_or(false)
+    }
+
+    /// Returns true if blue-green mode is enabled.
+    pub fn is_blue_green(&self) -> bool {
+        self.blue_green.unwrap_or(false)
+    }
+
+    /// Returns true if the services that are already installed are left untouched.
+    pub fn is_skip_existing(&self) -> bool {
+        self.skip_existing.unwrap_or(false)
+    }
+
+    /// Returns true if the first service is processed as a canary.
+    pub fn is_canary(&self) -> bool {
+        self.canary.unwrap_or(false)
+    }
+
+    /// Returns the number of services to process concurrently, which is between 1 and
+    /// `MAX_PARALLELISM`, and the maximum if configured as 0.
+    pub fn parallelism(&self) -> usize {
+        bounded_parallelism(self.parallelism)
+    }
+
+    /// Returns the number of hosts to process concurrently, which is between 1 and
+    /// `MAX_PARALLELISM`, and the maximum if configured as 0.
+    pub fn host_parallelism(&self) -> usize {
+        bounded_parallelism(self.host_parallelism)
+    }
+
+    /// Returns the distinct hosts of the services in the configuration order, where `None`
+    /// stands for the local machine and comes first if any service is installed locally.
+    pub fn hosts(&self) -> Vec<Option<String>> {
+        let mut hosts = Vec::new();
+
+        if self.services.iter().any(|service| service.is_on_host(None)) {
+            hosts.push(None);
+        }
+
+        for service_hosts in self.services.iter().filter_map(|service| service.hosts.as_ref()) {
+            for host in service_hosts {
+                if !hosts.iter().any(|existing| existing.as_ref() == Some(host)) {
+                    hosts.push(Some(host.clone()));
+                }
+            }
+        }
+
+        hosts
+    }
+
+    /// Returns a copy of the configuration that processes only the services installed on
+    /// the given remote host, or on the local machine if no host is given, with a fresh run
+    /// report if this configuration collects one.
+    pub fn for_host(&self, host: Option<&str>) -> FileConfig {
+        FileConfig {
+            nssm_path: self.nssm_path.clone(),
+            winsw_path: self.winsw_path.clone(),
+            winsw_dir: self.winsw_dir.clone(),
+            shawl_path: self.shawl_path.clone(),
+            systemd_unit_dir: self.systemd_unit_dir.clone(),
+            backend: self.backend,
+            pending_stop_poll_ms: self.pending_stop_poll_ms,
+            pending_stop_poll_count: self.pending_stop_poll_count,
+            pending_start_poll_ms: self.pending_start_poll_ms,
+            pending_start_poll_count: self.pending_start_poll_count,
+            dry_run: self.dry_run,
+            parallelism: self.parallelism,
+            host_parallelism: self.host_parallelism,
+            audit_log: self.audit_log.clone(),
+            registry_direct: self.registry_direct,
+            fail_fast: self.fail_fast,
+            blue_green: self.blue_green,
+            canary: self.canary,
+            skip_existing: self.skip_existing,
+            include: self.include.clone(),
+            name_prefix: self.name_prefix.clone(),
+            strict: self.strict,
+            variables: self.variables.clone(),
+            profiles: None,
+            bootstrap: self.bootstrap.clone(),
+            agent: self.agent.clone(),
+            serve: self.serve.clone(),
+            notify: self.notify.clone(),
+            metrics: self.metrics.clone(),
+            global: self.global.clone(),
+            services: self.services
+                .iter()
+                .filter(|service| service.is_on_host(host))
+                .cloned()
+                .collect(),
+            run_report: self.run_report.as_ref().map(|_| RunReport::default()),
+            transcript: self.transcript.clone(),
+            audit: self.audit.clone(),
+            session: self.session.clone(),
+            nssm_version: self.nssm_version,
+            host: host.map(|host| host.to_owned()),
+        }
+    }
+
+    /// Returns the stop poll interval, falling back to the default if not configured.
+    pub fn pending_stop_poll_interval(&self) -> Duration {
+        Duration::from_millis(self.pending_stop_poll_ms.unwrap_or(
+            PENDING_POLL_DEFAULT_MS,
+        ))
+    }
+
+    /// Returns the stop poll count, falling back to the default if not configured.
+    pub fn pending_stop_poll_count(&self) -> u64 {
+        self.pending_stop_poll_count.unwrap_or(
+            PENDING_POLL_DEFAULT_COUNT,
+        )
+    }
+
+    /// Returns the start poll interval, falling back to the default if not configured.
+    pub fn pending_start_poll_interval(&self) -> Duration {
+        Duration::from_millis(self.pending_start_poll_ms.unwrap_or(
+            PENDING_POLL_DEFAULT_MS,
+        ))
+    }
+
+    /// Returns the start poll count, falling back to the default if not configured.
+    pub fn pending_start_poll_count(&self) -> u64 {
+        self.pending_start_poll_count.unwrap_or(
+            PENDING_POLL_DEFAULT_COUNT,
+        )
+    }
+}
+
+/// Represents the section of a configuration file that a key belongs to, i.e. the
+/// configuration struct or the collection of structs holding it.
+#[derive(Clone, Copy)]
+enum Section {
+    File,
+    Included,
+    Secrets,
+    ServiceSecrets,
+    AccountSecrets,
+    Profile,
+    Service,
+    Other,
+    Account,
+    LogRotation,
+    StopMethod,
+    Hooks,
+    Health,
+    DrainCheck,
+    GreenCopy,
+    Firewall,
+    Recovery,
+    Artifact,
+    Bootstrap,
+    Agent,
+    Serve,
+    Notify,
+    EmailNotify,
+    WebhookNotify,
+    Metrics,
+
+    /// Services in order, or by name in the secrets file.
+    Services,
+    SecretServices,
+
+    /// Profiles by name.
+    Profiles,
+
+    /// Extra configurations of the services by name in a profile.
+    ProfileServices,
+}
+
+impl Section {
+    /// Returns the names of the settings of the section, or none for a collection, whose
+    /// keys are indices or names.
+    fn keys(self) -> &'static [&'static str] {
+        match self {
+            Section::File => strict::fields::<FileConfig>(),
+            Section::Included => strict::fields::<IncludedConfig>(),
+            Section::Secrets => strict::fields::<Secrets>(),
+            Section::ServiceSecrets => strict::fields::<ServiceSecrets>(),
+            Section::AccountSecrets => strict::fields::<AccountSecrets>(),
+            Section::Profile => strict::fields::<Profile>(),
+            Section::Service => strict::fields::<Service>(),
+            Section::Other => strict::fields::<OtherConfig>(),
+            Section::Account => strict::fields::<Account>(),
+            Section::LogRotation => strict::fields::<LogRotation>(),
+            Section::StopMethod => strict::fields::<StopMethod>(),
+            Section::Hooks => strict::fields::<Hooks>(),
+            Section::Health => strict::fields::<Health>(),
+            Section::DrainCheck => strict::fields::<DrainCheck>(),
+            Section::GreenCopy => strict::fields::<GreenCopy>(),
+            Section::Firewall => strict::fields::<Firewall>(),
+            Section::Recovery => strict::fields::<Recovery>(),
+            Section::Artifact => strict::fields::<Artifact>(),
+            Section::Bootstrap => strict::fields::<Bootstrap>(),
+            Section::Agent => strict::fields::<Agent>(),
+            Section::Serve => strict::fields::<Serve>(),
+            Section::Notify => strict::fields::<Notify>(),
+            Section::EmailNotify => strict::fields::<EmailNotify>(),
+            Section::WebhookNotify => strict::fields::<WebhookNotify>(),
+            Section::Metrics => strict::fields::<Metrics>(),
+            Section::Services
+            | Section::SecretServices
+            | Section::Profiles
+            | Section::ProfileServices => &[],
+        }
+    }
+
+    /// Returns the section held by the given key of this section, if it is a section.
+    fn child(self, key: &str) -> Option<Section> {
+        let child = match (self, key) {
+            (Section::Services, _) => Section::Service,
+            (Section::SecretServices, _) => Section::ServiceSecrets,
+            (Section::Profiles, _) => Section::Profile,
+            (Section::ProfileServices, _) => Section::Other,
+
+            (Section::File, "services") | (Section::Included, "services") => Section::Services,
+            (Section::File, "global")
+            | (Section::Included, "global")
+            | (Section::Profile, "global")
+            | (Section::Service, "other") => Section::Other,
+            (Section::File, "profiles") => Section::Profiles,
+            (Section::File, "bootstrap") => Section::Bootstrap,
+            (Section::File, "agent") => Section::Agent,
+            (Section::File, "serve") => Section::Serve,
+            (Section::File, "notify") => Section::Notify,
+            (Section::File, "metrics") => Section::Metrics,
+
+            (Section::Secrets, "global") | (Section::ServiceSecrets, "other") => {
+                Section::AccountSecrets
+            }
+            (Section::Secrets, "services") => Section::SecretServices,
+            (Section::AccountSecrets, "account") | (Section::Other, "account") => {
+                Section::Account
+            }
+
+            (Section::Profile, "services") => Section::ProfileServices,
+
+            (Section::Service, "log_rotation") | (Section::Other, "log_rotation") => {
+                Section::LogRotation
+            }
+            (Section::Service, "hooks") => Section::Hooks,
+            (Section::Service, "health") | (Section::GreenCopy, "health") => Section::Health,
+            (Section::Service, "drain_check") => Section::DrainCheck,
+            (Section::Service, "green") => Section::GreenCopy,
+            (Section::Service, "firewall") => Section::Firewall,
+            (Section::Service, "recovery") => Section::Recovery,
+            (Section::Service, "artifact") => Section::Artifact,
+            (Section::Other, "stop_method") => Section::StopMethod,
+
+            (Section::Notify, "email") => Section::EmailNotify,
+            (Section::Notify, "webhook") => Section::WebhookNotify,
+
+            _ => return None,
+        };
+
+        Some(child)
+    }
+}
+
+/// Returns the names of the settings of the section containing the dotted key, starting from
+/// the given section of the whole file, or none if the key is not in any section with
+/// settings, e.g. in the `env` table of a service.
+fn section_keys(root: Section, key: &str) -> &'static [&'static str] {
+    let segments: Vec<&str> = key.split('.').collect();
+    let mut section = root;
+
+    for segment in &segments[..segments.len() - 1] {
+        section = match section.child(segment) {
+            Some(child) => child,
+            None => return &[],
+        };
+    }
+
+    section.keys()
+}
+
+/// Describes every unknown key of the configuration file at the path if given, with the
+/// nearest known setting of the section holding it, if any, where the whole file is the
+/// given section.
+fn describe_unknown_keys(keys: &[String], path: Option<&Path>, root: Section) -> Vec<String> {
+    keys.iter()
+        .map(|key| {
+            let description = match path {
+                Some(path) => format!(
+                    "Unknown key '{}' in configuration file at '{}'",
+                    key,
+                    path.to_string_lossy()
+                ),
+                None => format!("Unknown key '{}' in configuration", key),
+            };
+
+            match strict::suggest(key, section_keys(root, key)) {
+                Some(suggestion) => format!("{}, did you mean '{}'?", description, suggestion),
+                None => description,
+            }
+        })
+        .collect()
+}
+
+/// Reads and parses the configuration file at the path, which is the given section, appending
+/// the description of every key that no setting matches.
+fn read_config_file<T: DeserializeOwned>(
+    path: &Path,
+    root: Section,
+    unknown_keys: &mut Vec<String>,
+) -> Result<T> {
+    let file_config_buf = file::get(path).chain_err(|| {
+        format!(
+            "Unable to read configuration file path at '{}'",
+            path.to_string_lossy()
+        )
+    })?;
+
+    let file_config_str = String::from_utf8(file_config_buf).chain_err(|| {
+        format!(
+            "Unable to convert configuration file content at '{}' into Rust String",
+            path.to_string_lossy()
+        )
+    })?;
+
+    let is_yaml = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            ext.eq_ignore_ascii_case("yml") || ext.eq_ignore_ascii_case("yaml")
+        })
+        .unwrap_or(false);
+
+    let mut keys = Vec::new();
+
+    let file_config = if is_yaml {
+        strict::from_yaml_str(&file_config_str, &mut keys).chain_err(|| {
+            ErrorKind::ConfigParse(format!(
+                "Unable to interpret configuration file content at '{}' as YAML",
+                path.to_string_lossy()
+            ))
+        })?
+    } else {
+        strict::from_toml_str(&file_config_str, &mut keys).chain_err(|| {
+            ErrorKind::ConfigParse(format!(
+                "Unable to interpret configuration file content at '{}' as TOML",
+                path.to_string_lossy()
+            ))
+        })?
+    };
+
+    unknown_keys.extend(describe_unknown_keys(&keys, Some(path), root));
+    Ok(file_config)
+}
+
+fn canonical_path(path: &Path) -> Result<PathBuf> {
+    path.canonicalize().chain_err(|| {
+        format!(
+            "Unable to resolve configuration file path at '{}'",
+            path.to_string_lossy()
+        )
+    })
+}
+
+/// Resolves the include patterns relative to the directory containing the including
+/// configuration file. Glob patterns may match no file, but plain paths must exist.
+fn resolve_includes(path: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut include_paths = Vec::new();
+
+    for pattern in patterns {
+        let full_pattern = base_dir.join(pattern);
+        let full_pattern = full_pattern.to_string_lossy();
+
+        if !pattern.contains(['*', '?', '[']) {
+            include_paths.push(PathBuf::from(full_pattern.into_owned()));
+            continue;
+        }
+
+        let matches = glob::glob(&full_pattern).chain_err(|| {
+            format!("Unable to interpret include pattern '{}'", pattern)
+        })?;
+
+        for include_path in matches {
+            include_paths.push(include_path.chain_err(|| {
+                format!("Unable to read path matching include pattern '{}'", pattern)
+            })?);
+        }
+    }
+
+    Ok(include_paths)
+}
+
+fn or_other_conf(lhs: Option<OtherConfig>, rhs: Option<OtherConfig>) -> Option<OtherConfig> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => Some(lhs.or(rhs)),
+        (lhs, rhs) => lhs.or(rhs),
+    }
+}
+
+fn or_stop_method(lhs: Option<StopMethod>, rhs: Option<StopMethod>) -> Option<StopMethod> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => Some(lhs.or(rhs)),
+        (lhs, rhs) => lhs.or(rhs),
+    }
+}
+
+fn bounded_parallelism(parallelism: Option<usize>) -> usize {
+    match parallelism {
+        Some(0) => MAX_PARALLELISM,
+        Some(parallelism) => cmp::min(parallelism, MAX_PARALLELISM),
+        None => 1,
+    }
+}
+
+fn merge_other_conf<'a, F, R>(
+    lhs: &'a Option<OtherConfig>,
+    rhs: &'a Option<OtherConfig>,
+    chooser: F,
+) -> Option<&'a R>
+where
+    F: Fn(&'a OtherConfig) -> Option<&'a R>,
+{
+    lhs.as_ref().and_then(&chooser).or(rhs.as_ref().and_then(
+        &chooser,
+    ))
+}
@@ -0,0 +1,107 @@
+//! Reads generic credentials from the Windows Credential Manager, e.g. the ones added with
+//! `cmdkey /generic:<target> /user:<username> /pass`.
+
+use errors::*;
+
+/// Holds the username and password of a stored credential.
+pub struct Credential {
+    /// Username of the credential.
+    pub user: String,
+
+    /// Password of the credential.
+    pub password: String,
+}
+
+/// Reads the generic credential stored under the given target name.
+pub fn read(target: &str) -> Result<Credential> {
+    imp::read(target)
+}
+
+#[cfg(windows)]
+mod imp {
+    use errors::*;
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use std::slice;
+    use super::Credential;
+    use winapi::um::wincred::{self, CRED_TYPE_GENERIC, PCREDENTIALW};
+
+    struct CredHandle(PCREDENTIALW);
+
+    impl Drop for CredHandle {
+        fn drop(&mut self) {
+            unsafe {
+                wincred::CredFree(self.0 as *mut _);
+            }
+        }
+    }
+
+    fn from_wide_ptr(ptr: *const u16) -> String {
+        if ptr.is_null() {
+            return String::new();
+        }
+
+        unsafe {
+            let len = (0..).take_while(|&index| *ptr.offset(index) != 0).count();
+            String::from_utf16_lossy(slice::from_raw_parts(ptr, len))
+        }
+    }
+
+    pub fn read(target: &str) -> Result<Credential> {
+        let wide_target: Vec<u16> = OsStr::new(target).encode_wide().chain(Some(0)).collect();
+        let mut credential: PCREDENTIALW = ptr::null_mut();
+
+        let res = unsafe {
+            wincred::CredReadW(wide_target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential)
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error()).chain_err(|| {
+                format!("Unable to read credential '{}' from Credential Manager", target)
+            });
+        }
+
+        let credential = CredHandle(credential);
+
+        let (blob_ptr, blob_size) = unsafe {
+            let credential = &*credential.0;
+            (credential.CredentialBlob, credential.CredentialBlobSize as usize)
+        };
+
+        // an empty password may be stored without any blob at all
+        let password = if blob_ptr.is_null() || blob_size == 0 {
+            String::new()
+        } else {
+            // the password blob is stored as UTF-16LE without any terminating null
+            let blob = unsafe { slice::from_raw_parts(blob_ptr, blob_size) };
+
+            let units: Vec<u16> = blob.chunks(2)
+                .map(|pair| u16::from(pair[0]) | (u16::from(*pair.get(1).unwrap_or(&0)) << 8))
+                .collect();
+
+            String::from_utf16(&units).chain_err(|| {
+                format!("Unable to decode password of credential '{}' as UTF-16", target)
+            })?
+        };
+
+        Ok(Credential {
+            user: from_wide_ptr(unsafe { (*credential.0).UserName }),
+            password,
+        })
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use errors::*;
+    use super::Credential;
+
+    pub fn read(target: &str) -> Result<Credential> {
+        bail!(
+            "Unable to read credential '{}' since Credential Manager is only available on Windows",
+            target
+        )
+    }
+}
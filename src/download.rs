@@ -0,0 +1,163 @@
+//! Downloads files with the tools already available on the machine, i.e. PowerShell on
+//! Windows and curl elsewhere, and verifies their SHA-256 checksums. Content is posted with
+//! the same tools, e.g. webhook messages.
+
+use errors::*;
+use file;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use super::{check_output, run_cmd};
+
+/// Downloads the file at the given URL into the given path, overwriting any existing file.
+pub fn download(url: &str, path: &Path) -> Result<()> {
+    imp::download(url, path).chain_err(|| {
+        format!(
+            "Unable to download '{}' into '{}'",
+            url,
+            path.to_string_lossy()
+        )
+    })
+}
+
+/// Posts the body with the given content type to the URL, failing on any unsuccessful
+/// HTTP status. The URL and body are kept out of the command line, since the URL may hold a
+/// secret, e.g. of a webhook.
+pub fn post(url: &str, content_type: &str, body: &str) -> Result<()> {
+    imp::post(url, content_type, body)
+}
+
+/// Returns the lowercase hex SHA-256 checksum of the file at the given path.
+pub fn sha256(path: &Path) -> Result<String> {
+    let buf = file::get(path).chain_err(|| {
+        format!("Unable to read file at '{}'", path.to_string_lossy())
+    })?;
+
+    let mut hasher = Sha256::default();
+    hasher.input(&buf);
+    Ok(format!("{:x}", hasher.result()))
+}
+
+/// Verifies that the file at the given path has the expected hex SHA-256 checksum,
+/// ignoring the letter case of the expected checksum.
+pub fn verify_sha256(path: &Path, expected: &str) -> Result<()> {
+    let actual = sha256(path)?;
+
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        bail!(
+            "Checksum mismatch for '{}', expected SHA-256 {} but found {}",
+            path.to_string_lossy(),
+            expected.trim(),
+            actual
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+mod imp {
+    use errors::*;
+    use remote;
+    use std::path::Path;
+    use std::process::Command;
+    use super::{check_output, run_cmd};
+
+    /// Script that posts the body from the environment variables set by `post`.
+    const POST_SCRIPT: &str = "$ProgressPreference = 'SilentlyContinue'; \
+        [Net.ServicePointManager]::SecurityProtocol = [Net.SecurityProtocolType]::Tls12; \
+        Invoke-RestMethod -Method Post -UseBasicParsing \
+        -ContentType $env:NSSM_EXEC_POST_CONTENT_TYPE -Uri $env:NSSM_EXEC_POST_URL \
+        -Body ([Text.Encoding]::UTF8.GetBytes($env:NSSM_EXEC_POST_BODY)) | Out-Null";
+
+    pub fn post(url: &str, content_type: &str, body: &str) -> Result<()> {
+        let output = Command::new(remote::program())
+            .args(remote::encoded_args(POST_SCRIPT))
+            .env("NSSM_EXEC_POST_URL", url)
+            .env("NSSM_EXEC_POST_CONTENT_TYPE", content_type)
+            .env("NSSM_EXEC_POST_BODY", body)
+            .output();
+
+        let cmd_name = format!("{} Invoke-RestMethod", remote::program().to_string_lossy());
+        check_output(&cmd_name, output)
+    }
+
+    pub fn download(url: &str, path: &Path) -> Result<()> {
+        // single quotes are escaped by doubling within PowerShell single-quoted strings
+        let script = format!(
+            "$ProgressPreference = 'SilentlyContinue'; \
+             [Net.ServicePointManager]::SecurityProtocol = [Net.SecurityProtocolType]::Tls12; \
+             Invoke-WebRequest -UseBasicParsing -Uri '{}' -OutFile '{}'",
+            url.replace('\'', "''"),
+            path.to_string_lossy().replace('\'', "''")
+        );
+
+        run_cmd(
+            Path::new("powershell"),
+            &["-NoProfile", "-NonInteractive", "-Command", &script],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use errors::*;
+    use std::io::Write;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use super::{check_output, run_cmd};
+
+    pub fn download(url: &str, path: &Path) -> Result<()> {
+        let path = path.to_string_lossy();
+        run_cmd(Path::new("curl"), &["-fsSL", "-o", &path, url])?;
+        Ok(())
+    }
+
+    /// Quotes the value as a string of a curl configuration file.
+    fn quote(value: &str) -> String {
+        let mut quoted = String::with_capacity(value.len() + 2);
+        quoted.push('"');
+
+        for c in value.chars() {
+            match c {
+                '\\' => quoted.push_str("\\\\"),
+                '"' => quoted.push_str("\\\""),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                c => quoted.push(c),
+            }
+        }
+
+        quoted.push('"');
+        quoted
+    }
+
+    pub fn post(url: &str, content_type: &str, body: &str) -> Result<()> {
+        // the URL and body are passed as the configuration on stdin
+        let config = format!(
+            "url = {}\nheader = {}\ndata-binary = {}\n",
+            quote(url),
+            quote(&format!("Content-Type: {}", content_type)),
+            quote(body)
+        );
+
+        let child = Command::new("curl")
+            .args(["-fsS", "-o", "/dev/null", "--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+
+        let output = child.and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(config.as_bytes())?;
+            }
+
+            child.wait_with_output()
+        });
+
+        check_output("curl", output)
+    }
+}
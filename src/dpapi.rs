@@ -0,0 +1,143 @@
+//! Protects and unprotects secrets with the Windows Data Protection API (DPAPI), so that
+//! only the same user on the same machine is able to recover them.
+//! The protected blobs are hex encoded, in the same format as PowerShell
+//! `ConvertFrom-SecureString` without any key.
+
+use errors::*;
+use std::str;
+
+/// Protects the secret for the current user and returns the hex encoded protected blob.
+pub fn protect(secret: &str) -> Result<String> {
+    // encoded as UTF-16LE like any other Windows string
+    let mut plain = Vec::with_capacity(secret.len() * 2);
+
+    for unit in secret.encode_utf16() {
+        plain.push(unit as u8);
+        plain.push((unit >> 8) as u8);
+    }
+
+    let protected = imp::protect(&plain)?;
+    Ok(protected.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Unprotects the hex encoded protected blob back into the secret.
+/// Returns error if the blob was not protected by the current user on this machine.
+pub fn unprotect(protected_hex: &str) -> Result<String> {
+    let protected_hex = protected_hex.trim();
+
+    if !protected_hex.len().is_multiple_of(2) {
+        bail!("Unable to decode protected blob with odd number of hex digits");
+    }
+
+    let protected = protected_hex
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| "Unable to decode protected blob as hex digits".into())
+        })
+        .collect::<Result<Vec<u8>>>()?;
+
+    let plain = imp::unprotect(&protected)?;
+
+    if plain.len() % 2 != 0 {
+        bail!("Unable to decode unprotected secret with odd number of bytes as UTF-16");
+    }
+
+    let units: Vec<u16> = plain
+        .chunks(2)
+        .map(|pair| u16::from(pair[0]) | (u16::from(pair[1]) << 8))
+        .collect();
+
+    String::from_utf16(&units).chain_err(|| "Unable to decode unprotected secret as UTF-16")
+}
+
+#[cfg(windows)]
+mod imp {
+    use errors::*;
+    use std::io;
+    use std::ptr;
+    use std::slice;
+    use winapi::shared::minwindef::DWORD;
+    use winapi::um::dpapi::{self, CRYPTPROTECT_UI_FORBIDDEN};
+    use winapi::um::winbase;
+    use winapi::um::wincrypt::DATA_BLOB;
+
+    fn to_blob(data: &[u8]) -> DATA_BLOB {
+        DATA_BLOB {
+            cbData: data.len() as DWORD,
+            pbData: data.as_ptr() as *mut u8,
+        }
+    }
+
+    fn take_blob(blob: DATA_BLOB) -> Vec<u8> {
+        unsafe {
+            let data = slice::from_raw_parts(blob.pbData, blob.cbData as usize).to_vec();
+            winbase::LocalFree(blob.pbData as *mut _);
+            data
+        }
+    }
+
+    pub fn protect(plain: &[u8]) -> Result<Vec<u8>> {
+        let mut input = to_blob(plain);
+        let mut output = to_blob(&[]);
+
+        let res = unsafe {
+            dpapi::CryptProtectData(
+                &mut input,
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error())
+                .chain_err(|| "Unable to protect secret with DPAPI");
+        }
+
+        Ok(take_blob(output))
+    }
+
+    pub fn unprotect(protected: &[u8]) -> Result<Vec<u8>> {
+        let mut input = to_blob(protected);
+        let mut output = to_blob(&[]);
+
+        let res = unsafe {
+            dpapi::CryptUnprotectData(
+                &mut input,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error())
+                .chain_err(|| "Unable to unprotect secret with DPAPI");
+        }
+
+        Ok(take_blob(output))
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use errors::*;
+
+    pub fn protect(_: &[u8]) -> Result<Vec<u8>> {
+        bail!("Unable to protect secret since DPAPI is only available on Windows")
+    }
+
+    pub fn unprotect(_: &[u8]) -> Result<Vec<u8>> {
+        bail!("Unable to unprotect secret since DPAPI is only available on Windows")
+    }
+}
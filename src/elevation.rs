@@ -0,0 +1,112 @@
+//! Checks whether the process is allowed to manage services, and relaunches the program
+//! elevated through the UAC prompt when it is not.
+
+use errors::*;
+
+/// States whether the process is able to create and configure services, i.e. whether it
+/// is able to open the Service Control Manager with full access.
+/// Always true on other platforms, where the services are not managed by the SCM.
+pub fn is_elevated() -> bool {
+    imp::is_elevated()
+}
+
+/// Relaunches the current executable with the given arguments through the UAC prompt,
+/// waits for the elevated process to exit and returns its exit code.
+pub fn relaunch_elevated<S: AsRef<str>>(args: &[S]) -> Result<i32> {
+    imp::relaunch_elevated(args)
+}
+
+#[cfg(windows)]
+mod imp {
+    use cmdline;
+    use errors::*;
+    use std::env;
+    use std::ffi::OsStr;
+    use std::io;
+    use std::mem;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use winapi::shared::minwindef::DWORD;
+    use winapi::um::handleapi;
+    use winapi::um::processthreadsapi;
+    use winapi::um::shellapi::{self, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW};
+    use winapi::um::synchapi;
+    use winapi::um::winbase::INFINITE;
+    use winapi::um::winsvc::{self, SC_MANAGER_ALL_ACCESS};
+    use winapi::um::winuser::SW_SHOWNORMAL;
+
+    fn to_wide<S: AsRef<OsStr>>(s: S) -> Vec<u16> {
+        s.as_ref().encode_wide().chain(Some(0)).collect()
+    }
+
+    pub fn is_elevated() -> bool {
+        let handle =
+            unsafe { winsvc::OpenSCManagerW(ptr::null(), ptr::null(), SC_MANAGER_ALL_ACCESS) };
+
+        if handle.is_null() {
+            return false;
+        }
+
+        unsafe {
+            winsvc::CloseServiceHandle(handle);
+        }
+
+        true
+    }
+
+    pub fn relaunch_elevated<S: AsRef<str>>(args: &[S]) -> Result<i32> {
+        let exe_path = env::current_exe()
+            .chain_err(|| "Unable to get the path of the current executable")?;
+
+        let verb = to_wide("runas");
+        let file = to_wide(&exe_path);
+        let parameters = to_wide(cmdline::join_args(args));
+
+        let mut info: SHELLEXECUTEINFOW = unsafe { mem::zeroed() };
+        info.cbSize = mem::size_of::<SHELLEXECUTEINFOW>() as DWORD;
+        info.fMask = SEE_MASK_NOCLOSEPROCESS;
+        info.lpVerb = verb.as_ptr();
+        info.lpFile = file.as_ptr();
+        info.lpParameters = parameters.as_ptr();
+        info.nShow = SW_SHOWNORMAL;
+
+        if unsafe { shellapi::ShellExecuteExW(&mut info) } == 0 {
+            return Err(io::Error::last_os_error()).chain_err(|| {
+                format!("Unable to relaunch '{}' elevated", exe_path.display())
+            });
+        }
+
+        if info.hProcess.is_null() {
+            return Err("Unable to wait for the elevated process without its handle".into());
+        }
+
+        let mut exit_code: DWORD = 0;
+
+        let res = unsafe {
+            synchapi::WaitForSingleObject(info.hProcess, INFINITE);
+            let res = processthreadsapi::GetExitCodeProcess(info.hProcess, &mut exit_code);
+            handleapi::CloseHandle(info.hProcess);
+            res
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error())
+                .chain_err(|| "Unable to get the exit code of the elevated process");
+        }
+
+        Ok(exit_code as i32)
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use errors::*;
+
+    pub fn is_elevated() -> bool {
+        true
+    }
+
+    pub fn relaunch_elevated<S: AsRef<str>>(_: &[S]) -> Result<i32> {
+        bail!("Relaunching elevated is only supported on Windows")
+    }
+}
@@ -0,0 +1,163 @@
+//! Errors of the crate. Besides the plain messages, the failures that callers may want to
+//! tell apart, e.g. to retry them or to summarize them in the JSON report, have their own
+//! variants.
+
+use state::ServiceState;
+use std::io;
+
+#[derive(Debug, ErrorChain)]
+pub enum ErrorKind {
+    Msg(String),
+
+    #[error_chain(foreign)]
+    Io(io::Error),
+
+    /// Configuration content that cannot be interpreted, e.g. invalid TOML or YAML.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_| "configuration parse error""#)]
+    #[error_chain(display = r#"|message| write!(f, "{}", message)"#)]
+    ConfigParse(String),
+
+    /// Configuration that cannot be used, e.g. failing to load or to validate.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_| "invalid configuration""#)]
+    #[error_chain(display = r#"|message| write!(f, "{}", message)"#)]
+    ConfigInvalid(String),
+
+    /// External command that cannot be run at all, e.g. because its executable is missing.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_| "command unavailable""#)]
+    #[error_chain(display = r#"|cmd| write!(f, "Unable to create command '{}'", cmd)"#)]
+    CommandUnavailable { cmd: String },
+
+    /// nssm executable that is missing or does not support the configuration.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_| "nssm unavailable""#)]
+    #[error_chain(display = r#"|message| write!(f, "{}", message)"#)]
+    NssmUnavailable(String),
+
+    /// External command, e.g. `nssm`, that has exited unsuccessfully.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_, _, _, _| "command failed""#)]
+    #[error_chain(display = r#"|cmd, code: &Option<i32>, stdout, stderr| write!(f,
+        "{} {{ exit code: {}, stdout: \"{}\", stderr: \"{}\" }}",
+        cmd,
+        match *code {
+            Some(code) => format!("{}", code),
+            None => "NIL".to_owned(),
+        },
+        stdout,
+        stderr)"#)]
+    CommandFailed {
+        cmd: String,
+        code: Option<i32>,
+        stdout: String,
+        stderr: String,
+    },
+
+    /// External command that has not exited within the command timeout and was killed.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_, _| "command timed out""#)]
+    #[error_chain(display = r#"|cmd, timeout_secs| write!(f,
+        "Command '{}' timed out after {}s and was killed", cmd, timeout_secs)"#)]
+    CommandTimeout { cmd: String, timeout_secs: u64 },
+
+    /// Service that has not reached the expected state within the poll count.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_, _| "service timed out""#)]
+    #[error_chain(display = r#"|name, expected_state| write!(f,
+        "Timeout waiting for service name '{}' to be in state {:?}", name, expected_state)"#)]
+    ServiceTimeout {
+        name: String,
+        expected_state: ServiceState,
+    },
+
+    /// Services of a run that have failed, while the others may have succeeded, out of the
+    /// services that have been processed, i.e. not skipped after an earlier failure.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_, _| "services failed""#)]
+    #[error_chain(display = r#"|failed, total| write!(f,
+        "{} out of {} service(s) failed", failed, total)"#)]
+    ServicesFailed { failed: usize, total: usize },
+
+    /// Remote hosts of a run on which processing the services has failed.
+    #[error_chain(custom)]
+    #[error_chain(description = r#"|_, _| "hosts failed""#)]
+    #[error_chain(display = r#"|failed, total| write!(f,
+        "{} out of {} host(s) failed", failed, total)"#)]
+    HostsFailed { failed: usize, total: usize },
+}
+
+/// Category of an error, which tells apart the typed failures from the plain messages.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    Message,
+    Io,
+    ConfigParse,
+    ConfigInvalid,
+    CommandUnavailable,
+    NssmUnavailable,
+    CommandFailed,
+    CommandTimeout,
+    ServiceTimeout,
+
+    /// Some of the services or hosts of a run have failed.
+    PartialFailure,
+
+    /// All the services or hosts of a run have failed.
+    TotalFailure,
+}
+
+impl ErrorKind {
+    /// Returns the category of this error kind alone.
+    pub fn category(&self) -> ErrorCategory {
+        match *self {
+            ErrorKind::Msg(_) => ErrorCategory::Message,
+            ErrorKind::Io(_) => ErrorCategory::Io,
+            ErrorKind::ConfigParse(_) => ErrorCategory::ConfigParse,
+            ErrorKind::ConfigInvalid(_) => ErrorCategory::ConfigInvalid,
+            ErrorKind::CommandUnavailable { .. } => ErrorCategory::CommandUnavailable,
+            ErrorKind::NssmUnavailable(_) => ErrorCategory::NssmUnavailable,
+            ErrorKind::CommandFailed { .. } => ErrorCategory::CommandFailed,
+            ErrorKind::CommandTimeout { .. } => ErrorCategory::CommandTimeout,
+            ErrorKind::ServiceTimeout { .. } => ErrorCategory::ServiceTimeout,
+            ErrorKind::ServicesFailed { failed, total }
+            | ErrorKind::HostsFailed { failed, total } => if failed < total {
+                ErrorCategory::PartialFailure
+            } else {
+                ErrorCategory::TotalFailure
+            },
+        }
+    }
+}
+
+impl Error {
+    /// Returns the category of the outermost typed error in the chain, since the typed
+    /// failures are usually wrapped by messages giving their context. Falls back to
+    /// `Message` if every error in the chain is a plain message.
+    pub fn category(&self) -> ErrorCategory {
+        let mut error = self;
+
+        loop {
+            let category = error.kind().category();
+
+            if category != ErrorCategory::Message {
+                return category;
+            }
+
+            let next_error = match error.1.next_error {
+                Some(ref next_error) => next_error,
+                None => return ErrorCategory::Message,
+            };
+
+            match next_error.downcast_ref::<Error>() {
+                Some(next_error) => error = next_error,
+                None if next_error.downcast_ref::<io::Error>().is_some() => {
+                    return ErrorCategory::Io
+                }
+                None => return ErrorCategory::Message,
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Explains the effective configuration of a service, i.e. every extra configuration after
+//! merging the ones of the service over the global ones together with where each value comes
+//! from, and the nssm commands that set the parameters of the service. The settings that the
+//! services take from the extra configurations, e.g. `startup_dir`, are shown with the values
+//! of the service after the variables are rendered.
+
+use cmdline;
+use config::{Backend, FileConfig, LogRotation, OtherConfig, Service};
+use errors::*;
+use nssm;
+use params::{self, ServiceParam};
+use serde::Serialize;
+use serde_json::{self, Map, Value};
+use strict;
+use transcript::SECRET_PLACEHOLDER;
+
+/// Source of the values set on the service.
+pub const SERVICE_SOURCE: &str = "service";
+
+/// Source of the values set in the global extra configurations.
+pub const GLOBAL_SOURCE: &str = "global";
+
+/// Source of the values set nowhere, which fall back to the default of the setting.
+pub const DEFAULT_SOURCE: &str = "default";
+
+/// Holds the effective value of a setting of the service.
+#[derive(Clone, Debug)]
+pub struct Setting {
+    /// Dotted name of the setting, e.g. `log_rotation.enabled`.
+    pub name: String,
+
+    /// Effective value in JSON with the password replaced, or none if the setting is not set.
+    pub value: Option<String>,
+
+    /// Where the value comes from, either `service` for the service and its extra
+    /// configurations, `global` or `default`.
+    pub source: &'static str,
+}
+
+/// Holds the explanation of the effective configuration of a service.
+#[derive(Clone, Debug)]
+pub struct Explanation {
+    /// Name of the service.
+    pub name: String,
+
+    /// Backend that installs and configures the service.
+    pub backend: Backend,
+
+    /// Extra configurations in the order they are declared.
+    pub settings: Vec<Setting>,
+
+    /// nssm commands that set the parameters of the service in order, with the secrets
+    /// replaced. Empty if the service does not use the nssm backend.
+    pub nssm_cmds: Vec<String>,
+}
+
+/// Serializes the configuration into a JSON object without the unset values.
+fn to_map<T: Serialize>(config: Option<&T>) -> Result<Map<String, Value>> {
+    let value = serde_json::to_value(config).chain_err(|| "Unable to serialize configuration")?;
+
+    match without_nulls(value) {
+        Value::Object(map) => Ok(map),
+        _ => Ok(Map::new()),
+    }
+}
+
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+
+        value => value,
+    }
+}
+
+/// Returns the setting with the value of the first layer that has the key.
+fn setting(name: String, key: &str, layers: &[(&'static str, &Map<String, Value>)]) -> Setting {
+    let found = layers
+        .iter()
+        .filter_map(|&(source, map)| map.get(key).map(|value| (source, value.clone())))
+        .next();
+
+    match found {
+        Some((source, mut value)) => {
+            if key == "account" {
+                if let Some(password) = value.get_mut("password") {
+                    if password.as_str().is_some_and(|password| !password.is_empty()) {
+                        *password = Value::String(SECRET_PLACEHOLDER.to_owned());
+                    }
+                }
+            }
+
+            Setting {
+                name,
+                value: Some(value.to_string()),
+                source,
+            }
+        }
+
+        None => Setting {
+            name,
+            value: None,
+            source: DEFAULT_SOURCE,
+        },
+    }
+}
+
+/// Returns the log rotation settings, which are merged individually from the service, its
+/// extra configurations and then the global extra configurations.
+fn log_rotation_settings(service: &Service, file_config: &FileConfig) -> Result<Vec<Setting>> {
+    let other_log_rotation = |other: &Option<OtherConfig>| {
+        other.as_ref().and_then(|other| other.log_rotation)
+    };
+
+    let service_map = to_map(service.log_rotation.as_ref())?;
+    let service_other_map = to_map(other_log_rotation(&service.other).as_ref())?;
+    let global_map = to_map(other_log_rotation(&file_config.global).as_ref())?;
+
+    let layers = [
+        (SERVICE_SOURCE, &service_map),
+        (SERVICE_SOURCE, &service_other_map),
+        (GLOBAL_SOURCE, &global_map),
+    ];
+
+    Ok(strict::fields::<LogRotation>()
+        .iter()
+        .map(|key| setting(format!("log_rotation.{}", key), key, &layers))
+        .collect())
+}
+
+/// Returns the setting that the service may take from the extra configurations, with the
+/// value of the service.
+fn service_setting<T: Serialize>(key: &str, value: &Option<T>, service: &Service) -> Setting {
+    let source = if service.from_global.iter().any(|name| name == key) {
+        GLOBAL_SOURCE
+    } else {
+        SERVICE_SOURCE
+    };
+
+    match value.as_ref().map(serde_json::to_value) {
+        Some(Ok(value)) => Setting {
+            name: key.to_owned(),
+            value: Some(value.to_string()),
+            source,
+        },
+
+        _ => Setting {
+            name: key.to_owned(),
+            value: None,
+            source: DEFAULT_SOURCE,
+        },
+    }
+}
+
+/// Returns the environment variables of the service, which are merged individually.
+fn env_settings(service: &Service) -> Vec<Setting> {
+    match service.env {
+        Some(ref env) if !env.is_empty() => env.iter()
+            .map(|(name, value)| service_setting(&format!("env.{}", name), &Some(value), service))
+            .collect(),
+
+        _ => vec![service_setting("env", &None::<String>, service)],
+    }
+}
+
+/// Returns the nssm command that sets the parameter, with the secrets replaced.
+fn nssm_cmd(service: &Service, param: &ServiceParam, file_config: &FileConfig) -> String {
+    let param = ServiceParam {
+        secret: param.secret.as_ref().map(|_| SECRET_PLACEHOLDER.to_owned()),
+        ..param.clone()
+    };
+
+    let cmd_line = cmdline::to_cmd_line(
+        &file_config.nssm_path.to_string_lossy(),
+        &nssm::set(&service.name, &param),
+    );
+
+    file_config
+        .passwords()
+        .iter()
+        .filter(|password| !password.is_empty())
+        .fold(cmd_line, |cmd_line, password| {
+            cmd_line.replace(password.as_str(), SECRET_PLACEHOLDER)
+        })
+}
+
+/// Explains the effective configuration of the service with the given name, which may be
+/// given without the name prefix.
+pub fn explain(file_config: &FileConfig, service_name: &str) -> Result<Explanation> {
+    let prefixed_name = file_config.prefixed_name(service_name);
+
+    let service = file_config
+        .services
+        .iter()
+        .find(|service| service.name == prefixed_name)
+        .ok_or_else(|| {
+            Error::from(format!(
+                "Unable to find service '{}' in the configuration",
+                service_name
+            ))
+        })?;
+
+    let service_map = to_map(service.other.as_ref())?;
+    let global_map = to_map(file_config.global.as_ref())?;
+    let layers = [(SERVICE_SOURCE, &service_map), (GLOBAL_SOURCE, &global_map)];
+
+    let mut settings = Vec::new();
+
+    // every extra configuration is listed, so that the new ones are explained as well
+    for key in strict::fields::<OtherConfig>() {
+        match *key {
+            "log_rotation" => settings.extend(log_rotation_settings(service, file_config)?),
+            "startup_dir" => settings.push(service_setting(key, &service.startup_dir, service)),
+            "args" => settings.push(service_setting(key, &service.args, service)),
+            "description" => settings.push(service_setting(key, &service.description, service)),
+            "env" => settings.extend(env_settings(service)),
+            "stdout_log" => settings.push(service_setting(key, &service.stdout_log, service)),
+            "stderr_log" => settings.push(service_setting(key, &service.stderr_log, service)),
+            _ => settings.push(setting((*key).to_owned(), key, &layers)),
+        }
+    }
+
+    let backend = file_config.backend(&service.name);
+
+    let nssm_cmds = if backend == Backend::Nssm {
+        params::desired_params(service, file_config)
+            .iter()
+            .map(|param| nssm_cmd(service, param, file_config))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(Explanation {
+        name: service.name.clone(),
+        backend,
+        settings,
+        nssm_cmds,
+    })
+}
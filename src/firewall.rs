@@ -0,0 +1,139 @@
+//! Provisions the inbound Windows Firewall rules allowing the ports of the services with
+//! `netsh advfirewall firewall`, on the host of the service.
+
+use config::{FileConfig, Firewall, Protocol};
+use errors::*;
+use output;
+use sc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use super::{run_host_cmd, run_host_modify_cmd};
+
+/// Path of the `netsh` executable, which is always on the path.
+pub fn program() -> &'static Path {
+    Path::new("netsh")
+}
+
+/// Returns the configured name of the firewall rule of the service.
+pub fn rule_name(service_name: &str, firewall: &Firewall) -> String {
+    firewall
+        .rule_name
+        .clone()
+        .unwrap_or_else(|| format!("nssm_exec_{}", service_name))
+}
+
+fn protocol(firewall: &Firewall) -> &'static str {
+    match firewall.protocol {
+        Some(Protocol::Udp) => "UDP",
+        Some(Protocol::Tcp) | None => "TCP",
+    }
+}
+
+fn ports(firewall: &Firewall) -> String {
+    firewall
+        .ports
+        .iter()
+        .map(|port| port.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Arguments to add the inbound rule allowing the ports.
+pub fn add(rule_name: &str, firewall: &Firewall) -> Vec<String> {
+    vec![
+        "advfirewall".to_owned(),
+        "firewall".to_owned(),
+        "add".to_owned(),
+        "rule".to_owned(),
+        format!("name={}", rule_name),
+        "dir=in".to_owned(),
+        "action=allow".to_owned(),
+        format!("protocol={}", protocol(firewall)),
+        format!("localport={}", ports(firewall)),
+        format!(
+            "profile={}",
+            firewall.profile.as_ref().map_or("any", |profile| profile.as_str())
+        ),
+    ]
+}
+
+/// Arguments to delete every rule with the name.
+pub fn delete(rule_name: &str) -> Vec<String> {
+    vec![
+        "advfirewall".to_owned(),
+        "firewall".to_owned(),
+        "delete".to_owned(),
+        "rule".to_owned(),
+        format!("name={}", rule_name),
+    ]
+}
+
+/// Arguments to show the rules with the name, which fails if there is none.
+pub fn show(rule_name: &str) -> Vec<String> {
+    vec![
+        "advfirewall".to_owned(),
+        "firewall".to_owned(),
+        "show".to_owned(),
+        "rule".to_owned(),
+        format!("name={}", rule_name),
+    ]
+}
+
+type RuleFields = BTreeMap<String, Vec<String>>;
+
+/// Returns the fields of the installed rule with the name, or `None` if there is no such
+/// rule.
+fn query_rule(rule_name: &str, file_config: &FileConfig) -> Option<RuleFields> {
+    run_host_cmd(program(), &show(rule_name), file_config)
+        .ok()
+        .map(|output| sc::parse_fields(&output::decode(&output.stdout)))
+}
+
+/// Creates the rule of the service, replacing any existing rule with the same name unless it
+/// already allows the configured ports and protocol. Returns true if the rule was created.
+pub fn apply(service_name: &str, firewall: &Firewall, file_config: &FileConfig) -> Result<bool> {
+    let rule_name = rule_name(service_name, firewall);
+
+    if let Some(fields) = query_rule(&rule_name, file_config) {
+        let field = |key: &str| {
+            fields
+                .get(key)
+                .and_then(|values| values.first())
+                .map_or("", |value| value.as_str())
+        };
+
+        let installed_ports: BTreeSet<&str> = field("LocalPort").split(',').collect();
+        let expected_ports = ports(firewall);
+        let expected_ports: BTreeSet<&str> = expected_ports.split(',').collect();
+
+        if installed_ports == expected_ports
+            && field("Protocol").eq_ignore_ascii_case(protocol(firewall))
+        {
+            return Ok(false);
+        }
+
+        run_host_modify_cmd(program(), &delete(&rule_name), file_config).chain_err(|| {
+            format!("Unable to delete the outdated firewall rule '{}'", rule_name)
+        })?;
+    }
+
+    run_host_modify_cmd(program(), &add(&rule_name, firewall), file_config)
+        .chain_err(|| format!("Unable to add firewall rule '{}'", rule_name))?;
+
+    Ok(true)
+}
+
+/// Deletes the rule of the service if it exists. Returns true if the rule was deleted.
+pub fn remove(service_name: &str, firewall: &Firewall, file_config: &FileConfig) -> Result<bool> {
+    let rule_name = rule_name(service_name, firewall);
+
+    // netsh fails to delete a rule that does not exist
+    if query_rule(&rule_name, file_config).is_none() {
+        return Ok(false);
+    }
+
+    run_host_modify_cmd(program(), &delete(&rule_name), file_config)
+        .chain_err(|| format!("Unable to delete firewall rule '{}'", rule_name))?;
+
+    Ok(true)
+}
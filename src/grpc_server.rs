@@ -0,0 +1,264 @@
+//! Serves the gRPC interface published in `proto/nssm_exec.proto`, to query the status of
+//! the configured services, and to apply the configuration or stop the services while
+//! streaming the result of every service as soon as it has been processed.
+//! Every call has to pass the shared token of the `[serve]` settings in the `x-auth-token`
+//! metadata. Only the services on the local machine are managed.
+
+use config::FileConfig;
+use errors::*;
+use grpc::{self, GrpcStatus, RequestOptions, ServerBuilder, SingleResponse, StreamingResponse};
+use grpc::protobuf::MarshallerProtobuf;
+use grpc::rt::{GrpcStreaming, MethodDescriptor, MethodHandlerServerStreaming,
+               MethodHandlerUnary, ServerMethod, ServerServiceDefinition};
+use protobuf::RepeatedField;
+use report::{RunReport, ServiceReport};
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use super::{nssm_exec_status, nssm_exec_stop, nssm_exec_update, print_recursive_err};
+
+/// Messages generated from `proto/nssm_exec.proto`.
+#[allow(warnings)]
+pub mod proto {
+    include!(concat!(env!("OUT_DIR"), "/nssm_exec.rs"));
+}
+
+use self::proto::{ApplyRequest, ServiceResult, ServiceStatus, StatusReply, StatusRequest,
+                  StopRequest};
+
+/// Address served on when no other address is configured.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:50051";
+
+/// Name of the metadata holding the shared token.
+pub const TOKEN_METADATA: &str = "x-auth-token";
+
+const SERVICE_PATH: &str = "/nssm_exec.NssmExec";
+
+type Load = dyn Fn() -> Result<FileConfig> + Send + Sync;
+
+struct Handler {
+    token: String,
+    load: Box<Load>,
+}
+
+/// Returns the configured address to serve the gRPC interface on.
+pub fn address(file_config: &FileConfig) -> &str {
+    file_config
+        .serve
+        .as_ref()
+        .and_then(|serve| serve.grpc_address.as_ref())
+        .map_or(DEFAULT_ADDRESS, |address| address.as_str())
+}
+
+/// Serves the gRPC interface on the address until the process is stopped. The configuration
+/// is loaded with `load` for every call, so that its changes are picked up without
+/// restarting.
+pub fn serve<F>(address: &str, token: &str, load: F) -> Result<()>
+where
+    F: Fn() -> Result<FileConfig> + Send + Sync + 'static,
+{
+    let handler = Arc::new(Handler {
+        token: token.to_owned(),
+        load: Box::new(load),
+    });
+
+    let mut server_builder = ServerBuilder::new_plain();
+
+    server_builder
+        .http
+        .set_addr(address)
+        .map_err(|e| Error::from(format!("Unable to listen on '{}': {}", address, e)))?;
+
+    server_builder.add_service(service_def(handler));
+
+    let _server = server_builder
+        .build()
+        .map_err(|e| Error::from(format!("Unable to serve on '{}': {}", address, e)))?;
+
+    info!("Serving the gRPC interface on '{}'...", address);
+
+    // the server runs on its own threads until the process is stopped
+    loop {
+        thread::park();
+    }
+}
+
+fn method<Req, Resp>(name: &str, streaming: GrpcStreaming) -> Arc<MethodDescriptor<Req, Resp>>
+where
+    Req: ::protobuf::Message,
+    Resp: ::protobuf::Message,
+{
+    Arc::new(MethodDescriptor {
+        name: format!("{}/{}", SERVICE_PATH, name),
+        streaming,
+        req_marshaller: Box::new(MarshallerProtobuf),
+        resp_marshaller: Box::new(MarshallerProtobuf),
+    })
+}
+
+fn service_def(handler: Arc<Handler>) -> ServerServiceDefinition {
+    let status_handler = handler.clone();
+    let apply_handler = handler.clone();
+    let stop_handler = handler;
+
+    ServerServiceDefinition::new(
+        SERVICE_PATH,
+        vec![
+            ServerMethod::new(
+                method::<StatusRequest, StatusReply>("Status", GrpcStreaming::Unary),
+                MethodHandlerUnary::new(move |o, p| status_handler.status(o, p)),
+            ),
+            ServerMethod::new(
+                method::<ApplyRequest, ServiceResult>("Apply", GrpcStreaming::ServerStreaming),
+                MethodHandlerServerStreaming::new(move |o, p: ApplyRequest| {
+                    apply_handler.run(o, p.get_names(), Operation::Apply)
+                }),
+            ),
+            ServerMethod::new(
+                method::<StopRequest, ServiceResult>("Stop", GrpcStreaming::ServerStreaming),
+                MethodHandlerServerStreaming::new(move |o, p: StopRequest| {
+                    stop_handler.run(o, p.get_names(), Operation::Stop)
+                }),
+            ),
+        ],
+    )
+}
+
+fn grpc_error(grpc_status: GrpcStatus, message: String) -> grpc::Error {
+    grpc::Error::GrpcMessage(grpc::GrpcMessageError {
+        grpc_status: grpc_status as i32,
+        grpc_message: message,
+    })
+}
+
+fn chain_message(e: &Error) -> String {
+    e.iter()
+        .map(|cause| cause.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+fn to_result(service_report: ServiceReport) -> ServiceResult {
+    let mut result = ServiceResult::new();
+    result.set_name(service_report.name);
+    result.set_outcome(format!("{:?}", service_report.outcome).to_lowercase());
+    result.set_actions(RepeatedField::from_vec(service_report.actions));
+    result.set_duration_ms(service_report.duration_ms);
+
+    if let Some(final_state) = service_report.final_state {
+        result.set_final_state(format!("{:?}", final_state));
+    }
+
+    result.set_errors(RepeatedField::from_vec(service_report.errors));
+    result
+}
+
+#[derive(Clone, Copy)]
+enum Operation {
+    Apply,
+    Stop,
+}
+
+impl Handler {
+    /// Loads the configuration of the services on the local machine with the given names,
+    /// or all the services if no name is given, after checking the token.
+    fn load(&self, options: &RequestOptions, names: &[String]) -> grpc::Result<FileConfig> {
+        let is_authorized = options
+            .metadata
+            .get(TOKEN_METADATA) == Some(self.token.as_bytes());
+
+        if !is_authorized {
+            return Err(grpc_error(
+                GrpcStatus::Unauthenticated,
+                "Missing or invalid token".to_owned(),
+            ));
+        }
+
+        let mut file_config = (self.load)()
+            .map_err(|e| grpc_error(GrpcStatus::FailedPrecondition, chain_message(&e)))?
+            .for_host(None);
+
+        if !names.is_empty() {
+            file_config
+                .retain_services(names)
+                .map_err(|e| grpc_error(GrpcStatus::NotFound, chain_message(&e)))?;
+        }
+
+        Ok(file_config)
+    }
+
+    fn status(
+        &self,
+        options: RequestOptions,
+        request: StatusRequest,
+    ) -> SingleResponse<StatusReply> {
+        let file_config = match self.load(&options, request.get_names()) {
+            Ok(file_config) => file_config,
+            Err(e) => return SingleResponse::err(e),
+        };
+
+        let statuses = match nssm_exec_status(&file_config) {
+            Ok(statuses) => statuses,
+            Err(e) => {
+                return SingleResponse::err(grpc_error(GrpcStatus::Internal, chain_message(&e)))
+            }
+        };
+
+        let mut reply = StatusReply::new();
+
+        for status in statuses {
+            let mut service_status = ServiceStatus::new();
+            service_status.set_name(status.name.to_owned());
+            service_status.set_installed(status.is_installed());
+
+            if let Some(state) = status.state {
+                service_status.set_state(format!("{:?}", state));
+            }
+
+            reply.mut_services().push(service_status);
+        }
+
+        SingleResponse::completed(reply)
+    }
+
+    fn run(
+        &self,
+        options: RequestOptions,
+        names: &[String],
+        operation: Operation,
+    ) -> StreamingResponse<ServiceResult> {
+        let mut file_config = match self.load(&options, names) {
+            Ok(file_config) => file_config,
+            Err(e) => return StreamingResponse::err(e),
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        file_config.run_report = Some(RunReport::with_listener(sender));
+
+        // the services are processed in the background while their results are streamed,
+        // and the stream ends when the run report with the sender is dropped
+        thread::spawn(move || {
+            let res = match operation {
+                Operation::Apply => nssm_exec_update(
+                    &file_config,
+                    &file_config.pending_stop_poll_interval(),
+                    file_config.pending_stop_poll_count(),
+                    &file_config.pending_start_poll_interval(),
+                    file_config.pending_start_poll_count(),
+                ),
+
+                Operation::Stop => nssm_exec_stop(
+                    &file_config,
+                    &file_config.pending_stop_poll_interval(),
+                    file_config.pending_stop_poll_count(),
+                ),
+            };
+
+            if let Err(e) = res {
+                print_recursive_err(&e);
+            }
+        });
+
+        StreamingResponse::iter(receiver.into_iter().map(to_result))
+    }
+}
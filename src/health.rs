@@ -0,0 +1,145 @@
+//! Checks the health probes of a started service, i.e. whether a TCP port accepts
+//! connections, whether a URL responds with the expected HTTP status, and whether a command
+//! exits successfully. The URLs are requested with the tools already available on the
+//! machine, i.e. PowerShell on Windows and curl elsewhere.
+
+use config::{FileConfig, Health};
+use errors::*;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use super::{run_cmd, run_cmd_line};
+
+/// Expected HTTP status of the URL when no other status is configured.
+pub const DEFAULT_HTTP_STATUS: u16 = 200;
+
+const TIMEOUT_SECS: u64 = 10;
+
+/// Checks every configured probe once, returning the error of the first failing probe.
+/// The TCP port and the command are checked on the host of the configuration, if set.
+pub fn check(health: &Health, file_config: &FileConfig) -> Result<()> {
+    if let Some(tcp_port) = health.tcp_port {
+        let host = file_config
+            .host
+            .as_ref()
+            .map_or("127.0.0.1", |host| host.as_str());
+
+        check_tcp(host, tcp_port)?;
+    }
+
+    if let Some(ref http_url) = health.http_url {
+        check_http(http_url, health.http_status.unwrap_or(DEFAULT_HTTP_STATUS))?;
+    }
+
+    if let Some(ref command) = health.command {
+        check_command(command, file_config)?;
+    }
+
+    Ok(())
+}
+
+fn check_tcp(host: &str, port: u16) -> Result<()> {
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .chain_err(|| format!("Unable to resolve '{}:{}'", host, port))?;
+
+    let mut last_err = None;
+
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, Duration::from_secs(TIMEOUT_SECS)) {
+            Ok(_) => return Ok(()),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    match last_err {
+        Some(e) => {
+            Err(e).chain_err(|| format!("Unable to connect to TCP port {} on '{}'", port, host))
+        }
+        None => bail!("Unable to resolve '{}:{}' to any address", host, port),
+    }
+}
+
+fn check_http(url: &str, expected_status: u16) -> Result<()> {
+    let status = imp::http_status(url)
+        .chain_err(|| format!("Unable to request '{}'", url))?;
+
+    if status != expected_status {
+        bail!(
+            "URL '{}' responded with HTTP status {} instead of {}",
+            url,
+            status,
+            expected_status
+        );
+    }
+
+    Ok(())
+}
+
+fn check_command(command: &str, file_config: &FileConfig) -> Result<()> {
+    run_cmd_line(command, file_config)
+        .map(|_| ())
+        .chain_err(|| format!("Health command '{}' failed", command))
+}
+
+fn parse_status(stdout: &[u8]) -> Result<u16> {
+    let stdout = String::from_utf8_lossy(stdout);
+
+    stdout
+        .trim()
+        .parse()
+        .chain_err(|| format!("Unable to parse HTTP status from '{}'", stdout.trim()))
+}
+
+#[cfg(windows)]
+mod imp {
+    use errors::*;
+    use std::path::Path;
+    use super::{parse_status, run_cmd, TIMEOUT_SECS};
+
+    pub fn http_status(url: &str) -> Result<u16> {
+        // error statuses are thrown by Invoke-WebRequest together with the response
+        let script = format!(
+            "$ProgressPreference = 'SilentlyContinue'; \
+             [Net.ServicePointManager]::SecurityProtocol = [Net.SecurityProtocolType]::Tls12; \
+             try {{ (Invoke-WebRequest -UseBasicParsing -TimeoutSec {} -Uri '{}').StatusCode }} \
+             catch {{ if ($_.Exception.Response) {{ [int]$_.Exception.Response.StatusCode }} \
+             else {{ throw }} }}",
+            TIMEOUT_SECS,
+            url.replace('\'', "''")
+        );
+
+        let output = run_cmd(
+            Path::new("powershell"),
+            &["-NoProfile", "-NonInteractive", "-Command", &script],
+        )?;
+
+        parse_status(&output.stdout)
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use errors::*;
+    use std::path::Path;
+    use super::{parse_status, run_cmd, TIMEOUT_SECS};
+
+    pub fn http_status(url: &str) -> Result<u16> {
+        let max_time = TIMEOUT_SECS.to_string();
+
+        let output = run_cmd(
+            Path::new("curl"),
+            &[
+                "-sS",
+                "-o",
+                "/dev/null",
+                "-w",
+                "%{http_code}",
+                "--max-time",
+                &max_time,
+                url,
+            ],
+        )?;
+
+        parse_status(&output.stdout)
+    }
+}
@@ -0,0 +1,654 @@
+//! Translates service definitions of other tools, i.e. systemd unit files and docker-compose
+//! files, into the services of the TOML configuration on a best-effort basis, warning about
+//! what cannot be translated.
+
+use errors::*;
+use file;
+use serde_yaml::{self, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use toml;
+
+/// Service translated from another tool, serialized the same way as the configured services.
+#[derive(Default, Serialize)]
+pub struct ImportedService {
+    name: String,
+    path: String,
+    startup_dir: Option<String>,
+    args: Option<Vec<String>>,
+    description: Option<String>,
+    stdout_log: Option<String>,
+    stderr_log: Option<String>,
+    exit_action: Option<ImportedExitAction>,
+    env: Option<BTreeMap<String, String>>,
+    other: Option<ImportedOther>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ImportedExitAction {
+    Default(&'static str),
+    PerCode {
+        default: &'static str,
+        codes: BTreeMap<String, &'static str>,
+    },
+}
+
+#[derive(Default, Serialize)]
+struct ImportedOther {
+    deps: Option<Vec<String>>,
+    restart_delay_ms: Option<u32>,
+    account: Option<ImportedAccount>,
+}
+
+#[derive(Serialize)]
+struct ImportedAccount {
+    user: String,
+}
+
+#[derive(Serialize)]
+struct ImportFile<'a> {
+    services: &'a [ImportedService],
+}
+
+/// Serializes the services as the `[[services]]` entries of a TOML configuration.
+pub fn to_toml(services: &[ImportedService]) -> Result<String> {
+    toml::to_string(&ImportFile { services })
+        .chain_err(|| "Unable to serialize imported services into TOML")
+}
+
+/// Serializes the services and writes them into the file at the path.
+pub fn write<P: AsRef<Path>>(services: &[ImportedService], path: P) -> Result<()> {
+    let path = path.as_ref();
+    let content = to_toml(services)?;
+
+    file::put_text(path, content).chain_err(|| {
+        format!("Unable to write imported services at '{}'", path.to_string_lossy())
+    })
+}
+
+/// Splits the value into words like systemd, where words may be wrapped in double or single
+/// quotes and backslashes escape the next character if `escapes` is set.
+fn split_words(value: &str, escapes: bool) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _) if escapes => {
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+
+                in_word = true;
+            }
+
+            ('"', None) | ('\'', None) => {
+                quote = Some(c);
+                in_word = true;
+            }
+
+            (_, Some(quote_char)) if c == quote_char => quote = None,
+
+            (_, None) if c.is_whitespace() => if in_word {
+                words.push(word.clone());
+                word.clear();
+                in_word = false;
+            },
+
+            _ => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Entries of a systemd unit file in order, as the section, the key and the value.
+struct Unit {
+    entries: Vec<(String, String, String)>,
+}
+
+impl Unit {
+    /// Parses the unit file, joining the lines continued with a trailing backslash.
+    fn parse(content: &str) -> Unit {
+        let mut entries = Vec::new();
+        let mut section = String::new();
+        let mut continued = String::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+
+            if continued.is_empty() && (line.starts_with('#') || line.starts_with(';')) {
+                continue;
+            }
+
+            if let Some(line) = line.strip_suffix('\\') {
+                continued.push_str(line);
+                continued.push(' ');
+                continue;
+            }
+
+            let line = format!("{}{}", continued, line);
+            continued.clear();
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].to_owned();
+            } else if let Some(separator_index) = line.find('=') {
+                entries.push((
+                    section.clone(),
+                    line[..separator_index].trim().to_owned(),
+                    line[separator_index + 1..].trim().to_owned(),
+                ));
+            }
+        }
+
+        Unit { entries }
+    }
+
+    /// Returns every value of the key in the section in order, where an empty value resets
+    /// the values before it like in systemd.
+    fn values(&self, section: &str, key: &str) -> Vec<&str> {
+        let mut values = Vec::new();
+
+        for (entry_section, entry_key, value) in &self.entries {
+            if entry_section == section && entry_key == key {
+                if value.is_empty() {
+                    values.clear();
+                } else {
+                    values.push(value.as_str());
+                }
+            }
+        }
+
+        values
+    }
+
+    fn value(&self, section: &str, key: &str) -> Option<&str> {
+        self.values(section, key).pop()
+    }
+}
+
+/// Parses the systemd time span in seconds by default, e.g. `5`, `5s`, `500ms` or `1min`,
+/// into milliseconds.
+fn parse_time_span_ms(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let unit_index = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+
+    let number: u32 = value[..unit_index].parse().ok()?;
+
+    let factor = match value[unit_index..].trim() {
+        "ms" | "msec" => 1,
+        "" | "s" | "sec" => 1000,
+        "m" | "min" => 60_000,
+        _ => return None,
+    };
+
+    number.checked_mul(factor)
+}
+
+/// Returns the path of the file that the output is redirected to, e.g. by `file:<path>`.
+fn output_path(value: Option<&str>) -> Option<String> {
+    value.and_then(|value| {
+        value
+            .strip_prefix("file:")
+            .or_else(|| value.strip_prefix("append:"))
+            .map(|path| path.to_owned())
+    })
+}
+
+/// Returns the exit action equivalent to the systemd `Restart` setting.
+fn exit_action(restart: Option<&str>) -> Option<ImportedExitAction> {
+    let mut success_codes = BTreeMap::new();
+
+    match restart.unwrap_or("no") {
+        // systemd does not restart by default unlike nssm
+        "no" => Some(ImportedExitAction::Default("Exit")),
+        "always" => Some(ImportedExitAction::Default("Restart")),
+
+        "on-failure" | "on-abnormal" | "on-abort" | "on-watchdog" => {
+            success_codes.insert("0".to_owned(), "Exit");
+
+            Some(ImportedExitAction::PerCode {
+                default: "Restart",
+                codes: success_codes,
+            })
+        }
+
+        "on-success" => {
+            success_codes.insert("0".to_owned(), "Restart");
+
+            Some(ImportedExitAction::PerCode {
+                default: "Exit",
+                codes: success_codes,
+            })
+        }
+
+        _ => None,
+    }
+}
+
+/// Translates the systemd unit at the path into a service named after the unit.
+fn from_systemd_unit(unit_path: &Path) -> Result<ImportedService> {
+    let content = file::get_text(unit_path).chain_err(|| {
+        format!(
+            "Unable to read unit file at '{}'",
+            unit_path.to_string_lossy()
+        )
+    })?;
+
+    let unit = Unit::parse(&content);
+
+    let file_name = unit_path
+        .file_name()
+        .map_or_else(String::new, |file_name| file_name.to_string_lossy().into_owned());
+
+    let name = file_name.trim_end_matches(".service").to_owned();
+
+    if name.contains('@') {
+        warn!("Unit '{}' is a template, which is imported as is", file_name);
+    }
+
+    let exec_starts = unit.values("Service", "ExecStart");
+
+    if exec_starts.len() > 1 {
+        warn!("Only the last ExecStart of unit '{}' is imported", file_name);
+    }
+
+    let exec_start = match exec_starts.last() {
+        Some(exec_start) => exec_start,
+        None => bail!("Unable to import unit '{}' without ExecStart", file_name),
+    };
+
+    // the prefixes only change how systemd runs the executable
+    let has_argv0 = exec_start
+        .chars()
+        .take_while(|c| "-@:+!".contains(*c))
+        .any(|c| c == '@');
+
+    let mut words = split_words(exec_start.trim_start_matches(|c| "-@:+!".contains(c)), true);
+
+    if has_argv0 && words.len() > 1 {
+        words.remove(1);
+    }
+
+    if words.is_empty() {
+        bail!("Unable to import unit '{}' with empty ExecStart", file_name);
+    }
+
+    let path = words.remove(0);
+
+    let startup_dir = unit.value("Service", "WorkingDirectory")
+        .map(|dir| dir.trim_start_matches('-'))
+        .filter(|dir| *dir != "~")
+        .map(|dir| dir.to_owned());
+
+    let mut env = BTreeMap::new();
+
+    for value in unit.values("Service", "Environment") {
+        for assignment in split_words(value, true) {
+            if let Some(separator_index) = assignment.find('=') {
+                env.insert(
+                    assignment[..separator_index].to_owned(),
+                    assignment[separator_index + 1..].to_owned(),
+                );
+            }
+        }
+    }
+
+    if !unit.values("Service", "EnvironmentFile").is_empty() {
+        warn!("EnvironmentFile of unit '{}' is not imported", file_name);
+    }
+
+    let mut deps: Vec<String> = Vec::new();
+
+    for value in unit.values("Unit", "After")
+        .into_iter()
+        .chain(unit.values("Unit", "Requires"))
+    {
+        // targets and other kinds of units have no counterpart
+        for dep in value.split_whitespace().filter(|dep| dep.ends_with(".service")) {
+            let dep = dep.trim_end_matches(".service").to_owned();
+
+            if !deps.contains(&dep) {
+                deps.push(dep);
+            }
+        }
+    }
+
+    let restart = unit.value("Service", "Restart");
+    let exit_action = exit_action(restart);
+
+    if exit_action.is_none() {
+        warn!(
+            "Restart={} of unit '{}' is not imported",
+            restart.unwrap_or(""),
+            file_name
+        );
+    }
+
+    let restart_delay_ms = unit.value("Service", "RestartSec").and_then(|restart_sec| {
+        let restart_delay_ms = parse_time_span_ms(restart_sec);
+
+        if restart_delay_ms.is_none() {
+            warn!(
+                "RestartSec={} of unit '{}' is not imported",
+                restart_sec,
+                file_name
+            );
+        }
+
+        restart_delay_ms
+    });
+
+    let account = unit.value("Service", "User").map(|user| {
+        warn!(
+            "User of unit '{}' is imported without password, which has to be configured",
+            file_name
+        );
+
+        ImportedAccount {
+            user: user.to_owned(),
+        }
+    });
+
+    match unit.value("Service", "Type") {
+        Some("forking") | Some("oneshot") => {
+            warn!("Type of unit '{}' has no counterpart in nssm", file_name)
+        }
+        _ => (),
+    }
+
+    let other = ImportedOther {
+        deps: if deps.is_empty() { None } else { Some(deps) },
+        restart_delay_ms,
+        account,
+    };
+
+    let has_other = other.deps.is_some() || other.restart_delay_ms.is_some()
+        || other.account.is_some();
+
+    Ok(ImportedService {
+        name,
+        path,
+        startup_dir,
+        args: if words.is_empty() { None } else { Some(words) },
+        description: unit.value("Unit", "Description").map(|value| value.to_owned()),
+        stdout_log: output_path(unit.value("Service", "StandardOutput")),
+        stderr_log: output_path(unit.value("Service", "StandardError")),
+        exit_action,
+        env: if env.is_empty() { None } else { Some(env) },
+        other: if has_other { Some(other) } else { None },
+    })
+}
+
+/// Translates the systemd units at the paths into services, in the same order.
+pub fn from_systemd_units<P: AsRef<Path>>(unit_paths: &[P]) -> Result<Vec<ImportedService>> {
+    unit_paths
+        .iter()
+        .map(|unit_path| from_systemd_unit(unit_path.as_ref()))
+        .collect()
+}
+
+/// Keys of a docker-compose service that are translated.
+const COMPOSE_KEYS: &[&str] = &[
+    "command",
+    "entrypoint",
+    "working_dir",
+    "environment",
+    "depends_on",
+    "restart",
+    "deploy",
+    "user",
+];
+
+/// Returns the words of the docker-compose value, given either as a list or as a command line.
+fn compose_words(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Sequence(words)) => words.iter().filter_map(compose_string).collect(),
+        // backslashes are kept as is for Windows paths
+        Some(Value::String(cmd_line)) => split_words(cmd_line, false),
+        _ => Vec::new(),
+    }
+}
+
+fn compose_string(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref value) => Some(value.clone()),
+        Value::Number(ref value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the variables of the docker-compose `environment`, given either as a mapping or as
+/// a list of `NAME=value`, skipping those passed through from the host without a value.
+fn compose_env(name: &str, environment: Option<&Value>) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+    let mut passed_through = Vec::new();
+
+    match environment {
+        Some(Value::Mapping(variables)) => for (key, value) in variables {
+            if let Some(key) = compose_string(key) {
+                match compose_string(value) {
+                    Some(value) => {
+                        env.insert(key, value);
+                    }
+                    None => passed_through.push(key),
+                }
+            }
+        },
+
+        Some(Value::Sequence(assignments)) => {
+            for assignment in assignments.iter().filter_map(compose_string) {
+                match assignment.find('=') {
+                    Some(separator_index) => {
+                        env.insert(
+                            assignment[..separator_index].to_owned(),
+                            assignment[separator_index + 1..].to_owned(),
+                        );
+                    }
+                    None => passed_through.push(assignment),
+                }
+            }
+        }
+
+        _ => (),
+    }
+
+    if !passed_through.is_empty() {
+        warn!(
+            "Environment variables {} of compose service '{}' without value are not imported",
+            passed_through.join(", "),
+            name
+        );
+    }
+
+    env
+}
+
+/// Returns the services in the docker-compose `depends_on`, given either as a list or as a
+/// mapping of the services to their conditions.
+fn compose_deps(depends_on: Option<&Value>) -> Vec<String> {
+    match depends_on {
+        Some(Value::Sequence(deps)) => deps.iter().filter_map(compose_string).collect(),
+
+        Some(Value::Mapping(deps)) => deps.iter()
+            .filter_map(|(dep, _)| compose_string(dep))
+            .collect(),
+
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the systemd `Restart` setting equivalent to the docker-compose `restart` policy, or
+/// to the `condition` of the `deploy.restart_policy` if there is no `restart`.
+fn compose_restart(name: &str, service: &Value) -> Option<&'static str> {
+    let restart = service.get("restart").and_then(Value::as_str);
+
+    let condition = service
+        .get("deploy")
+        .and_then(|deploy| deploy.get("restart_policy"))
+        .and_then(|restart_policy| restart_policy.get("condition"))
+        .and_then(Value::as_str);
+
+    match (restart, condition) {
+        (Some("no"), _) | (None, Some("none")) | (None, None) => Some("no"),
+        (Some("always"), _) | (Some("unless-stopped"), _) | (None, Some("any")) => Some("always"),
+
+        (Some(restart), _) if restart.starts_with("on-failure") => {
+            if restart != "on-failure" {
+                warn!(
+                    "Maximum retries of restart policy of compose service '{}' are not imported",
+                    name
+                );
+            }
+
+            Some("on-failure")
+        }
+
+        (None, Some("on-failure")) => Some("on-failure"),
+
+        (restart, condition) => {
+            warn!(
+                "Restart policy {} of compose service '{}' is not imported",
+                restart.or(condition).unwrap_or(""),
+                name
+            );
+
+            None
+        }
+    }
+}
+
+/// Translates the docker-compose service with the name into a service with the same name.
+fn from_compose_service(name: &str, service: &Value) -> Result<ImportedService> {
+    let keys = match *service {
+        Value::Mapping(ref keys) => keys,
+        _ => bail!("Unable to import compose service '{}' that is not a mapping", name),
+    };
+
+    // the entrypoint of the image is unknown without the entrypoint in the compose file
+    let mut words = compose_words(service.get("entrypoint"));
+    words.extend(compose_words(service.get("command")));
+
+    if words.is_empty() {
+        bail!(
+            "Unable to import compose service '{}' without command or entrypoint",
+            name
+        );
+    }
+
+    let path = words.remove(0);
+    let env = compose_env(name, service.get("environment"));
+    let deps = compose_deps(service.get("depends_on"));
+    let exit_action = exit_action(compose_restart(name, service));
+
+    let restart_delay_ms = service
+        .get("deploy")
+        .and_then(|deploy| deploy.get("restart_policy"))
+        .and_then(|restart_policy| restart_policy.get("delay"))
+        .and_then(Value::as_str)
+        .and_then(|delay| {
+            let restart_delay_ms = parse_time_span_ms(delay);
+
+            if restart_delay_ms.is_none() {
+                warn!(
+                    "Restart delay {} of compose service '{}' is not imported",
+                    delay,
+                    name
+                );
+            }
+
+            restart_delay_ms
+        });
+
+    let account = service.get("user").and_then(compose_string).map(|user| {
+        warn!(
+            "User of compose service '{}' is imported without password, which has to be \
+             configured",
+            name
+        );
+
+        ImportedAccount { user }
+    });
+
+    let unsupported: Vec<String> = keys.iter()
+        .filter_map(|(key, _)| compose_string(key))
+        .filter(|key| !COMPOSE_KEYS.contains(&key.as_str()))
+        .collect();
+
+    if !unsupported.is_empty() {
+        warn!(
+            "Settings {} of compose service '{}' are not imported",
+            unsupported.join(", "),
+            name
+        );
+    }
+
+    let other = ImportedOther {
+        deps: if deps.is_empty() { None } else { Some(deps) },
+        restart_delay_ms,
+        account,
+    };
+
+    let has_other = other.deps.is_some() || other.restart_delay_ms.is_some()
+        || other.account.is_some();
+
+    Ok(ImportedService {
+        name: name.to_owned(),
+        path,
+        startup_dir: service.get("working_dir").and_then(compose_string),
+        args: if words.is_empty() { None } else { Some(words) },
+        exit_action,
+        env: if env.is_empty() { None } else { Some(env) },
+        other: if has_other { Some(other) } else { None },
+        ..Default::default()
+    })
+}
+
+/// Translates the services of the docker-compose file at the path, in the same order.
+pub fn from_compose_file<P: AsRef<Path>>(compose_path: P) -> Result<Vec<ImportedService>> {
+    let compose_path = compose_path.as_ref();
+
+    let content = file::get_text(compose_path).chain_err(|| {
+        format!(
+            "Unable to read compose file at '{}'",
+            compose_path.to_string_lossy()
+        )
+    })?;
+
+    let compose: Value = serde_yaml::from_str(&content).chain_err(|| {
+        format!(
+            "Unable to interpret compose file content at '{}' as YAML",
+            compose_path.to_string_lossy()
+        )
+    })?;
+
+    let services = match compose.get("services") {
+        Some(Value::Mapping(services)) => services,
+        _ => bail!(
+            "Unable to find services in compose file at '{}'",
+            compose_path.to_string_lossy()
+        ),
+    };
+
+    services
+        .iter()
+        .map(|(name, service)| match compose_string(name) {
+            Some(name) => from_compose_service(&name, service),
+            None => bail!("Unable to import compose service with invalid name"),
+        })
+        .collect()
+}
@@ -0,0 +1,213 @@
+//! Generates a starter configuration by prompting for the nssm path and then for every
+//! service, so that new users do not need to learn the configuration schema first. The
+//! default logging configuration is written alongside it.
+
+use cmdline;
+use config::FileConfig;
+use dpapi;
+use errors::*;
+use file;
+use rpassword;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use toml;
+
+/// nssm path suggested when none is entered.
+pub const NSSM_PATH_DEFAULT: &str = r"third-party\nssm.exe";
+
+/// Default logging configuration, the same as the one shipped in the repository.
+pub const LOGGING_CONFIG: &str = include_str!("../config/logging_nssm_exec.yml");
+
+#[derive(Serialize)]
+struct InitFile {
+    nssm_path: String,
+    services: Vec<InitService>,
+}
+
+#[derive(Serialize)]
+struct InitService {
+    name: String,
+    path: String,
+    args: Option<Vec<String>>,
+    other: Option<InitOther>,
+}
+
+#[derive(Serialize)]
+struct InitOther {
+    account: InitAccount,
+}
+
+#[derive(Default, Serialize)]
+struct InitAccount {
+    user: String,
+    password: Option<String>,
+    password_env: Option<String>,
+    encrypted_password: Option<String>,
+}
+
+/// Prints the question and returns the trimmed answer, or the default if the answer is
+/// empty.
+fn ask(question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+
+    io::stdout().flush().chain_err(|| "Unable to print question")?;
+
+    let mut answer = String::new();
+
+    let read = io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .chain_err(|| "Unable to read answer")?;
+
+    if read == 0 {
+        bail!("Unable to read answer to '{}' since the input has ended", question);
+    }
+
+    let answer = answer.trim();
+
+    Ok(if answer.is_empty() {
+        default.to_owned()
+    } else {
+        answer.to_owned()
+    })
+}
+
+/// Prompts for the account of the service, or none for the default `LocalSystem`. An
+/// entered password is protected with DPAPI where available, so that it is not stored in
+/// plain text.
+fn ask_account() -> Result<Option<InitAccount>> {
+    let user = ask("  Account user, empty for LocalSystem", "")?;
+
+    if user.is_empty() {
+        return Ok(None);
+    }
+
+    let mut account = InitAccount {
+        user,
+        ..Default::default()
+    };
+
+    let password_env = ask(
+        "  Environment variable holding the password, empty to enter the password",
+        "",
+    )?;
+
+    if !password_env.is_empty() {
+        account.password_env = Some(password_env);
+        return Ok(Some(account));
+    }
+
+    let password = rpassword::prompt_password_stdout("  Password, empty for none: ")
+        .chain_err(|| "Unable to prompt for password")?;
+
+    if !password.is_empty() {
+        match dpapi::protect(&password) {
+            Ok(encrypted_password) => account.encrypted_password = Some(encrypted_password),
+            Err(_) => {
+                warn!("Unable to protect the password with DPAPI, storing it in plain text");
+                account.password = Some(password);
+            }
+        }
+    }
+
+    Ok(Some(account))
+}
+
+/// Prompts for the services until an empty name is entered.
+fn ask_services() -> Result<Vec<InitService>> {
+    let mut services: Vec<InitService> = Vec::new();
+
+    loop {
+        let name = ask("Service name, empty to finish", "")?;
+
+        if name.is_empty() {
+            return Ok(services);
+        }
+
+        if services.iter().any(|service| service.name == name) {
+            println!("  Service '{}' has already been entered", name);
+            continue;
+        }
+
+        let mut path = String::new();
+
+        while path.is_empty() {
+            path = ask("  Executable path", "")?;
+        }
+
+        let args = cmdline::split_args(&ask("  Arguments, empty for none", "")?);
+
+        services.push(InitService {
+            name,
+            path,
+            args: if args.is_empty() { None } else { Some(args) },
+            other: ask_account()?.map(|account| InitOther { account }),
+        });
+    }
+}
+
+/// Fails if the file exists, unless it may be overwritten.
+fn check_absent(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!(
+            "Unable to overwrite '{}', which already exists, without --force",
+            path.to_string_lossy()
+        );
+    }
+
+    Ok(())
+}
+
+/// Prompts for the nssm path and the services, and writes the configuration into the file at
+/// the configuration path and the default logging configuration into the file at the
+/// logging configuration path. Fails if either file exists unless `force` is set.
+pub fn init<P, Q>(config_path: P, log_config_path: Q, force: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let config_path = config_path.as_ref();
+    let log_config_path = log_config_path.as_ref();
+
+    check_absent(config_path, force)?;
+    check_absent(log_config_path, force)?;
+
+    let init_file = InitFile {
+        nssm_path: ask("nssm path", NSSM_PATH_DEFAULT)?,
+        services: ask_services()?,
+    };
+
+    let content =
+        toml::to_string(&init_file).chain_err(|| "Unable to serialize configuration into TOML")?;
+
+    // guards against writing a configuration that cannot be loaded back
+    toml::from_str::<FileConfig>(&content).chain_err(|| {
+        ErrorKind::ConfigParse("Unable to interpret the generated configuration".to_owned())
+    })?;
+
+    for path in &[config_path, log_config_path] {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent).chain_err(|| {
+                    format!("Unable to create directory at '{}'", parent.to_string_lossy())
+                })?;
+            }
+        }
+    }
+
+    file::put_text(config_path, content).chain_err(|| {
+        format!("Unable to write configuration at '{}'", config_path.to_string_lossy())
+    })?;
+
+    file::put_text(log_config_path, LOGGING_CONFIG).chain_err(|| {
+        format!(
+            "Unable to write logging configuration at '{}'",
+            log_config_path.to_string_lossy()
+        )
+    })
+}
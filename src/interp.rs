@@ -0,0 +1,184 @@
+//! Expands `${NAME}` environment variable references and renders `{{ name }}` variable
+//! references within configuration values, without expanding the inserted values again.
+//! A literal `${` may be written as `$${`.
+
+use errors::*;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+
+/// Expands every `${NAME}` reference in the value with the value returned by the lookup.
+/// Returns error if the lookup does not have any value for a referenced name, or if a
+/// reference is not terminated.
+pub fn expand_vars<F>(value: &str, lookup: F) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(index) = rest.find("${") {
+        // escaped as `$${`, which expands into a literal `${`
+        if rest[..index].ends_with('$') {
+            expanded.push_str(&rest[..index - 1]);
+            expanded.push_str("${");
+            rest = &rest[index + 2..];
+            continue;
+        }
+
+        expanded.push_str(&rest[..index]);
+
+        let name_start = index + 2;
+
+        let name_len = match rest[name_start..].find('}') {
+            Some(name_len) => name_len,
+            None => bail!("Unable to find closing '}}' of reference in '{}'", value),
+        };
+
+        let name = &rest[name_start..name_start + name_len];
+
+        match lookup(name) {
+            Some(var_value) => expanded.push_str(&var_value),
+            None => bail!("Unable to find variable '{}' referenced in '{}'", name, value),
+        }
+
+        rest = &rest[name_start + name_len + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expands every `${NAME}` reference in the value with the environment variable value.
+pub fn expand_env_vars(value: &str) -> Result<String> {
+    expand_vars(value, |name| env::var(name).ok())
+}
+
+/// Renders every `{{ name }}` reference in the value with the given variable value.
+/// Whitespace around the variable name is ignored.
+/// Returns error if a referenced variable does not exist, or if a reference is not terminated.
+pub fn render_vars(value: &str, variables: &BTreeMap<String, String>) -> Result<String> {
+    let mut rendered = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(index) = rest.find("{{") {
+        rendered.push_str(&rest[..index]);
+
+        let name_start = index + 2;
+
+        let name_len = match rest[name_start..].find("}}") {
+            Some(name_len) => name_len,
+            None => bail!("Unable to find closing '}}}}' of reference in '{}'", value),
+        };
+
+        let name = rest[name_start..name_start + name_len].trim();
+
+        match variables.get(name) {
+            Some(var_value) => rendered.push_str(var_value),
+            None => bail!("Unable to find variable '{}' referenced in '{}'", name, value),
+        }
+
+        rest = &rest[name_start + name_len + 2..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Expands every `${NAME}` reference in the value with the value returned by the lookup and
+/// renders every `{{ name }}` reference with the given variable value in a single pass, so
+/// that neither the environment variable values nor the variable values are expanded again,
+/// e.g. an environment variable value containing `{{` is kept as is.
+/// Returns error if a referenced name does not have any value, or if a reference is not
+/// terminated.
+pub fn interpolate<F>(
+    value: &str,
+    variables: &BTreeMap<String, String>,
+    lookup: F,
+) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut interpolated = String::with_capacity(value.len());
+    let mut rest = value;
+
+    loop {
+        let env_index = rest.find("${");
+        let var_index = rest.find("{{");
+
+        let (index, is_env) = match (env_index, var_index) {
+            (Some(env_index), Some(var_index)) if var_index < env_index => (var_index, false),
+            (Some(env_index), _) => (env_index, true),
+            (None, Some(var_index)) => (var_index, false),
+            (None, None) => break,
+        };
+
+        // escaped as `$${`, which expands into a literal `${`
+        if is_env && rest[..index].ends_with('$') {
+            interpolated.push_str(&rest[..index - 1]);
+            interpolated.push_str("${");
+            rest = &rest[index + 2..];
+            continue;
+        }
+
+        interpolated.push_str(&rest[..index]);
+
+        let name_start = index + 2;
+        let closing = if is_env { "}" } else { "}}" };
+
+        let name_len = match rest[name_start..].find(closing) {
+            Some(name_len) => name_len,
+            None => bail!(
+                "Unable to find closing '{}' of reference in '{}'",
+                closing,
+                value
+            ),
+        };
+
+        let name = &rest[name_start..name_start + name_len];
+
+        let var_value = if is_env {
+            lookup(name)
+        } else {
+            variables.get(name.trim()).cloned()
+        };
+
+        match var_value {
+            Some(var_value) => interpolated.push_str(&var_value),
+            None => bail!(
+                "Unable to find variable '{}' referenced in '{}'",
+                name.trim(),
+                value
+            ),
+        }
+
+        rest = &rest[name_start + name_len + closing.len()..];
+    }
+
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+/// Expands every `${NAME}` reference in the value with the environment variable value and
+/// renders every `{{ name }}` reference with the given variable value in a single pass.
+pub fn interpolate_env(value: &str, variables: &BTreeMap<String, String>) -> Result<String> {
+    interpolate(value, variables, |name| env::var(name).ok())
+}
+
+/// Expands the string value in place with the given expansion.
+pub fn expand_in<F>(value: &mut String, expand: &F) -> Result<()>
+where
+    F: Fn(&str) -> Result<String>,
+{
+    *value = expand(value)?;
+    Ok(())
+}
+
+/// Expands the path value in place with the given expansion.
+pub fn expand_in_path<F>(path: &mut PathBuf, expand: &F) -> Result<()>
+where
+    F: Fn(&str) -> Result<String>,
+{
+    *path = PathBuf::from(expand(&path.to_string_lossy())?);
+    Ok(())
+}
@@ -0,0 +1,2410 @@
+//! Library to facilitate easy adding of nssm services based on predefined TOML or YAML
+//! configuration.
+//!
+//! The `nssm_exec` binary is a thin command line wrapper over the functions exposed here.
+
+#[macro_use]
+extern crate derive_error_chain;
+#[macro_use]
+extern crate error_chain;
+extern crate chrono;
+extern crate crossbeam;
+extern crate crossterm;
+extern crate file;
+extern crate glob;
+extern crate grpc;
+extern crate itertools;
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate log;
+extern crate protobuf;
+extern crate ratatui;
+extern crate rpassword;
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_ignored;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate sha2;
+extern crate strsim;
+extern crate tiny_http;
+extern crate toml;
+#[cfg(windows)]
+extern crate winapi;
+extern crate zip;
+
+pub mod agent;
+pub mod ansible;
+pub(crate) mod artifact;
+pub mod audit;
+pub(crate) mod backend;
+pub mod bootstrap;
+pub(crate) mod cmdline;
+pub(crate) mod config;
+pub(crate) mod credman;
+pub mod dpapi;
+pub(crate) mod download;
+pub mod elevation;
+pub mod errors;
+pub mod explain;
+pub(crate) mod firewall;
+pub mod grpc_server;
+pub(crate) mod health;
+pub mod import;
+pub mod init;
+pub(crate) mod interp;
+pub(crate) mod logdir;
+pub(crate) mod lsa;
+pub mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(not(feature = "mock"))]
+pub(crate) mod mock;
+pub(crate) mod nssm;
+pub mod notify;
+pub(crate) mod output;
+pub(crate) mod params;
+pub(crate) mod recovery;
+pub(crate) mod registry;
+pub(crate) mod remote;
+pub(crate) mod report;
+pub(crate) mod sc;
+pub(crate) mod scm;
+pub mod script;
+pub mod serve;
+pub mod session;
+pub(crate) mod shawl;
+pub mod snapshot;
+pub(crate) mod state;
+pub(crate) mod strict;
+pub(crate) mod systemd;
+pub(crate) mod transcript;
+pub mod tui;
+pub mod validate;
+pub mod version;
+pub(crate) mod winsw;
+
+pub use audit::AuditLog;
+pub use config::{Account, Agent, Args, Artifact, Backend, Bootstrap, BuiltinAccount,
+                 DrainCheck, EmailNotify, ExitAction, ExitActionConfig, FileConfig, Firewall,
+                 GreenCopy, Health, Hooks, LogRotation, Metrics, NameList, Notify, OtherConfig,
+                 OtherConfigRef, Priority, Profile, Protocol, Recovery, RecoveryAction, Serve,
+                 Service, StopMethod, WebhookNotify, PENDING_POLL_DEFAULT_COUNT,
+                 PENDING_POLL_DEFAULT_MS};
+pub use params::{ParamDiff, ServiceParam};
+pub use report::{Outcome, RunReport, ServiceReport};
+pub use session::Session;
+pub use snapshot::Snapshot;
+pub use state::{state_from_str, ServiceState};
+pub use transcript::Transcript;
+pub use version::NssmVersion;
+
+use errors::*;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+trait ChainService<T> {
+    fn chain_service_msg(self, description: &str, service_name: &str) -> Result<T>;
+}
+
+impl<T, E> ChainService<T> for std::result::Result<T, E>
+where
+    E: std::error::Error + Send + 'static,
+{
+    fn chain_service_msg(self, description: &str, service_name: &str) -> Result<T> {
+        self.chain_err(|| format!("{} service '{}'", description, service_name))
+    }
+}
+
+/// Interval in milliseconds between the checks of whether a command with a timeout has
+/// exited.
+const CMD_POLL_INTERVAL_MS: u64 = 50;
+
+/// Runs the program directly with the given arguments without going through any shell,
+/// so that the arguments are received by the program exactly as given.
+pub(crate) fn run_cmd<S: AsRef<str>>(program: &Path, args: &[S]) -> Result<Output> {
+    run_cmd_with_timeout(program, args, None)
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut bytes)?;
+        }
+
+        Ok(bytes)
+    })
+}
+
+/// Waits for the child to exit while collecting its output, killing it if it has not exited
+/// within the timeout, in which case `None` is returned.
+fn wait_with_timeout(mut child: Child, timeout: &Duration) -> io::Result<Option<Output>> {
+    // the pipes are drained concurrently so that the child never blocks on a full pipe
+    let stdout_reader = read_pipe(child.stdout.take());
+    let stderr_reader = read_pipe(child.stderr.take());
+    let start_time = Instant::now();
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if start_time.elapsed() >= *timeout {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+
+        thread::sleep(Duration::from_millis(CMD_POLL_INTERVAL_MS));
+    };
+
+    let join = |reader: JoinHandle<io::Result<Vec<u8>>>| {
+        reader
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("Reader panicked")))
+    };
+
+    Ok(Some(Output {
+        status,
+        stdout: join(stdout_reader)?,
+        stderr: join(stderr_reader)?,
+    }))
+}
+
+/// Runs the program like `run_cmd`, killing it and failing if it has not exited within the
+/// timeout if set.
+pub(crate) fn run_cmd_with_timeout<S: AsRef<str>>(
+    program: &Path,
+    args: &[S],
+    timeout: Option<Duration>,
+) -> Result<Output> {
+    let cmd_line = cmdline::to_cmd_line(&program.to_string_lossy(), args);
+    debug!("{}", cmd_line);
+
+    let mut cmd = Command::new(program);
+    cmd.args(args.iter().map(|arg| arg.as_ref()));
+
+    let output = match timeout {
+        Some(timeout) => {
+            let child = cmd.stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .chain_err(|| ErrorKind::CommandUnavailable { cmd: cmd_line.clone() })?;
+
+            let output = wait_with_timeout(child, &timeout)
+                .chain_err(|| format!("Unable to wait for command '{}'", cmd_line))?;
+
+            match output {
+                Some(output) => output,
+                None => bail!(ErrorKind::CommandTimeout {
+                    cmd: cmd_line,
+                    timeout_secs: timeout.as_secs(),
+                }),
+            }
+        }
+
+        None => cmd.output()
+            .chain_err(|| ErrorKind::CommandUnavailable { cmd: cmd_line.clone() })?,
+    };
+
+    if !output.status.success() {
+        // nssm writes UTF-16LE, unlike the other programs
+        bail!(ErrorKind::CommandFailed {
+            cmd: cmd_line,
+            code: output.status.code(),
+            stdout: output::decode(&output.stdout).trim().to_owned(),
+            stderr: output::decode(&output.stderr).trim().to_owned(),
+        });
+    }
+
+    Ok(output)
+}
+
+/// Fails if the command cannot be run or has exited unsuccessfully. The command line is
+/// given by name only, since its arguments and environment may hold secrets.
+pub(crate) fn check_output(cmd_name: &str, output: io::Result<Output>) -> Result<()> {
+    let output = output.chain_err(|| ErrorKind::CommandUnavailable {
+        cmd: cmd_name.to_owned(),
+    })?;
+
+    if !output.status.success() {
+        bail!(ErrorKind::CommandFailed {
+            cmd: cmd_name.to_owned(),
+            code: output.status.code(),
+            stdout: output::decode(&output.stdout).trim().to_owned(),
+            stderr: output::decode(&output.stderr).trim().to_owned(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Appends the command that has been run to the audit log of the configuration if set, with
+/// the exit code of its result. A failure to append is only logged, since the command has
+/// already been run.
+pub(crate) fn audit_cmd<S: AsRef<str>>(
+    file_config: &FileConfig,
+    program: &Path,
+    args: &[S],
+    res: &Result<Output>,
+) {
+    let audit_log = match file_config.audit {
+        Some(ref audit_log) => audit_log,
+        None => return,
+    };
+
+    let exit_code = match *res {
+        Ok(ref output) => output.status.code(),
+        Err(ref e) => match *e.kind() {
+            ErrorKind::CommandFailed { code, .. } => code,
+            _ => None,
+        },
+    };
+
+    let host = file_config.host.as_deref();
+
+    if let Err(e) = audit_log.append(&program.to_string_lossy(), args, host, exit_code) {
+        print_recursive_err(&e);
+    }
+}
+
+/// Records the command that has been run with its result into the session of the
+/// configuration if set.
+pub(crate) fn record_cmd<S: AsRef<str>>(
+    file_config: &FileConfig,
+    program: &Path,
+    args: &[S],
+    res: &Result<Output>,
+) {
+    if let Some(ref session) = file_config.session {
+        let host = file_config.host.as_deref();
+        session.record(program, args, host, res);
+    }
+}
+
+/// Runs the program with the given arguments on the remote host of the configuration if set,
+/// or on the local machine otherwise, with the global command timeout.
+pub(crate) fn run_host_cmd<S: AsRef<str>>(
+    program: &Path,
+    args: &[S],
+    file_config: &FileConfig,
+) -> Result<Output> {
+    run_host_cmd_with_timeout(program, args, file_config, file_config.command_timeout(None))
+}
+
+fn run_host_cmd_with_timeout<S: AsRef<str>>(
+    program: &Path,
+    args: &[S],
+    file_config: &FileConfig,
+    timeout: Option<Duration>,
+) -> Result<Output> {
+    match file_config.host {
+        Some(ref host) => {
+            debug!(
+                "[{}] {}",
+                host,
+                cmdline::to_cmd_line(&program.to_string_lossy(), args)
+            );
+
+            let remote_args = remote::args(host, program, args);
+
+            let res = run_cmd_with_timeout(remote::program(), &remote_args, timeout);
+            audit_cmd(file_config, program, args, &res);
+            record_cmd(file_config, program, args, &res);
+
+            res.chain_err(|| {
+                format!(
+                    "Unable to run '{}' on host '{}'",
+                    program.to_string_lossy(),
+                    host
+                )
+            })
+        }
+
+        None => {
+            let res = run_cmd_with_timeout(program, args, timeout);
+            audit_cmd(file_config, program, args, &res);
+            record_cmd(file_config, program, args, &res);
+            res
+        }
+    }
+}
+
+/// Logs the operation that a dry run skips, prefixed with the remote host of the
+/// configuration if set, and records it into the transcript of the configuration if set.
+pub(crate) fn log_dry_run(file_config: &FileConfig, operation: &str) {
+    let line = match file_config.host {
+        Some(ref host) => format!("[{}] {}", host, operation),
+        None => operation.to_owned(),
+    };
+
+    info!("[DRY RUN] {}", line);
+
+    if let Some(ref transcript) = file_config.transcript {
+        transcript.record(&line);
+    }
+}
+
+/// Logs the command that a dry run skips like `log_dry_run`, replacing the secrets in its
+/// arguments for the transcript before they are quoted into the command line.
+pub(crate) fn log_dry_run_cmd<S: AsRef<str>>(file_config: &FileConfig, program: &str, args: &[S]) {
+    let args = match file_config.transcript {
+        Some(ref transcript) => transcript.redact_args(args),
+        None => args.iter().map(|arg| arg.as_ref().to_owned()).collect(),
+    };
+
+    log_dry_run(file_config, &cmdline::to_cmd_line(program, &args));
+}
+
+/// Runs the program with the given arguments that modify the machine, on the remote host of
+/// the configuration if set. Only logs the command without running it if dry run is enabled.
+pub(crate) fn run_host_modify_cmd<S: AsRef<str>>(
+    program: &Path,
+    args: &[S],
+    file_config: &FileConfig,
+) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run_cmd(file_config, &program.to_string_lossy(), args);
+
+        return Ok(());
+    }
+
+    run_host_cmd(program, args, file_config).map(|_| ())
+}
+
+/// Runs the command line, whose first argument is the program, on the remote host of the
+/// configuration if set.
+pub(crate) fn run_cmd_line(cmd_line: &str, file_config: &FileConfig) -> Result<Output> {
+    let mut args = cmdline::split_args(cmd_line);
+
+    if args.is_empty() {
+        bail!("Unable to run an empty command line");
+    }
+
+    let program = args.remove(0);
+    run_host_cmd(Path::new(&program), &args, file_config)
+}
+
+/// Runs the given nssm arguments with the configured nssm executable, on the remote host
+/// of the configuration if set, with the command timeout of the service operated on.
+pub(crate) fn run_nssm_cmd(args: &[String], file_config: &FileConfig) -> Result<Output> {
+    // the service name always follows the nssm command
+    let timeout = file_config.command_timeout(args.get(1).map(|arg| arg.as_str()));
+    run_host_cmd_with_timeout(&file_config.nssm_path, args, file_config, timeout)
+}
+
+/// Messages of the Service Control Manager errors that are likely gone after a while, i.e.
+/// `ERROR_SERVICE_DATABASE_LOCKED`, `ERROR_SERVICE_MARKED_FOR_DELETE`,
+/// `ERROR_SERVICE_CANNOT_ACCEPT_CTRL` and `ERROR_SERVICE_REQUEST_TIMEOUT`, in lower case.
+const TRANSIENT_FAILURE_MESSAGES: &[&str] = &[
+    "database is locked",
+    "marked for deletion",
+    "cannot accept control messages at this time",
+    "did not respond to the start or control request in a timely fashion",
+];
+
+/// Returns true if the command has failed for a reason that is likely gone after a while,
+/// e.g. while the Service Control Manager is busy or the previous service is still marked
+/// for deletion, which is worth retrying.
+fn is_transient_failure(e: &Error) -> bool {
+    match *e.kind() {
+        ErrorKind::CommandFailed {
+            ref stdout,
+            ref stderr,
+            ..
+        } => {
+            let output = format!("{}\n{}", stdout, stderr).to_lowercase();
+
+            TRANSIENT_FAILURE_MESSAGES
+                .iter()
+                .any(|message| output.contains(message))
+        }
+        _ => false,
+    }
+}
+
+/// Runs the given nssm arguments that modify any service, retrying them up to the configured
+/// number of times while they fail transiently. Any other failure is returned immediately.
+/// Only logs the command without running it if dry run is enabled.
+pub(crate) fn run_nssm_modify_cmd(args: &[String], file_config: &FileConfig) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run_cmd(file_config, &file_config.nssm_path.to_string_lossy(), args);
+
+        return Ok(());
+    }
+
+    // the service name always follows the nssm command
+    let service_name = args.get(1).map(|arg| arg.as_str());
+    let retries = file_config.command_retries(service_name);
+    let mut attempt = 0;
+
+    loop {
+        let e = match run_nssm_cmd(args, file_config) {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        if attempt >= retries || !is_transient_failure(&e) {
+            return Err(e);
+        }
+
+        attempt += 1;
+
+        warn!(
+            "nssm {} failed transiently ({}), retrying {}/{}...",
+            args.first().map(|arg| arg.as_str()).unwrap_or(""),
+            e,
+            attempt,
+            retries
+        );
+
+        thread::sleep(file_config.command_retry_delay(service_name));
+    }
+}
+
+fn set_service_param(
+    service_name: &str,
+    param: &ServiceParam,
+    file_config: &FileConfig,
+) -> Result<()> {
+    backend::set_param(service_name, param, file_config).chain_service_msg(
+        &format!("Unable to set '{}' for", param.key()),
+        service_name,
+    )?;
+
+    record_action(file_config, service_name, format!("set {}", param.key()));
+    Ok(())
+}
+
+/// Sets the parameters of the service in order. If registry direct mode is enabled for an
+/// nssm service, the parameters stored under its `Parameters` registry key are written
+/// in a single pass first, and only the rest are set through nssm.
+fn set_service_params(
+    service_name: &str,
+    params: &[ServiceParam],
+    file_config: &FileConfig,
+) -> Result<()> {
+    let remaining = if file_config.is_registry_direct()
+        && file_config.backend(service_name) == Backend::Nssm
+    {
+        let remaining = registry::write_params(service_name, params, file_config)?;
+
+        for param in params.iter().filter(|param| !remaining.contains(param)) {
+            record_action(file_config, service_name, format!("set {}", param.key()));
+        }
+
+        remaining
+    } else {
+        params.to_vec()
+    };
+
+    for param in &remaining {
+        set_service_param(service_name, param, file_config)?;
+    }
+
+    Ok(())
+}
+
+/// Queries nssm for the current state of the given service, where the service control
+/// manager cannot be queried directly.
+/// Returns error if the service does not exist.
+#[cfg(not(windows))]
+pub(crate) fn run_nssm_status_cmd_extract_status(
+    service_name: &str,
+    file_config: &FileConfig,
+) -> Result<ServiceState> {
+    run_nssm_cmd(&nssm::status(service_name), file_config).and_then(|output| {
+        let status = output::decode(&output.stdout);
+        state_from_str(status.trim())
+    })
+}
+
+/// Polls the state of the given service until it reaches the expected state,
+/// sleeping for the poll interval in between. Returns error on timeout.
+pub fn poll_service_state_until(
+    service_name: &str,
+    file_config: &FileConfig,
+    poll_interval: &Duration,
+    poll_count: u64,
+    expected_state: ServiceState,
+) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run(
+            file_config,
+            &format!("Wait for service '{}' to be in state {:?}", service_name, expected_state),
+        );
+
+        return Ok(());
+    }
+
+    let status_check_iter = (0..poll_count).map(|_| {
+        record_poll(file_config, service_name);
+
+        backend::status(service_name, file_config)
+            .map(|status| status == expected_state)
+            .unwrap_or(false)
+    });
+
+    // starts from 1 to reduce the count by 1 and prevent underflow
+    let between_delay_iter = (1..poll_count).map(|_| {
+        info!(
+            "Service '{}' is still not in state {:?}, waiting...",
+            service_name,
+            expected_state
+        );
+
+        thread::sleep(*poll_interval);
+        false
+    });
+
+    let state_reached = itertools::interleave(status_check_iter, between_delay_iter)
+        .any(|reached| reached);
+
+    if !state_reached {
+        bail!(ErrorKind::ServiceTimeout {
+            name: service_name.to_owned(),
+            expected_state,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks the health probes of the given service until all of them pass, sleeping for the
+/// poll interval in between. Returns the error of the last failing probe on timeout.
+pub(crate) fn poll_service_health(
+    service_name: &str,
+    health: &Health,
+    file_config: &FileConfig,
+    poll_interval: &Duration,
+    poll_count: u64,
+) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run(file_config, &format!("Check health of service '{}'", service_name));
+        return Ok(());
+    }
+
+    let mut attempt = 1;
+
+    loop {
+        let e = match health::check(health, file_config) {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        if attempt >= poll_count {
+            return Err(e).chain_service_msg("Timeout waiting for health of", service_name);
+        }
+
+        info!(
+            "Service '{}' is still not healthy ({}), waiting...",
+            service_name,
+            e
+        );
+
+        thread::sleep(*poll_interval);
+        attempt += 1;
+    }
+}
+
+/// Runs the drain check command of the service until it succeeds, waiting for the poll
+/// interval of the drain check in between.
+/// Returns error if the command still fails after the poll count of the drain check.
+fn poll_service_drain(
+    service_name: &str,
+    drain_check: &DrainCheck,
+    file_config: &FileConfig,
+) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run(
+            file_config,
+            &format!(
+                "Wait for drain check of service '{}': {}",
+                service_name, drain_check.command
+            ),
+        );
+
+        return Ok(());
+    }
+
+    let poll_count = drain_check.poll_count();
+    let mut attempt = 1;
+
+    loop {
+        let e = match run_cmd_line(&drain_check.command, file_config) {
+            Ok(_) => break,
+            Err(e) => e,
+        };
+
+        if attempt >= poll_count {
+            return Err(e).chain_service_msg("Timeout waiting for drain of", service_name);
+        }
+
+        info!(
+            "Service '{}' is still not drained ({}), waiting...",
+            service_name,
+            e
+        );
+
+        thread::sleep(drain_check.poll_interval());
+        attempt += 1;
+    }
+
+    record_action(file_config, service_name, "drained".to_owned());
+    Ok(())
+}
+
+/// Checks that the application process of the given service is running and is still the same
+/// process after the poll interval, i.e. that it has not exited and been restarted by nssm.
+fn check_app_process(
+    service_name: &str,
+    file_config: &FileConfig,
+    poll_interval: &Duration,
+) -> Result<()> {
+    let process_id = scm::app_process_id(service_name, file_config)?;
+    thread::sleep(*poll_interval);
+
+    match scm::app_process_id(service_name, file_config) {
+        Ok(current_process_id) if current_process_id == process_id => Ok(()),
+        Ok(_) => bail!("Application process {} has exited and restarted", process_id),
+        Err(e) => Err(e).chain_err(|| format!("Application process {} has exited", process_id)),
+    }
+}
+
+/// Checks that the application process of the given service keeps running until it does,
+/// sleeping for the poll interval in between. Returns the error of the last check on timeout.
+pub(crate) fn poll_service_process(
+    service_name: &str,
+    file_config: &FileConfig,
+    poll_interval: &Duration,
+    poll_count: u64,
+) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run(
+            file_config,
+            &format!("Verify application process of service '{}'", service_name),
+        );
+
+        return Ok(());
+    }
+
+    let mut attempt = 1;
+
+    loop {
+        let e = match check_app_process(service_name, file_config, poll_interval) {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        if attempt >= poll_count {
+            return Err(e).chain_service_msg(
+                "Timeout waiting for the application process of",
+                service_name,
+            );
+        }
+
+        info!(
+            "Application process of service '{}' is still not running ({}), waiting...",
+            service_name,
+            e
+        );
+
+        thread::sleep(*poll_interval);
+        attempt += 1;
+    }
+}
+
+fn record_action(file_config: &FileConfig, service_name: &str, action: String) {
+    if let Some(ref run_report) = file_config.run_report {
+        run_report.record_action(service_name, action);
+    }
+}
+
+fn record_poll(file_config: &FileConfig, service_name: &str) {
+    if let Some(ref run_report) = file_config.run_report {
+        run_report.record_poll(service_name);
+    }
+}
+
+fn record_service(
+    file_config: &FileConfig,
+    service_name: &str,
+    res: Option<&Result<()>>,
+    start_time: Instant,
+) {
+    if let Some(ref run_report) = file_config.run_report {
+        // unable to get status probably means that the service does not exist
+        let final_state = if res.is_some() {
+            backend::status(service_name, file_config).ok()
+        } else {
+            None
+        };
+
+        run_report.record_service(service_name, res, start_time.elapsed(), final_state);
+    }
+}
+
+/// Runs the command line of the hook of the service if configured, failing if the command
+/// fails. Only logs the command line without running it if dry run is enabled.
+fn run_hook(
+    service_name: &str,
+    hook: &str,
+    cmd_line: Option<&String>,
+    file_config: &FileConfig,
+) -> Result<()> {
+    let cmd_line = match cmd_line {
+        Some(cmd_line) => cmd_line,
+        None => return Ok(()),
+    };
+
+    if file_config.is_dry_run() {
+        log_dry_run(
+            file_config,
+            &format!("Run {} hook of service '{}': {}", hook, service_name, cmd_line),
+        );
+
+        return Ok(());
+    }
+
+    debug!("Running {} hook of service '{}'...", hook, service_name);
+
+    run_cmd_line(cmd_line, file_config)
+        .chain_err(|| format!("Hook {} of service '{}' failed", hook, service_name))?;
+
+    record_action(file_config, service_name, format!("ran {}", hook));
+    Ok(())
+}
+
+/// Returns the configured service with the given name, if any, e.g. to look up its
+/// settings when only the name of the service is known.
+fn find_service<'a>(file_config: &'a FileConfig, service_name: &str) -> Option<&'a Service> {
+    file_config
+        .services
+        .iter()
+        .find(|service| service.name == service_name)
+}
+
+fn do_service_stop(
+    service_name: &str,
+    file_config: &FileConfig,
+    state: ServiceState,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+) -> Result<()> {
+    if state != ServiceState::Stopped {
+        if let Some(service) = find_service(file_config, service_name) {
+            let pre_stop = file_config.merged_other(service).pre_stop;
+            run_hook(service_name, "pre_stop", pre_stop, file_config)?;
+
+            if let Some(ref drain_check) = service.drain_check {
+                poll_service_drain(service_name, drain_check, file_config)?;
+            }
+        }
+
+        // sometimes the error message happens
+        // "Unexpected status SERVICE_STOP_PENDING in response to STOP control"
+        // even though the service will eventually stop
+        // so allow for this to happen
+
+        let stop_res = backend::stop(service_name, file_config).chain_service_msg(
+            "Service stopping returned error, temporarily allowing this for",
+            service_name,
+        );
+
+        if let Err(e) = stop_res {
+            print_recursive_warning(&e);
+        }
+
+        // sometimes it takes a while to stop the service so wait for it
+        let poll_res = poll_service_state_until(
+            service_name,
+            file_config,
+            pending_stop_poll_interval,
+            pending_stop_poll_count,
+            ServiceState::Stopped,
+        );
+
+        match poll_res {
+            Ok(()) => record_action(file_config, service_name, "stopped".to_owned()),
+
+            Err(e) => {
+                if !is_kill_tree(service_name, file_config) {
+                    return Err(e);
+                }
+
+                print_recursive_warning(&e);
+
+                kill_service_tree(
+                    service_name,
+                    file_config,
+                    pending_stop_poll_interval,
+                    pending_stop_poll_count,
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true if the process tree of the service is terminated when it does not stop.
+fn is_kill_tree(service_name: &str, file_config: &FileConfig) -> bool {
+    find_service(file_config, service_name)
+        .and_then(|service| file_config.merged_other(service).stop_method)
+        .is_some_and(|stop_method| stop_method.is_kill_tree())
+}
+
+/// Terminates the process tree of the service that has not stopped, and waits for the
+/// service to be stopped with the same poll budget as the stop.
+fn kill_service_tree(
+    service_name: &str,
+    file_config: &FileConfig,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+) -> Result<()> {
+    warn!(
+        "Service '{}' has not stopped, killing its process tree...",
+        service_name
+    );
+
+    backend::kill_tree(service_name, file_config)
+        .chain_service_msg("Unable to kill the process tree of", service_name)?;
+
+    poll_service_state_until(
+        service_name,
+        file_config,
+        pending_stop_poll_interval,
+        pending_stop_poll_count,
+        ServiceState::Stopped,
+    )?;
+
+    record_action(file_config, service_name, "killed process tree".to_owned());
+    Ok(())
+}
+
+fn do_service_start(
+    service_name: &str,
+    file_config: &FileConfig,
+    pending_start_poll_interval: &Duration,
+    pending_start_poll_count: u64,
+) -> Result<()> {
+    if let Some(service) = find_service(file_config, service_name) {
+        do_service_log_dirs(service, file_config)?;
+    }
+
+    let start_res = backend::start(service_name, file_config).chain_service_msg(
+        "Service starting returned error, temporarily allowing this for",
+        service_name,
+    );
+
+    if let Err(e) = start_res {
+        print_recursive_warning(&e);
+    }
+
+    // may take some time to start the service
+    poll_service_state_until(
+        service_name,
+        file_config,
+        pending_start_poll_interval,
+        pending_start_poll_count,
+        ServiceState::Running,
+    )?;
+
+    record_action(file_config, service_name, "started".to_owned());
+
+    let service = match find_service(file_config, service_name) {
+        Some(service) => service,
+        None => return Ok(()),
+    };
+
+    // the service process keeps running while nssm throttles a crashing application
+    let verifies_process = service.verify_process == Some(true)
+        && file_config.backend(service_name) == Backend::Nssm
+        && file_config.host.is_none();
+
+    if verifies_process {
+        poll_service_process(
+            service_name,
+            file_config,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        )?;
+
+        record_action(file_config, service_name, "verified process".to_owned());
+    }
+
+    // running alone does not mean that the executable is ready to serve
+    if let Some(ref health) = service.health {
+        poll_service_health(
+            service_name,
+            health,
+            file_config,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        )?;
+
+        record_action(file_config, service_name, "healthy".to_owned());
+    }
+
+    let post_start = file_config.merged_other(service).post_start;
+    run_hook(service_name, "post_start", post_start, file_config)
+}
+
+fn do_service_create(
+    service: &Service,
+    file_config: &FileConfig,
+    pending_start_poll_interval: &Duration,
+    pending_start_poll_count: u64,
+) -> Result<()> {
+    let other = file_config.merged_other(service);
+    run_hook(&service.name, "pre_install", other.pre_install, file_config)?;
+    do_service_artifact(service, file_config)?;
+
+    // install service first
+    // note that the service path is relative from nssm.exe
+    backend::install(&service.name, &service.path, file_config)
+        .chain_service_msg("Unable to install", &service.name)?;
+
+    record_action(file_config, &service.name, "installed".to_owned());
+
+    // then set the rest of the parameters
+    // application path has already been set during install
+    let rest_params: Vec<ServiceParam> = params::desired_params(service, file_config)
+        .into_iter()
+        .filter(|param| param.name != params::APPLICATION)
+        .collect();
+
+    set_service_params(&service.name, &rest_params, file_config)?;
+    do_service_logon_right(service, file_config)?;
+    do_service_recovery(service, file_config)?;
+    do_service_firewall(service, file_config)?;
+    run_hook(&service.name, "post_install", other.post_install, file_config)?;
+
+    if let Some(&true) = other.start_on_create {
+        do_service_start(
+            &service.name,
+            file_config,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn do_service_remove(service_name: &str, file_config: &FileConfig) -> Result<()> {
+    backend::remove(service_name, file_config).chain_service_msg(
+        "Unable to remove",
+        service_name,
+    )?;
+
+    record_action(file_config, service_name, "removed".to_owned());
+
+    let firewall = find_service(file_config, service_name)
+        .filter(|_| is_windows_service(service_name, file_config))
+        .and_then(|service| service.firewall.as_ref());
+
+    if let Some(firewall) = firewall {
+        if firewall::remove(service_name, firewall, file_config)? {
+            record_action(file_config, service_name, "removed firewall rule".to_owned());
+        }
+    }
+
+    Ok(())
+}
+
+/// Downloads the artifact of the service if configured and not yet at its destination,
+/// extracts it into the extract directory if configured and changed, and then points the
+/// junction at the extract directory if configured.
+/// Downloads and verifies the outdated artifact of the service unless it has already been
+/// downloaded, which is done before the running service is stopped, so that a slow or failed
+/// download neither extends the downtime nor leaves the service removed.
+fn do_service_artifact_fetch(service: &Service, file_config: &FileConfig) -> Result<()> {
+    let artifact = match service.artifact {
+        Some(ref artifact) if !artifact::is_up_to_date(artifact) => artifact,
+        _ => return Ok(()),
+    };
+
+    if file_config.host.is_some() {
+        bail!(
+            "Unable to fetch artifact of service '{}' on a remote host",
+            service.name
+        );
+    }
+
+    if !artifact::is_downloaded(artifact) {
+        let url = artifact.url.as_ref().map_or("", |url| url.as_str());
+
+        if file_config.is_dry_run() {
+            log_dry_run(
+                file_config,
+                &format!("Download '{}' to '{}'", url, artifact.dest.to_string_lossy()),
+            );
+        } else {
+            info!("Downloading artifact of service '{}' from '{}'...", service.name, url);
+            artifact::fetch(artifact)?;
+        }
+
+        record_action(file_config, &service.name, "fetched artifact".to_owned());
+    }
+
+    Ok(())
+}
+
+/// Extracts the artifact of the service fetched by `do_service_artifact_fetch` and points the
+/// junction at it, while the service is stopped.
+fn do_service_artifact(service: &Service, file_config: &FileConfig) -> Result<()> {
+    let artifact = match service.artifact {
+        Some(ref artifact) if !artifact::is_up_to_date(artifact) => artifact,
+        _ => return Ok(()),
+    };
+
+    if let Some(ref extract_dir) = artifact.extract_dir {
+        // the extract marker holds the hash of the archive it has been extracted from, so a
+        // newly downloaded archive is always extracted again
+        if !artifact::is_downloaded(artifact) || !artifact::is_extracted(artifact) {
+            if file_config.is_dry_run() {
+                log_dry_run(
+                    file_config,
+                    &format!(
+                        "Extract '{}' into '{}'",
+                        artifact.dest.to_string_lossy(),
+                        extract_dir.to_string_lossy(),
+                    ),
+                );
+            } else {
+                info!(
+                    "Extracting artifact of service '{}' into '{}'...",
+                    service.name,
+                    extract_dir.to_string_lossy()
+                );
+
+                artifact::extract(artifact)?;
+            }
+
+            record_action(file_config, &service.name, "extracted artifact".to_owned());
+        }
+
+        if let Some(ref junction) = artifact.junction {
+            if !artifact::is_switched(artifact) {
+                if file_config.is_dry_run() {
+                    log_dry_run(
+                        file_config,
+                        &format!(
+                            "Point junction '{}' to '{}'",
+                            junction.to_string_lossy(),
+                            extract_dir.to_string_lossy(),
+                        ),
+                    );
+                } else {
+                    info!(
+                        "Pointing junction '{}' of service '{}' to '{}'...",
+                        junction.to_string_lossy(),
+                        service.name,
+                        extract_dir.to_string_lossy()
+                    );
+
+                    artifact::switch(artifact)?;
+                }
+
+                record_action(file_config, &service.name, "switched junction".to_owned());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true if the service is managed by the Service Control Manager, i.e. neither by
+/// systemd nor simulated, so that its account, recovery actions and firewall rule are applied.
+fn is_windows_service(service_name: &str, file_config: &FileConfig) -> bool {
+    !matches!(
+        file_config.backend(service_name),
+        Backend::Systemd | Backend::Mock
+    )
+}
+
+/// Sets the recovery actions of the service if configured and changed.
+fn do_service_recovery(service: &Service, file_config: &FileConfig) -> Result<()> {
+    if !is_windows_service(&service.name, file_config) {
+        return Ok(());
+    }
+
+    if let Some(ref recovery) = service.recovery {
+        if recovery::apply(&service.name, recovery, file_config)? {
+            record_action(file_config, &service.name, "configured recovery".to_owned());
+        }
+    }
+
+    Ok(())
+}
+
+/// Grants the account of the service the right to log on as a service if it lacks the right.
+/// Built-in and virtual accounts already have the right.
+fn do_service_logon_right(service: &Service, file_config: &FileConfig) -> Result<()> {
+    if !is_windows_service(&service.name, file_config) {
+        return Ok(());
+    }
+
+    let account = match file_config.merged_other(service).account {
+        Some(account) if account.takes_password() => account,
+        _ => return Ok(()),
+    };
+
+    let host = file_config.host.as_deref();
+
+    // the right may not be checked in dry run, e.g. on other platforms
+    let has_right = match lsa::has_service_logon_right(host, &account.user) {
+        Ok(has_right) => has_right,
+        Err(_) if file_config.is_dry_run() => false,
+        Err(e) => return Err(e),
+    };
+
+    if has_right {
+        return Ok(());
+    }
+
+    if file_config.is_dry_run() {
+        log_dry_run(
+            file_config,
+            &format!("Grant right '{}' to account '{}'", lsa::SERVICE_LOGON_RIGHT, account.user),
+        );
+    } else {
+        lsa::grant_service_logon_right(host, &account.user)?;
+    }
+
+    record_action(file_config, &service.name, "granted logon right".to_owned());
+    Ok(())
+}
+
+/// Creates or updates the firewall rule of the service if configured.
+fn do_service_firewall(service: &Service, file_config: &FileConfig) -> Result<()> {
+    if !is_windows_service(&service.name, file_config) {
+        return Ok(());
+    }
+
+    if let Some(ref firewall) = service.firewall {
+        if firewall::apply(&service.name, firewall, file_config)? {
+            record_action(file_config, &service.name, "added firewall rule".to_owned());
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the missing directories of the log files of the service, and grants its account
+/// write access to them if configured, so that the service does not fail to start.
+fn do_service_log_dirs(service: &Service, file_config: &FileConfig) -> Result<()> {
+    let other = file_config.merged_other(service);
+
+    let grantee = if other.grant_log_access == Some(&true)
+        && is_windows_service(&service.name, file_config)
+    {
+        logdir::grantee(&service.name, other.account)
+    } else {
+        None
+    };
+
+    for dir in logdir::dirs(service) {
+        if logdir::create(&dir, file_config)? {
+            record_action(file_config, &service.name, "created log directory".to_owned());
+        }
+
+        if let Some(ref grantee) = grantee {
+            logdir::grant_access(&dir, grantee, file_config)?;
+            record_action(file_config, &service.name, "granted log access".to_owned());
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces any installed service with the service in the snapshot,
+/// starting it again if it was running.
+fn do_service_restore(
+    prev_snapshot: &Snapshot,
+    file_config: &FileConfig,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+    pending_start_poll_interval: &Duration,
+    pending_start_poll_count: u64,
+) -> Result<()> {
+    let service_name = &prev_snapshot.service_name;
+
+    if let Ok(state) = backend::status(service_name, file_config) {
+        do_service_stop(
+            service_name,
+            file_config,
+            state,
+            pending_stop_poll_interval,
+            pending_stop_poll_count,
+        )?;
+
+        do_service_remove(service_name, file_config)?;
+    }
+
+    snapshot::restore(prev_snapshot, file_config)?;
+
+    if prev_snapshot.was_running() {
+        do_service_start(
+            service_name,
+            file_config,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Logs the outcome of the service, where `None` means that the service was skipped,
+/// together with the number of services processed so far out of the total.
+fn log_service_status(res: Option<&Result<()>>, name: &str, processed: usize, total: usize) {
+    match res {
+        Some(&Ok(_)) => info!("Service '{}' [OK] ({}/{})", name, processed, total),
+        Some(Err(e)) => {
+            error!("Service '{}' [FAILED] ({}/{})", name, processed, total);
+            print_recursive_err(e);
+        }
+        None => warn!("Service '{}' [SKIPPED] ({}/{})", name, processed, total),
+    }
+}
+
+/// Logs the error and its chain of causes at warning level.
+pub(crate) fn print_recursive_warning(e: &Error) {
+    warn!("WARNING: {}", e);
+
+    for e in e.iter().skip(1) {
+        warn!("> Caused by: {}", e);
+    }
+}
+
+/// Logs the error and its chain of causes at error level.
+pub fn print_recursive_err(e: &Error) {
+    error!("ERROR: {}", e);
+
+    for e in e.iter().skip(1) {
+        error!("> Caused by: {}", e);
+    }
+}
+
+fn service_deps<'a>(service: &'a Service, file_config: &'a FileConfig) -> Vec<&'a str> {
+    file_config
+        .merged_other(service)
+        .deps
+        .map(|deps| deps.names())
+        .unwrap_or_default()
+}
+
+fn visit_in_dependency_order(
+    index: usize,
+    file_config: &FileConfig,
+    visited: &mut [bool],
+    order: &mut Vec<usize>,
+) {
+    // marks the service before visiting its dependencies, so that cycles are cut
+    if visited[index] {
+        return;
+    }
+
+    visited[index] = true;
+    let services = &file_config.services;
+
+    for dep in service_deps(&services[index], file_config) {
+        if let Some(dep_index) = services.iter().position(|service| service.name == dep) {
+            visit_in_dependency_order(dep_index, file_config, visited, order);
+        }
+    }
+
+    order.push(index);
+}
+
+/// Returns the indices of the services ordered such that every service comes after the
+/// configured services it depends on, and otherwise in the configuration order.
+/// Services in a dependency cycle, which is reported by `validate`, are only partially
+/// ordered.
+fn dependency_order(file_config: &FileConfig) -> Vec<usize> {
+    let mut visited = vec![false; file_config.services.len()];
+    let mut order = Vec::with_capacity(file_config.services.len());
+
+    for index in 0..file_config.services.len() {
+        visit_in_dependency_order(index, file_config, &mut visited, &mut order);
+    }
+
+    order
+}
+
+/// Returns, for every service, the indices of the configured services that it has to be
+/// processed after. Dependencies that close a cycle are left out, so that every service is
+/// eventually processed.
+fn dependency_indices(file_config: &FileConfig, order: &[usize]) -> Vec<Vec<usize>> {
+    let services = &file_config.services;
+    let mut positions = vec![0; services.len()];
+
+    for (position, &index) in order.iter().enumerate() {
+        positions[index] = position;
+    }
+
+    services
+        .iter()
+        .enumerate()
+        .map(|(index, service)| {
+            service_deps(service, file_config)
+                .into_iter()
+                .filter_map(|dep| services.iter().position(|service| service.name == dep))
+                .filter(|&dep_index| positions[dep_index] < positions[index])
+                .collect()
+        })
+        .collect()
+}
+
+/// Inverts the indices returned by `dependency_indices` into the indices of the services
+/// depending on every service.
+fn dependent_indices(dependencies: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut dependents = vec![Vec::new(); dependencies.len()];
+
+    for (index, dep_indices) in dependencies.iter().enumerate() {
+        for &dep_index in dep_indices {
+            dependents[dep_index].push(index);
+        }
+    }
+
+    dependents
+}
+
+/// Holds the services that are yet to be processed by the workers of `nssm_exec_each`.
+struct Schedule {
+    pending: Vec<usize>,
+    done: Vec<bool>,
+}
+
+/// Marks the service taken by a worker of `nssm_exec_each` as processed when dropped, and
+/// wakes the other workers waiting for it, so that they do not wait forever if processing
+/// the service panics. A panicking service is also counted as failed.
+struct ScheduledService<'a> {
+    index: usize,
+    schedule: &'a Mutex<Schedule>,
+    schedule_changed: &'a Condvar,
+    failed_count: &'a AtomicUsize,
+}
+
+impl<'a> Drop for ScheduledService<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.failed_count.fetch_add(1, Ordering::SeqCst);
+        }
+
+        // the lock is not held while processing the service, but the poisoning of another
+        // worker must not stop this one from waking the rest
+        let mut state = match self.schedule.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        state.done[self.index] = true;
+        self.schedule_changed.notify_all();
+    }
+}
+
+/// Runs the given function over every service and logs the outcome of each service.
+/// Services are processed after the configured services they depend on.
+/// Up to the configured parallelism, every service is processed concurrently with the
+/// others as soon as the services it depends on have been processed, so that their waits
+/// for the pending states and health overlap.
+/// Returns error if any service has failed. If fail fast is enabled, the services that have
+/// not been processed yet are skipped after the first failure.
+fn nssm_exec_each<F>(file_config: &FileConfig, f: F) -> Result<()>
+where
+    F: Fn(&Service) -> Result<()> + Sync,
+{
+    nssm_exec_each_in_order(file_config, false, f)
+}
+
+/// Same as `nssm_exec_each`, except that services are processed after the configured
+/// services depending on them, e.g. to stop the dependents before their dependencies.
+fn nssm_exec_each_reversed<F>(file_config: &FileConfig, f: F) -> Result<()>
+where
+    F: Fn(&Service) -> Result<()> + Sync,
+{
+    nssm_exec_each_in_order(file_config, true, f)
+}
+
+fn nssm_exec_each_in_order<F>(file_config: &FileConfig, reverse: bool, f: F) -> Result<()>
+where
+    F: Fn(&Service) -> Result<()> + Sync,
+{
+    let fail_fast = file_config.is_fail_fast();
+    let failed_count = AtomicUsize::new(0);
+    let attempted_count = AtomicUsize::new(0);
+    let processed_count = AtomicUsize::new(0);
+    let total = file_config.services.len();
+
+    let skip_service = |service: &Service| {
+        let processed = processed_count.fetch_add(1, Ordering::SeqCst) + 1;
+        log_service_status(None, &service.name, processed, total);
+        record_service(file_config, &service.name, None, Instant::now());
+    };
+
+    let exec_service = |service: &Service| {
+        let start_time = Instant::now();
+
+        if fail_fast && failed_count.load(Ordering::SeqCst) > 0 {
+            skip_service(service);
+            return;
+        }
+
+        attempted_count.fetch_add(1, Ordering::SeqCst);
+        let res = f(service);
+        let processed = processed_count.fetch_add(1, Ordering::SeqCst) + 1;
+        log_service_status(Some(&res), &service.name, processed, total);
+        record_service(file_config, &service.name, Some(&res), start_time);
+
+        if res.is_err() {
+            failed_count.fetch_add(1, Ordering::SeqCst);
+        }
+    };
+
+    let mut order = dependency_order(file_config);
+    let mut dependencies = dependency_indices(file_config, &order);
+
+    if reverse {
+        order.reverse();
+        dependencies = dependent_indices(&dependencies);
+    }
+
+    let mut done = vec![false; total];
+
+    // the canary is processed alone, and the remaining services only if it succeeds
+    if file_config.is_canary() && !order.is_empty() {
+        let canary_index = order.remove(0);
+        let canary = &file_config.services[canary_index];
+
+        info!("Processing service '{}' as canary first...", canary.name);
+        exec_service(canary);
+        done[canary_index] = true;
+
+        if failed_count.load(Ordering::SeqCst) > 0 {
+            warn!(
+                "Canary service '{}' failed, skipping the remaining services",
+                canary.name
+            );
+
+            for index in order.drain(..) {
+                skip_service(&file_config.services[index]);
+            }
+        }
+    }
+
+    let parallelism = file_config.parallelism();
+
+    if parallelism <= 1 {
+        for index in order {
+            exec_service(&file_config.services[index]);
+        }
+    } else {
+        let schedule = Mutex::new(Schedule {
+            pending: order,
+            done,
+        });
+
+        let schedule_changed = Condvar::new();
+
+        crossbeam::scope(|scope| {
+            for _ in 0..cmp::min(parallelism, total) {
+                scope.spawn(|| {
+                    let mut state = schedule.lock().expect("Schedule lock is poisoned");
+
+                    // each worker keeps taking the next service whose dependencies have been
+                    // processed, waiting for the other workers if there is none yet
+                    while !state.pending.is_empty() {
+                        let ready_position = {
+                            let done = &state.done;
+
+                            state.pending.iter().position(|&index| {
+                                dependencies[index].iter().all(|&dep_index| done[dep_index])
+                            })
+                        };
+
+                        let index = match ready_position {
+                            Some(position) => state.pending.remove(position),
+                            None => {
+                                state = schedule_changed
+                                    .wait(state)
+                                    .expect("Schedule lock is poisoned");
+
+                                continue;
+                            }
+                        };
+
+                        drop(state);
+
+                        {
+                            let _scheduled = ScheduledService {
+                                index,
+                                schedule: &schedule,
+                                schedule_changed: &schedule_changed,
+                                failed_count: &failed_count,
+                            };
+
+                            exec_service(&file_config.services[index]);
+                        }
+
+                        state = schedule.lock().expect("Schedule lock is poisoned");
+                    }
+                });
+            }
+        });
+    }
+
+    let failed_count = failed_count.load(Ordering::SeqCst);
+
+    // the skipped services are not counted, so that the run is only partially failed if
+    // some of the processed services have succeeded
+    if failed_count > 0 {
+        bail!(ErrorKind::ServicesFailed {
+            failed: failed_count,
+            total: attempted_count.load(Ordering::SeqCst),
+        });
+    }
+
+    Ok(())
+}
+
+/// Holds the queried status of a configured service.
+#[derive(Debug, Serialize)]
+pub struct ServiceStatus<'a> {
+    /// Name of service.
+    pub name: &'a str,
+
+    /// Current state of service. `None` if the service is not installed.
+    pub state: Option<ServiceState>,
+}
+
+impl<'a> ServiceStatus<'a> {
+    /// Returns true if the service is installed.
+    pub fn is_installed(&self) -> bool {
+        self.state.is_some()
+    }
+}
+
+/// Queries the status of all the services in the configuration.
+pub fn nssm_exec_status(file_config: &FileConfig) -> Result<Vec<ServiceStatus<'_>>> {
+    let statuses = file_config
+        .services
+        .iter()
+        .map(|service| {
+            // unable to get status probably means that the service does not exist
+            ServiceStatus {
+                name: &service.name,
+                state: backend::status(&service.name, file_config).ok(),
+            }
+        })
+        .collect();
+
+    Ok(statuses)
+}
+
+/// Holds a configured or installed service with its status.
+#[derive(Debug, Serialize)]
+pub struct ServiceListing {
+    /// Name of service.
+    pub name: String,
+
+    /// States whether the service is in the configuration.
+    pub configured: bool,
+
+    /// Current state of service. `None` if the service is not installed.
+    pub state: Option<ServiceState>,
+
+    /// States whether the installed service runs nssm. `None` if unknown, e.g. if the
+    /// service is not installed or the installed services cannot be listed.
+    pub nssm_managed: Option<bool>,
+}
+
+/// Returns true if the command line of the installed service runs nssm, i.e. the configured
+/// nssm executable or any other `nssm.exe`.
+fn is_nssm_binary_path(binary_path: &str, file_config: &FileConfig) -> bool {
+    let file_name = |path: &str| {
+        path.rsplit(['\\', '/'])
+            .next()
+            .unwrap_or("")
+            .to_lowercase()
+    };
+
+    let exe_name = match cmdline::split_args(binary_path).into_iter().next() {
+        Some(exe_path) => file_name(&exe_path),
+        None => return false,
+    };
+
+    exe_name == "nssm.exe" || exe_name == file_name(&file_config.nssm_path.to_string_lossy())
+}
+
+/// Lists the services in the configuration with their status, followed by the installed
+/// nssm services that are not in the configuration. Only the configured services are listed
+/// if the installed services cannot be listed, e.g. on other platforms than Windows.
+pub fn nssm_exec_list(file_config: &FileConfig) -> Result<Vec<ServiceListing>> {
+    let installed_services = match scm::installed_services(file_config) {
+        Ok(installed_services) => installed_services,
+        Err(e) => {
+            warn!("Only listing the configured services: {}", e);
+            Vec::new()
+        }
+    };
+
+    let nssm_managed = |service_name: &str| {
+        installed_services
+            .iter()
+            .find(|installed| installed.name.eq_ignore_ascii_case(service_name))
+            .and_then(|installed| installed.binary_path.as_ref())
+            .map(|binary_path| is_nssm_binary_path(binary_path, file_config))
+    };
+
+    let mut listings: Vec<ServiceListing> = file_config
+        .services
+        .iter()
+        .map(|service| {
+            // unable to get status probably means that the service does not exist
+            ServiceListing {
+                name: service.name.clone(),
+                configured: true,
+                state: backend::status(&service.name, file_config).ok(),
+                nssm_managed: nssm_managed(&service.name),
+            }
+        })
+        .collect();
+
+    for installed in &installed_services {
+        let is_configured = file_config
+            .services
+            .iter()
+            .any(|service| service.name.eq_ignore_ascii_case(&installed.name));
+
+        if !is_configured && nssm_managed(&installed.name) == Some(true) {
+            listings.push(ServiceListing {
+                name: installed.name.clone(),
+                configured: false,
+                state: Some(installed.state),
+                nssm_managed: Some(true),
+            });
+        }
+    }
+
+    Ok(listings)
+}
+
+/// Holds the differences between the configuration and the installed state of a service.
+#[derive(Debug)]
+pub struct ServiceDiff<'a> {
+    /// Name of service.
+    pub name: &'a str,
+
+    /// States whether the service is installed. There are no parameter differences if the
+    /// service is not installed.
+    pub installed: bool,
+
+    /// Desired parameters whose values differ from the installed ones.
+    pub params: Vec<ParamDiff>,
+}
+
+impl<'a> ServiceDiff<'a> {
+    /// Returns true if the service is installed with all the desired parameter values.
+    pub fn is_up_to_date(&self) -> bool {
+        self.installed && self.params.is_empty()
+    }
+}
+
+/// Compares the desired parameters of all the services in the configuration against the
+/// installed ones, without modifying any service.
+pub fn nssm_exec_diff(file_config: &FileConfig) -> Result<Vec<ServiceDiff<'_>>> {
+    file_config
+        .services
+        .iter()
+        .map(|service| {
+            // unable to get status probably means that the service does not exist
+            if backend::status(&service.name, file_config).is_err() {
+                return Ok(ServiceDiff {
+                    name: &service.name,
+                    installed: false,
+                    params: Vec::new(),
+                });
+            }
+
+            let params = params::param_diffs(service, file_config)
+                .chain_service_msg("Unable to compare parameters of", &service.name)?;
+
+            Ok(ServiceDiff {
+                name: &service.name,
+                installed: true,
+                params,
+            })
+        })
+        .collect()
+}
+
+/// Takes snapshots of the installed parameters of all the installed services in the
+/// configuration. Services that are not installed are skipped.
+pub fn nssm_exec_backup(file_config: &FileConfig) -> Result<Vec<Snapshot>> {
+    let snapshots = file_config
+        .services
+        .iter()
+        .filter_map(|service| match backend::status(&service.name, file_config) {
+            Ok(state) => Some(snapshot::take(&service.name, state, file_config)),
+            Err(_) => {
+                warn!("Service '{}' is not installed, skipping backup", service.name);
+                None
+            }
+        })
+        .collect();
+
+    Ok(snapshots)
+}
+
+/// Reinstalls every service in the snapshots with its snapshot parameters, replacing any
+/// installed service of the same name, and starts it again if it was running.
+pub fn nssm_exec_restore(
+    file_config: &FileConfig,
+    snapshots: &[Snapshot],
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+    pending_start_poll_interval: &Duration,
+    pending_start_poll_count: u64,
+) -> Result<()> {
+    let mut failed_count = 0;
+
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        let start_time = Instant::now();
+
+        if file_config.is_fail_fast() && failed_count > 0 {
+            log_service_status(None, &snapshot.service_name, index + 1, snapshots.len());
+            record_service(file_config, &snapshot.service_name, None, start_time);
+            continue;
+        }
+
+        info!("Restoring service '{}'...", snapshot.service_name);
+
+        let res = do_service_restore(
+            snapshot,
+            file_config,
+            pending_stop_poll_interval,
+            pending_stop_poll_count,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        );
+
+        log_service_status(
+            Some(&res),
+            &snapshot.service_name,
+            index + 1,
+            snapshots.len(),
+        );
+        record_service(file_config, &snapshot.service_name, Some(&res), start_time);
+
+        if res.is_err() {
+            failed_count += 1;
+        }
+    }
+
+    if failed_count > 0 {
+        bail!(ErrorKind::ServicesFailed {
+            failed: failed_count,
+            total: snapshots.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Stops all the services in the configuration, where every service is only stopped after
+/// the configured services depending on it have stopped.
+pub fn nssm_exec_stop(
+    file_config: &FileConfig,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+) -> Result<()> {
+    nssm_exec_each_reversed(file_config, |service| {
+        if let Ok(state) = backend::status(&service.name, file_config) {
+            debug!(
+                "Service '{}' exists, attempting to stop service...",
+                service.name
+            );
+
+            do_service_stop(
+                &service.name,
+                file_config,
+                state,
+                pending_stop_poll_interval,
+                pending_stop_poll_count,
+            )?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Starts all the installed services in the configuration that are not running yet.
+pub fn nssm_exec_start(
+    file_config: &FileConfig,
+    pending_start_poll_interval: &Duration,
+    pending_start_poll_count: u64,
+) -> Result<()> {
+    nssm_exec_each(file_config, |service| {
+        let state = backend::status(&service.name, file_config)
+            .chain_service_msg("Unable to get status to start", &service.name)?;
+
+        if state == ServiceState::Running {
+            debug!("Service '{}' is already running", service.name);
+            return Ok(());
+        }
+
+        debug!("Attempting to start service '{}'...", service.name);
+
+        do_service_start(
+            &service.name,
+            file_config,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        )
+    })
+}
+
+/// Stops and then starts all the services in the configuration, without reinstalling them.
+pub fn nssm_exec_restart(
+    file_config: &FileConfig,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+    pending_start_poll_interval: &Duration,
+    pending_start_poll_count: u64,
+) -> Result<()> {
+    nssm_exec_each(file_config, |service| {
+        let state = backend::status(&service.name, file_config)
+            .chain_service_msg("Unable to get status to restart", &service.name)?;
+
+        debug!("Attempting to stop service '{}'...", service.name);
+
+        do_service_stop(
+            &service.name,
+            file_config,
+            state,
+            pending_stop_poll_interval,
+            pending_stop_poll_count,
+        )?;
+
+        debug!("Next attempting to start service '{}'...", service.name);
+
+        do_service_start(
+            &service.name,
+            file_config,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        )
+    })
+}
+
+/// Stops and removes all the services in the configuration, where every service is only
+/// stopped after the configured services depending on it have been removed.
+pub fn nssm_exec_remove(
+    file_config: &FileConfig,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+) -> Result<()> {
+    nssm_exec_each_reversed(file_config, |service| {
+        if let Ok(state) = backend::status(&service.name, file_config) {
+            debug!(
+                "Service '{}' exists, attempting to stop service first...",
+                service.name
+            );
+
+            do_service_stop(
+                &service.name,
+                file_config,
+                state,
+                pending_stop_poll_interval,
+                pending_stop_poll_count,
+            )?;
+
+            debug!("Next attempting to remove service '{}'...", service.name);
+            do_service_remove(&service.name, file_config)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Updates the services in place by only setting the parameters that differ from the
+/// installed ones. Services that do not exist yet are created, while installed services that
+/// are identical to the configuration, or all of them if the existing services are skipped,
+/// are left untouched.
+pub fn nssm_exec_update(
+    file_config: &FileConfig,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+    pending_start_poll_interval: &Duration,
+    pending_start_poll_count: u64,
+) -> Result<()> {
+    nssm_exec_each(file_config, |service| {
+        if skips_existing(service, file_config) {
+            return Ok(());
+        }
+
+        do_service_artifact_fetch(service, file_config)?;
+
+        let state = match backend::status(&service.name, file_config) {
+            Ok(state) => state,
+            Err(_) => {
+                info!("Service '{}' does not exist, creating service...", service.name);
+
+                return do_service_create(
+                    service,
+                    file_config,
+                    pending_start_poll_interval,
+                    pending_start_poll_count,
+                );
+            }
+        };
+
+        let changed_params = params::changed_params(service, file_config)
+            .chain_service_msg("Unable to get installed parameters of", &service.name)?;
+
+        do_service_logon_right(service, file_config)?;
+        do_service_recovery(service, file_config)?;
+        do_service_firewall(service, file_config)?;
+
+        let is_running = state == ServiceState::Running;
+        let start_on_create = file_config.merged_other(service).start_on_create == Some(&true);
+
+        let is_artifact_outdated = service
+            .artifact
+            .as_ref()
+            .is_some_and(|artifact| !artifact::is_up_to_date(artifact));
+
+        if changed_params.is_empty() && !is_artifact_outdated {
+            info!("Service '{}' is already up to date", service.name);
+
+            if is_running || !start_on_create {
+                return Ok(());
+            }
+        } else {
+            for param in &changed_params {
+                info!(
+                    "Service '{}' parameter '{}' changed to '{}'",
+                    service.name,
+                    param.key(),
+                    param.value()
+                );
+            }
+
+            if is_artifact_outdated {
+                info!("Service '{}' artifact changed", service.name);
+            }
+
+            let is_metadata_only =
+                !is_artifact_outdated && changed_params.iter().all(|param| param.is_metadata());
+
+            if is_metadata_only {
+                info!(
+                    "Service '{}' only has metadata changed, setting it without stopping",
+                    service.name
+                );
+            } else {
+                do_service_stop(
+                    &service.name,
+                    file_config,
+                    state,
+                    pending_stop_poll_interval,
+                    pending_stop_poll_count,
+                )?;
+            }
+
+            let other = file_config.merged_other(service);
+            run_hook(&service.name, "pre_install", other.pre_install, file_config)?;
+            do_service_artifact(service, file_config)?;
+            set_service_params(&service.name, &changed_params, file_config)?;
+            run_hook(&service.name, "post_install", other.post_install, file_config)?;
+
+            // only start if stopped above and previously running, or configured to start
+            if (is_metadata_only && is_running) || (!is_running && !start_on_create) {
+                return Ok(());
+            }
+        }
+
+        do_service_start(
+            &service.name,
+            file_config,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        )
+    })
+}
+
+/// Suffix of the name of the copy of a service that is installed in blue-green mode.
+pub(crate) const GREEN_SUFFIX: &str = "-green";
+
+/// Returns the name of the copy of the service installed in blue-green mode.
+fn green_name(service_name: &str) -> String {
+    format!("{}{}", service_name, GREEN_SUFFIX)
+}
+
+/// Returns the copy of the service installed next to it in blue-green mode, with the
+/// settings of `green` applied over the service. The copy leaves the firewall rule and the
+/// recovery actions to the service, and is started explicitly. Returns error if the service
+/// has no `green` settings, or if the copy would share the log files or the health probes
+/// of the service, since the copy would then clash with the running service, or its health
+/// would be checked against the service.
+fn green_service(service: &Service) -> Result<Service> {
+    let overrides = match service.green {
+        Some(ref overrides) if overrides.args.is_some() || overrides.env.is_some() => overrides,
+        _ => bail!(
+            "Service '{}' requires 'args' or 'env' in [services.green] for blue-green mode, \
+             so that its copy does not use the same ports",
+            service.name
+        ),
+    };
+
+    let mut green = service.clone();
+    green.name = green_name(&service.name);
+
+    // the display names of the services must be unique as well
+    green.display_name = Some(format!(
+        "{}{}",
+        service.display_name.as_ref().unwrap_or(&service.name),
+        GREEN_SUFFIX
+    ));
+
+    if overrides.args.is_some() {
+        green.args = overrides.args.clone();
+    }
+
+    if let Some(ref env) = overrides.env {
+        green
+            .env
+            .get_or_insert_with(BTreeMap::new)
+            .extend(env.iter().map(|(name, value)| (name.clone(), value.clone())));
+    }
+
+    let name = green.name.clone();
+    let render_path = |path: &PathBuf| {
+        PathBuf::from(path.to_string_lossy().replace(config::NAME_PLACEHOLDER, &name))
+    };
+
+    let logs = [
+        ("stdout_log", &service.stdout_log, &overrides.stdout_log),
+        ("stderr_log", &service.stderr_log, &overrides.stderr_log),
+    ];
+
+    for &(key, log, green_log) in &logs {
+        if log.is_some() && (green_log.is_none() || green_log == log) {
+            bail!(
+                "Service '{}' requires a different '{}' in [services.green] for blue-green \
+                 mode, so that its copy does not write to the same file",
+                service.name,
+                key
+            );
+        }
+    }
+
+    green.stdout_log = overrides.stdout_log.as_ref().map(&render_path);
+    green.stderr_log = overrides.stderr_log.as_ref().map(&render_path);
+
+    if let Some(ref health) = service.health {
+        let is_distinct = overrides.health.as_ref().is_some_and(|green_health| {
+            !green_health.is_empty()
+                && (health.tcp_port.is_none() || green_health.tcp_port != health.tcp_port)
+                && (health.http_url.is_none() || green_health.http_url != health.http_url)
+        });
+
+        if !is_distinct {
+            bail!(
+                "Service '{}' requires 'health' probes in [services.green] for blue-green \
+                 mode, which must not probe the same port or URL as the service",
+                service.name
+            );
+        }
+    }
+
+    green.health = overrides.health.clone();
+    green.firewall = None;
+    green.recovery = None;
+    green.green = None;
+    green.other.get_or_insert_with(OtherConfig::default).start_on_create = Some(false);
+    Ok(green)
+}
+
+/// Stops and removes the service if it exists.
+fn do_service_discard(
+    service_name: &str,
+    file_config: &FileConfig,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+) -> Result<()> {
+    if let Ok(state) = backend::status(service_name, file_config) {
+        do_service_stop(
+            service_name,
+            file_config,
+            state,
+            pending_stop_poll_interval,
+            pending_stop_poll_count,
+        )?;
+
+        do_service_remove(service_name, file_config)?;
+    }
+
+    Ok(())
+}
+
+/// Installs and starts the green copy of the service, waiting until it is healthy if the
+/// service has health probes. The copy is removed again if it fails.
+fn do_service_green(
+    service: &Service,
+    file_config: &FileConfig,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+    pending_start_poll_interval: &Duration,
+    pending_start_poll_count: u64,
+) -> Result<()> {
+    let green = green_service(service)?;
+
+    info!("Starting green copy '{}' of service '{}'...", green.name, service.name);
+
+    // a copy left behind by an interrupted run is replaced
+    do_service_discard(
+        &green.name,
+        file_config,
+        pending_stop_poll_interval,
+        pending_stop_poll_count,
+    )?;
+
+    let res = do_service_create(
+        &green,
+        file_config,
+        pending_start_poll_interval,
+        pending_start_poll_count,
+    ).and_then(|_| {
+        do_service_start(
+            &green.name,
+            file_config,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        )
+    })
+        .and_then(|_| match green.health {
+            Some(ref health) => poll_service_health(
+                &green.name,
+                health,
+                file_config,
+                pending_start_poll_interval,
+                pending_start_poll_count,
+            ),
+            None => Ok(()),
+        });
+
+    match res {
+        Ok(_) => record_action(file_config, &service.name, "started green copy".to_owned()),
+        Err(_) => {
+            let discard_res = do_service_discard(
+                &green.name,
+                file_config,
+                pending_stop_poll_interval,
+                pending_stop_poll_count,
+            ).chain_service_msg("Unable to remove green copy of", &service.name);
+
+            if let Err(e) = discard_res {
+                print_recursive_err(&e);
+            }
+        }
+    }
+
+    res.chain_service_msg("Unable to start green copy of", &service.name)
+}
+
+/// Returns true if the service is left untouched because it exists and the existing
+/// services are skipped.
+fn skips_existing(service: &Service, file_config: &FileConfig) -> bool {
+    if !file_config.is_skip_existing() || backend::status(&service.name, file_config).is_err() {
+        return false;
+    }
+
+    info!("Service '{}' already exists, leaving it untouched", service.name);
+    record_action(file_config, &service.name, "skipped existing".to_owned());
+    true
+}
+
+/// Stops and removes any existing services, then installs and configures all the services in
+/// the configuration, optionally starting them. In blue-green mode, a copy of every existing
+/// service is started before the service is replaced, and removed after. The existing services
+/// are left untouched instead if they are skipped.
+pub fn nssm_exec(
+    file_config: &FileConfig,
+    pending_stop_poll_interval: &Duration,
+    pending_stop_poll_count: u64,
+    pending_start_poll_interval: &Duration,
+    pending_start_poll_count: u64,
+) -> Result<()> {
+    nssm_exec_each(file_config, |service| {
+        if skips_existing(service, file_config) {
+            return Ok(());
+        }
+
+        do_service_artifact_fetch(service, file_config)?;
+
+        let is_blue_green = file_config.is_blue_green()
+            && backend::status(&service.name, file_config).is_ok();
+
+        if is_blue_green {
+            do_service_green(
+                service,
+                file_config,
+                pending_stop_poll_interval,
+                pending_stop_poll_count,
+                pending_start_poll_interval,
+                pending_start_poll_count,
+            )?;
+        }
+
+        info!("Creating service '{}'...", service.name);
+
+        // ignore if cannot get status, which probably means that the service does not exist yet
+        let prev_snapshot = match backend::status(&service.name, file_config) {
+            Ok(state) => {
+                debug!(
+                    "Service '{}' exists, taking snapshot of its parameters first...",
+                    service.name
+                );
+
+                let prev_snapshot = snapshot::take(&service.name, state, file_config);
+
+                debug!("Next attempting to stop service '{}'...", service.name);
+
+                do_service_stop(
+                    &service.name,
+                    file_config,
+                    state,
+                    pending_stop_poll_interval,
+                    pending_stop_poll_count,
+                )?;
+
+                debug!("Next attempting to remove service '{}'...", service.name);
+                do_service_remove(&service.name, file_config)?;
+
+                Some(prev_snapshot)
+            }
+
+            Err(_) => None,
+        };
+
+        let create_res = do_service_create(
+            service,
+            file_config,
+            pending_start_poll_interval,
+            pending_start_poll_count,
+        );
+
+        if let (&Err(_), Some(prev_snapshot)) = (&create_res, prev_snapshot) {
+            warn!(
+                "Unable to create service '{}', rolling back to its previous parameters...",
+                service.name
+            );
+
+            let rollback_res = do_service_restore(
+                &prev_snapshot,
+                file_config,
+                pending_stop_poll_interval,
+                pending_stop_poll_count,
+                pending_start_poll_interval,
+                pending_start_poll_count,
+            ).chain_service_msg("Unable to roll back", &service.name);
+
+            // the creation error is still returned as the cause of failure
+            match rollback_res {
+                Ok(_) => record_action(file_config, &service.name, "rolled back".to_owned()),
+                Err(e) => print_recursive_err(&e),
+            }
+        }
+
+        if is_blue_green {
+            let discard_res = do_service_discard(
+                &green_name(&service.name),
+                file_config,
+                pending_stop_poll_interval,
+                pending_stop_poll_count,
+            ).chain_service_msg("Unable to remove green copy of", &service.name);
+
+            match discard_res {
+                Ok(_) => record_action(file_config, &service.name, "removed green copy".to_owned()),
+                Err(e) => if create_res.is_ok() {
+                    return Err(e);
+                } else {
+                    print_recursive_err(&e);
+                },
+            }
+        }
+
+        create_res
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::str::FromStr;
+    use std::sync::mpsc;
+    use super::*;
+
+    #[test]
+    fn panicking_service_does_not_block_other_workers() {
+        let content = r#"
+nssm_path = "nssm.exe"
+parallelism = 2
+
+[[services]]
+name = "panicking_app"
+path = "app.exe"
+
+[[services]]
+name = "dependent_app"
+path = "app.exe"
+other = { deps = ["panicking_app"] }
+"#;
+
+        let file_config = FileConfig::from_str(content).unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let processed = Mutex::new(Vec::new());
+
+            let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                nssm_exec_each(&file_config, |service| {
+                    if service.name == "panicking_app" {
+                        panic!("service panicked");
+                    }
+
+                    processed.lock().unwrap().push(service.name.clone());
+                    Ok(())
+                })
+            }));
+
+            sender
+                .send((res.is_err(), processed.into_inner().unwrap()))
+                .unwrap();
+        });
+
+        let (is_panicked, processed) = receiver
+            .recv_timeout(Duration::from_secs(30))
+            .expect("workers hang after a service panicked");
+
+        assert!(is_panicked);
+        assert_eq!(processed, vec!["dependent_app"]);
+    }
+}
@@ -0,0 +1,101 @@
+//! Prepares the directories of the files that the standard output and error of the services
+//! are redirected to, without which the services fail to start: creates the missing
+//! directories, and grants the account of the service write access to them with `icacls`,
+//! on the host of the configuration if set.
+
+use config::{Account, BuiltinAccount, FileConfig, Service};
+use errors::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::{log_dry_run, run_host_modify_cmd};
+
+/// Path of the `icacls` executable, which is always on the path.
+pub fn program() -> &'static Path {
+    Path::new("icacls")
+}
+
+/// Returns the distinct directories of the log files of the service in order.
+pub fn dirs(service: &Service) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+
+    for log in service.stdout_log.iter().chain(service.stderr_log.iter()) {
+        if let Some(dir) = log.parent() {
+            if !dir.as_os_str().is_empty() && !dirs.iter().any(|existing| existing == dir) {
+                dirs.push(dir.to_owned());
+            }
+        }
+    }
+
+    dirs
+}
+
+/// Arguments of `cmd` to create the directory together with its missing parents, unless it
+/// already exists.
+pub fn mkdir(dir: &str) -> Vec<String> {
+    vec![
+        "/C".to_owned(),
+        "if".to_owned(),
+        "not".to_owned(),
+        "exist".to_owned(),
+        dir.to_owned(),
+        "mkdir".to_owned(),
+        dir.to_owned(),
+    ]
+}
+
+/// Arguments to grant the account modify access to the directory, which is inherited by the
+/// files and directories in it.
+pub fn grant(dir: &str, account_name: &str) -> Vec<String> {
+    vec![
+        dir.to_owned(),
+        "/grant".to_owned(),
+        format!("{}:(OI)(CI)M", account_name),
+    ]
+}
+
+/// Returns the name of the account of the service to grant the access to, or `None` if the
+/// service runs under `LocalSystem`, which already has full access.
+pub fn grantee(service_name: &str, account: Option<&Account>) -> Option<String> {
+    match account.map(|account| account.object_name(service_name)) {
+        Some(ref name) if name.eq_ignore_ascii_case(BuiltinAccount::LocalSystem.name()) => None,
+        name => name,
+    }
+}
+
+/// Creates the directory together with its missing parents. Returns true if the directory
+/// was created, which is only known on the local machine.
+pub fn create(dir: &Path, file_config: &FileConfig) -> Result<bool> {
+    let dir_str = dir.to_string_lossy();
+
+    if file_config.host.is_some() {
+        run_host_modify_cmd(Path::new("cmd"), &mkdir(&dir_str), file_config)
+            .chain_err(|| format!("Unable to create log directory '{}'", dir_str))?;
+
+        return Ok(false);
+    }
+
+    if dir.is_dir() {
+        return Ok(false);
+    }
+
+    if file_config.is_dry_run() {
+        log_dry_run(file_config, &format!("Create log directory '{}'", dir_str));
+    } else {
+        fs::create_dir_all(dir)
+            .chain_err(|| format!("Unable to create log directory '{}'", dir_str))?;
+    }
+
+    Ok(true)
+}
+
+/// Grants the account modify access to the directory.
+pub fn grant_access(dir: &Path, account_name: &str, file_config: &FileConfig) -> Result<()> {
+    let dir_str = dir.to_string_lossy();
+
+    run_host_modify_cmd(program(), &grant(&dir_str, account_name), file_config).chain_err(|| {
+        format!(
+            "Unable to grant '{}' access to log directory '{}'",
+            account_name, dir_str
+        )
+    })
+}
@@ -0,0 +1,233 @@
+//! Checks and grants the right to log on as a service, i.e. `SeServiceLogonRight`, to the
+//! accounts running the services through the Local Security Authority of the host, without
+//! which the services fail to start.
+
+use errors::*;
+
+/// Name of the right that an account requires to run a service.
+pub const SERVICE_LOGON_RIGHT: &str = "SeServiceLogonRight";
+
+/// Returns true if the account has the right to log on as a service on the host, or on the
+/// local machine if no host is given.
+pub fn has_service_logon_right(host: Option<&str>, user: &str) -> Result<bool> {
+    imp::has_right(host, user, SERVICE_LOGON_RIGHT)
+}
+
+/// Grants the account the right to log on as a service on the host, or on the local machine
+/// if no host is given.
+pub fn grant_service_logon_right(host: Option<&str>, user: &str) -> Result<()> {
+    imp::grant_right(host, user, SERVICE_LOGON_RIGHT)
+}
+
+/// Strips the `.\` prefix of local accounts, which the account lookup does not accept.
+fn lookup_name(user: &str) -> &str {
+    user.strip_prefix(".\\").unwrap_or(user)
+}
+
+#[cfg(windows)]
+mod imp {
+    use errors::*;
+    use std::ffi::OsStr;
+    use std::io;
+    use std::mem;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use std::slice;
+    use super::lookup_name;
+    use winapi::shared::minwindef::{DWORD, ULONG};
+    use winapi::shared::ntdef::NTSTATUS;
+    use winapi::shared::ntstatus::STATUS_OBJECT_NAME_NOT_FOUND;
+    use winapi::um::lsalookup::{LSA_OBJECT_ATTRIBUTES, LSA_UNICODE_STRING,
+                                PLSA_UNICODE_STRING};
+    use winapi::um::ntlsa::{self, LSA_HANDLE};
+    use winapi::um::ntsecapi::{POLICY_CREATE_ACCOUNT, POLICY_LOOKUP_NAMES};
+    use winapi::um::winbase::LookupAccountNameW;
+    use winapi::um::winnt::{ACCESS_MASK, PSID, PVOID, SID_NAME_USE};
+
+    struct PolicyHandle(LSA_HANDLE);
+
+    impl Drop for PolicyHandle {
+        fn drop(&mut self) {
+            unsafe {
+                ntlsa::LsaClose(self.0);
+            }
+        }
+    }
+
+    fn to_wide(value: &str) -> Vec<u16> {
+        OsStr::new(value).encode_wide().collect()
+    }
+
+    fn to_wide_null(value: &str) -> Vec<u16> {
+        OsStr::new(value).encode_wide().chain(Some(0)).collect()
+    }
+
+    /// The string borrows the buffer, whose length is in bytes without any terminating null.
+    fn lsa_string(wide: &mut [u16]) -> LSA_UNICODE_STRING {
+        let len = (wide.len() * 2) as u16;
+
+        LSA_UNICODE_STRING {
+            Length: len,
+            MaximumLength: len,
+            Buffer: wide.as_mut_ptr(),
+        }
+    }
+
+    fn check_status(status: NTSTATUS) -> io::Result<()> {
+        if status == 0 {
+            Ok(())
+        } else {
+            let code = unsafe { ntlsa::LsaNtStatusToWinError(status) };
+            Err(io::Error::from_raw_os_error(code as i32))
+        }
+    }
+
+    fn lookup_sid(host: Option<&str>, user: &str) -> Result<Vec<u8>> {
+        let wide_host = host.map(to_wide_null);
+        let host_ptr = wide_host.as_ref().map_or(ptr::null(), |host| host.as_ptr());
+        let wide_user = to_wide_null(lookup_name(user));
+
+        let mut sid_len: DWORD = 0;
+        let mut domain_len: DWORD = 0;
+        let mut sid_use: SID_NAME_USE = 0;
+
+        // the first call only returns the sizes of the buffers
+        unsafe {
+            LookupAccountNameW(
+                host_ptr,
+                wide_user.as_ptr(),
+                ptr::null_mut(),
+                &mut sid_len,
+                ptr::null_mut(),
+                &mut domain_len,
+                &mut sid_use,
+            );
+        }
+
+        if sid_len == 0 {
+            return Err(io::Error::last_os_error())
+                .chain_err(|| format!("Unable to look up account '{}'", user));
+        }
+
+        let mut sid = vec![0u8; sid_len as usize];
+        let mut domain = vec![0u16; domain_len as usize];
+
+        let res = unsafe {
+            LookupAccountNameW(
+                host_ptr,
+                wide_user.as_ptr(),
+                sid.as_mut_ptr() as PSID,
+                &mut sid_len,
+                domain.as_mut_ptr(),
+                &mut domain_len,
+                &mut sid_use,
+            )
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error())
+                .chain_err(|| format!("Unable to look up account '{}'", user));
+        }
+
+        Ok(sid)
+    }
+
+    fn open_policy(host: Option<&str>, access: ACCESS_MASK) -> Result<PolicyHandle> {
+        let mut wide_host = host.map(to_wide);
+        let mut system_name = wide_host.as_mut().map(|host| lsa_string(host));
+
+        let system_name_ptr = system_name
+            .as_mut()
+            .map_or(ptr::null_mut(), |system_name| system_name as PLSA_UNICODE_STRING);
+
+        let mut attributes: LSA_OBJECT_ATTRIBUTES = unsafe { mem::zeroed() };
+        // the handle is returned as a void pointer, unlike the handle taken by other functions
+        let mut handle: PVOID = ptr::null_mut();
+
+        check_status(unsafe {
+            ntlsa::LsaOpenPolicy(system_name_ptr, &mut attributes, access, &mut handle)
+        }).chain_err(|| {
+            format!(
+                "Unable to open the security policy of '{}'",
+                host.unwrap_or("localhost")
+            )
+        })?;
+
+        Ok(PolicyHandle(handle as LSA_HANDLE))
+    }
+
+    pub fn has_right(host: Option<&str>, user: &str, right: &str) -> Result<bool> {
+        let mut sid = lookup_sid(host, user)?;
+        let policy = open_policy(host, POLICY_LOOKUP_NAMES)?;
+
+        let mut rights: PLSA_UNICODE_STRING = ptr::null_mut();
+        let mut count: ULONG = 0;
+
+        let status = unsafe {
+            ntlsa::LsaEnumerateAccountRights(
+                policy.0,
+                sid.as_mut_ptr() as PSID,
+                &mut rights,
+                &mut count,
+            )
+        };
+
+        // the account is not found if it has no right at all
+        if status == STATUS_OBJECT_NAME_NOT_FOUND {
+            return Ok(false);
+        }
+
+        check_status(status)
+            .chain_err(|| format!("Unable to list the rights of account '{}'", user))?;
+
+        let has_right = unsafe { slice::from_raw_parts(rights, count as usize) }
+            .iter()
+            .any(|account_right| {
+                let units = unsafe {
+                    slice::from_raw_parts(account_right.Buffer, account_right.Length as usize / 2)
+                };
+
+                String::from_utf16_lossy(units) == right
+            });
+
+        unsafe {
+            ntlsa::LsaFreeMemory(rights as *mut _);
+        }
+
+        Ok(has_right)
+    }
+
+    pub fn grant_right(host: Option<&str>, user: &str, right: &str) -> Result<()> {
+        let mut sid = lookup_sid(host, user)?;
+        let policy = open_policy(host, POLICY_LOOKUP_NAMES | POLICY_CREATE_ACCOUNT)?;
+
+        let mut wide_right = to_wide(right);
+        let mut account_right = lsa_string(&mut wide_right);
+
+        check_status(unsafe {
+            ntlsa::LsaAddAccountRights(policy.0, sid.as_mut_ptr() as PSID, &mut account_right, 1)
+        }).chain_err(|| format!("Unable to grant right '{}' to account '{}'", right, user))
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use errors::*;
+    use super::lookup_name;
+
+    pub fn has_right(_: Option<&str>, user: &str, right: &str) -> Result<bool> {
+        bail!(
+            "Unable to check right '{}' of account '{}' since LSA is only available on Windows",
+            right,
+            lookup_name(user)
+        )
+    }
+
+    pub fn grant_right(_: Option<&str>, user: &str, right: &str) -> Result<()> {
+        bail!(
+            "Unable to grant right '{}' to account '{}' since LSA is only available on Windows",
+            right,
+            lookup_name(user)
+        )
+    }
+}
@@ -0,0 +1,236 @@
+//! Exports the Prometheus metrics of a run in the text exposition format, either by pushing
+//! them to a Pushgateway or by serving them at `/metrics` for a while at the end of the run,
+//! so that the durations and failure rates of the deployments can be charted.
+
+use config::FileConfig;
+use download;
+use errors::*;
+use notify;
+use report::{Outcome, ServiceReport};
+use std::fmt::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Method, Response, Server};
+use super::log_dry_run;
+
+/// Job label of the pushed metrics when no other job is configured.
+pub const JOB_DEFAULT: &str = "nssm_exec";
+
+/// Number of seconds to serve the metrics for when no other duration is configured.
+pub const LISTEN_SECS_DEFAULT: u64 = 60;
+
+/// Content type of the text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Name and help of a metric of every service, with the value of the metric for a service.
+type ServiceMetric = (&'static str, &'static str, fn(&ServiceReport) -> f64);
+
+fn secs(duration: &Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+/// Escapes the value of a label, where the backslash, the double quote and the line feed
+/// are special.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Percent-encodes the value as a segment of the Pushgateway URL path.
+fn encode_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            b if b.is_ascii_alphanumeric() => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn write_header(text: &mut String, name: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} gauge", name);
+}
+
+/// Renders the metrics of the run with the given service reports in the text exposition
+/// format.
+pub fn render(services: &[ServiceReport], success: bool, run_duration: &Duration) -> String {
+    let mut text = String::new();
+
+    write_header(
+        &mut text,
+        "nssm_exec_run_success",
+        "Whether the last run has succeeded.",
+    );
+
+    let _ = writeln!(text, "nssm_exec_run_success {}", if success { 1 } else { 0 });
+
+    write_header(
+        &mut text,
+        "nssm_exec_run_duration_seconds",
+        "Duration of the last run in seconds.",
+    );
+
+    let _ = writeln!(text, "nssm_exec_run_duration_seconds {}", secs(run_duration));
+
+    write_header(
+        &mut text,
+        "nssm_exec_run_timestamp_seconds",
+        "Unix time at which the last run has completed.",
+    );
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0);
+
+    let _ = writeln!(text, "nssm_exec_run_timestamp_seconds {}", timestamp);
+
+    write_header(
+        &mut text,
+        "nssm_exec_services",
+        "Number of services processed in the last run by outcome.",
+    );
+
+    for &(outcome, label) in &[
+        (Outcome::Ok, "ok"),
+        (Outcome::Failed, "failed"),
+        (Outcome::Skipped, "skipped"),
+    ] {
+        let count = services
+            .iter()
+            .filter(|service| service.outcome == outcome)
+            .count();
+
+        let _ = writeln!(text, "nssm_exec_services{{outcome=\"{}\"}} {}", label, count);
+    }
+
+    let service_metrics: [ServiceMetric; 3] = [
+        (
+            "nssm_exec_service_success",
+            "Whether processing the service has succeeded in the last run.",
+            |service| if service.outcome == Outcome::Ok { 1.0 } else { 0.0 },
+        ),
+        (
+            "nssm_exec_service_duration_seconds",
+            "Duration of processing the service in the last run in seconds.",
+            |service| service.duration_ms as f64 / 1000.0,
+        ),
+        (
+            "nssm_exec_service_poll_iterations",
+            "Number of times the state of the service was polled in the last run.",
+            |service| service.poll_count as f64,
+        ),
+    ];
+
+    for &(name, help, value) in &service_metrics {
+        write_header(&mut text, name, help);
+
+        for service in services {
+            let _ = writeln!(
+                text,
+                "{}{{service=\"{}\",host=\"{}\"}} {}",
+                name,
+                escape_label(&service.name),
+                escape_label(service.host.as_ref().map_or("", |host| host.as_str())),
+                value(service)
+            );
+        }
+    }
+
+    text
+}
+
+/// Exports the metrics of the run as configured, pushing them to the Pushgateway and then
+/// serving them until the configured duration has elapsed. Only logs the export if dry run
+/// is enabled.
+pub fn export(
+    file_config: &FileConfig,
+    services: &[ServiceReport],
+    success: bool,
+    run_duration: &Duration,
+) -> Result<()> {
+    let metrics = match file_config.metrics {
+        Some(ref metrics) => metrics,
+        None => return Ok(()),
+    };
+
+    let text = render(services, success, run_duration);
+
+    if let Some(ref pushgateway_url) = metrics.pushgateway_url {
+        let url = format!(
+            "{}/metrics/job/{}/instance/{}",
+            pushgateway_url.trim_end_matches('/'),
+            encode_segment(metrics.job.as_ref().map_or(JOB_DEFAULT, |job| job.as_str())),
+            encode_segment(&notify::machine_name())
+        );
+
+        if file_config.is_dry_run() {
+            log_dry_run(file_config, &format!("Push metrics to '{}'", url));
+        } else {
+            download::post(&url, CONTENT_TYPE, &text)
+                .chain_err(|| format!("Unable to push metrics to '{}'", url))?;
+
+            info!("Metrics pushed to '{}'", url);
+        }
+    }
+
+    if let Some(ref listen_address) = metrics.listen_address {
+        let listen_secs = metrics.listen_secs.unwrap_or(LISTEN_SECS_DEFAULT);
+
+        if file_config.is_dry_run() {
+            log_dry_run(
+                file_config,
+                &format!("Serve metrics on '{}' for {}s", listen_address, listen_secs),
+            );
+        } else {
+            serve(listen_address, &Duration::from_secs(listen_secs), &text)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Serves the metrics at `/metrics` on the address until the duration has elapsed.
+fn serve(address: &str, duration: &Duration, text: &str) -> Result<()> {
+    let server = Server::http(address)
+        .map_err(|e| Error::from(format!("Unable to listen on '{}': {}", address, e)))?;
+
+    let content_type = Header::from_bytes(&b"Content-Type"[..], CONTENT_TYPE.as_bytes())
+        .expect("Content-Type header is valid");
+
+    info!(
+        "Serving metrics on '{}' for {}s...",
+        address,
+        duration.as_secs()
+    );
+
+    let start_time = Instant::now();
+
+    while let Some(remaining) = duration.checked_sub(start_time.elapsed()) {
+        let request = match server.recv_timeout(remaining) {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Unable to receive request: {}", e);
+                continue;
+            }
+        };
+
+        let path = request.url().split('?').next().unwrap_or("").to_owned();
+
+        let response = if *request.method() == Method::Get && path == "/metrics" {
+            Response::from_string(text.to_owned()).with_header(content_type.clone())
+        } else {
+            Response::from_string("Not found".to_owned()).with_status_code(404)
+        };
+
+        if let Err(e) = request.respond(response) {
+            warn!("Unable to send response: {}", e);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,311 @@
+//! Simulates the services in memory instead of managing them with any tool, recording every
+//! operation that modifies them, so that the whole flow can be exercised without touching the
+//! machine, e.g. in tests. Started and stopped services are reported as pending once before
+//! reaching their final state, like services that take a while to start or stop.
+
+use backend::ServiceBackend;
+use config::FileConfig;
+use errors::*;
+use params::{self, ServiceParam};
+use state::ServiceState;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use super::log_dry_run;
+
+/// Simulated service with its installed parameters.
+struct MockService {
+    state: ServiceState,
+    params: Vec<ServiceParam>,
+}
+
+lazy_static! {
+    static ref SERVICES: Mutex<BTreeMap<String, MockService>> = Mutex::new(BTreeMap::new());
+
+    /// Operations in order with the name of the service operated on.
+    static ref OPERATIONS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+    /// Prefixes of the operations to fail once, with the name of the service operated on.
+    static ref FAILURES: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+}
+
+/// Removes every simulated service, recorded operation and pending failure, e.g. to start
+/// each test afresh.
+#[cfg(feature = "mock")]
+pub fn reset() {
+    SERVICES.lock().unwrap().clear();
+    OPERATIONS.lock().unwrap().clear();
+    FAILURES.lock().unwrap().clear();
+}
+
+/// Adds a service as if it has been installed before with the executable at the path.
+#[cfg(feature = "mock")]
+pub fn add_service(service_name: &str, path: &str, state: ServiceState) {
+    SERVICES.lock().unwrap().insert(
+        service_name.to_owned(),
+        MockService {
+            state,
+            params: vec![param(params::APPLICATION, vec![path.to_owned()])],
+        },
+    );
+}
+
+/// Returns the operations on the service in order, e.g. `install app.exe`,
+/// `set AppDirectory C:\app`, `start`, `stop` or `remove`.
+#[cfg(feature = "mock")]
+pub fn operations(service_name: &str) -> Vec<String> {
+    OPERATIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|&(name, _)| name == service_name)
+        .map(|(_, operation)| operation.clone())
+        .collect()
+}
+
+/// Returns the current state of the service, or `None` if it is not installed.
+pub fn state(service_name: &str) -> Option<ServiceState> {
+    SERVICES
+        .lock()
+        .unwrap()
+        .get(service_name)
+        .map(|service| service.state)
+}
+
+/// Returns the installed values of the parameter of the service on separate lines, e.g. for
+/// the key `AppExit Default`, or `None` if the service or parameter is not set.
+pub fn param_value(service_name: &str, key: &str) -> Option<String> {
+    SERVICES
+        .lock()
+        .unwrap()
+        .get(service_name)
+        .and_then(|service| service.params.iter().find(|param| param.key() == key))
+        .map(|param| param.values.join("\n"))
+}
+
+/// Fails the next operation on the service that starts with the given prefix, e.g. `start`
+/// or `set AppDirectory`, without applying it.
+#[cfg(feature = "mock")]
+pub fn fail_next(service_name: &str, operation_prefix: &str) {
+    FAILURES
+        .lock()
+        .unwrap()
+        .push((service_name.to_owned(), operation_prefix.to_owned()));
+}
+
+fn param(name: &'static str, values: Vec<String>) -> ServiceParam {
+    ServiceParam {
+        name,
+        sub: None,
+        values,
+        secret: None,
+    }
+}
+
+/// Records the operation on the service, failing if a failure is pending for it. Only logs
+/// the operation without recording it if dry run is enabled, in which case `false` is
+/// returned so that the operation is not applied.
+fn record(service_name: &str, operation: String, file_config: &FileConfig) -> Result<bool> {
+    if file_config.is_dry_run() {
+        log_dry_run(
+            file_config,
+            &format!("Mock {} of service '{}'", operation, service_name),
+        );
+
+        return Ok(false);
+    }
+
+    {
+        let mut failures = FAILURES.lock().unwrap();
+
+        let failure_index = failures.iter().position(|(name, prefix)| {
+            name == service_name && operation.starts_with(prefix.as_str())
+        });
+
+        if let Some(failure_index) = failure_index {
+            failures.remove(failure_index);
+            bail!("Mock {} of service '{}' failed", operation, service_name);
+        }
+    }
+
+    OPERATIONS
+        .lock()
+        .unwrap()
+        .push((service_name.to_owned(), operation));
+
+    Ok(true)
+}
+
+fn not_installed(service_name: &str) -> Error {
+    format!("Service '{}' is not installed in the mock backend", service_name).into()
+}
+
+/// Simulates the services in memory.
+pub struct Mock;
+
+impl ServiceBackend for Mock {
+    fn install(&self, service_name: &str, path: &Path, file_config: &FileConfig) -> Result<()> {
+        let path = path.to_string_lossy().into_owned();
+
+        if !record(service_name, format!("install {}", path), file_config)? {
+            return Ok(());
+        }
+
+        let mut services = SERVICES.lock().unwrap();
+
+        if services.contains_key(service_name) {
+            bail!("Service '{}' is already installed in the mock backend", service_name);
+        }
+
+        services.insert(
+            service_name.to_owned(),
+            MockService {
+                state: ServiceState::Stopped,
+                params: vec![param(params::APPLICATION, vec![path])],
+            },
+        );
+
+        Ok(())
+    }
+
+    fn set_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<()> {
+        let operation = if param.is_reset() {
+            format!("reset {}", param.key())
+        } else {
+            format!("set {} {}", param.key(), param.value())
+        };
+
+        if !record(service_name, operation, file_config)? {
+            return Ok(());
+        }
+
+        let mut services = SERVICES.lock().unwrap();
+
+        let service = services
+            .get_mut(service_name)
+            .ok_or_else(|| not_installed(service_name))?;
+
+        if param.is_reset() {
+            service.params.retain(|existing| existing.key() != param.key());
+            return Ok(());
+        }
+
+        let param = ServiceParam {
+            secret: None,
+            ..param.clone()
+        };
+
+        match service
+            .params
+            .iter()
+            .position(|existing| existing.key() == param.key())
+        {
+            Some(index) => service.params[index] = param,
+            None => service.params.push(param),
+        }
+
+        Ok(())
+    }
+
+    fn get_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        _: &FileConfig,
+    ) -> Result<String> {
+        if state(service_name).is_none() {
+            return Err(not_installed(service_name));
+        }
+
+        // unset parameters are empty like in nssm
+        Ok(param_value(service_name, &param.key()).unwrap_or_default())
+    }
+
+    fn status(&self, service_name: &str, _: &FileConfig) -> Result<ServiceState> {
+        let mut services = SERVICES.lock().unwrap();
+
+        let service = services
+            .get_mut(service_name)
+            .ok_or_else(|| not_installed(service_name))?;
+
+        let state = service.state;
+
+        // pending states are only reported once
+        service.state = match state {
+            ServiceState::StartPending => ServiceState::Running,
+            ServiceState::StopPending => ServiceState::Stopped,
+            state => state,
+        };
+
+        Ok(state)
+    }
+
+    fn start(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        if !record(service_name, "start".to_owned(), file_config)? {
+            return Ok(());
+        }
+
+        let mut services = SERVICES.lock().unwrap();
+
+        let service = services
+            .get_mut(service_name)
+            .ok_or_else(|| not_installed(service_name))?;
+
+        if service.state == ServiceState::Stopped {
+            service.state = ServiceState::StartPending;
+        }
+
+        Ok(())
+    }
+
+    fn stop(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        if !record(service_name, "stop".to_owned(), file_config)? {
+            return Ok(());
+        }
+
+        let mut services = SERVICES.lock().unwrap();
+
+        let service = services
+            .get_mut(service_name)
+            .ok_or_else(|| not_installed(service_name))?;
+
+        if service.state != ServiceState::Stopped {
+            service.state = ServiceState::StopPending;
+        }
+
+        Ok(())
+    }
+
+    fn kill_tree(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        if !record(service_name, "kill_tree".to_owned(), file_config)? {
+            return Ok(());
+        }
+
+        let mut services = SERVICES.lock().unwrap();
+
+        let service = services
+            .get_mut(service_name)
+            .ok_or_else(|| not_installed(service_name))?;
+
+        service.state = ServiceState::Stopped;
+        Ok(())
+    }
+
+    fn remove(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        if !record(service_name, "remove".to_owned(), file_config)? {
+            return Ok(());
+        }
+
+        SERVICES
+            .lock()
+            .unwrap()
+            .remove(service_name)
+            .map(|_| ())
+            .ok_or_else(|| not_installed(service_name))
+    }
+}
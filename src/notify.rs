@@ -0,0 +1,308 @@
+//! Sends notifications of the outcome of a run, so that unattended deployments do not fail
+//! silently. The summary email is sent through PowerShell's `Send-MailMessage`, which takes
+//! care of STARTTLS and authentication, and the webhook message is posted like the
+//! downloads.
+
+use config::{EmailNotify, FileConfig, WebhookNotify};
+use download;
+use errors::*;
+use interp;
+use remote;
+use report::{Outcome, ServiceReport};
+use serde_json;
+use std::collections::BTreeMap;
+use std::env;
+use std::process::Command;
+use super::{check_output, log_dry_run, print_recursive_err};
+
+/// Default port of the SMTP server.
+pub const SMTP_PORT_DEFAULT: u16 = 25;
+
+/// Default template of the webhook message, which both Slack and Teams incoming webhooks
+/// accept.
+pub const WEBHOOK_TEMPLATE_DEFAULT: &str = r#"{"text": "{{ summary }}"}"#;
+
+/// Names of the variables available to the webhook template.
+pub const WEBHOOK_VARIABLES: &[&str] = &[
+    "status",
+    "machine",
+    "summary",
+    "installed",
+    "started",
+    "stopped",
+    "removed",
+    "failed",
+    "skipped",
+];
+
+/// Script that sends the email from the environment variables set by `send_email`, so that
+/// none of the values needs quoting.
+const SEND_MAIL_SCRIPT: &str = "$params = @{ \
+    SmtpServer = $env:NSSM_EXEC_SMTP_SERVER; \
+    Port = [int]$env:NSSM_EXEC_SMTP_PORT; \
+    From = $env:NSSM_EXEC_MAIL_FROM; \
+    To = ($env:NSSM_EXEC_MAIL_TO -split \"`n\"); \
+    Subject = $env:NSSM_EXEC_MAIL_SUBJECT; \
+    Body = $env:NSSM_EXEC_MAIL_BODY; \
+    Encoding = [System.Text.Encoding]::UTF8 }; \
+    if ($env:NSSM_EXEC_SMTP_TLS -eq '1') { $params.UseSsl = $true }; \
+    if ($env:NSSM_EXEC_SMTP_USERNAME) { \
+    $password = ConvertTo-SecureString $env:NSSM_EXEC_SMTP_PASSWORD -AsPlainText -Force; \
+    $params.Credential = New-Object System.Management.Automation.PSCredential(\
+    $env:NSSM_EXEC_SMTP_USERNAME, $password) }; \
+    Send-MailMessage @params -ErrorAction Stop";
+
+/// Sends the configured notifications of the run with the given service reports and result.
+/// A notification that fails to be sent is logged without affecting the other ones. Only
+/// logs the notifications if dry run is enabled.
+pub fn notify(file_config: &FileConfig, services: &[ServiceReport], res: &Result<()>) {
+    let notify = match file_config.notify {
+        Some(ref notify) => notify,
+        None => return,
+    };
+
+    let subject = subject(res.is_ok());
+
+    if let Some(ref email) = notify.email {
+        if res.is_err() || email.only_on_failure != Some(true) {
+            if file_config.is_dry_run() {
+                log_dry_run(
+                    file_config,
+                    &format!("Send email '{}' to {}", subject, email.to.join(", ")),
+                );
+            } else if let Err(e) = send_email(email, &subject, &body(services, res))
+                .chain_err(|| format!("Unable to send email '{}'", subject))
+            {
+                print_recursive_err(&e);
+            }
+        }
+    }
+
+    if let Some(ref webhook) = notify.webhook {
+        if res.is_err() || webhook.only_on_failure != Some(true) {
+            if file_config.is_dry_run() {
+                log_dry_run(file_config, &format!("Post webhook message '{}'", subject));
+            } else if let Err(e) = post_webhook(webhook, services, res)
+                .chain_err(|| format!("Unable to post webhook message '{}'", subject))
+            {
+                print_recursive_err(&e);
+            }
+        }
+    }
+}
+
+/// Returns the name of this machine for the notifications, or `localhost` if unknown.
+pub fn machine_name() -> String {
+    env::var("COMPUTERNAME")
+        .or_else(|_| env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "localhost".to_owned())
+}
+
+fn subject(success: bool) -> String {
+    format!(
+        "nssm_exec run {} on {}",
+        if success { "succeeded" } else { "failed" },
+        machine_name()
+    )
+}
+
+/// Returns the plain text body with the error of the run, if any, and the outcome, final
+/// state and errors of every service.
+fn body(services: &[ServiceReport], res: &Result<()>) -> String {
+    let mut body = format!("{}.\n", subject(res.is_ok()));
+
+    if let Err(ref e) = *res {
+        body.push_str("\nError:\n");
+
+        for cause in e.iter() {
+            body.push_str(&format!("  {}\n", cause));
+        }
+    }
+
+    if !services.is_empty() {
+        body.push_str("\nServices:\n");
+    }
+
+    for service in services {
+        let outcome = match service.outcome {
+            Outcome::Ok => "ok",
+            Outcome::Failed => "failed",
+            Outcome::Skipped => "skipped",
+        };
+
+        let final_state = match service.final_state {
+            Some(state) => format!("{:?}", state),
+            None => "not installed".to_owned(),
+        };
+
+        match service.host {
+            Some(ref host) => body.push_str(&format!(
+                "  {} on {}: {} ({})\n",
+                service.name,
+                host,
+                outcome,
+                final_state
+            )),
+            None => body.push_str(&format!(
+                "  {}: {} ({})\n",
+                service.name,
+                outcome,
+                final_state
+            )),
+        }
+
+        for error in &service.errors {
+            body.push_str(&format!("    {}\n", error));
+        }
+    }
+
+    body
+}
+
+/// Returns the password to authenticate to the SMTP server with, if any.
+fn password(email: &EmailNotify) -> Result<Option<String>> {
+    match (email.password.as_ref(), email.password_env.as_ref()) {
+        (Some(_), Some(_)) => bail!("Unable to use both password and password_env for email"),
+        (Some(password), None) => Ok(Some(password.clone())),
+        (None, Some(password_env)) => env::var(password_env).map(Some).chain_err(|| {
+            format!(
+                "Unable to read email password from environment variable '{}'",
+                password_env
+            )
+        }),
+        (None, None) => Ok(None),
+    }
+}
+
+fn send_email(email: &EmailNotify, subject: &str, body: &str) -> Result<()> {
+    if email.to.is_empty() {
+        bail!("Unable to send email without any recipient in [notify.email]");
+    }
+
+    let mut cmd = Command::new(remote::program());
+
+    cmd.args(remote::encoded_args(SEND_MAIL_SCRIPT))
+        .env("NSSM_EXEC_SMTP_SERVER", &email.smtp_server)
+        .env(
+            "NSSM_EXEC_SMTP_PORT",
+            email.smtp_port.unwrap_or(SMTP_PORT_DEFAULT).to_string(),
+        )
+        .env(
+            "NSSM_EXEC_SMTP_TLS",
+            if email.use_tls == Some(true) { "1" } else { "0" },
+        )
+        .env("NSSM_EXEC_MAIL_FROM", &email.from)
+        .env("NSSM_EXEC_MAIL_TO", email.to.join("\n"))
+        .env("NSSM_EXEC_MAIL_SUBJECT", subject)
+        .env("NSSM_EXEC_MAIL_BODY", body)
+        .env(
+            "NSSM_EXEC_SMTP_USERNAME",
+            email.username.as_deref().unwrap_or(""),
+        )
+        .env("NSSM_EXEC_SMTP_PASSWORD", password(email)?.unwrap_or_default());
+
+    debug!("Sending email '{}' through '{}'...", subject, email.smtp_server);
+
+    let cmd_name = format!("{} Send-MailMessage", remote::program().to_string_lossy());
+    check_output(&cmd_name, cmd.output())
+}
+
+/// Returns the names of the services that have the given action, or that have the given
+/// outcome if no action is given, with the host of the service if any.
+fn service_names(
+    services: &[ServiceReport],
+    action: Option<&str>,
+    outcome: Option<Outcome>,
+) -> Vec<String> {
+    services
+        .iter()
+        .filter(|service| match action {
+            Some(action) => service.actions.iter().any(|done| done == action),
+            None => Some(service.outcome) == outcome,
+        })
+        .map(|service| match service.host {
+            Some(ref host) => format!("{} on {}", service.name, host),
+            None => service.name.clone(),
+        })
+        .collect()
+}
+
+/// Returns the values of the webhook template variables, which are not yet escaped.
+fn webhook_values(services: &[ServiceReport], res: &Result<()>) -> BTreeMap<String, String> {
+    let lists = [
+        ("installed", "Installed", service_names(services, Some("installed"), None)),
+        ("started", "Started", service_names(services, Some("started"), None)),
+        ("stopped", "Stopped", service_names(services, Some("stopped"), None)),
+        ("removed", "Removed", service_names(services, Some("removed"), None)),
+        ("failed", "Failed", service_names(services, None, Some(Outcome::Failed))),
+        ("skipped", "Skipped", service_names(services, None, Some(Outcome::Skipped))),
+    ];
+
+    let mut summary = format!("{}.", subject(res.is_ok()));
+    let mut values = BTreeMap::new();
+
+    for &(name, label, ref names) in &lists {
+        if !names.is_empty() {
+            summary.push_str(&format!(" {}: {}.", label, names.join(", ")));
+        }
+
+        values.insert(name.to_owned(), names.join(", "));
+    }
+
+    if let Err(ref e) = *res {
+        summary.push_str(&format!(" Error: {}", e));
+    }
+
+    let status = if res.is_ok() { "succeeded" } else { "failed" };
+    values.insert("status".to_owned(), status.to_owned());
+    values.insert("machine".to_owned(), machine_name());
+    values.insert("summary".to_owned(), summary);
+    values
+}
+
+/// Renders the webhook template with the given variable values, escaping every value as
+/// the content of a JSON string, since the template is JSON.
+pub fn render_webhook_template(
+    template: &str,
+    values: &BTreeMap<String, String>,
+) -> Result<String> {
+    let escaped = values
+        .iter()
+        .map(|(name, value)| {
+            let quoted = serde_json::to_string(value)
+                .chain_err(|| format!("Unable to escape webhook variable '{}'", name))?;
+
+            Ok((name.clone(), quoted[1..quoted.len() - 1].to_owned()))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+    interp::render_vars(template, &escaped).chain_err(|| "Unable to render webhook template")
+}
+
+/// Returns the URL of the webhook.
+fn webhook_url(webhook: &WebhookNotify) -> Result<String> {
+    match (webhook.url.as_ref(), webhook.url_env.as_ref()) {
+        (Some(_), Some(_)) => bail!("Unable to use both url and url_env for webhook"),
+        (Some(url), None) => Ok(url.clone()),
+        (None, Some(url_env)) => env::var(url_env).chain_err(|| {
+            format!("Unable to read webhook URL from environment variable '{}'", url_env)
+        }),
+        (None, None) => bail!("Unable to post webhook message without url or url_env"),
+    }
+}
+
+fn post_webhook(
+    webhook: &WebhookNotify,
+    services: &[ServiceReport],
+    res: &Result<()>,
+) -> Result<()> {
+    let template = webhook
+        .template.as_deref()
+        .unwrap_or(WEBHOOK_TEMPLATE_DEFAULT);
+
+    let body = render_webhook_template(template, &webhook_values(services, res))?;
+    let url = webhook_url(webhook)?;
+
+    debug!("Posting webhook message...");
+    download::post(&url, "application/json", &body)
+}
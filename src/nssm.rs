@@ -0,0 +1,64 @@
+//! Builds the argument vectors of the nssm commands, which are passed to the nssm executable
+//! directly without going through any shell, so that no manual quoting is needed.
+
+use params::ServiceParam;
+use std::path::Path;
+
+/// Arguments to install the service with the given executable path.
+pub fn install(service_name: &str, path: &Path) -> Vec<String> {
+    vec![
+        "install".to_owned(),
+        service_name.to_owned(),
+        path.to_string_lossy().into_owned(),
+    ]
+}
+
+/// Arguments to remove the service without any confirmation prompt.
+pub fn remove(service_name: &str) -> Vec<String> {
+    vec![
+        "remove".to_owned(),
+        service_name.to_owned(),
+        "confirm".to_owned(),
+    ]
+}
+
+/// Arguments to start the service.
+pub fn start(service_name: &str) -> Vec<String> {
+    vec!["start".to_owned(), service_name.to_owned()]
+}
+
+/// Arguments to stop the service.
+pub fn stop(service_name: &str) -> Vec<String> {
+    vec!["stop".to_owned(), service_name.to_owned()]
+}
+
+/// Arguments to query the status of the service.
+#[cfg(not(windows))]
+pub fn status(service_name: &str) -> Vec<String> {
+    vec!["status".to_owned(), service_name.to_owned()]
+}
+
+/// Arguments to get the installed value of the service parameter.
+pub fn get(service_name: &str, param: &ServiceParam) -> Vec<String> {
+    param_args("get", service_name, param)
+}
+
+/// Arguments to set the service parameter to its values, followed by the secret if any.
+/// Each value is passed as a separate argument, which nssm joins with spaces for single
+/// value parameters anyway. A parameter without any value is reset to its default instead.
+pub fn set(service_name: &str, param: &ServiceParam) -> Vec<String> {
+    if param.is_reset() {
+        return param_args("reset", service_name, param);
+    }
+
+    let mut args = param_args("set", service_name, param);
+    args.extend(param.values.iter().cloned());
+    args.extend(param.secret.iter().cloned());
+    args
+}
+
+fn param_args(cmd: &str, service_name: &str, param: &ServiceParam) -> Vec<String> {
+    let mut args = vec![cmd.to_owned(), service_name.to_owned(), param.name.to_owned()];
+    args.extend(param.sub.iter().cloned());
+    args
+}
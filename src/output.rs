@@ -0,0 +1,103 @@
+//! Decodes the output of the external commands, which comes in different encodings on
+//! Windows: nssm writes UTF-16LE to the console, while the other tools, e.g. `sc` and
+//! `netsh`, write in the OEM codepage of the console.
+
+use std::str;
+
+/// Decodes the output of a command into text, detecting UTF-16LE by its BOM or by the zero
+/// high bytes of its ASCII characters, and falling back from UTF-8 to the OEM codepage of
+/// the console on Windows. Any null character is removed.
+pub fn decode(bytes: &[u8]) -> String {
+    let text = if bytes.starts_with(&[0xFF, 0xFE]) {
+        decode_utf16le(&bytes[2..])
+    } else if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        String::from_utf8_lossy(&bytes[3..]).into_owned()
+    } else if is_utf16le(bytes) {
+        decode_utf16le(bytes)
+    } else {
+        match str::from_utf8(bytes) {
+            Ok(text) => text.to_owned(),
+            Err(_) => imp::decode_oem(bytes),
+        }
+    };
+
+    text.chars().filter(|&c| c != '\0').collect()
+}
+
+/// Returns true if the bytes without BOM look like UTF-16LE, i.e. the high byte of at least
+/// half of the code units is zero, since the output is mostly ASCII, while zero bytes hardly
+/// appear in the other encodings.
+fn is_utf16le(bytes: &[u8]) -> bool {
+    let units = bytes.chunks(2).filter(|unit| unit.len() == 2);
+    let unit_count = units.clone().count();
+    let zero_high_count = units.filter(|unit| unit[1] == 0 && unit[0] != 0).count();
+
+    unit_count > 0 && zero_high_count * 2 >= unit_count
+}
+
+fn decode_utf16le(bytes: &[u8]) -> String {
+    // an odd trailing byte is not a complete code unit
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .filter(|unit| unit.len() == 2)
+        .map(|unit| u16::from(unit[0]) | u16::from(unit[1]) << 8)
+        .collect();
+
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::ptr;
+    use winapi::ctypes::c_int;
+    use winapi::um::stringapiset;
+    use winapi::um::winnls::CP_OEMCP;
+    use winapi::um::winnt::LPCSTR;
+
+    pub fn decode_oem(bytes: &[u8]) -> String {
+        if bytes.is_empty() {
+            return String::new();
+        }
+
+        let len = unsafe {
+            stringapiset::MultiByteToWideChar(
+                CP_OEMCP,
+                0,
+                bytes.as_ptr() as LPCSTR,
+                bytes.len() as c_int,
+                ptr::null_mut(),
+                0,
+            )
+        };
+
+        if len <= 0 {
+            return String::from_utf8_lossy(bytes).into_owned();
+        }
+
+        let mut wide: Vec<u16> = vec![0; len as usize];
+
+        let len = unsafe {
+            stringapiset::MultiByteToWideChar(
+                CP_OEMCP,
+                0,
+                bytes.as_ptr() as LPCSTR,
+                bytes.len() as c_int,
+                wide.as_mut_ptr(),
+                wide.len() as c_int,
+            )
+        };
+
+        if len <= 0 {
+            return String::from_utf8_lossy(bytes).into_owned();
+        }
+
+        String::from_utf16_lossy(&wide[..len as usize])
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    pub fn decode_oem(bytes: &[u8]) -> String {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
@@ -0,0 +1,457 @@
+use backend;
+use config::{Backend, ExitActionConfig, FileConfig, Service};
+use errors::*;
+
+/// nssm parameter name of the service executable file path.
+pub const APPLICATION: &str = "Application";
+
+/// nssm parameters that are only read by the Service Control Manager, and not by nssm when
+/// it starts the executable, so that changing them takes effect without a restart.
+pub const METADATA_PARAMS: &[&str] =
+    &["DisplayName", "Description", "DependOnService", "DependOnGroup"];
+
+/// nssm parameters without any subparameter that nssm_exec manages, with the values that nssm
+/// reports for them when they are not set. They are reset once they are no longer configured,
+/// unlike the parameters that nssm derives when installing the service, e.g. `AppDirectory`.
+const RESETTABLE_PARAMS: &[(&str, &str)] = &[
+    ("AppParameters", ""),
+    ("AppEnvironmentExtra", ""),
+    ("Description", ""),
+    ("AppStdout", ""),
+    ("AppStderr", ""),
+    ("AppRotateFiles", "0"),
+    ("AppRotateOnline", "0"),
+    ("AppRotateSeconds", "0"),
+    ("AppRotateBytes", "0"),
+    ("AppPriority", "NORMAL_PRIORITY_CLASS"),
+    ("AppNoConsole", "0"),
+    ("AppThrottle", "1500"),
+    ("AppRestartDelay", "0"),
+    ("AppStopMethodSkip", "0"),
+    ("AppStopMethodConsole", "1500"),
+    ("AppStopMethodWindow", "1500"),
+    ("AppStopMethodThreads", "1500"),
+    ("AppKillProcessTree", "1"),
+    ("DependOnService", ""),
+    ("DependOnGroup", ""),
+    ("ObjectName", "LocalSystem"),
+];
+
+/// Represents a single nssm parameter of a service.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceParam {
+    /// nssm parameter name, e.g. `AppDirectory`.
+    pub name: &'static str,
+
+    /// nssm subparameter name, e.g. the exit code for `AppExit`.
+    pub sub: Option<String>,
+
+    /// Values of the parameter. Only multi-string parameters, e.g. `DependOnService`,
+    /// have more than one value, each of which is passed as a separate argument to nssm.
+    pub values: Vec<String>,
+
+    /// Additional value that is only used when setting the parameter and is never compared
+    /// against the installed value, e.g. the account password. Hence `update` never sets the
+    /// account again only because its password has changed.
+    pub secret: Option<String>,
+}
+
+impl ServiceParam {
+    fn new<S: Into<String>>(name: &'static str, value: S) -> ServiceParam {
+        ServiceParam {
+            name,
+            sub: None,
+            values: vec![value.into()],
+            secret: None,
+        }
+    }
+
+    fn multi<I, S>(name: &'static str, values: I) -> ServiceParam
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ServiceParam {
+            values: values.into_iter().map(|value| value.into()).collect(),
+            ..ServiceParam::new(name, "")
+        }
+    }
+
+    fn reset(name: &'static str) -> ServiceParam {
+        ServiceParam {
+            values: Vec::new(),
+            ..ServiceParam::new(name, "")
+        }
+    }
+
+    fn with_sub<S: Into<String>, T: Into<String>>(
+        name: &'static str,
+        sub: S,
+        value: T,
+    ) -> ServiceParam {
+        ServiceParam {
+            sub: Some(sub.into()),
+            ..ServiceParam::new(name, value)
+        }
+    }
+
+    /// Returns the parameter name followed by the subparameter name if any,
+    /// as used in the nssm command line.
+    pub fn key(&self) -> String {
+        match self.sub {
+            Some(ref sub) => format!("{} {}", self.name, sub),
+            None => self.name.to_owned(),
+        }
+    }
+
+    /// Returns the values of the parameter delimited by space, for display purposes.
+    pub fn value(&self) -> String {
+        self.values.join(" ")
+    }
+
+    /// Returns true if the parameter only changes the metadata of the service, which takes
+    /// effect without restarting the service.
+    pub fn is_metadata(&self) -> bool {
+        METADATA_PARAMS.contains(&self.name)
+    }
+
+    /// Returns true if the parameter has no value, which resets it to the default of nssm.
+    pub fn is_reset(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn is_multi(&self) -> bool {
+        self.name == "DependOnService" || self.name == "DependOnGroup"
+            || self.name == "AppEnvironmentExtra"
+    }
+}
+
+/// Returns the nssm parameters that the service should have according to the configuration.
+/// Only the configured parameters are returned, in the order they should be set.
+pub fn desired_params(service: &Service, file_config: &FileConfig) -> Vec<ServiceParam> {
+    let mut params = vec![
+        ServiceParam::new(APPLICATION, service.path.to_string_lossy()),
+    ];
+
+    if let Some(ref startup_dir) = service.startup_dir {
+        // app directory is also relative from nssm.exe
+        params.push(ServiceParam::new(
+            "AppDirectory",
+            startup_dir.to_string_lossy(),
+        ));
+    }
+
+    if let Some(ref args) = service.args {
+        params.push(ServiceParam::new("AppParameters", args.to_line()));
+    }
+
+    if let Some(ref env) = service.env {
+        params.push(ServiceParam::multi(
+            "AppEnvironmentExtra",
+            env.iter().map(|(name, value)| format!("{}={}", name, value)),
+        ));
+    }
+
+    if let Some(ref display_name) = service.display_name {
+        params.push(ServiceParam::new("DisplayName", display_name.as_str()));
+    }
+
+    if let Some(ref description) = service.description {
+        params.push(ServiceParam::new("Description", description.as_str()));
+    }
+
+    if let Some(ref stdout_log) = service.stdout_log {
+        params.push(ServiceParam::new("AppStdout", stdout_log.to_string_lossy()));
+    }
+
+    if let Some(ref stderr_log) = service.stderr_log {
+        params.push(ServiceParam::new("AppStderr", stderr_log.to_string_lossy()));
+    }
+
+    if let Some(ref exit_action) = service.exit_action {
+        match *exit_action {
+            ExitActionConfig::Default(action) => {
+                params.push(ServiceParam::with_sub("AppExit", "Default", action.as_str()));
+            }
+
+            ExitActionConfig::PerCode {
+                ref default,
+                ref codes,
+            } => {
+                if let Some(action) = *default {
+                    params.push(ServiceParam::with_sub("AppExit", "Default", action.as_str()));
+                }
+
+                for (code, action) in codes {
+                    params.push(ServiceParam::with_sub("AppExit", code.as_str(), action.as_str()));
+                }
+            }
+        }
+    }
+
+    if let Some(ref hooks) = service.hooks {
+        for (event, cmd) in hooks.events() {
+            params.push(ServiceParam::with_sub("AppEvents", event, cmd));
+        }
+    }
+
+    if let Some(priority) = service.priority {
+        params.push(ServiceParam::new("AppPriority", priority.as_str()));
+    }
+
+    let log_rotation = file_config.merged_log_rotation(service);
+
+    if let Some(enabled) = log_rotation.enabled {
+        params.push(ServiceParam::new("AppRotateFiles", bool_value(enabled)));
+    }
+
+    if let Some(online) = log_rotation.online {
+        params.push(ServiceParam::new("AppRotateOnline", bool_value(online)));
+    }
+
+    if let Some(seconds) = log_rotation.seconds {
+        params.push(ServiceParam::new("AppRotateSeconds", seconds.to_string()));
+    }
+
+    if let Some(bytes) = log_rotation.bytes {
+        params.push(ServiceParam::new("AppRotateBytes", bytes.to_string()));
+    }
+
+    let merged_other = file_config.merged_other(service);
+
+    if let Some(deps) = merged_other.deps {
+        params.push(ServiceParam::multi("DependOnService", deps.names()));
+    }
+
+    if let Some(dep_groups) = merged_other.dep_groups {
+        params.push(ServiceParam::multi("DependOnGroup", dep_groups.names()));
+    }
+
+    if let Some(account) = merged_other.account {
+        // built-in and virtual accounts are set without any password
+        let secret = if account.takes_password() {
+            Some(account.password.clone())
+        } else {
+            None
+        };
+
+        params.push(ServiceParam {
+            secret,
+            ..ServiceParam::new("ObjectName", account.object_name(&service.name))
+        });
+    }
+
+    if let Some(throttle_ms) = merged_other.throttle_ms {
+        params.push(ServiceParam::new("AppThrottle", throttle_ms.to_string()));
+    }
+
+    if let Some(restart_delay_ms) = merged_other.restart_delay_ms {
+        params.push(ServiceParam::new(
+            "AppRestartDelay",
+            restart_delay_ms.to_string(),
+        ));
+    }
+
+    if let Some(&no_console) = merged_other.no_console {
+        params.push(ServiceParam::new("AppNoConsole", bool_value(no_console)));
+    }
+
+    if let Some(stop_method) = merged_other.stop_method {
+        if let Some(skip_mask) = stop_method.skip_mask() {
+            params.push(ServiceParam::new(
+                "AppStopMethodSkip",
+                skip_mask.to_string(),
+            ));
+        }
+
+        if let Some(console_ms) = stop_method.console_ms {
+            params.push(ServiceParam::new(
+                "AppStopMethodConsole",
+                console_ms.to_string(),
+            ));
+        }
+
+        if let Some(window_ms) = stop_method.window_ms {
+            params.push(ServiceParam::new(
+                "AppStopMethodWindow",
+                window_ms.to_string(),
+            ));
+        }
+
+        if let Some(threads_ms) = stop_method.threads_ms {
+            params.push(ServiceParam::new(
+                "AppStopMethodThreads",
+                threads_ms.to_string(),
+            ));
+        }
+
+        if let Some(kill_process_tree) = stop_method.kill_process_tree {
+            params.push(ServiceParam::new(
+                "AppKillProcessTree",
+                bool_value(kill_process_tree),
+            ));
+        }
+    }
+
+    params
+}
+
+fn bool_value(value: bool) -> &'static str {
+    if value { "1" } else { "0" }
+}
+
+/// Queries the backend of the service for the installed value of the given parameter.
+pub fn installed_param(
+    service_name: &str,
+    param: &ServiceParam,
+    file_config: &FileConfig,
+) -> Result<String> {
+    backend::get_param(service_name, param, file_config)
+}
+
+/// Queries the backend of the service for the installed value of the given parameter, as a
+/// parameter that can be set again later, e.g. when restoring the service.
+pub fn installed_as_param(
+    service_name: &str,
+    name: &'static str,
+    sub: Option<&str>,
+    file_config: &FileConfig,
+) -> Result<ServiceParam> {
+    let mut param = ServiceParam {
+        name,
+        sub: sub.map(|sub| sub.to_owned()),
+        values: Vec::new(),
+        secret: None,
+    };
+
+    let installed = installed_param(service_name, &param, file_config)?;
+
+    // multiple values are returned on separate lines
+    param.values = if param.is_multi() {
+        installed
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_owned())
+            .collect()
+    } else {
+        vec![installed]
+    };
+
+    Ok(param)
+}
+
+/// Represents a desired parameter of a service whose value differs from the installed one.
+#[derive(Clone, Debug)]
+pub struct ParamDiff {
+    /// Desired parameter according to the configuration.
+    pub param: ServiceParam,
+
+    /// Installed value of the parameter, where multiple values are on separate lines.
+    pub installed: String,
+}
+
+/// Returns the parameters that reset the managed nssm parameters that are no longer
+/// configured for the service but are still set, together with their installed values.
+/// Only applies to the backends that keep every parameter individually, since the other
+/// backends regenerate their whole configuration from the desired parameters.
+fn reset_diffs(
+    service: &Service,
+    desired: &[ServiceParam],
+    file_config: &FileConfig,
+) -> Vec<ParamDiff> {
+    match file_config.backend(&service.name) {
+        Backend::Nssm | Backend::Mock => (),
+        _ => return Vec::new(),
+    }
+
+    RESETTABLE_PARAMS
+        .iter()
+        .filter(|&&(name, _)| !desired.iter().any(|param| param.name == name))
+        .filter_map(|&(name, default)| {
+            let param = ServiceParam::reset(name);
+
+            // parameters unknown to older nssm versions cannot be set either
+            let installed = installed_param(&service.name, &param, file_config).ok()?;
+            let value = installed.trim();
+
+            if value.is_empty() || value.eq_ignore_ascii_case(default) {
+                return None;
+            }
+
+            debug!(
+                "Service '{}' parameter '{}' is no longer configured but has installed value '{}'",
+                service.name,
+                name,
+                installed
+            );
+
+            Some(ParamDiff {
+                param,
+                installed,
+            })
+        })
+        .collect()
+}
+
+/// Returns the desired parameters of the service whose values differ from the installed ones,
+/// together with the installed values, followed by the parameters that reset the managed
+/// parameters that are no longer configured.
+pub fn param_diffs(service: &Service, file_config: &FileConfig) -> Result<Vec<ParamDiff>> {
+    let mut diffs = Vec::new();
+    let desired = desired_params(service, file_config);
+
+    for param in desired.iter().cloned() {
+        let installed = installed_param(&service.name, &param, file_config)?;
+
+        if !values_match(&param, &installed) {
+            debug!(
+                "Service '{}' parameter '{}' has installed value '{}' instead of '{}'",
+                service.name,
+                param.key(),
+                installed,
+                param.value()
+            );
+
+            diffs.push(ParamDiff {
+                param,
+                installed,
+            });
+        }
+    }
+
+    diffs.extend(reset_diffs(service, &desired, file_config));
+    Ok(diffs)
+}
+
+/// Returns the desired parameters of the service whose values differ from the installed ones.
+pub fn changed_params(service: &Service, file_config: &FileConfig) -> Result<Vec<ServiceParam>> {
+    Ok(param_diffs(service, file_config)?
+        .into_iter()
+        .map(|diff| diff.param)
+        .collect())
+}
+
+fn values_match(param: &ServiceParam, installed: &str) -> bool {
+    // multiple values are returned on separate lines
+    if param.is_multi() {
+        return installed
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .eq(param.values.iter().map(|value| value.as_str()));
+    }
+
+    let desired = param.value();
+
+    match param.name {
+        // nssm returns the local account with the '.\' prefix and the name is case insensitive
+        "ObjectName" => {
+            let normalize = |user: &str| user.strip_prefix(".\\").unwrap_or(user).to_lowercase();
+
+            normalize(installed) == normalize(&desired)
+        }
+
+        _ => installed.split_whitespace().eq(desired.split_whitespace()),
+    }
+}
@@ -0,0 +1,171 @@
+//! Sets the recovery actions that the Service Control Manager takes when a service fails,
+//! with `sc failure` and `sc failureflag` on the host of the service.
+
+use config::{FileConfig, Recovery, RecoveryAction};
+use errors::*;
+use output;
+use sc;
+use std::collections::BTreeMap;
+use super::{run_host_cmd, run_host_modify_cmd};
+
+/// Duration in seconds after which the failure count is reset when no other is configured.
+pub const DEFAULT_RESET_SECS: u32 = 86_400;
+
+/// Delay in milliseconds before each action when no other delay is configured.
+pub const DEFAULT_RESTART_DELAY_MS: u32 = 60_000;
+
+fn actions(recovery: &Recovery) -> Vec<RecoveryAction> {
+    vec![recovery.first, recovery.second, recovery.subsequent]
+        .into_iter()
+        .map(|action| action.unwrap_or(RecoveryAction::None))
+        .collect()
+}
+
+fn action_name(action: RecoveryAction) -> &'static str {
+    match action {
+        RecoveryAction::None => "",
+        RecoveryAction::Restart => "restart",
+        RecoveryAction::Reboot => "reboot",
+    }
+}
+
+type Fields = BTreeMap<String, Vec<String>>;
+
+fn query(args: &[String], file_config: &FileConfig) -> Option<String> {
+    run_host_cmd(sc::program(), args, file_config)
+        .ok()
+        .map(|output| output::decode(&output.stdout))
+}
+
+/// Parses the actions listed by `sc qfailure`, where the continuation lines of the
+/// `FAILURE_ACTIONS` field, unlike the other fields, have no separator.
+fn parse_failure_actions(output: &str) -> Vec<&str> {
+    let mut failure_actions = Vec::new();
+    let mut is_in_actions = false;
+
+    for line in output.lines() {
+        match line.find(':') {
+            Some(separator_index) => {
+                is_in_actions = line[..separator_index].trim() == "FAILURE_ACTIONS";
+
+                if is_in_actions {
+                    failure_actions.push(line[separator_index + 1..].trim());
+                }
+            }
+
+            None => if is_in_actions && !line.trim().is_empty() {
+                failure_actions.push(line.trim());
+            },
+        }
+    }
+
+    failure_actions.retain(|action| !action.is_empty());
+    failure_actions
+}
+
+/// Returns the values of the field whose key starts with the prefix, since some keys carry
+/// their unit, e.g. `RESET_PERIOD (in seconds)`.
+fn field<'a>(fields: &'a Fields, prefix: &str) -> Vec<&'a str> {
+    fields
+        .iter()
+        .find(|&(key, _)| key.starts_with(prefix))
+        .map_or_else(Vec::new, |(_, values)| {
+            values.iter().map(|value| value.as_str()).collect()
+        })
+}
+
+/// Returns true if the installed recovery actions of the service are the configured ones.
+/// The installed actions are listed by `sc qfailure` as e.g.
+/// `RESTART -- Delay = 60000 milliseconds.`, without the trailing actions that do nothing.
+fn is_up_to_date(service_name: &str, recovery: &Recovery, file_config: &FileConfig) -> bool {
+    let failure_output = match query(&sc::qfailure(service_name), file_config) {
+        Some(failure_output) => failure_output,
+        None => return false,
+    };
+
+    let flag_output = match query(&sc::qfailureflag(service_name), file_config) {
+        Some(flag_output) => flag_output,
+        None => return false,
+    };
+
+    let failure_fields = sc::parse_fields(&failure_output);
+    let flag_fields = sc::parse_fields(&flag_output);
+
+    let reset_secs = recovery.reset_secs.unwrap_or(DEFAULT_RESET_SECS).to_string();
+    let delay_ms = recovery.restart_delay_ms.unwrap_or(DEFAULT_RESTART_DELAY_MS);
+
+    let mut expected_actions: Vec<String> = actions(recovery)
+        .into_iter()
+        .map(|action| {
+            let name = match action {
+                RecoveryAction::None => "NONE",
+                RecoveryAction::Restart => "RESTART",
+                RecoveryAction::Reboot => "REBOOT",
+            };
+
+            format!("{} -- Delay = {} milliseconds.", name, delay_ms)
+        })
+        .collect();
+
+    while expected_actions
+        .last()
+        .is_some_and(|action| action.starts_with("NONE"))
+    {
+        expected_actions.pop();
+    }
+
+    let non_crash_failures = if recovery.non_crash_failures.unwrap_or(false) {
+        "TRUE"
+    } else {
+        "FALSE"
+    };
+
+    let installed_non_crash_failures = field(&flag_fields, "FAILURE_ACTIONS_ON_NONCRASH_FAILURES");
+
+    field(&failure_fields, "RESET_PERIOD") == vec![reset_secs.as_str()]
+        && parse_failure_actions(&failure_output) == expected_actions
+        && installed_non_crash_failures == vec![non_crash_failures]
+}
+
+/// Arguments of `sc failure` to set the configured recovery actions of the service.
+pub fn failure_args(service_name: &str, recovery: &Recovery) -> Vec<String> {
+    let delay_ms = recovery.restart_delay_ms.unwrap_or(DEFAULT_RESTART_DELAY_MS);
+
+    let actions: Vec<(&str, u32)> = actions(recovery)
+        .into_iter()
+        .map(|action| (action_name(action), delay_ms))
+        .collect();
+
+    sc::failure(
+        service_name,
+        recovery.reset_secs.unwrap_or(DEFAULT_RESET_SECS),
+        &actions,
+    )
+}
+
+/// Sets the recovery actions of the service unless they are already the configured ones.
+/// Returns true if the recovery actions were set.
+pub fn apply(service_name: &str, recovery: &Recovery, file_config: &FileConfig) -> Result<bool> {
+    if is_up_to_date(service_name, recovery, file_config) {
+        return Ok(false);
+    }
+
+    run_host_modify_cmd(
+        sc::program(),
+        &failure_args(service_name, recovery),
+        file_config,
+    ).chain_err(|| format!("Unable to set recovery actions of service '{}'", service_name))?;
+
+    run_host_modify_cmd(
+        sc::program(),
+        &sc::failureflag(service_name, recovery.non_crash_failures.unwrap_or(false)),
+        file_config,
+    ).chain_err(|| {
+        format!(
+            "Unable to set recovery actions on non-crash failures of service '{}'",
+            service_name
+        )
+    })?;
+
+    Ok(true)
+}
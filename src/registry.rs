@@ -0,0 +1,293 @@
+//! Writes the application parameters of nssm services directly into the registry, under
+//! `HKLM\SYSTEM\CurrentControlSet\Services\<name>\Parameters` where nssm reads them from,
+//! instead of running one `nssm set` per parameter.
+
+use config::FileConfig;
+use errors::*;
+use log_dry_run;
+use params::ServiceParam;
+
+/// nssm parameters stored as strings with expandable environment variables.
+const EXPAND_SZ_PARAMS: &[&str] = &[
+    "Application",
+    "AppDirectory",
+    "AppParameters",
+    "AppStdout",
+    "AppStderr",
+];
+
+/// nssm parameters stored as numbers.
+const DWORD_PARAMS: &[&str] = &[
+    "AppThrottle",
+    "AppRestartDelay",
+    "AppNoConsole",
+    "AppStopMethodSkip",
+    "AppStopMethodConsole",
+    "AppStopMethodWindow",
+    "AppStopMethodThreads",
+    "AppKillProcessTree",
+    "AppRotateFiles",
+    "AppRotateOnline",
+    "AppRotateSeconds",
+    "AppRotateBytes",
+];
+
+/// nssm priority class names with their numeric values as stored by nssm.
+const PRIORITIES: &[(&str, u32)] = &[
+    ("IDLE_PRIORITY_CLASS", 0x0000_0040),
+    ("BELOW_NORMAL_PRIORITY_CLASS", 0x0000_4000),
+    ("NORMAL_PRIORITY_CLASS", 0x0000_0020),
+    ("ABOVE_NORMAL_PRIORITY_CLASS", 0x0000_8000),
+    ("HIGH_PRIORITY_CLASS", 0x0000_0080),
+    ("REALTIME_PRIORITY_CLASS", 0x0000_0100),
+];
+
+/// Represents the data of a registry value.
+#[derive(Debug, PartialEq)]
+pub enum RegData {
+    ExpandSz(String),
+    MultiSz(Vec<String>),
+    Sz(String),
+    Dword(u32),
+}
+
+/// Represents a registry value under the `Parameters` key of a service.
+#[derive(Debug, PartialEq)]
+pub struct RegValue {
+    /// Subkey under the `Parameters` key, e.g. `AppExit`.
+    pub subkey: Option<&'static str>,
+
+    /// Name of the value, where the empty name is the default value of the key.
+    pub name: String,
+
+    /// Data of the value.
+    pub data: RegData,
+}
+
+/// Returns the registry value that nssm stores the parameter as, or `None` if the parameter
+/// has to be set through nssm, e.g. the parameters of the service itself like `ObjectName`.
+pub fn reg_value(param: &ServiceParam) -> Option<RegValue> {
+    // the default values are only known to nssm
+    if param.is_reset() {
+        return None;
+    }
+
+    let name = param.name;
+    let value = param.value();
+
+    let (subkey, value_name, data) = if EXPAND_SZ_PARAMS.contains(&name) {
+        (None, name.to_owned(), RegData::ExpandSz(value))
+    } else if DWORD_PARAMS.contains(&name) {
+        (None, name.to_owned(), RegData::Dword(value.parse().ok()?))
+    } else if name == "AppPriority" {
+        let &(_, priority) = PRIORITIES
+            .iter()
+            .find(|&&(priority_name, _)| priority_name == value)?;
+
+        (None, name.to_owned(), RegData::Dword(priority))
+    } else if name == "AppEnvironmentExtra" {
+        (None, name.to_owned(), RegData::MultiSz(param.values.clone()))
+    } else if name == "AppExit" {
+        // the default action is the default value of the key
+        let code = match param.sub.as_deref() {
+            Some("Default") | None => String::new(),
+            Some(code) => code.to_owned(),
+        };
+
+        (Some("AppExit"), code, RegData::Sz(value))
+    } else {
+        return None;
+    };
+
+    Some(RegValue {
+        subkey,
+        name: value_name,
+        data,
+    })
+}
+
+/// Writes every parameter that nssm stores under the `Parameters` key of the service
+/// directly into the registry, and returns the remaining parameters that have to be set
+/// through nssm.
+pub fn write_params(
+    service_name: &str,
+    params: &[ServiceParam],
+    file_config: &FileConfig,
+) -> Result<Vec<ServiceParam>> {
+    let mut reg_values = Vec::new();
+    let mut remaining = Vec::new();
+
+    for param in params {
+        match reg_value(param) {
+            Some(reg_value) => reg_values.push(reg_value),
+            None => remaining.push(param.clone()),
+        }
+    }
+
+    if reg_values.is_empty() {
+        return Ok(remaining);
+    }
+
+    if file_config.is_dry_run() {
+        log_dry_run(
+            file_config,
+            &format!(
+                "Write {} parameter(s) of service '{}' into the registry",
+                reg_values.len(),
+                service_name,
+            ),
+        );
+    } else {
+        imp::write_values(service_name, &reg_values).chain_err(|| {
+            format!(
+                "Unable to write parameters of service '{}' into the registry",
+                service_name
+            )
+        })?;
+    }
+
+    Ok(remaining)
+}
+
+#[cfg(windows)]
+mod imp {
+    use errors::*;
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use super::{RegData, RegValue};
+    use winapi::shared::minwindef::{DWORD, HKEY};
+    use winapi::shared::winerror::ERROR_SUCCESS;
+    use winapi::um::winnt::{KEY_SET_VALUE, REG_DWORD, REG_EXPAND_SZ, REG_MULTI_SZ,
+                            REG_OPTION_NON_VOLATILE, REG_SZ};
+    use winapi::um::winreg::{self, HKEY_LOCAL_MACHINE};
+
+    struct RegKey(HKEY);
+
+    impl Drop for RegKey {
+        fn drop(&mut self) {
+            unsafe {
+                winreg::RegCloseKey(self.0);
+            }
+        }
+    }
+
+    fn to_wide(s: &str) -> Vec<u16> {
+        OsStr::new(s).encode_wide().chain(Some(0)).collect()
+    }
+
+    fn check(res: i32) -> io::Result<()> {
+        if res as DWORD == ERROR_SUCCESS {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(res))
+        }
+    }
+
+    fn create_key(path: &str) -> Result<RegKey> {
+        let wide_path = to_wide(path);
+        let mut key: HKEY = ptr::null_mut();
+
+        let res = unsafe {
+            winreg::RegCreateKeyExW(
+                HKEY_LOCAL_MACHINE,
+                wide_path.as_ptr(),
+                0,
+                ptr::null_mut(),
+                REG_OPTION_NON_VOLATILE,
+                KEY_SET_VALUE,
+                ptr::null_mut(),
+                &mut key,
+                ptr::null_mut(),
+            )
+        };
+
+        check(res).chain_err(|| format!("Unable to open registry key 'HKLM\\{}'", path))?;
+        Ok(RegKey(key))
+    }
+
+    fn set_value(key: &RegKey, reg_value: &RegValue) -> Result<()> {
+        let (value_type, bytes): (DWORD, Vec<u8>) = match reg_value.data {
+            RegData::ExpandSz(ref value) => (REG_EXPAND_SZ, wide_bytes(&to_wide(value))),
+            RegData::Sz(ref value) => (REG_SZ, wide_bytes(&to_wide(value))),
+            RegData::MultiSz(ref values) => {
+                // each string is null terminated, followed by another null at the end
+                let mut wide: Vec<u16> = Vec::new();
+
+                for value in values {
+                    wide.extend(to_wide(value));
+                }
+
+                wide.push(0);
+                (REG_MULTI_SZ, wide_bytes(&wide))
+            }
+            RegData::Dword(value) => (
+                REG_DWORD,
+                vec![
+                    value as u8,
+                    (value >> 8) as u8,
+                    (value >> 16) as u8,
+                    (value >> 24) as u8,
+                ],
+            ),
+        };
+
+        let wide_name = to_wide(&reg_value.name);
+
+        let res = unsafe {
+            winreg::RegSetValueExW(
+                key.0,
+                wide_name.as_ptr(),
+                0,
+                value_type,
+                bytes.as_ptr(),
+                bytes.len() as DWORD,
+            )
+        };
+
+        check(res).chain_err(|| format!("Unable to set registry value '{}'", reg_value.name))
+    }
+
+    fn wide_bytes(wide: &[u16]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(wide.len() * 2);
+
+        for unit in wide {
+            bytes.push(*unit as u8);
+            bytes.push((*unit >> 8) as u8);
+        }
+
+        bytes
+    }
+
+    pub fn write_values(service_name: &str, reg_values: &[RegValue]) -> Result<()> {
+        let params_path = format!(
+            "SYSTEM\\CurrentControlSet\\Services\\{}\\Parameters",
+            service_name
+        );
+
+        let params_key = create_key(&params_path)?;
+
+        for reg_value in reg_values {
+            match reg_value.subkey {
+                Some(subkey) => {
+                    let subkey = create_key(&format!("{}\\{}", params_path, subkey))?;
+                    set_value(&subkey, reg_value)?;
+                }
+                None => set_value(&params_key, reg_value)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use errors::*;
+    use super::RegValue;
+
+    pub fn write_values(_: &str, _: &[RegValue]) -> Result<()> {
+        bail!("Writing the registry is only supported on Windows")
+    }
+}
@@ -0,0 +1,110 @@
+//! Runs programs on remote hosts through PowerShell remoting, i.e. `Invoke-Command`,
+//! passing back the output and the exit code of the remote program.
+
+use std::path::Path;
+
+/// Returns the PowerShell executable that invokes the remote commands.
+pub fn program() -> &'static Path {
+    Path::new("powershell")
+}
+
+/// Quotes the value as a PowerShell single-quoted string, where no character is special
+/// other than the single quotes, which PowerShell also takes from the curly and low single
+/// quotation marks, and which are escaped by doubling them.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+
+    for c in value.chars() {
+        match c {
+            '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' => quoted.push(c),
+            _ => (),
+        }
+
+        quoted.push(c);
+    }
+
+    quoted.push('\'');
+    quoted
+}
+
+/// Encodes the bytes as base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(CHARS[((n >> (18 - 6 * index)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Returns the PowerShell script that runs the program with the arguments on the host,
+/// and exits with the exit code of the remote program, or 1 if the host is unreachable.
+pub fn script<S: AsRef<str>>(host: &str, program: &Path, args: &[S]) -> String {
+    let mut cmd = format!("& {}", quote(&program.to_string_lossy()));
+
+    for arg in args {
+        cmd.push(' ');
+        cmd.push_str(&quote(arg.as_ref()));
+    }
+
+    format!(
+        "$code = Invoke-Command -ComputerName {} -ScriptBlock {{ {} | Write-Host; \
+         $LASTEXITCODE }}; if ($code -is [int]) {{ exit $code }} else {{ exit 1 }}",
+        quote(host),
+        cmd
+    )
+}
+
+/// Returns the arguments of the PowerShell executable to run the program with the
+/// arguments on the host.
+pub fn args<S: AsRef<str>>(host: &str, program: &Path, args: &[S]) -> Vec<String> {
+    encoded_args(&script(host, program, args))
+}
+
+/// Returns the arguments of the PowerShell executable to run the script, which is passed
+/// encoded so that it needs no further quoting.
+pub fn encoded_args(script: &str) -> Vec<String> {
+    let utf16: Vec<u8> = script
+        .encode_utf16()
+        .flat_map(|unit| vec![unit as u8, (unit >> 8) as u8])
+        .collect();
+
+    vec![
+        "-NoProfile".to_owned(),
+        "-NonInteractive".to_owned(),
+        "-EncodedCommand".to_owned(),
+        base64(&utf16),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_doubles_every_single_quotation_mark() {
+        assert_eq!(quote("it's"), "'it''s'");
+        assert_eq!(
+            quote("a\u{2018}b\u{2019}c\u{201A}d\u{201B}e"),
+            "'a\u{2018}\u{2018}b\u{2019}\u{2019}c\u{201A}\u{201A}d\u{201B}\u{201B}e'"
+        );
+        assert_eq!(quote("\"$env:PATH\""), "'\"$env:PATH\"'");
+    }
+}
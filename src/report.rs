@@ -0,0 +1,198 @@
+//! Records the actions taken and the outcome of every service during a run, so that the
+//! run can be summarized as a machine-readable JSON report.
+
+use errors::*;
+use file;
+use serde_json;
+use state::ServiceState;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// Outcome of processing a service.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// All the operations of the service have succeeded.
+    Ok,
+
+    /// Any operation of the service has failed.
+    Failed,
+
+    /// The service was skipped after another service had failed.
+    Skipped,
+}
+
+/// Holds the summary of processing a service.
+#[derive(Clone, Debug, Serialize)]
+pub struct ServiceReport {
+    /// Name of service.
+    pub name: String,
+
+    /// Remote host of the service, or `None` for the local machine.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    /// Outcome of processing the service.
+    pub outcome: Outcome,
+
+    /// Actions taken on the service in order, e.g. `stopped`, `removed`, `installed`,
+    /// `set AppDirectory` and `started`. Only planned if dry run is enabled.
+    pub actions: Vec<String>,
+
+    /// Duration of processing the service in milliseconds.
+    pub duration_ms: u64,
+
+    /// Number of times the state of the service was polled while waiting for it to stop or
+    /// start.
+    pub poll_count: u64,
+
+    /// State of the service after processing it. `None` if the service is not installed.
+    pub final_state: Option<ServiceState>,
+
+    /// Error messages from the outermost error to the innermost cause, if failed.
+    pub errors: Vec<String>,
+
+    /// Category of the failure, e.g. `command_failed` or `service_timeout`, if failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_category: Option<ErrorCategory>,
+}
+
+#[derive(Serialize)]
+struct ReportFile<'a> {
+    success: bool,
+    services: &'a [ServiceReport],
+}
+
+/// Collects the service reports of a run, which may be shared across concurrent workers.
+#[derive(Debug, Default)]
+pub struct RunReport {
+    actions: Mutex<BTreeMap<String, Vec<String>>>,
+    poll_counts: Mutex<BTreeMap<String, u64>>,
+    services: Mutex<Vec<ServiceReport>>,
+    listener: Mutex<Option<Sender<ServiceReport>>>,
+}
+
+impl RunReport {
+    /// Creates a report that also sends every service report to the listener as soon as it
+    /// is recorded, e.g. to stream the progress of a run.
+    pub fn with_listener(listener: Sender<ServiceReport>) -> RunReport {
+        RunReport {
+            listener: Mutex::new(Some(listener)),
+            ..Default::default()
+        }
+    }
+
+    /// Records the action taken on the service.
+    pub fn record_action(&self, service_name: &str, action: String) {
+        self.actions
+            .lock()
+            .unwrap()
+            .entry(service_name.to_owned())
+            .or_default()
+            .push(action);
+    }
+
+    /// Records that the state of the service has been polled once.
+    pub fn record_poll(&self, service_name: &str) {
+        *self.poll_counts
+            .lock()
+            .unwrap()
+            .entry(service_name.to_owned())
+            .or_insert(0) += 1;
+    }
+
+    /// Records the outcome of the service together with the actions recorded so far.
+    pub fn record_service(
+        &self,
+        service_name: &str,
+        res: Option<&Result<()>>,
+        duration: Duration,
+        final_state: Option<ServiceState>,
+    ) {
+        let actions = self.actions
+            .lock()
+            .unwrap()
+            .remove(service_name)
+            .unwrap_or_default();
+
+        let poll_count = self.poll_counts
+            .lock()
+            .unwrap()
+            .remove(service_name)
+            .unwrap_or(0);
+
+        let (outcome, errors, error_category) = match res {
+            Some(&Ok(_)) => (Outcome::Ok, Vec::new(), None),
+            Some(Err(e)) => (
+                Outcome::Failed,
+                e.iter().map(|cause| cause.to_string()).collect(),
+                Some(e.category()),
+            ),
+            None => (Outcome::Skipped, Vec::new(), None),
+        };
+
+        let service_report = ServiceReport {
+            name: service_name.to_owned(),
+            host: None,
+            outcome,
+            actions,
+            duration_ms: duration.as_secs() * 1000 + u64::from(duration.subsec_millis()),
+            poll_count,
+            final_state,
+            errors,
+            error_category,
+        };
+
+        if let Some(ref listener) = *self.listener.lock().unwrap() {
+            // the listener may have gone away, which does not affect the run
+            let _ = listener.send(service_report.clone());
+        }
+
+        self.services.lock().unwrap().push(service_report);
+    }
+
+    /// Appends the service reports of the run on the given remote host, or on the local
+    /// machine if no host is given.
+    pub fn append(&self, other: RunReport, host: Option<&str>) {
+        let mut services = other.services.into_inner().unwrap();
+
+        for service in &mut services {
+            service.host = host.map(|host| host.to_owned());
+        }
+
+        self.services.lock().unwrap().extend(services);
+    }
+
+    /// Returns the service reports recorded so far, in the order the services completed.
+    pub fn services(&self) -> Vec<ServiceReport> {
+        self.services.lock().unwrap().clone()
+    }
+
+    /// Serializes the report as pretty JSON.
+    pub fn to_json(&self) -> Result<String> {
+        let services = self.services();
+
+        let report_file = ReportFile {
+            success: services
+                .iter()
+                .all(|service| service.outcome == Outcome::Ok),
+            services: &services,
+        };
+
+        serde_json::to_string_pretty(&report_file)
+            .chain_err(|| "Unable to serialize run report into JSON")
+    }
+
+    /// Writes the report as JSON into the file at the given path.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = self.to_json()?;
+
+        file::put_text(path, content).chain_err(|| {
+            format!("Unable to write run report at '{}'", path.to_string_lossy())
+        })
+    }
+}
@@ -0,0 +1,130 @@
+//! Builds the argument vectors of the `sc` commands used for services that are not installed
+//! by nssm, and parses the configuration queried by them.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Path of the `sc` executable, which is always on the path.
+pub fn program() -> &'static Path {
+    Path::new("sc")
+}
+
+/// Arguments to set the given options of the service configuration, e.g. `DisplayName=`.
+pub fn config(service_name: &str, options: &[(&str, &str)]) -> Vec<String> {
+    let mut args = vec!["config".to_owned(), service_name.to_owned()];
+
+    for &(option, value) in options {
+        args.push(format!("{}=", option));
+        args.push(value.to_owned());
+    }
+
+    args
+}
+
+/// Arguments to set the description of the service.
+pub fn description(service_name: &str, description: &str) -> Vec<String> {
+    vec![
+        "description".to_owned(),
+        service_name.to_owned(),
+        description.to_owned(),
+    ]
+}
+
+/// Arguments to delete the service.
+pub fn delete(service_name: &str) -> Vec<String> {
+    vec!["delete".to_owned(), service_name.to_owned()]
+}
+
+/// Arguments to query the configuration of the service, with a buffer large enough for
+/// long command lines.
+pub fn qc(service_name: &str) -> Vec<String> {
+    vec!["qc".to_owned(), service_name.to_owned(), "8192".to_owned()]
+}
+
+/// Arguments to query the description of the service.
+pub fn qdescription(service_name: &str) -> Vec<String> {
+    vec![
+        "qdescription".to_owned(),
+        service_name.to_owned(),
+        "8192".to_owned(),
+    ]
+}
+
+/// Arguments to set the recovery actions of the service, each with the action, i.e.
+/// `restart`, `reboot` or empty for none, and its delay in milliseconds.
+pub fn failure(service_name: &str, reset_secs: u32, actions: &[(&str, u32)]) -> Vec<String> {
+    let actions: Vec<String> = actions
+        .iter()
+        .map(|&(action, delay_ms)| format!("{}/{}", action, delay_ms))
+        .collect();
+
+    vec![
+        "failure".to_owned(),
+        service_name.to_owned(),
+        "reset=".to_owned(),
+        reset_secs.to_string(),
+        "actions=".to_owned(),
+        actions.join("/"),
+    ]
+}
+
+/// Arguments to set whether the recovery actions also apply when the service stops with an
+/// error instead of crashing.
+pub fn failureflag(service_name: &str, non_crash_failures: bool) -> Vec<String> {
+    vec![
+        "failureflag".to_owned(),
+        service_name.to_owned(),
+        if non_crash_failures { "1" } else { "0" }.to_owned(),
+    ]
+}
+
+/// Arguments to query the recovery actions of the service.
+pub fn qfailure(service_name: &str) -> Vec<String> {
+    vec![
+        "qfailure".to_owned(),
+        service_name.to_owned(),
+        "8192".to_owned(),
+    ]
+}
+
+/// Arguments to query whether the recovery actions apply to non-crash failures.
+pub fn qfailureflag(service_name: &str) -> Vec<String> {
+    vec!["qfailureflag".to_owned(), service_name.to_owned()]
+}
+
+/// Parses the `KEY : value` lines of the `sc qc` or `sc qdescription` output, where the
+/// continuation lines without any key, e.g. of `DEPENDENCIES`, add further values to the
+/// previous key. Empty values are skipped.
+pub fn parse_fields(output: &str) -> BTreeMap<String, Vec<String>> {
+    let mut fields: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut last_key: Option<String> = None;
+
+    for line in output.lines() {
+        let separator_index = match line.find(':') {
+            Some(separator_index) => separator_index,
+            None => continue,
+        };
+
+        let key = line[..separator_index].trim();
+        let value = line[separator_index + 1..].trim();
+
+        let key = if key.is_empty() {
+            match last_key {
+                Some(ref last_key) => last_key.clone(),
+                None => continue,
+            }
+        } else {
+            key.to_owned()
+        };
+
+        let values = fields.entry(key.clone()).or_default();
+
+        if !value.is_empty() {
+            values.push(value.to_owned());
+        }
+
+        last_key = Some(key);
+    }
+
+    fields
+}
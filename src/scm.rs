@@ -0,0 +1,436 @@
+//! Queries, stops and starts services directly through the Windows Service Control Manager,
+//! so that nssm is only needed for installing and configuring the services.
+//! Falls back to the equivalent nssm commands on other platforms.
+
+use config::FileConfig;
+use errors::*;
+use log_dry_run;
+use log_dry_run_cmd;
+use run_host_cmd;
+use state::ServiceState;
+use std::path::Path;
+
+/// Represents a Win32 service installed on the machine.
+#[derive(Debug)]
+pub struct InstalledService {
+    /// Name of service.
+    pub name: String,
+
+    /// Current state of service.
+    pub state: ServiceState,
+
+    /// Command line run by the service. `None` if the configuration of the service cannot
+    /// be queried.
+    pub binary_path: Option<String>,
+}
+
+/// Lists every Win32 service installed on the machine, or on the remote host if set.
+pub fn installed_services(file_config: &FileConfig) -> Result<Vec<InstalledService>> {
+    imp::installed_services(file_config)
+}
+
+/// Returns the ID of the application process that nssm has launched for the given service on
+/// the local machine, i.e. the child process of the service process.
+/// Returns error if the service process or the application process is not running.
+pub fn app_process_id(service_name: &str, file_config: &FileConfig) -> Result<u32> {
+    if file_config.host.is_some() {
+        bail!("Finding the application process is only supported on the local machine");
+    }
+
+    imp::app_process_id(service_name, file_config)
+}
+
+/// Queries the current state of the given service.
+/// Returns error if the service does not exist.
+pub fn query_state(service_name: &str, file_config: &FileConfig) -> Result<ServiceState> {
+    imp::query_state(service_name, file_config)
+}
+
+/// Sends the stop control to the given service without waiting for it to stop.
+pub fn stop(service_name: &str, file_config: &FileConfig) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run(file_config, &format!("Stop service '{}'", service_name));
+        return Ok(());
+    }
+
+    imp::stop(service_name, file_config)
+}
+
+/// Starts the given service without waiting for it to be running.
+pub fn start(service_name: &str, file_config: &FileConfig) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run(file_config, &format!("Start service '{}'", service_name));
+        return Ok(());
+    }
+
+    imp::start(service_name, file_config)
+}
+
+/// Forcibly terminates the process of the given service together with every process that it
+/// has started, e.g. the executable wrapped by nssm and its child processes, with `taskkill`
+/// on the local machine or on the remote host if set.
+pub fn kill_tree(service_name: &str, file_config: &FileConfig) -> Result<()> {
+    let program = Path::new("taskkill");
+
+    let args = [
+        "/F".to_owned(),
+        "/T".to_owned(),
+        "/FI".to_owned(),
+        format!("SERVICES eq {}", service_name),
+    ];
+
+    if file_config.is_dry_run() {
+        log_dry_run_cmd(file_config, &program.to_string_lossy(), &args);
+
+        return Ok(());
+    }
+
+    run_host_cmd(program, &args, file_config).map(|_| ())
+}
+
+#[cfg(windows)]
+mod imp {
+    use config::FileConfig;
+    use errors::*;
+    use state::ServiceState;
+    use std::ffi::OsStr;
+    use std::io;
+    use std::mem;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use std::slice;
+    use super::InstalledService;
+    use winapi::shared::minwindef::{DWORD, LPBYTE};
+    use winapi::shared::winerror::ERROR_MORE_DATA;
+    use winapi::um::handleapi::{self, INVALID_HANDLE_VALUE};
+    use winapi::um::tlhelp32::{self, PROCESSENTRY32W, TH32CS_SNAPPROCESS};
+    use winapi::um::winnt::{HANDLE, SERVICE_WIN32};
+    use winapi::um::winsvc::{self, ENUM_SERVICE_STATUS_PROCESSW, QUERY_SERVICE_CONFIGW,
+                             SC_HANDLE, SERVICE_STATUS, SERVICE_STATUS_PROCESS};
+
+    /// Size in bytes of the buffer receiving each batch of the listed services.
+    const ENUM_BUF_SIZE: usize = 64 * 1024;
+
+    struct ScHandle(SC_HANDLE);
+
+    impl Drop for ScHandle {
+        fn drop(&mut self) {
+            unsafe {
+                winsvc::CloseServiceHandle(self.0);
+            }
+        }
+    }
+
+    struct SnapshotHandle(HANDLE);
+
+    impl Drop for SnapshotHandle {
+        fn drop(&mut self) {
+            unsafe {
+                handleapi::CloseHandle(self.0);
+            }
+        }
+    }
+
+    fn to_wide(s: &str) -> Vec<u16> {
+        OsStr::new(s).encode_wide().chain(Some(0)).collect()
+    }
+
+    fn from_wide_ptr(ptr: *const u16) -> String {
+        if ptr.is_null() {
+            return String::new();
+        }
+
+        unsafe {
+            let len = (0..).take_while(|&index| *ptr.offset(index) != 0).count();
+            String::from_utf16_lossy(slice::from_raw_parts(ptr, len))
+        }
+    }
+
+    fn open_manager(access: DWORD, file_config: &FileConfig) -> Result<ScHandle> {
+        // the service control manager of the remote host if set
+        let wide_host = file_config.host.as_ref().map(|host| to_wide(host));
+
+        let manager = unsafe {
+            winsvc::OpenSCManagerW(
+                wide_host.as_ref().map_or(ptr::null(), |host| host.as_ptr()),
+                ptr::null(),
+                access,
+            )
+        };
+
+        if manager.is_null() {
+            return Err(io::Error::last_os_error()).chain_err(
+                || "Unable to connect to the service control manager",
+            );
+        }
+
+        Ok(ScHandle(manager))
+    }
+
+    fn open_service(
+        service_name: &str,
+        access: DWORD,
+        file_config: &FileConfig,
+    ) -> Result<ScHandle> {
+        let manager = open_manager(winsvc::SC_MANAGER_CONNECT, file_config)?;
+        open_manager_service(&manager, service_name, access)
+    }
+
+    fn open_manager_service(
+        manager: &ScHandle,
+        service_name: &str,
+        access: DWORD,
+    ) -> Result<ScHandle> {
+        let wide_name = to_wide(service_name);
+
+        let service = unsafe { winsvc::OpenServiceW(manager.0, wide_name.as_ptr(), access) };
+
+        if service.is_null() {
+            return Err(io::Error::last_os_error()).chain_err(|| {
+                format!("Unable to open service '{}'", service_name)
+            });
+        }
+
+        Ok(ScHandle(service))
+    }
+
+    fn state_from_raw(raw_state: DWORD) -> Result<ServiceState> {
+        let state = match raw_state {
+            winsvc::SERVICE_CONTINUE_PENDING => ServiceState::ContinuePending,
+            winsvc::SERVICE_PAUSE_PENDING => ServiceState::PausePending,
+            winsvc::SERVICE_PAUSED => ServiceState::Paused,
+            winsvc::SERVICE_RUNNING => ServiceState::Running,
+            winsvc::SERVICE_START_PENDING => ServiceState::StartPending,
+            winsvc::SERVICE_STOP_PENDING => ServiceState::StopPending,
+            winsvc::SERVICE_STOPPED => ServiceState::Stopped,
+            _ => bail!("Unable to obtain valid state from raw state {}", raw_state),
+        };
+
+        Ok(state)
+    }
+
+    fn query_binary_path(manager: &ScHandle, service_name: &str) -> Result<String> {
+        let service = open_manager_service(manager, service_name, winsvc::SERVICE_QUERY_CONFIG)?;
+        let mut needed: DWORD = 0;
+
+        // only queries the needed size of the buffer, which always fails
+        unsafe {
+            winsvc::QueryServiceConfigW(service.0, ptr::null_mut(), 0, &mut needed);
+        }
+
+        // allocated as u64 to be aligned for the configuration structure
+        let mut buf: Vec<u64> = vec![0; (needed as usize).div_ceil(8)];
+
+        let res = unsafe {
+            winsvc::QueryServiceConfigW(
+                service.0,
+                buf.as_mut_ptr() as *mut QUERY_SERVICE_CONFIGW,
+                (buf.len() * 8) as DWORD,
+                &mut needed,
+            )
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error()).chain_err(|| {
+                format!("Unable to query configuration of service '{}'", service_name)
+            });
+        }
+
+        let config = buf.as_ptr() as *const QUERY_SERVICE_CONFIGW;
+        Ok(from_wide_ptr(unsafe { (*config).lpBinaryPathName }))
+    }
+
+    pub fn installed_services(file_config: &FileConfig) -> Result<Vec<InstalledService>> {
+        let manager = open_manager(
+            winsvc::SC_MANAGER_CONNECT | winsvc::SC_MANAGER_ENUMERATE_SERVICE,
+            file_config,
+        )?;
+
+        let mut installed_services = Vec::new();
+        let mut buf: Vec<u64> = vec![0; ENUM_BUF_SIZE / 8];
+        let mut resume_handle: DWORD = 0;
+
+        loop {
+            let mut needed: DWORD = 0;
+            let mut returned: DWORD = 0;
+
+            let res = unsafe {
+                winsvc::EnumServicesStatusExW(
+                    manager.0,
+                    winsvc::SC_ENUM_PROCESS_INFO,
+                    SERVICE_WIN32,
+                    winsvc::SERVICE_STATE_ALL,
+                    buf.as_mut_ptr() as LPBYTE,
+                    ENUM_BUF_SIZE as DWORD,
+                    &mut needed,
+                    &mut returned,
+                    &mut resume_handle,
+                    ptr::null(),
+                )
+            };
+
+            // the remaining services are listed from the resume handle in the next batch
+            let has_more = if res == 0 {
+                let e = io::Error::last_os_error();
+
+                if e.raw_os_error() != Some(ERROR_MORE_DATA as i32) {
+                    return Err(e).chain_err(|| "Unable to list the installed services");
+                }
+
+                true
+            } else {
+                false
+            };
+
+            let entries = unsafe {
+                slice::from_raw_parts(
+                    buf.as_ptr() as *const ENUM_SERVICE_STATUS_PROCESSW,
+                    returned as usize,
+                )
+            };
+
+            for entry in entries {
+                let name = from_wide_ptr(entry.lpServiceName);
+
+                installed_services.push(InstalledService {
+                    state: state_from_raw(entry.ServiceStatusProcess.dwCurrentState)?,
+                    binary_path: query_binary_path(&manager, &name).ok(),
+                    name,
+                });
+            }
+
+            if !has_more {
+                return Ok(installed_services);
+            }
+        }
+    }
+
+    pub fn app_process_id(service_name: &str, file_config: &FileConfig) -> Result<u32> {
+        let service = open_service(service_name, winsvc::SERVICE_QUERY_STATUS, file_config)?;
+        let mut status: SERVICE_STATUS_PROCESS = unsafe { mem::zeroed() };
+        let mut needed: DWORD = 0;
+
+        let res = unsafe {
+            winsvc::QueryServiceStatusEx(
+                service.0,
+                winsvc::SC_STATUS_PROCESS_INFO,
+                &mut status as *mut SERVICE_STATUS_PROCESS as LPBYTE,
+                mem::size_of::<SERVICE_STATUS_PROCESS>() as DWORD,
+                &mut needed,
+            )
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error()).chain_err(|| {
+                format!("Unable to query process of service '{}'", service_name)
+            });
+        }
+
+        if status.dwProcessId == 0 {
+            bail!("Service '{}' has no running process", service_name);
+        }
+
+        let snapshot =
+            unsafe { tlhelp32::CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error())
+                .chain_err(|| "Unable to take a snapshot of the running processes");
+        }
+
+        let snapshot = SnapshotHandle(snapshot);
+        let mut entry: PROCESSENTRY32W = unsafe { mem::zeroed() };
+        entry.dwSize = mem::size_of::<PROCESSENTRY32W>() as DWORD;
+
+        let mut has_entry = unsafe { tlhelp32::Process32FirstW(snapshot.0, &mut entry) } != 0;
+
+        while has_entry {
+            // the console host of nssm is a child process as well
+            let is_app_process = entry.th32ParentProcessID == status.dwProcessId
+                && !from_wide_ptr(entry.szExeFile.as_ptr()).eq_ignore_ascii_case("conhost.exe");
+
+            if is_app_process {
+                return Ok(entry.th32ProcessID);
+            }
+
+            has_entry = unsafe { tlhelp32::Process32NextW(snapshot.0, &mut entry) } != 0;
+        }
+
+        bail!(
+            "Application process of service '{}' is not running",
+            service_name
+        )
+    }
+
+    pub fn query_state(service_name: &str, file_config: &FileConfig) -> Result<ServiceState> {
+        let service = open_service(service_name, winsvc::SERVICE_QUERY_STATUS, file_config)?;
+        let mut status: SERVICE_STATUS = unsafe { mem::zeroed() };
+
+        if unsafe { winsvc::QueryServiceStatus(service.0, &mut status) } == 0 {
+            return Err(io::Error::last_os_error()).chain_err(|| {
+                format!("Unable to query status of service '{}'", service_name)
+            });
+        }
+
+        state_from_raw(status.dwCurrentState)
+    }
+
+    pub fn stop(service_name: &str, file_config: &FileConfig) -> Result<()> {
+        let service = open_service(service_name, winsvc::SERVICE_STOP, file_config)?;
+        let mut status: SERVICE_STATUS = unsafe { mem::zeroed() };
+
+        let res = unsafe {
+            winsvc::ControlService(service.0, winsvc::SERVICE_CONTROL_STOP, &mut status)
+        };
+
+        if res == 0 {
+            return Err(io::Error::last_os_error()).chain_err(|| {
+                format!("Unable to send stop control to service '{}'", service_name)
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn start(service_name: &str, file_config: &FileConfig) -> Result<()> {
+        let service = open_service(service_name, winsvc::SERVICE_START, file_config)?;
+
+        if unsafe { winsvc::StartServiceW(service.0, 0, ptr::null_mut()) } == 0 {
+            return Err(io::Error::last_os_error()).chain_err(|| {
+                format!("Unable to start service '{}'", service_name)
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use config::FileConfig;
+    use errors::*;
+    use nssm;
+    use state::ServiceState;
+    use super::InstalledService;
+    use super::super::{run_nssm_modify_cmd, run_nssm_status_cmd_extract_status};
+
+    pub fn installed_services(_: &FileConfig) -> Result<Vec<InstalledService>> {
+        bail!("Listing the installed services is only supported on Windows")
+    }
+
+    pub fn app_process_id(_: &str, _: &FileConfig) -> Result<u32> {
+        bail!("Finding the application process is only supported on Windows")
+    }
+
+    pub fn query_state(service_name: &str, file_config: &FileConfig) -> Result<ServiceState> {
+        run_nssm_status_cmd_extract_status(service_name, file_config)
+    }
+
+    pub fn stop(service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_nssm_modify_cmd(&nssm::stop(service_name), file_config)
+    }
+
+    pub fn start(service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_nssm_modify_cmd(&nssm::start(service_name), file_config)
+    }
+}
@@ -0,0 +1,847 @@
+//! Generates a standalone batch or PowerShell script reproducing the creation of the
+//! services, i.e. stopping, removing, installing and configuring each service with nssm,
+//! so that it can be copied over to hosts that nssm_exec cannot run on nor reach, and
+//! PowerShell DSC configurations wrapping the same nssm calls.
+
+use cmdline;
+use config::{Backend, FileConfig, Service};
+use errors::*;
+use file;
+use firewall;
+use nssm;
+use params::{self, ServiceParam};
+use recovery;
+use sc;
+use std::path::Path;
+use std::time::Duration;
+use super::{dependency_order, find_service, service_deps};
+
+/// Kinds of scripts that can be generated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScriptKind {
+    /// Batch script run by `cmd.exe`.
+    Batch,
+
+    /// PowerShell script.
+    PowerShell,
+}
+
+impl ScriptKind {
+    /// Returns the kind of script from the extension of the path, i.e. `.bat` or `.cmd`
+    /// for batch and `.ps1` for PowerShell.
+    pub fn from_path(path: &Path) -> Result<ScriptKind> {
+        let extension = path.extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("bat") | Some("cmd") => Ok(ScriptKind::Batch),
+            Some("ps1") => Ok(ScriptKind::PowerShell),
+            _ => bail!(
+                "Unable to tell the kind of script '{}', expected extension .bat, .cmd or .ps1",
+                path.to_string_lossy()
+            ),
+        }
+    }
+}
+
+/// Argument of a command in the script.
+enum Arg {
+    Literal(String),
+
+    /// Value of the environment variable with the name, e.g. to keep passwords out of the
+    /// script.
+    Env(String),
+}
+
+#[derive(Clone, Copy)]
+enum WaitState {
+    Stopped,
+    Running,
+}
+
+enum Step {
+    Comment(String),
+
+    /// Runs the program, failing the script if it fails unless unchecked.
+    Run {
+        program: String,
+        args: Vec<Arg>,
+        checked: bool,
+    },
+
+    /// Runs the steps only if the service exists.
+    IfExists(String, Vec<Step>),
+
+    /// Runs the steps only if the service is not stopped.
+    IfNotStopped(String, Vec<Step>),
+
+    /// Waits for the service to be in the state, checking it up to the count of times.
+    Wait {
+        service_name: String,
+        state: WaitState,
+        interval: Duration,
+        count: u64,
+    },
+}
+
+/// Password that the script reads from an environment variable instead of containing it.
+struct Password {
+    user: String,
+    env_var: String,
+}
+
+fn run(program: &str, args: Vec<String>, checked: bool) -> Step {
+    Step::Run {
+        program: program.to_owned(),
+        args: args.into_iter().map(Arg::Literal).collect(),
+        checked,
+    }
+}
+
+fn hook_steps(cmd_line: Option<&String>) -> Vec<Step> {
+    let mut args = cmd_line.map_or_else(Vec::new, |cmd_line| cmdline::split_args(cmd_line));
+
+    if args.is_empty() {
+        return Vec::new();
+    }
+
+    let program = args.remove(0);
+    vec![run(&program, args, true)]
+}
+
+/// Returns the environment variable holding the password of the user, adding it if new.
+fn password_env_var(user: &str, passwords: &mut Vec<Password>) -> String {
+    if let Some(password) = passwords.iter().find(|password| password.user == user) {
+        return password.env_var.clone();
+    }
+
+    let env_var = format!("NSSM_EXEC_PASSWORD_{}", passwords.len() + 1);
+
+    passwords.push(Password {
+        user: user.to_owned(),
+        env_var: env_var.clone(),
+    });
+
+    env_var
+}
+
+fn set_step(
+    service: &Service,
+    param: ServiceParam,
+    nssm_path: &str,
+    file_config: &FileConfig,
+    passwords: &mut Vec<Password>,
+) -> Step {
+    let has_secret = param.secret.is_some();
+
+    let param = ServiceParam {
+        secret: None,
+        ..param
+    };
+
+    let mut args: Vec<Arg> = nssm::set(&service.name, &param)
+        .into_iter()
+        .map(Arg::Literal)
+        .collect();
+
+    if has_secret {
+        let user = file_config
+            .merged_other(service)
+            .account
+            .map_or("", |account| account.user.as_str());
+
+        args.push(Arg::Env(password_env_var(user, passwords)));
+    }
+
+    Step::Run {
+        program: nssm_path.to_owned(),
+        args,
+        checked: true,
+    }
+}
+
+fn service_steps(
+    service: &Service,
+    file_config: &FileConfig,
+    passwords: &mut Vec<Password>,
+) -> Result<Vec<Step>> {
+    if file_config.backend(&service.name) != Backend::Nssm {
+        bail!(
+            "Unable to generate script for service '{}' that does not use the nssm backend",
+            service.name
+        );
+    }
+
+    if service.artifact.is_some() {
+        bail!(
+            "Unable to generate script for service '{}' with an artifact to download",
+            service.name
+        );
+    }
+
+    let nssm_path = file_config.nssm_path.to_string_lossy().into_owned();
+    let other = file_config.merged_other(service);
+
+    let mut stop_steps = hook_steps(other.pre_stop);
+    stop_steps.push(run(&nssm_path, nssm::stop(&service.name), false));
+
+    stop_steps.push(Step::Wait {
+        service_name: service.name.clone(),
+        state: WaitState::Stopped,
+        interval: file_config.pending_stop_poll_interval(),
+        count: file_config.pending_stop_poll_count(),
+    });
+
+    let mut steps = vec![
+        Step::IfExists(
+            service.name.clone(),
+            vec![
+                Step::IfNotStopped(service.name.clone(), stop_steps),
+                run(&nssm_path, nssm::remove(&service.name), true),
+            ],
+        ),
+    ];
+
+    steps.extend(hook_steps(other.pre_install));
+    steps.push(run(&nssm_path, nssm::install(&service.name, &service.path), true));
+
+    // application path has already been set during install
+    for param in params::desired_params(service, file_config) {
+        if param.name != params::APPLICATION {
+            steps.push(set_step(service, param, &nssm_path, file_config, passwords));
+        }
+    }
+
+    if let Some(ref recovery) = service.recovery {
+        // sc is an alias of Set-Content in PowerShell
+        let sc_path = format!("{}.exe", sc::program().to_string_lossy());
+        let non_crash_failures = recovery.non_crash_failures.unwrap_or(false);
+
+        steps.push(run(&sc_path, recovery::failure_args(&service.name, recovery), true));
+        steps.push(run(&sc_path, sc::failureflag(&service.name, non_crash_failures), true));
+    }
+
+    if let Some(ref firewall) = service.firewall {
+        let netsh_path = firewall::program().to_string_lossy().into_owned();
+        let rule_name = firewall::rule_name(&service.name, firewall);
+
+        // netsh fails to delete a rule that does not exist
+        steps.push(run(&netsh_path, firewall::delete(&rule_name), false));
+        steps.push(run(&netsh_path, firewall::add(&rule_name, firewall), true));
+    }
+
+    steps.extend(hook_steps(other.post_install));
+
+    if let Some(&true) = other.start_on_create {
+        steps.push(run(&nssm_path, nssm::start(&service.name), false));
+
+        steps.push(Step::Wait {
+            service_name: service.name.clone(),
+            state: WaitState::Running,
+            interval: file_config.pending_start_poll_interval(),
+            count: file_config.pending_start_poll_count(),
+        });
+
+        if service.health.is_some() {
+            warn!(
+                "Health probes of service '{}' are not checked by the script",
+                service.name
+            );
+
+            steps.push(Step::Comment(format!(
+                "Health probes of service '{}' are not checked",
+                service.name
+            )));
+        }
+
+        steps.extend(hook_steps(other.post_start));
+    }
+
+    Ok(steps)
+}
+
+/// Escapes the characters that `cmd.exe` interprets outside of double quotes, tracking the
+/// double quotes the same way as `cmd.exe`, i.e. regardless of any preceding backslash.
+/// Percent signs are always doubled since they are expanded even inside double quotes.
+fn batch_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut in_quotes = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '%' => escaped.push('%'),
+            '^' | '&' | '|' | '<' | '>' | '(' | ')' if !in_quotes => escaped.push('^'),
+            _ => (),
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+fn batch_arg(arg: &Arg) -> String {
+    match *arg {
+        Arg::Literal(ref value) => batch_escape(&cmdline::quote_arg(value)),
+        Arg::Env(ref env_var) => format!("\"%{}%\"", env_var),
+    }
+}
+
+fn powershell_string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn powershell_arg(arg: &Arg) -> String {
+    match *arg {
+        Arg::Literal(ref value) => powershell_string(value),
+        Arg::Env(ref env_var) => format!("$env:{}", env_var),
+    }
+}
+
+const BATCH_HEADER: &str = "\
+@echo off
+rem Generated by nssm_exec to recreate the services with nssm on this machine.
+setlocal
+";
+
+const BATCH_FOOTER: &str = "\
+echo All services created
+exit /b 0
+
+:is_state
+sc query %1 | find \"%~2\" >nul
+exit /b
+
+:wait_state
+set /a \"remaining=%~3\"
+
+:wait_state_loop
+call :is_state %1 %2
+if not errorlevel 1 exit /b 0
+set /a \"remaining-=1\"
+if %remaining% leq 0 goto wait_state_timeout
+ping -n %~4 127.0.0.1 >nul
+goto wait_state_loop
+
+:wait_state_timeout
+echo Timeout waiting for service '%~1' to be in state %~2 1>&2
+exit /b 1
+";
+
+const POWERSHELL_HEADER: &str = "\
+# Generated by nssm_exec to recreate the services with nssm on this machine.
+$ErrorActionPreference = 'Stop'
+
+";
+
+/// Functions that the PowerShell steps call, which are also defined inside the DSC scripts
+/// since those run on their own.
+const POWERSHELL_FUNCTIONS: &str = "\
+function Invoke-Step {
+    param([string] $Program, [string[]] $Arguments, [switch] $Unchecked)
+
+    & $Program @Arguments
+
+    if (-not $Unchecked -and $LASTEXITCODE -ne 0) {
+        throw \"'$Program $Arguments' failed with exit code $LASTEXITCODE\"
+    }
+}
+
+function Wait-ServiceStatus {
+    param([string] $Name, [string] $Status, [int] $Count, [int] $IntervalMs)
+
+    for ($i = 1; $i -le $Count; $i++) {
+        if ((Get-Service -Name $Name).Status -eq $Status) {
+            return
+        }
+
+        if ($i -lt $Count) {
+            Start-Sleep -Milliseconds $IntervalMs
+        }
+    }
+
+    throw \"Timeout waiting for service '$Name' to be in state $Status\"
+}
+";
+
+const POWERSHELL_FOOTER: &str = "\
+Write-Output 'All services created'
+";
+
+struct Renderer {
+    kind: ScriptKind,
+    lines: Vec<String>,
+    label_count: usize,
+}
+
+impl Renderer {
+    fn push(&mut self, indent: usize, line: String) {
+        self.lines.push(format!("{}{}", "    ".repeat(indent), line));
+    }
+
+    fn passwords<'a, I>(&mut self, passwords: I, indent: usize)
+    where
+        I: IntoIterator<Item = &'a Password>,
+    {
+        for password in passwords {
+            self.lines.push(String::new());
+
+            let message = format!(
+                "Set {} to the password of account '{}'",
+                password.env_var,
+                password.user
+            );
+
+            match self.kind {
+                ScriptKind::Batch => {
+                    self.push(indent, format!("if not defined {} (", password.env_var));
+                    self.push(indent + 1, format!("echo {} 1>&2", batch_escape(&message)));
+                    self.push(indent + 1, "exit /b 1".to_owned());
+                    self.push(indent, ")".to_owned());
+                }
+
+                ScriptKind::PowerShell => {
+                    self.push(indent, format!("if (-not $env:{}) {{", password.env_var));
+                    self.push(indent + 1, format!("throw {}", powershell_string(&message)));
+                    self.push(indent, "}".to_owned());
+                }
+            }
+        }
+    }
+
+    /// Renders the steps, where batch scripts skip over the conditional steps with labels
+    /// instead of blocks, which would need every nested parenthesis to be escaped.
+    fn steps(&mut self, steps: &[Step], indent: usize) {
+        for step in steps {
+            match *step {
+                Step::Comment(ref text) => match self.kind {
+                    ScriptKind::Batch => {
+                        self.lines.push(String::new());
+                        self.push(indent, format!("rem {}", text.replace('%', "%%")));
+                    }
+
+                    ScriptKind::PowerShell => {
+                        self.lines.push(String::new());
+                        self.push(indent, format!("# {}", text));
+                    }
+                },
+
+                Step::Run {
+                    ref program,
+                    ref args,
+                    checked,
+                } => {
+                    let line = match self.kind {
+                        ScriptKind::Batch => {
+                            let mut line = batch_escape(&cmdline::quote_arg(program));
+
+                            for arg in args {
+                                line.push(' ');
+                                line.push_str(&batch_arg(arg));
+                            }
+
+                            if checked {
+                                line.push_str(" || exit /b 1");
+                            }
+
+                            line
+                        }
+
+                        ScriptKind::PowerShell => {
+                            let args: Vec<String> = args.iter().map(powershell_arg).collect();
+
+                            format!(
+                                "Invoke-Step {} @({}){}",
+                                powershell_string(program),
+                                args.join(", "),
+                                if checked { "" } else { " -Unchecked" }
+                            )
+                        }
+                    };
+
+                    self.push(indent, line);
+                }
+
+                Step::IfExists(ref service_name, ref inner_steps) => {
+                    let condition = match self.kind {
+                        ScriptKind::Batch => format!(
+                            "sc query {} >nul 2>&1\nif errorlevel 1",
+                            batch_escape(&cmdline::quote_arg(service_name))
+                        ),
+
+                        ScriptKind::PowerShell => format!(
+                            "Get-Service -Name {} -ErrorAction SilentlyContinue",
+                            powershell_string(service_name)
+                        ),
+                    };
+
+                    self.conditional(&condition, inner_steps, indent);
+                }
+
+                Step::IfNotStopped(ref service_name, ref inner_steps) => {
+                    let condition = match self.kind {
+                        ScriptKind::Batch => format!(
+                            "call :is_state {} STOPPED\nif not errorlevel 1",
+                            batch_escape(&cmdline::quote_arg(service_name))
+                        ),
+
+                        ScriptKind::PowerShell => format!(
+                            "(Get-Service -Name {}).Status -ne 'Stopped'",
+                            powershell_string(service_name)
+                        ),
+                    };
+
+                    self.conditional(&condition, inner_steps, indent);
+                }
+
+                Step::Wait {
+                    ref service_name,
+                    state,
+                    ref interval,
+                    count,
+                } => {
+                    let line = match self.kind {
+                        ScriptKind::Batch => {
+                            // ping waits a second between each of its echo requests
+                            let interval_secs = match interval.subsec_nanos() {
+                                0 => interval.as_secs().max(1),
+                                _ => interval.as_secs() + 1,
+                            };
+
+                            format!(
+                                "call :wait_state {} {} {} {} || exit /b 1",
+                                batch_escape(&cmdline::quote_arg(service_name)),
+                                match state {
+                                    WaitState::Stopped => "STOPPED",
+                                    WaitState::Running => "RUNNING",
+                                },
+                                count,
+                                interval_secs + 1
+                            )
+                        }
+
+                        ScriptKind::PowerShell => format!(
+                            "Wait-ServiceStatus {} {} {} {}",
+                            powershell_string(service_name),
+                            match state {
+                                WaitState::Stopped => "'Stopped'",
+                                WaitState::Running => "'Running'",
+                            },
+                            count,
+                            interval.as_secs() * 1000
+                                + u64::from(interval.subsec_millis())
+                        ),
+                    };
+
+                    self.push(indent, line);
+                }
+            }
+        }
+    }
+
+    /// Renders the steps to only run if the condition holds. The batch condition is the
+    /// command followed by the check that skips the steps.
+    fn conditional(&mut self, condition: &str, steps: &[Step], indent: usize) {
+        match self.kind {
+            ScriptKind::Batch => {
+                self.label_count += 1;
+                let label = format!("skip_{}", self.label_count);
+
+                for line in condition.lines() {
+                    self.push(indent, line.to_owned());
+                }
+
+                let last_index = self.lines.len() - 1;
+                self.lines[last_index].push_str(&format!(" goto {}", label));
+
+                self.steps(steps, indent);
+                self.push(indent, format!(":{}", label));
+            }
+
+            ScriptKind::PowerShell => {
+                self.push(indent, format!("if ({}) {{", condition));
+                self.steps(steps, indent + 1);
+                self.push(indent, "}".to_owned());
+            }
+        }
+    }
+}
+
+/// Generates the script recreating every service in the dependency order, with the passwords
+/// of the accounts read from environment variables when the script runs.
+pub fn generate(file_config: &FileConfig, kind: ScriptKind) -> Result<String> {
+    let mut passwords = Vec::new();
+    let mut steps = Vec::new();
+
+    for index in dependency_order(file_config) {
+        let service = &file_config.services[index];
+        steps.push(Step::Comment(format!("Service '{}'", service.name)));
+        steps.extend(service_steps(service, file_config, &mut passwords)?);
+    }
+
+    let mut renderer = Renderer {
+        kind,
+        lines: Vec::new(),
+        label_count: 0,
+    };
+
+    renderer.passwords(&passwords, 0);
+    renderer.steps(&steps, 0);
+
+    let (header, footer) = match kind {
+        ScriptKind::Batch => (BATCH_HEADER.to_owned(), BATCH_FOOTER),
+        ScriptKind::PowerShell => (
+            format!("{}{}", POWERSHELL_HEADER, POWERSHELL_FUNCTIONS),
+            POWERSHELL_FOOTER,
+        ),
+    };
+
+    let script = format!("{}{}\n\n{}", header, renderer.lines.join("\n"), footer);
+
+    // batch scripts may fail to find their labels with other line endings
+    Ok(script.replace('\n', "\r\n"))
+}
+
+/// Generates the script of the kind told by the extension of the path, and writes it into
+/// the file at the path.
+pub fn write<P: AsRef<Path>>(file_config: &FileConfig, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let script = generate(file_config, ScriptKind::from_path(path)?)?;
+
+    file::put_text(path, script)
+        .chain_err(|| format!("Unable to write script at '{}'", path.to_string_lossy()))
+}
+
+/// Name of the DSC configuration generated by `generate_dsc`.
+pub const DSC_CONFIGURATION_NAME: &str = "NssmExec";
+
+const DSC_HEADER: &str = "\
+# Generated by nssm_exec from the service configurations. Compile it into MOF files with
+# `. .\\<this file>; NssmExec -OutputPath <dir>` and apply them with Start-DscConfiguration.
+";
+
+/// Gets the installed value of the nssm parameter with the whitespace collapsed, which nssm
+/// writes as UTF-16 when its output is redirected.
+const DSC_GET_PARAM_FUNCTION: &str = "\
+function Get-NssmParam {
+    param([string] $Program, [string[]] $Arguments)
+
+    $value = ((& $Program @Arguments) -join ' ') -replace \"[`0`r]\", ''
+    (($value -split '\\s+') -ne '') -join ' '
+}
+";
+
+/// Returns the name of the DSC resource of the service, which may only contain letters,
+/// digits and underscores.
+fn dsc_resource_name(service_name: &str) -> String {
+    let name: String = service_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("NssmService_{}", name)
+}
+
+impl Renderer {
+    fn block(&mut self, indent: usize, text: &str) {
+        for line in text.lines() {
+            if line.is_empty() {
+                self.lines.push(String::new());
+            } else {
+                self.push(indent, line.to_owned());
+            }
+        }
+    }
+
+    /// Renders the test of the DSC script of the service, which holds if the service exists
+    /// and has the configured nssm parameters.
+    fn dsc_test(&mut self, service: &Service, file_config: &FileConfig, indent: usize) {
+        let nssm_path = powershell_string(&file_config.nssm_path.to_string_lossy());
+
+        self.block(indent, DSC_GET_PARAM_FUNCTION);
+        self.lines.push(String::new());
+
+        self.push(
+            indent,
+            format!(
+                "if (-not (Get-Service -Name {} -ErrorAction SilentlyContinue)) {{",
+                powershell_string(&service.name)
+            ),
+        );
+
+        self.push(indent + 1, "return $false".to_owned());
+        self.push(indent, "}".to_owned());
+
+        for param in params::desired_params(service, file_config) {
+            let args: Vec<String> = nssm::get(&service.name, &param)
+                .iter()
+                .map(|arg| powershell_string(arg))
+                .collect();
+
+            let mut installed = format!("Get-NssmParam {} @({})", nssm_path, args.join(", "));
+            let mut expected = param
+                .values
+                .iter()
+                .flat_map(|value| value.split_whitespace())
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            // nssm returns the local account with the '.\' prefix
+            if param.name == "ObjectName" {
+                installed = format!("({}) -replace '^\\.\\\\', ''", installed);
+
+                if expected.starts_with(".\\") {
+                    expected = expected[2..].to_owned();
+                }
+            }
+
+            self.lines.push(String::new());
+
+            self.push(
+                indent,
+                format!("if (({}) -ne {}) {{", installed, powershell_string(&expected)),
+            );
+
+            self.push(indent + 1, "return $false".to_owned());
+            self.push(indent, "}".to_owned());
+        }
+
+        self.lines.push(String::new());
+        self.push(indent, "return $true".to_owned());
+    }
+}
+
+/// Generates a PowerShell DSC configuration with a `Script` resource per service, which
+/// recreates the service with the same nssm calls as the PowerShell script whenever the
+/// service is missing or any of its nssm parameters differs, and a `Service` resource keeping
+/// the services that start on creation running. The passwords of the accounts are read from
+/// environment variables on the nodes.
+pub fn generate_dsc(file_config: &FileConfig) -> Result<String> {
+    let mut passwords = Vec::new();
+
+    let mut renderer = Renderer {
+        kind: ScriptKind::PowerShell,
+        lines: Vec::new(),
+        label_count: 0,
+    };
+
+    let nodes: Vec<String> = file_config
+        .hosts()
+        .iter()
+        .map(|host| powershell_string(host.as_ref().map_or("localhost", |host| host.as_str())))
+        .collect();
+
+    renderer.push(0, format!("Configuration {} {{", DSC_CONFIGURATION_NAME));
+    renderer.push(1, "Import-DscResource -ModuleName PSDesiredStateConfiguration".to_owned());
+    renderer.lines.push(String::new());
+    renderer.push(1, format!("Node @({}) {{", nodes.join(", ")));
+
+    for (position, index) in dependency_order(file_config).into_iter().enumerate() {
+        let service = &file_config.services[index];
+        let steps = service_steps(service, file_config, &mut passwords)?;
+        let resource_name = dsc_resource_name(&service.name);
+
+        // only the passwords of the account of the service are checked by its script
+        let service_passwords: Vec<&Password> = file_config
+            .merged_other(service)
+            .account
+            .into_iter()
+            .flat_map(|account| {
+                passwords
+                    .iter()
+                    .filter(move |password| password.user == account.user)
+            })
+            .collect();
+
+        let depends_on: Vec<String> = service_deps(service, file_config)
+            .into_iter()
+            .filter(|dep| find_service(file_config, dep).is_some())
+            .map(|dep| powershell_string(&format!("[Script]{}", dsc_resource_name(dep))))
+            .collect();
+
+        if position > 0 {
+            renderer.lines.push(String::new());
+        }
+
+        renderer.push(2, format!("Script {} {{", powershell_string(&resource_name)));
+
+        renderer.push(3, "GetScript = {".to_owned());
+
+        renderer.push(
+            4,
+            format!(
+                "$service = Get-Service -Name {} -ErrorAction SilentlyContinue",
+                powershell_string(&service.name)
+            ),
+        );
+
+        renderer.push(4, "@{ Result = [string] $service.Status }".to_owned());
+
+        renderer.push(3, "}".to_owned());
+        renderer.lines.push(String::new());
+
+        renderer.push(3, "TestScript = {".to_owned());
+        renderer.dsc_test(service, file_config, 4);
+        renderer.push(3, "}".to_owned());
+        renderer.lines.push(String::new());
+
+        renderer.push(3, "SetScript = {".to_owned());
+        renderer.push(4, "$ErrorActionPreference = 'Stop'".to_owned());
+        renderer.lines.push(String::new());
+        renderer.block(4, POWERSHELL_FUNCTIONS);
+
+        renderer.passwords(service_passwords, 4);
+
+        renderer.lines.push(String::new());
+        renderer.steps(&steps, 4);
+        renderer.push(3, "}".to_owned());
+
+        if !depends_on.is_empty() {
+            renderer.lines.push(String::new());
+            renderer.push(3, format!("DependsOn = @({})", depends_on.join(", ")));
+        }
+
+        renderer.push(2, "}".to_owned());
+
+        if let Some(&true) = file_config.merged_other(service).start_on_create {
+            renderer.lines.push(String::new());
+
+            renderer.push(
+                2,
+                format!("Service {} {{", powershell_string(&format!("{}_State", resource_name))),
+            );
+
+            renderer.push(3, format!("Name = {}", powershell_string(&service.name)));
+            renderer.push(3, "State = 'Running'".to_owned());
+
+            renderer.push(
+                3,
+                format!(
+                    "DependsOn = {}",
+                    powershell_string(&format!("[Script]{}", resource_name))
+                ),
+            );
+
+            renderer.push(2, "}".to_owned());
+        }
+    }
+
+    renderer.push(1, "}".to_owned());
+    renderer.push(0, "}".to_owned());
+
+    Ok(format!("{}\n{}\n", DSC_HEADER, renderer.lines.join("\n")).replace('\n', "\r\n"))
+}
+
+/// Generates the DSC configuration and writes it into the file at the path.
+pub fn write_dsc<P: AsRef<Path>>(file_config: &FileConfig, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let configuration = generate_dsc(file_config)?;
+
+    file::put_text(path, configuration).chain_err(|| {
+        format!(
+            "Unable to write DSC configuration at '{}'",
+            path.to_string_lossy()
+        )
+    })
+}
@@ -0,0 +1,285 @@
+//! Serves a small HTTP API to list the configured services, query their status, start, stop
+//! and restart them, and apply the configuration, so that the services may be managed
+//! remotely. Every request has to pass the shared token in the `X-Auth-Token` header.
+//!
+//! The routes are:
+//!
+//! - `GET /services` returns the status of all the services.
+//! - `GET /services/<name>` returns the status of the service.
+//! - `POST /services/<name>/start`, `/stop` and `/restart` control the service, returning the
+//!   run report.
+//! - `POST /apply` updates all the services like the `update` subcommand, returning the run
+//!   report.
+//!
+//! Only the services on the local machine are managed.
+
+use config::FileConfig;
+use errors::*;
+use report::RunReport;
+use serde::Serialize;
+use serde_json;
+use std::env;
+use std::io::Cursor;
+use std::slice;
+use tiny_http::{Header, Method, Request, Response, Server};
+use super::{nssm_exec_restart, nssm_exec_start, nssm_exec_status, nssm_exec_stop,
+            nssm_exec_update};
+
+/// Address served on when no other address is configured.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+/// Name of the header holding the shared token.
+pub const TOKEN_HEADER: &str = "X-Auth-Token";
+
+#[derive(Serialize)]
+struct ErrorBody {
+    errors: Vec<String>,
+}
+
+/// Returns the configured address to serve on.
+pub fn address(file_config: &FileConfig) -> &str {
+    file_config
+        .serve
+        .as_ref()
+        .and_then(|serve| serve.address.as_ref())
+        .map_or(DEFAULT_ADDRESS, |address| address.as_str())
+}
+
+/// Returns the configured shared token, read from the environment variable if configured so.
+/// Returns error if no token is configured, since the API must not be served without one.
+pub fn token(file_config: &FileConfig) -> Result<String> {
+    let serve = match file_config.serve {
+        Some(ref serve) => serve,
+        None => bail!("Unable to serve without the [serve] settings holding the shared token"),
+    };
+
+    let token = match (serve.token.as_ref(), serve.token_env.as_ref()) {
+        (Some(_), Some(_)) => bail!("Unable to use both token and token_env to serve"),
+        (Some(token), None) => token.clone(),
+        (None, Some(token_env)) => env::var(token_env).chain_err(|| {
+            format!("Unable to read token from environment variable '{}'", token_env)
+        })?,
+        (None, None) => bail!("Unable to serve without token or token_env in [serve]"),
+    };
+
+    if token.is_empty() {
+        bail!("Unable to serve with an empty token");
+    }
+
+    Ok(token)
+}
+
+/// Serves the API on the address until the process is stopped. The configuration is loaded
+/// with `load` for every request, so that its changes are picked up without restarting.
+pub fn serve<F>(address: &str, token: &str, load: F) -> Result<()>
+where
+    F: Fn() -> Result<FileConfig>,
+{
+    let server = Server::http(address)
+        .map_err(|e| Error::from(format!("Unable to listen on '{}': {}", address, e)))?;
+
+    info!("Serving the HTTP API on '{}'...", address);
+
+    for request in server.incoming_requests() {
+        let (status_code, body) = if is_authorized(&request, token) {
+            handle(request.method(), request.url(), &load)
+        } else {
+            error_response(401, "Missing or invalid token".to_owned())
+        };
+
+        info!(
+            "{} {} -> {}",
+            request.method().as_str(),
+            request.url(),
+            status_code
+        );
+
+        if let Err(e) = request.respond(json_response(status_code, body)) {
+            warn!("Unable to send response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn is_authorized(request: &Request, token: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(TOKEN_HEADER))
+        .is_some_and(|header| {
+            // compares every byte to not leak the matching prefix length through timing
+            let value = header.value.as_str().as_bytes();
+
+            value.len() == token.len()
+                && value
+                    .iter()
+                    .zip(token.as_bytes())
+                    .fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+        })
+}
+
+fn json_response(status_code: u16, body: String) -> Response<Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("Content-Type header is valid");
+
+    Response::from_string(body)
+        .with_status_code(status_code)
+        .with_header(content_type)
+}
+
+fn to_body<T: Serialize>(status_code: u16, value: &T) -> (u16, String) {
+    match serde_json::to_string_pretty(value) {
+        Ok(body) => (status_code, body),
+        Err(e) => error_response(500, format!("Unable to serialize response: {}", e)),
+    }
+}
+
+fn error_response(status_code: u16, message: String) -> (u16, String) {
+    let body = ErrorBody {
+        errors: vec![message],
+    };
+
+    (
+        status_code,
+        serde_json::to_string_pretty(&body).unwrap_or_else(|_| "{}".to_owned()),
+    )
+}
+
+fn failed_response(e: &Error) -> (u16, String) {
+    let body = ErrorBody {
+        errors: e.iter().map(|cause| cause.to_string()).collect(),
+    };
+
+    to_body(500, &body)
+}
+
+/// Loads the configuration of the services on the local machine, collecting the run report.
+fn load_local<F>(load: &F) -> Result<FileConfig>
+where
+    F: Fn() -> Result<FileConfig>,
+{
+    let mut file_config = load()?.for_host(None);
+    file_config.run_report = Some(RunReport::default());
+    Ok(file_config)
+}
+
+/// Decodes the percent-encoded bytes of the path segment, e.g. `my%20app` into `my app`.
+/// Returns `None` if an escape is not followed by two hex digits, or if the decoded bytes
+/// are not UTF-8.
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = segment
+                .get(index + 1..index + 3)
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))?;
+
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+fn handle<F>(method: &Method, url: &str, load: &F) -> (u16, String)
+where
+    F: Fn() -> Result<FileConfig>,
+{
+    let mut file_config = match load_local(load) {
+        Ok(file_config) => file_config,
+        Err(e) => return failed_response(&e),
+    };
+
+    let path = url.split('?').next().unwrap_or("");
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+
+    // the service in the path must be in the configuration, and is replaced by `*` in the
+    // route to match against
+    let is_service_path = segments.len() > 1 && segments[0] == "services";
+
+    if is_service_path {
+        let name = match percent_decode(segments[1]) {
+            Some(name) => name,
+            None => {
+                return error_response(
+                    400,
+                    format!("Unable to decode service name '{}'", segments[1]),
+                )
+            }
+        };
+
+        if file_config.retain_services(slice::from_ref(&name)).is_err() {
+            return error_response(404, format!("Service '{}' is not configured", name));
+        }
+    }
+
+    let route = segments
+        .iter()
+        .enumerate()
+        .map(|(index, segment)| if is_service_path && index == 1 { "*" } else { segment })
+        .collect::<Vec<_>>()
+        .join("/");
+
+    let res = match (method, route.as_str()) {
+        (&Method::Get, "services") | (&Method::Get, "services/*") => {
+            return match nssm_exec_status(&file_config) {
+                Ok(statuses) => if segments.len() == 1 {
+                    to_body(200, &statuses)
+                } else {
+                    to_body(200, &statuses[0])
+                },
+                Err(e) => failed_response(&e),
+            };
+        }
+
+        (&Method::Post, "services/*/start") => nssm_exec_start(
+            &file_config,
+            &file_config.pending_start_poll_interval(),
+            file_config.pending_start_poll_count(),
+        ),
+
+        (&Method::Post, "services/*/stop") => nssm_exec_stop(
+            &file_config,
+            &file_config.pending_stop_poll_interval(),
+            file_config.pending_stop_poll_count(),
+        ),
+
+        (&Method::Post, "services/*/restart") => nssm_exec_restart(
+            &file_config,
+            &file_config.pending_stop_poll_interval(),
+            file_config.pending_stop_poll_count(),
+            &file_config.pending_start_poll_interval(),
+            file_config.pending_start_poll_count(),
+        ),
+
+        (&Method::Post, "apply") => nssm_exec_update(
+            &file_config,
+            &file_config.pending_stop_poll_interval(),
+            file_config.pending_stop_poll_count(),
+            &file_config.pending_start_poll_interval(),
+            file_config.pending_start_poll_count(),
+        ),
+
+        _ => return error_response(404, format!("No route for {} {}", method.as_str(), path)),
+    };
+
+    if let Err(ref e) = res {
+        super::print_recursive_err(e);
+    }
+
+    // the run report holds the outcome and errors of every service
+    let run_report = file_config.run_report.unwrap_or_default();
+
+    match run_report.to_json() {
+        Ok(body) => (if res.is_ok() { 200 } else { 500 }, body),
+        Err(e) => failed_response(&e),
+    }
+}
@@ -0,0 +1,292 @@
+//! Records the commands run during an apply session with their outputs, and replays them on
+//! another machine, so that a run that behaves differently between machines can be
+//! reproduced step by step and the first diverging command found.
+
+use cmdline;
+use errors::*;
+use file;
+use notify;
+use output;
+use serde_json;
+use std::path::Path;
+use std::process::Output;
+use std::sync::Mutex;
+use transcript::{self, SECRET_PLACEHOLDER};
+use super::run_cmd;
+
+/// Holds a command that has been run with its outcome.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordedCommand {
+    /// Remote host that the command has been run on, or none for the recording machine.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    /// Program of the command.
+    pub program: String,
+
+    /// Arguments of the command with the secrets replaced.
+    pub args: Vec<String>,
+
+    /// Exit code of the command. `None` if the command could not be run, timed out or was
+    /// terminated by a signal.
+    pub exit_code: Option<i32>,
+
+    /// Standard output of the command, trimmed.
+    pub stdout: String,
+
+    /// Standard error of the command, trimmed.
+    pub stderr: String,
+
+    /// Error if the command could not be run or timed out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Holds the commands of a session in the order they have been run.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SessionFile {
+    /// Name of the machine that the session has been recorded on.
+    pub machine: String,
+
+    /// Version of nssm_exec that has recorded the session.
+    pub version: String,
+
+    /// Commands of the session in order.
+    pub commands: Vec<RecordedCommand>,
+}
+
+/// Collects the commands run, which may be shared across concurrent workers.
+#[derive(Debug, Default)]
+pub struct Session {
+    commands: Mutex<Vec<RecordedCommand>>,
+    secrets: Vec<String>,
+}
+
+/// Returns the exit code, trimmed standard output and error, and the error if the command
+/// could not be run, of the result of a command.
+fn outcome(res: &Result<Output>) -> (Option<i32>, String, String, Option<String>) {
+    match *res {
+        Ok(ref output) => (
+            output.status.code(),
+            output::decode(&output.stdout).trim().to_owned(),
+            output::decode(&output.stderr).trim().to_owned(),
+            None,
+        ),
+
+        Err(ref e) => match *e.kind() {
+            ErrorKind::CommandFailed {
+                code,
+                ref stdout,
+                ref stderr,
+                ..
+            } => (code, stdout.clone(), stderr.clone(), None),
+
+            _ => (None, String::new(), String::new(), Some(e.to_string())),
+        },
+    }
+}
+
+impl Session {
+    /// Creates a session that replaces every occurrence of the given secrets.
+    pub fn new(secrets: Vec<String>) -> Session {
+        Session {
+            commands: Mutex::new(Vec::new()),
+            secrets: secrets
+                .into_iter()
+                .filter(|secret| !secret.is_empty())
+                .collect(),
+        }
+    }
+
+    fn redact(&self, value: &str) -> String {
+        transcript::redact(value, &self.secrets)
+    }
+
+    /// Records the command run on the given remote host, or on this machine if no host is
+    /// given, with its result.
+    pub fn record<S: AsRef<str>>(
+        &self,
+        program: &Path,
+        args: &[S],
+        host: Option<&str>,
+        res: &Result<Output>,
+    ) {
+        let (exit_code, stdout, stderr, error) = outcome(res);
+
+        let command = RecordedCommand {
+            host: host.map(|host| host.to_owned()),
+            program: program.to_string_lossy().into_owned(),
+            args: transcript::redact_args(args, &self.secrets),
+            exit_code,
+            stdout: self.redact(&stdout),
+            stderr: self.redact(&stderr),
+            error: error.map(|error| self.redact(&error)),
+        };
+
+        self.commands.lock().unwrap().push(command);
+    }
+
+    /// Returns the commands recorded so far in order.
+    pub fn commands(&self) -> Vec<RecordedCommand> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Writes the commands into the JSON file at the given path.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        let session_file = SessionFile {
+            machine: notify::machine_name(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            commands: self.commands(),
+        };
+
+        let content = serde_json::to_string_pretty(&session_file)
+            .chain_err(|| "Unable to serialize session")?;
+
+        file::put_text(path, content).chain_err(|| {
+            format!("Unable to write session at '{}'", path.to_string_lossy())
+        })
+    }
+}
+
+/// Reads the session from the JSON file at the given path.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<SessionFile> {
+    let path = path.as_ref();
+
+    let content = file::get_text(path)
+        .chain_err(|| format!("Unable to read session at '{}'", path.to_string_lossy()))?;
+
+    serde_json::from_str(&content)
+        .chain_err(|| format!("Unable to interpret session at '{}'", path.to_string_lossy()))
+}
+
+/// Runs the commands of the session at the given path in order on this machine, whichever
+/// host they were recorded on, and logs every command whose exit code or output differs from
+/// the recording. Commands holding a replaced secret cannot be run and are skipped. Only logs
+/// the commands with their recorded exit codes if dry run is enabled. Fails if any command
+/// differs.
+pub fn replay<P: AsRef<Path>>(path: P, dry_run: bool) -> Result<()> {
+    let session_file = read(path)?;
+    let total = session_file.commands.len();
+
+    info!(
+        "Replaying {} command(s) recorded on '{}' by version {}...",
+        total, session_file.machine, session_file.version
+    );
+
+    let mut differing = 0;
+    let mut skipped = 0;
+
+    for (index, command) in session_file.commands.iter().enumerate() {
+        let cmd_line = cmdline::to_cmd_line(&command.program, &command.args);
+
+        let recorded_on = match command.host {
+            Some(ref host) => format!(" (recorded on host '{}')", host),
+            None => String::new(),
+        };
+
+        if dry_run {
+            info!(
+                "[DRY RUN] [{}/{}] {}{}, recorded exit code {:?}",
+                index + 1,
+                total,
+                cmd_line,
+                recorded_on,
+                command.exit_code
+            );
+
+            continue;
+        }
+
+        if command
+            .args
+            .iter()
+            .any(|arg| arg.contains(SECRET_PLACEHOLDER))
+        {
+            warn!(
+                "[{}/{}] Skipping '{}', which holds a replaced secret",
+                index + 1,
+                total,
+                cmd_line
+            );
+
+            skipped += 1;
+            continue;
+        }
+
+        let res = run_cmd(Path::new(&command.program), &command.args);
+        let (exit_code, stdout, stderr, error) = outcome(&res);
+
+        if exit_code != command.exit_code || error.is_some() != command.error.is_some() {
+            warn!(
+                "[{}/{}] '{}' exited with code {:?}{}, recorded {:?}",
+                index + 1,
+                total,
+                cmd_line,
+                exit_code,
+                error.map(|error| format!(" ({})", error)).unwrap_or_default(),
+                command.exit_code
+            );
+
+            differing += 1;
+        } else if stdout != command.stdout || stderr != command.stderr {
+            warn!(
+                "[{}/{}] '{}' output differs, stdout: \"{}\", stderr: \"{}\", recorded \
+                 stdout: \"{}\", stderr: \"{}\"",
+                index + 1,
+                total,
+                cmd_line,
+                stdout,
+                stderr,
+                command.stdout,
+                command.stderr
+            );
+
+            differing += 1;
+        }
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    if skipped > 0 {
+        warn!("{} command(s) holding replaced secrets were skipped", skipped);
+    }
+
+    if differing > 0 {
+        bail!(
+            "{} out of {} replayed command(s) differ from the recording",
+            differing,
+            total - skipped
+        );
+    }
+
+    info!("All {} replayed command(s) match the recording", total - skipped);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::*;
+
+    #[test]
+    fn redacts_secret_that_quoting_escapes() {
+        let secret = r#"pa"ss\"#;
+        let session = Session::new(vec![secret.to_owned()]);
+        let args = ["set", "app", "ObjectName", "app", secret];
+
+        let res = Err(ErrorKind::CommandTimeout {
+            cmd: cmdline::to_cmd_line("nssm.exe", &args),
+            timeout_secs: 1,
+        }.into());
+
+        session.record(Path::new("nssm.exe"), &args, None, &res);
+
+        let command = &session.commands()[0];
+        assert_eq!(command.args, vec!["set", "app", "ObjectName", "app", "<secret>"]);
+        assert!(!command.error.as_ref().unwrap().contains("pa"));
+    }
+}
@@ -0,0 +1,321 @@
+//! Manages services with shawl, which wraps the executable through its own command line in
+//! the service configuration. The shawl options are regenerated from the same parameters as
+//! used for nssm whenever a parameter is set, while the parameters of the service itself,
+//! e.g. the display name, are set with `sc`.
+
+use backend::ServiceBackend;
+use cmdline;
+use config::FileConfig;
+use errors::*;
+use output;
+use params::{self, ServiceParam};
+use sc;
+use scm;
+use state::ServiceState;
+use std::collections::BTreeMap;
+use std::path::Path;
+use super::{audit_cmd, log_dry_run_cmd, record_cmd, run_cmd};
+
+/// nssm parameters that map to a single shawl option.
+const OPTIONS: &[(&str, &str)] = &[
+    ("AppDirectory", "--cwd"),
+    ("AppRestartDelay", "--restart-delay"),
+    ("AppStopMethodConsole", "--stop-timeout"),
+    ("AppPriority", "--priority"),
+];
+
+/// nssm priority class names with the equivalent shawl priority names.
+const PRIORITIES: &[(&str, &str)] = &[
+    ("IDLE_PRIORITY_CLASS", "idle"),
+    ("BELOW_NORMAL_PRIORITY_CLASS", "below-normal"),
+    ("NORMAL_PRIORITY_CLASS", "normal"),
+    ("ABOVE_NORMAL_PRIORITY_CLASS", "above-normal"),
+    ("HIGH_PRIORITY_CLASS", "high"),
+    ("REALTIME_PRIORITY_CLASS", "realtime"),
+];
+
+/// nssm parameters of the service itself, which are set with `sc` instead of shawl options.
+const SERVICE_PARAMS: &[&str] = &["DisplayName", "Description", "DependOnService", "ObjectName"];
+
+/// Returns true if the nssm parameter can be set for services using the shawl backend.
+pub fn is_supported(param_name: &str) -> bool {
+    param_name == params::APPLICATION || param_name == "AppParameters"
+        || param_name == "AppEnvironmentExtra"
+        || OPTIONS.iter().any(|&(name, _)| name == param_name)
+        || SERVICE_PARAMS.contains(&param_name)
+}
+
+/// Manages the services with the configured shawl executable.
+pub struct Shawl;
+
+impl ServiceBackend for Shawl {
+    fn install(&self, service_name: &str, path: &Path, file_config: &FileConfig) -> Result<()> {
+        let path = path.to_string_lossy();
+        let args = ["add", "--name", service_name, "--", &path];
+        run_modify_cmd(shawl_path(file_config)?, &args, file_config)
+    }
+
+    fn set_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<()> {
+        if !is_supported(param.name) {
+            bail!("Parameter '{}' is not supported by the shawl backend", param.key());
+        }
+
+        let args = match param.name {
+            "DisplayName" => sc::config(service_name, &[("DisplayName", &param.value())]),
+            "Description" => sc::description(service_name, &param.value()),
+            "DependOnService" => {
+                // a single slash clears the dependencies
+                let deps = if param.values.is_empty() {
+                    "/".to_owned()
+                } else {
+                    param.values.join("/")
+                };
+
+                sc::config(service_name, &[("depend", &deps)])
+            }
+            "ObjectName" => {
+                let password = param.secret.as_deref().unwrap_or("");
+                sc::config(service_name, &[("obj", &param.value()), ("password", password)])
+            }
+            _ => {
+                let mut shawl_cmd = if file_config.is_dry_run() {
+                    // the service may not have been installed in dry run
+                    ShawlCmd::from_bin_path(&query_bin_path(service_name).unwrap_or_default())
+                } else {
+                    ShawlCmd::from_bin_path(&query_bin_path(service_name)?)
+                };
+
+                shawl_cmd.set(param.clone());
+
+                let bin_path = shawl_cmd.to_bin_path(shawl_path(file_config)?, service_name);
+                sc::config(service_name, &[("binPath", &bin_path)])
+            }
+        };
+
+        run_modify_cmd(sc::program(), &args, file_config)
+    }
+
+    fn get_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        _: &FileConfig,
+    ) -> Result<String> {
+        if !is_supported(param.name) {
+            bail!("Parameter '{}' is not supported by the shawl backend", param.key());
+        }
+
+        // unset parameters are empty like in nssm
+        let values = match param.name {
+            "Description" => query_fields(&sc::qdescription(service_name))?
+                .remove("DESCRIPTION")
+                .unwrap_or_default(),
+            "DisplayName" => query_fields(&sc::qc(service_name))?
+                .remove("DISPLAY_NAME")
+                .unwrap_or_default(),
+            "DependOnService" => query_fields(&sc::qc(service_name))?
+                .remove("DEPENDENCIES")
+                .unwrap_or_default(),
+            "ObjectName" => query_fields(&sc::qc(service_name))?
+                .remove("SERVICE_START_NAME")
+                .unwrap_or_default(),
+            _ => ShawlCmd::from_bin_path(&query_bin_path(service_name)?)
+                .params
+                .into_iter()
+                .find(|installed| installed.key() == param.key())
+                .map(|installed| installed.values)
+                .unwrap_or_default(),
+        };
+
+        Ok(values.join("\n"))
+    }
+
+    fn status(&self, service_name: &str, file_config: &FileConfig) -> Result<ServiceState> {
+        scm::query_state(service_name, file_config)
+    }
+
+    fn start(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        scm::start(service_name, file_config)
+    }
+
+    fn stop(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        scm::stop(service_name, file_config)
+    }
+
+    fn kill_tree(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        scm::kill_tree(service_name, file_config)
+    }
+
+    fn remove(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_modify_cmd(sc::program(), &sc::delete(service_name), file_config)
+    }
+}
+
+fn shawl_path(file_config: &FileConfig) -> Result<&Path> {
+    file_config
+        .shawl_path.as_deref()
+        .ok_or_else(|| {
+            "Unable to use the shawl backend without shawl_path in the configuration".into()
+        })
+}
+
+fn run_modify_cmd<S: AsRef<str>>(
+    program: &Path,
+    args: &[S],
+    file_config: &FileConfig,
+) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run_cmd(file_config, &program.to_string_lossy(), args);
+
+        return Ok(());
+    }
+
+    let res = run_cmd(program, args);
+    audit_cmd(file_config, program, args, &res);
+    record_cmd(file_config, program, args, &res);
+    res.map(|_| ())
+}
+
+fn query_fields(args: &[String]) -> Result<BTreeMap<String, Vec<String>>> {
+    let output = run_cmd(sc::program(), args)?;
+    Ok(sc::parse_fields(&output::decode(&output.stdout)))
+}
+
+fn query_bin_path(service_name: &str) -> Result<String> {
+    query_fields(&sc::qc(service_name))?
+        .remove("BINARY_PATH_NAME")
+        .and_then(|values| values.into_iter().next())
+        .ok_or_else(|| format!("Unable to find command line of service '{}'", service_name).into())
+}
+
+fn param(name: &'static str, values: Vec<String>) -> ServiceParam {
+    ServiceParam {
+        name,
+        sub: None,
+        values,
+        secret: None,
+    }
+}
+
+/// Holds the shawl command line of a service, with the values in the same format as the
+/// nssm parameters.
+struct ShawlCmd {
+    params: Vec<ServiceParam>,
+}
+
+impl ShawlCmd {
+    /// Parses the shawl command line, e.g. `shawl.exe run --name x --cwd C:\ -- app.exe`.
+    fn from_bin_path(bin_path: &str) -> ShawlCmd {
+        let mut shawl_cmd = ShawlCmd { params: Vec::new() };
+        let mut args = cmdline::split_args(bin_path).into_iter().skip(2);
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                break;
+            }
+
+            let value = match args.next() {
+                Some(value) => value,
+                None => break,
+            };
+
+            if arg == "--env" {
+                shawl_cmd.push_value("AppEnvironmentExtra", value);
+            } else if let Some(&(name, _)) = OPTIONS.iter().find(|&&(_, option)| option == arg) {
+                let value = if name == "AppPriority" {
+                    PRIORITIES
+                        .iter()
+                        .find(|&&(_, shawl_value)| shawl_value == value)
+                        .map(|&(nssm_value, _)| nssm_value.to_owned())
+                        .unwrap_or(value)
+                } else {
+                    value
+                };
+
+                shawl_cmd.set(param(name, vec![value]));
+            }
+        }
+
+        if let Some(application) = args.next() {
+            shawl_cmd.set(param(params::APPLICATION, vec![application]));
+        }
+
+        let app_args: Vec<String> = args.collect();
+
+        if !app_args.is_empty() {
+            shawl_cmd.set(param("AppParameters", vec![cmdline::join_args(app_args)]));
+        }
+
+        shawl_cmd
+    }
+
+    fn to_bin_path(&self, shawl_path: &Path, service_name: &str) -> String {
+        let mut args = vec![
+            shawl_path.to_string_lossy().into_owned(),
+            "run".to_owned(),
+            "--name".to_owned(),
+            service_name.to_owned(),
+        ];
+
+        for &(name, option) in OPTIONS {
+            if let Some(value) = self.values(name).first() {
+                let value = if name == "AppPriority" {
+                    PRIORITIES
+                        .iter()
+                        .find(|&&(nssm_value, _)| nssm_value == value)
+                        .map(|&(_, shawl_value)| shawl_value.to_owned())
+                        .unwrap_or_else(|| value.clone())
+                } else {
+                    value.clone()
+                };
+
+                args.push(option.to_owned());
+                args.push(value);
+            }
+        }
+
+        for env in self.values("AppEnvironmentExtra") {
+            args.push("--env".to_owned());
+            args.push(env.clone());
+        }
+
+        args.push("--".to_owned());
+        args.extend(self.values(params::APPLICATION).iter().cloned());
+
+        for app_args in self.values("AppParameters") {
+            args.extend(cmdline::split_args(app_args));
+        }
+
+        cmdline::join_args(args)
+    }
+
+    fn set(&mut self, param: ServiceParam) {
+        match self.params
+            .iter()
+            .position(|existing| existing.key() == param.key())
+        {
+            Some(index) => self.params[index] = param,
+            None => self.params.push(param),
+        }
+    }
+
+    fn push_value(&mut self, name: &'static str, value: String) {
+        match self.params.iter().position(|existing| existing.name == name) {
+            Some(index) => self.params[index].values.push(value),
+            None => self.params.push(param(name, vec![value])),
+        }
+    }
+
+    fn values(&self, name: &str) -> &[String] {
+        self.params
+            .iter()
+            .find(|param| param.name == name)
+            .map(|param| param.values.as_slice())
+            .unwrap_or(&[])
+    }
+}
@@ -0,0 +1,302 @@
+//! Takes snapshots of the installed nssm parameters of services, so that a service can be
+//! reinstalled with its previous parameters, e.g. when replacing it has failed partway.
+
+use backend;
+use config::{self, FileConfig};
+use errors::*;
+use file;
+use params::{self, ServiceParam};
+use state::ServiceState;
+use std::path::Path;
+use toml;
+use super::{record_action, set_service_param, ChainService};
+
+/// nssm parameters without any subparameter that are captured in the snapshot,
+/// in the order they are restored.
+const PARAMS: &[&str] = &[
+    params::APPLICATION,
+    "AppDirectory",
+    "AppParameters",
+    "DisplayName",
+    "Description",
+    "Start",
+    "Type",
+    "AppStdout",
+    "AppStderr",
+    "AppRotateFiles",
+    "AppRotateOnline",
+    "AppRotateSeconds",
+    "AppRotateBytes",
+    "AppPriority",
+    "AppNoConsole",
+    "AppThrottle",
+    "AppRestartDelay",
+    "AppStopMethodSkip",
+    "AppStopMethodConsole",
+    "AppStopMethodWindow",
+    "AppStopMethodThreads",
+    "AppKillProcessTree",
+    "AppEnvironmentExtra",
+    "DependOnService",
+    "DependOnGroup",
+    "ObjectName",
+];
+
+/// nssm event hooks that are captured in the snapshot as `AppEvents` subparameters.
+const EVENTS: &[&str] = &[
+    "Start/Pre",
+    "Start/Post",
+    "Stop/Pre",
+    "Exit/Post",
+    "Rotate/Pre",
+    "Rotate/Post",
+    "Power/Change",
+    "Power/Resume",
+];
+
+/// Holds the installed nssm parameters and state of a service at some point in time.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// Name of service.
+    pub service_name: String,
+
+    /// State of the service when the snapshot was taken.
+    pub state: ServiceState,
+
+    /// Installed parameters that have any value.
+    pub params: Vec<ServiceParam>,
+}
+
+impl Snapshot {
+    /// Returns true if the service was running when the snapshot was taken.
+    pub fn was_running(&self) -> bool {
+        self.state == ServiceState::Running
+    }
+}
+
+/// Takes a snapshot of the installed parameters of the service. Parameters that cannot be
+/// queried, e.g. those not supported by the installed nssm version, are left out.
+pub fn take(service_name: &str, state: ServiceState, file_config: &FileConfig) -> Snapshot {
+    let keys = PARAMS
+        .iter()
+        .map(|&name| (name, None))
+        .chain(Some(("AppExit", Some("Default"))))
+        .chain(EVENTS.iter().map(|&event| ("AppEvents", Some(event))));
+
+    let params = keys.filter_map(|(name, sub)| {
+        match params::installed_as_param(service_name, name, sub, file_config) {
+            Ok(param) => Some(param),
+            Err(e) => {
+                debug!(
+                    "Unable to snapshot parameter '{}' of service '{}': {}",
+                    name,
+                    service_name,
+                    e
+                );
+
+                None
+            }
+        }
+    }).filter(|param| param.values.iter().any(|value| !value.is_empty()))
+        .collect();
+
+    Snapshot {
+        service_name: service_name.to_owned(),
+        state,
+        params,
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct BackupFile {
+    services: Vec<BackupService>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct BackupService {
+    name: String,
+    state: ServiceState,
+    params: Vec<BackupParam>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct BackupParam {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+    values: Vec<String>,
+}
+
+/// Writes the snapshots into the TOML backup file at the given path.
+pub fn write_backup<P: AsRef<Path>>(path: P, snapshots: &[Snapshot]) -> Result<()> {
+    let path = path.as_ref();
+
+    let backup_file = BackupFile {
+        services: snapshots
+            .iter()
+            .map(|snapshot| BackupService {
+                name: snapshot.service_name.clone(),
+                state: snapshot.state,
+                params: snapshot
+                    .params
+                    .iter()
+                    .map(|param| BackupParam {
+                        name: param.name.to_owned(),
+                        sub: param.sub.clone(),
+                        values: param.values.clone(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    let content = toml::to_string(&backup_file)
+        .chain_err(|| "Unable to serialize snapshots into TOML")?;
+
+    file::put_text(path, content).chain_err(|| {
+        format!(
+            "Unable to write backup file at '{}'",
+            path.to_string_lossy()
+        )
+    })
+}
+
+/// Reads the snapshots from the TOML backup file at the given path.
+pub fn read_backup<P: AsRef<Path>>(path: P) -> Result<Vec<Snapshot>> {
+    let path = path.as_ref();
+
+    let content = file::get_text(path).chain_err(|| {
+        format!("Unable to read backup file at '{}'", path.to_string_lossy())
+    })?;
+
+    let backup_file: BackupFile = toml::from_str(&content).chain_err(|| {
+        format!(
+            "Unable to interpret backup file content at '{}' as TOML",
+            path.to_string_lossy()
+        )
+    })?;
+
+    backup_file
+        .services
+        .into_iter()
+        .map(|service| {
+            let service_name = service.name;
+
+            let params = service
+                .params
+                .into_iter()
+                .map(|param| {
+                    let name = known_param_name(&param.name).ok_or_else(|| {
+                        format!(
+                            "Unable to restore unknown parameter '{}' of service '{}'",
+                            param.name,
+                            service_name
+                        )
+                    })?;
+
+                    Ok(ServiceParam {
+                        name,
+                        sub: param.sub,
+                        values: param.values,
+                        secret: None,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(Snapshot {
+                service_name,
+                state: service.state,
+                params,
+            })
+        })
+        .collect()
+}
+
+fn known_param_name(name: &str) -> Option<&'static str> {
+    PARAMS
+        .iter()
+        .chain(&["AppExit", "AppEvents"])
+        .find(|known_name| **known_name == name)
+        .copied()
+}
+
+/// Returns true if both names refer to the same account, ignoring the case and the local
+/// machine prefix `.\` that nssm reports for local accounts.
+fn is_same_account(lhs: &str, rhs: &str) -> bool {
+    let strip = |name: &str| name.trim_start_matches(".\\").to_lowercase();
+    strip(lhs) == strip(rhs)
+}
+
+/// Returns the password to restore the account of the service with, or `None` if the
+/// account takes no password. The password is never captured, so it is only known if the
+/// same account is still configured for the service.
+fn account_secret(
+    service_name: &str,
+    object_name: &str,
+    file_config: &FileConfig,
+) -> Result<Option<String>> {
+    if config::is_passwordless_account(object_name) {
+        return Ok(None);
+    }
+
+    let account = file_config
+        .services
+        .iter()
+        .find(|service| service.name == service_name)
+        .and_then(|service| file_config.merged_other(service).account)
+        .filter(|account| is_same_account(&account.object_name(service_name), object_name));
+
+    match account {
+        Some(account) => Ok(Some(account.password.clone())),
+        None => bail!(
+            "Unable to restore account '{}' of service '{}' without its password, leaving \
+             the service stopped",
+            object_name,
+            service_name
+        ),
+    }
+}
+
+/// Installs the service again with the parameters in the snapshot, without starting it.
+/// The service must not be installed. The account password is never captured, so an
+/// account other than the built-in and virtual ones is only restored with the password of
+/// the configured account if it is the same account, and fails otherwise, so that the
+/// service never runs under `LocalSystem` instead.
+pub fn restore(snapshot: &Snapshot, file_config: &FileConfig) -> Result<()> {
+    let service_name = &snapshot.service_name;
+
+    let application = snapshot
+        .params
+        .iter()
+        .find(|param| param.name == params::APPLICATION)
+        .ok_or_else(|| {
+            format!(
+                "Unable to find application path in snapshot of service '{}'",
+                service_name
+            )
+        })?;
+
+    backend::install(service_name, Path::new(&application.value()), file_config)
+        .chain_service_msg("Unable to reinstall", service_name)?;
+
+    record_action(file_config, service_name, "installed".to_owned());
+
+    for param in snapshot
+        .params
+        .iter()
+        .filter(|param| param.name != params::APPLICATION)
+    {
+        if param.name == "ObjectName" {
+            let param = ServiceParam {
+                secret: account_secret(service_name, &param.value(), file_config)?,
+                ..param.clone()
+            };
+
+            set_service_param(service_name, &param, file_config)?;
+        } else {
+            set_service_param(service_name, param, file_config)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use errors::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ServiceState {
+    /// SERVICE_CONTINUE_PENDING (0x00000005)
+    /// The service continue is pending.
+    ContinuePending,
+
+    /// SERVICE_PAUSE_PENDING (0x00000006)
+    /// The service pause is pending.
+    PausePending,
+
+    /// SERVICE_PAUSED (0x00000007)
+    /// The service is paused.
+    Paused,
+
+    /// SERVICE_RUNNING (0x00000004)
+    /// The service is running.
+    Running,
+
+    /// SERVICE_START_PENDING (0x00000002)
+    /// The service is starting.
+    StartPending,
+
+    /// SERVICE_STOP_PENDING (0x00000003)
+    /// The service is stopping.
+    StopPending,
+
+    /// SERVICE_STOPPED (0x00000001)
+    /// The service is not running.
+    Stopped,
+}
+
+lazy_static! {
+    static ref STATE_MAP: HashMap<&'static str, ServiceState> = {
+        let mut m = HashMap::new();
+        m.insert("SERVICE_CONTINUE_PENDING", ServiceState::ContinuePending);
+        m.insert("SERVICE_PAUSE_PENDING", ServiceState::PausePending);
+        m.insert("SERVICE_PAUSED", ServiceState::Paused);
+        m.insert("SERVICE_RUNNING", ServiceState::Running);
+        m.insert("SERVICE_START_PENDING", ServiceState::StartPending);
+        m.insert("SERVICE_STOP_PENDING", ServiceState::StopPending);
+        m.insert("SERVICE_STOPPED", ServiceState::Stopped);
+        m
+    };
+}
+
+/// Converts the nssm status string (e.g. `SERVICE_RUNNING`) into the service state.
+pub fn state_from_str(status: &str) -> Result<ServiceState> {
+    let state = STATE_MAP
+        .get(status)
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "Unable to obtain valid state from status string '{}'",
+                status
+            )
+        })?;
+
+    Ok(state)
+}
@@ -0,0 +1,97 @@
+//! Finds the keys of the configuration files that no setting matches, which serde otherwise
+//! ignores silently, e.g. the typo `start_on_creat`, and suggests the nearest known setting
+//! for each of them.
+
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer, Visitor};
+use serde_ignored::{self, Path};
+use serde_yaml;
+use strsim;
+use toml;
+
+/// Deserializer that only records the field names of the struct deserialized from it.
+struct FieldsProbe<'a>(&'a mut &'static [&'static str]);
+
+impl<'de, 'a> Deserializer<'de> for FieldsProbe<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("only structs are probed"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("fields probed"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Returns the names of the fields of the struct, or none if it is not a struct.
+pub fn fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldsProbe(&mut fields));
+    fields
+}
+
+/// Returns the dotted key of the path, e.g. `services.0.other.start_on_create`, without the
+/// segments of the optional values.
+fn key(path: &Path) -> String {
+    match *path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => join(parent, &index.to_string()),
+        Path::Map { parent, ref key } => join(parent, key),
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => key(parent),
+    }
+}
+
+fn join(parent: &Path, segment: &str) -> String {
+    match key(parent) {
+        ref parent_key if parent_key.is_empty() => segment.to_owned(),
+        parent_key => format!("{}.{}", parent_key, segment),
+    }
+}
+
+/// Parses the TOML content, appending the dotted key of every key that no setting matches.
+pub fn from_toml_str<T: DeserializeOwned>(
+    s: &str,
+    unknown_keys: &mut Vec<String>,
+) -> Result<T, toml::de::Error> {
+    let mut deserializer = toml::de::Deserializer::new(s);
+    let value =
+        serde_ignored::deserialize(&mut deserializer, |path| unknown_keys.push(key(&path)))?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Parses the YAML content, appending the dotted key of every key that no setting matches.
+pub fn from_yaml_str<T: DeserializeOwned>(
+    s: &str,
+    unknown_keys: &mut Vec<String>,
+) -> Result<T, serde_yaml::Error> {
+    let value: serde_yaml::Value = serde_yaml::from_str(s)?;
+    serde_ignored::deserialize(value, |path| unknown_keys.push(key(&path)))
+}
+
+/// Returns the candidate nearest to the last segment of the dotted key, if it is near enough
+/// to be a typo of it.
+pub fn suggest<'a>(key: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let name = key.rsplit('.').next().unwrap_or(key);
+    let max_distance = ::std::cmp::max(2, name.len() / 3);
+
+    candidates
+        .iter()
+        .map(|candidate| (strsim::levenshtein(name, candidate), *candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
@@ -0,0 +1,551 @@
+//! Manages services on Linux with systemd, where every service has a unit file in the systemd
+//! unit directory. The unit file is generated from the same parameters as used for nssm, and
+//! is overwritten whenever a parameter is set, while the service is controlled with
+//! `systemctl`.
+
+use backend::ServiceBackend;
+use config::FileConfig;
+use errors::*;
+use file;
+use params::{self, ServiceParam};
+use state::ServiceState;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use super::{audit_cmd, log_dry_run, log_dry_run_cmd, record_cmd, run_cmd};
+
+/// Directory holding the unit files if not configured.
+pub const DEFAULT_UNIT_DIR: &str = "/etc/systemd/system";
+
+/// nssm parameters that map to a single unit setting, as the section and the key.
+const SETTINGS: &[(&str, &str, &str)] = &[
+    ("Description", "Unit", "Description"),
+    ("AppDirectory", "Service", "WorkingDirectory"),
+    ("ObjectName", "Service", "User"),
+];
+
+/// nssm parameters holding a log file path, with the unit setting that appends to the file.
+const OUTPUTS: &[(&str, &str)] = &[("AppStdout", "StandardOutput"), ("AppStderr", "StandardError")];
+
+/// nssm parameters holding milliseconds, with the unit setting of the same duration.
+const DURATIONS: &[(&str, &str)] = &[
+    ("AppRestartDelay", "RestartSec"),
+    ("AppStopMethodConsole", "TimeoutStopSec"),
+];
+
+/// nssm priority class names with the equivalent nice values.
+const PRIORITIES: &[(&str, &str)] = &[
+    ("IDLE_PRIORITY_CLASS", "19"),
+    ("BELOW_NORMAL_PRIORITY_CLASS", "10"),
+    ("NORMAL_PRIORITY_CLASS", "0"),
+    ("ABOVE_NORMAL_PRIORITY_CLASS", "-5"),
+    ("HIGH_PRIORITY_CLASS", "-10"),
+    ("REALTIME_PRIORITY_CLASS", "-20"),
+];
+
+/// Returns true if the nssm parameter can be set for services using the systemd backend.
+pub fn is_supported(param_name: &str) -> bool {
+    SETTINGS.iter().any(|&(name, _, _)| name == param_name)
+        || OUTPUTS.iter().any(|&(name, _)| name == param_name)
+        || DURATIONS.iter().any(|&(name, _)| name == param_name)
+        || matches!(
+            param_name,
+            "Application" | "AppParameters" | "AppEnvironmentExtra" | "DependOnService"
+                | "AppExit" | "AppPriority"
+        )
+}
+
+/// Manages the services with unit files and `systemctl`.
+pub struct Systemd;
+
+impl ServiceBackend for Systemd {
+    fn install(&self, service_name: &str, path: &Path, file_config: &FileConfig) -> Result<()> {
+        let mut unit = SystemdUnit::default();
+        unit.set(param(params::APPLICATION, vec![path.to_string_lossy().into_owned()]));
+        write_unit(service_name, &unit, file_config)?;
+
+        run_modify_cmd(&["enable", &unit_name(service_name)], file_config)
+    }
+
+    fn set_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<()> {
+        if !is_supported(param.name) {
+            bail!("Parameter '{}' is not supported by the systemd backend", param.key());
+        }
+
+        if param.name == "AppExit" && param.value() != "Restart" && param.value() != "Exit" {
+            bail!(
+                "Exit action '{}' is not supported by the systemd backend",
+                param.value()
+            );
+        }
+
+        if file_config.is_dry_run() {
+            log_dry_run(
+                file_config,
+                &format!(
+                    "Set '{}' of service '{}' to '{}' in its systemd unit",
+                    param.key(),
+                    service_name,
+                    param.value(),
+                ),
+            );
+
+            return Ok(());
+        }
+
+        let mut unit = read_unit(service_name, file_config)?;
+
+        // the password is not needed to run as the user
+        unit.set(ServiceParam {
+            secret: None,
+            ..param.clone()
+        });
+
+        write_unit(service_name, &unit, file_config)
+    }
+
+    fn get_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<String> {
+        if !is_supported(param.name) {
+            bail!("Parameter '{}' is not supported by the systemd backend", param.key());
+        }
+
+        let unit = read_unit(service_name, file_config)?;
+
+        // unset parameters are empty like in nssm
+        let value = unit.params
+            .iter()
+            .find(|installed| installed.key() == param.key())
+            .map(|installed| installed.values.join("\n"))
+            .unwrap_or_default();
+
+        Ok(value)
+    }
+
+    fn status(&self, service_name: &str, _: &FileConfig) -> Result<ServiceState> {
+        let output = run_systemctl(&[
+            "show",
+            &unit_name(service_name),
+            "--property=LoadState",
+            "--property=ActiveState",
+        ])?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut load_state = "";
+        let mut active_state = "";
+
+        for line in stdout.lines() {
+            if let Some(state) = line.strip_prefix("LoadState=") {
+                load_state = state;
+            } else if let Some(state) = line.strip_prefix("ActiveState=") {
+                active_state = state;
+            }
+        }
+
+        if load_state == "not-found" {
+            bail!("Service '{}' is not installed with systemd", service_name);
+        }
+
+        match active_state {
+            "active" | "reloading" => Ok(ServiceState::Running),
+            "activating" => Ok(ServiceState::StartPending),
+            "deactivating" => Ok(ServiceState::StopPending),
+            "inactive" | "failed" => Ok(ServiceState::Stopped),
+            state => bail!("Unable to obtain valid state from systemd state '{}'", state),
+        }
+    }
+
+    fn start(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_modify_cmd(&["start", "--no-block", &unit_name(service_name)], file_config)
+    }
+
+    fn stop(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_modify_cmd(&["stop", "--no-block", &unit_name(service_name)], file_config)
+    }
+
+    fn kill_tree(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        // every process of the unit is in its control group
+        run_modify_cmd(
+            &["kill", "--signal=SIGKILL", &unit_name(service_name)],
+            file_config,
+        )
+    }
+
+    fn remove(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_modify_cmd(&["disable", &unit_name(service_name)], file_config)?;
+
+        let unit_path = unit_path(service_name, file_config);
+
+        if file_config.is_dry_run() {
+            log_dry_run(
+                file_config,
+                &format!("Remove systemd unit '{}'", unit_path.to_string_lossy()),
+            );
+        } else {
+            fs::remove_file(&unit_path).chain_err(|| {
+                format!(
+                    "Unable to remove systemd unit at '{}'",
+                    unit_path.to_string_lossy()
+                )
+            })?;
+        }
+
+        run_modify_cmd(&["daemon-reload"], file_config)
+    }
+}
+
+fn unit_name(service_name: &str) -> String {
+    format!("{}.service", service_name)
+}
+
+fn unit_path(service_name: &str, file_config: &FileConfig) -> PathBuf {
+    file_config
+        .systemd_unit_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_UNIT_DIR))
+        .join(unit_name(service_name))
+}
+
+fn run_modify_cmd(args: &[&str], file_config: &FileConfig) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run_cmd(file_config, "systemctl", args);
+        return Ok(());
+    }
+
+    let res = run_systemctl(args);
+    audit_cmd(file_config, Path::new("systemctl"), args, &res);
+    record_cmd(file_config, Path::new("systemctl"), args, &res);
+    res.map(|_| ())
+}
+
+fn run_systemctl(args: &[&str]) -> Result<Output> {
+    run_cmd(Path::new("systemctl"), args)
+}
+
+fn param(name: &'static str, values: Vec<String>) -> ServiceParam {
+    ServiceParam {
+        name,
+        sub: None,
+        values,
+        secret: None,
+    }
+}
+
+/// Escapes the value for a unit file, where `%` starts a specifier.
+fn escape(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("%%", "%")
+}
+
+/// Quotes the value as a single word of a unit setting if needed.
+fn quote(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\')
+    {
+        return escape(value);
+    }
+
+    format!(
+        "\"{}\"",
+        escape(&value.replace('\\', "\\\\").replace('"', "\\\""))
+    )
+}
+
+/// Splits the setting value into its first word, unquoted, and the rest of the value.
+fn split_first_word(value: &str) -> (String, &str) {
+    let value = value.trim_start();
+
+    if !value.starts_with('"') {
+        let word_end = value.find(char::is_whitespace).unwrap_or(value.len());
+        return (unescape(&value[..word_end]), value[word_end..].trim_start());
+    }
+
+    let mut word = String::new();
+    let mut chars = value.char_indices().skip(1);
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => if let Some((_, escaped)) = chars.next() {
+                word.push(escaped);
+            },
+            '"' => return (unescape(&word), value[index + 1..].trim_start()),
+            c => word.push(c),
+        }
+    }
+
+    (unescape(&word), "")
+}
+
+/// Holds the content of the unit file of a service, with the values in the same format as
+/// the nssm parameters.
+#[derive(Default)]
+struct SystemdUnit {
+    params: Vec<ServiceParam>,
+}
+
+impl SystemdUnit {
+    fn set(&mut self, param: ServiceParam) {
+        match self.params
+            .iter()
+            .position(|existing| existing.key() == param.key())
+        {
+            Some(index) => self.params[index] = param,
+            None => self.params.push(param),
+        }
+    }
+
+    fn values(&self, key: &str) -> &[String] {
+        self.params
+            .iter()
+            .find(|param| param.key() == key)
+            .map(|param| param.values.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns the exit codes with the given action, as set through `AppExit <code>`.
+    fn exit_codes(&self, action: &str) -> Vec<String> {
+        self.params
+            .iter()
+            .filter(|param| param.name == "AppExit" && param.value() == action)
+            .filter_map(|param| param.sub.clone())
+            .filter(|code| code != "Default")
+            .collect()
+    }
+
+    fn to_unit(&self, service_name: &str) -> String {
+        let mut unit_lines = vec![
+            format!("# Generated by nssm_exec for service '{}'", service_name),
+            "[Unit]".to_owned(),
+        ];
+
+        let mut service_lines = vec!["[Service]".to_owned()];
+
+        if let Some(application) = self.values(params::APPLICATION).first() {
+            let mut exec_start = quote(application);
+
+            if let Some(app_args) = self.values("AppParameters").first() {
+                if !app_args.is_empty() {
+                    exec_start.push(' ');
+                    exec_start.push_str(&escape(app_args));
+                }
+            }
+
+            service_lines.push(format!("ExecStart={}", exec_start));
+        }
+
+        for &(name, section, key) in SETTINGS {
+            if let Some(value) = self.values(name).first() {
+                let lines = if section == "Unit" {
+                    &mut unit_lines
+                } else {
+                    &mut service_lines
+                };
+
+                lines.push(format!("{}={}", key, escape(value)));
+            }
+        }
+
+        let deps: Vec<String> = self.values("DependOnService")
+            .iter()
+            .map(|dep| unit_name(dep))
+            .collect();
+
+        if !deps.is_empty() {
+            unit_lines.push(format!("Requires={}", deps.join(" ")));
+            unit_lines.push(format!("After={}", deps.join(" ")));
+        }
+
+        for env in self.values("AppEnvironmentExtra") {
+            service_lines.push(format!("Environment={}", quote(env)));
+        }
+
+        for &(name, key) in OUTPUTS {
+            if let Some(path) = self.values(name).first() {
+                service_lines.push(format!("{}=append:{}", key, escape(path)));
+            }
+        }
+
+        // nssm restarts the executable by default
+        let restart = match self.values("AppExit Default").first() {
+            Some(action) if action == "Exit" => "no",
+            _ => "always",
+        };
+
+        service_lines.push(format!("Restart={}", restart));
+
+        let prevent_codes = self.exit_codes("Exit");
+
+        if !prevent_codes.is_empty() {
+            service_lines.push(format!("RestartPreventExitStatus={}", prevent_codes.join(" ")));
+        }
+
+        let force_codes = self.exit_codes("Restart");
+
+        if !force_codes.is_empty() {
+            service_lines.push(format!("RestartForceExitStatus={}", force_codes.join(" ")));
+        }
+
+        for &(name, key) in DURATIONS {
+            if let Some(ms) = self.values(name).first() {
+                service_lines.push(format!("{}={}ms", key, ms));
+            }
+        }
+
+        if let Some(priority) = self.values("AppPriority").first() {
+            let nice = PRIORITIES
+                .iter()
+                .find(|&&(nssm_value, _)| nssm_value == priority)
+                .map(|&(_, nice)| nice)
+                .unwrap_or("0");
+
+            service_lines.push(format!("Nice={}", nice));
+        }
+
+        let mut lines = unit_lines;
+        lines.push(String::new());
+        lines.extend(service_lines);
+        lines.push(String::new());
+        lines.push("[Install]".to_owned());
+        lines.push("WantedBy=multi-user.target".to_owned());
+        lines.join("\n") + "\n"
+    }
+
+    /// Parses the unit file as written by `to_unit`, ignoring unknown lines.
+    fn from_unit(content: &str) -> SystemdUnit {
+        let mut unit = SystemdUnit::default();
+
+        for line in content.lines().map(|line| line.trim()) {
+            let separator_index = match line.find('=') {
+                Some(separator_index) if !line.starts_with('#') => separator_index,
+                _ => continue,
+            };
+
+            let key = &line[..separator_index];
+            let value = &line[separator_index + 1..];
+
+            match key {
+                "ExecStart" => {
+                    let (application, app_args) = split_first_word(value);
+                    unit.set(param(params::APPLICATION, vec![application]));
+
+                    if !app_args.is_empty() {
+                        unit.set(param("AppParameters", vec![unescape(app_args)]));
+                    }
+                }
+
+                "Requires" => {
+                    let deps = value
+                        .split_whitespace()
+                        .map(|dep| dep.trim_end_matches(".service").to_owned())
+                        .collect();
+
+                    unit.set(param("DependOnService", deps));
+                }
+
+                "Environment" => {
+                    let env = split_first_word(value).0;
+
+                    let mut envs = unit.values("AppEnvironmentExtra").to_vec();
+                    envs.push(env);
+                    unit.set(param("AppEnvironmentExtra", envs));
+                }
+
+                "Restart" => {
+                    let action = if value == "no" { "Exit" } else { "Restart" };
+
+                    unit.set(ServiceParam {
+                        sub: Some("Default".to_owned()),
+                        ..param("AppExit", vec![action.to_owned()])
+                    });
+                }
+
+                "RestartPreventExitStatus" | "RestartForceExitStatus" => {
+                    let action = if key == "RestartPreventExitStatus" {
+                        "Exit"
+                    } else {
+                        "Restart"
+                    };
+
+                    for code in value.split_whitespace() {
+                        unit.set(ServiceParam {
+                            sub: Some(code.to_owned()),
+                            ..param("AppExit", vec![action.to_owned()])
+                        });
+                    }
+                }
+
+                "Nice" => {
+                    let priority = PRIORITIES
+                        .iter()
+                        .find(|&&(_, nice)| nice == value)
+                        .map(|&(nssm_value, _)| nssm_value)
+                        .unwrap_or("NORMAL_PRIORITY_CLASS");
+
+                    unit.set(param("AppPriority", vec![priority.to_owned()]));
+                }
+
+                key => {
+                    let setting = SETTINGS.iter().find(|&&(_, _, setting)| setting == key);
+                    let output = OUTPUTS.iter().find(|&&(_, output)| output == key);
+                    let duration = DURATIONS.iter().find(|&&(_, duration)| duration == key);
+
+                    if let Some(&(name, _, _)) = setting {
+                        unit.set(param(name, vec![unescape(value)]));
+                    } else if let Some(&(name, _)) = output {
+                        let path = value.trim_start_matches("append:");
+                        unit.set(param(name, vec![unescape(path)]));
+                    } else if let Some(&(name, _)) = duration {
+                        unit.set(param(name, vec![value.trim_end_matches("ms").to_owned()]));
+                    }
+                }
+            }
+        }
+
+        unit
+    }
+}
+
+fn read_unit(service_name: &str, file_config: &FileConfig) -> Result<SystemdUnit> {
+    let unit_path = unit_path(service_name, file_config);
+
+    let content = file::get_text(&unit_path).chain_err(|| {
+        format!(
+            "Unable to read systemd unit at '{}'",
+            unit_path.to_string_lossy()
+        )
+    })?;
+
+    Ok(SystemdUnit::from_unit(&content))
+}
+
+/// Writes the unit file and reloads systemd so that the changes take effect on the next start.
+fn write_unit(service_name: &str, unit: &SystemdUnit, file_config: &FileConfig) -> Result<()> {
+    let unit_path = unit_path(service_name, file_config);
+
+    if file_config.is_dry_run() {
+        log_dry_run(
+            file_config,
+            &format!("Write systemd unit '{}'", unit_path.to_string_lossy()),
+        );
+    } else {
+        file::put_text(&unit_path, unit.to_unit(service_name)).chain_err(|| {
+            format!(
+                "Unable to write systemd unit at '{}'",
+                unit_path.to_string_lossy()
+            )
+        })?;
+    }
+
+    run_modify_cmd(&["daemon-reload"], file_config)
+}
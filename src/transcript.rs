@@ -0,0 +1,123 @@
+//! Records the commands that a dry run would have run in order, so that the planned changes
+//! can be reviewed or run later as a transcript file, with the secrets replaced.
+
+use cmdline;
+use errors::*;
+use file;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Placeholder written into the transcript in place of every secret.
+pub const SECRET_PLACEHOLDER: &str = "<secret>";
+
+/// Replaces every occurrence of the secrets in the value, including their quoted forms in the
+/// command lines that the value may hold, e.g. in the message of a failed command.
+pub fn redact(value: &str, secrets: &[String]) -> String {
+    secrets.iter().fold(value.to_owned(), |value, secret| {
+        value
+            .replace(cmdline::quote_arg(secret).as_ref(), SECRET_PLACEHOLDER)
+            .replace(secret.as_str(), SECRET_PLACEHOLDER)
+    })
+}
+
+/// Replaces every occurrence of the secrets in each of the arguments. The arguments must be
+/// redacted before they are quoted into a command line, since quoting escapes the double
+/// quotes and backslashes in the secrets, after which the secrets no longer match.
+pub fn redact_args<S: AsRef<str>>(args: &[S], secrets: &[String]) -> Vec<String> {
+    args.iter()
+        .map(|arg| {
+            secrets.iter().fold(arg.as_ref().to_owned(), |arg, secret| {
+                arg.replace(secret.as_str(), SECRET_PLACEHOLDER)
+            })
+        })
+        .collect()
+}
+
+/// Collects the commands of a dry run, which may be shared across concurrent workers.
+#[derive(Debug, Default)]
+pub struct Transcript {
+    lines: Mutex<Vec<String>>,
+    secrets: Vec<String>,
+}
+
+impl Transcript {
+    /// Creates a transcript that replaces every occurrence of the given secrets.
+    pub fn new(secrets: Vec<String>) -> Transcript {
+        Transcript {
+            lines: Mutex::new(Vec::new()),
+            secrets: secrets
+                .into_iter()
+                .filter(|secret| !secret.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Returns the arguments with the secrets replaced, to be quoted into a command line.
+    pub fn redact_args<S: AsRef<str>>(&self, args: &[S]) -> Vec<String> {
+        redact_args(args, &self.secrets)
+    }
+
+    /// Records the command after replacing the secrets in it. The arguments of a command line
+    /// must have been redacted with `redact_args` before quoting.
+    pub fn record(&self, line: &str) {
+        self.lines.lock().unwrap().push(redact(line, &self.secrets));
+    }
+
+    /// Returns the commands recorded so far in order.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().clone()
+    }
+
+    /// Writes the commands into the file at the given path, one command per line.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        let content: String = self.lines()
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        file::put_text(path, content).chain_err(|| {
+            format!("Unable to write transcript at '{}'", path.to_string_lossy())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cmdline;
+    use super::*;
+
+    const SECRET: &str = r#"pa"ss\"#;
+
+    #[test]
+    fn redacts_secret_that_quoting_escapes() {
+        let secrets = vec![SECRET.to_owned()];
+        let args = ["set", "app", "ObjectName", r".\app", SECRET];
+
+        // the quoted secret no longer matches the raw one
+        let raw_line = cmdline::to_cmd_line("nssm.exe", &args);
+        assert!(!raw_line.contains(SECRET));
+
+        let line = cmdline::to_cmd_line("nssm.exe", &redact_args(&args, &secrets));
+        assert_eq!(line, r"nssm.exe set app ObjectName .\app <secret>");
+
+        assert_eq!(
+            redact(&format!("Command '{}' failed", raw_line), &secrets),
+            r"Command 'nssm.exe set app ObjectName .\app <secret>' failed"
+        );
+    }
+
+    #[test]
+    fn records_redacted_dry_run_commands() {
+        let transcript = Transcript::new(vec![SECRET.to_owned()]);
+        let args = transcript.redact_args(&["set", "app", "ObjectName", "app", SECRET]);
+
+        transcript.record(&cmdline::to_cmd_line("nssm.exe", &args));
+
+        assert_eq!(
+            transcript.lines(),
+            vec!["nssm.exe set app ObjectName app <secret>"]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Shows the configured services with their live status in the terminal, and starts, stops,
+//! restarts or reapplies the selected service on a key press, so that the services may be
+//! watched and managed without repeatedly running the `status` subcommand.
+//!
+//! Like the HTTP API, only the services on the local machine are managed, and the
+//! configuration is reloaded before every refresh and action.
+
+use config::FileConfig;
+use crossterm::ExecutableCommand;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use errors::*;
+use ratatui::{Frame, Terminal};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use report::RunReport;
+use state::ServiceState;
+use std::io::{self, Stdout};
+use std::time::{Duration, Instant};
+use super::{nssm_exec_restart, nssm_exec_start, nssm_exec_status, nssm_exec_stop,
+            nssm_exec_update};
+
+/// Interval between the refreshes of the status of the services.
+pub const REFRESH_INTERVAL_MS: u64 = 2000;
+
+/// Key bindings shown at the bottom of the screen.
+const HELP: &str = "Up/Down select | s start | x stop | r restart | a reapply | q quit";
+
+#[derive(Clone, Copy, Debug)]
+enum Action {
+    Start,
+    Stop,
+    Restart,
+    Reapply,
+}
+
+impl Action {
+    fn progress(&self) -> &'static str {
+        match *self {
+            Action::Start => "Starting",
+            Action::Stop => "Stopping",
+            Action::Restart => "Restarting",
+            Action::Reapply => "Reapplying",
+        }
+    }
+
+    fn done(&self) -> &'static str {
+        match *self {
+            Action::Start => "started",
+            Action::Stop => "stopped",
+            Action::Restart => "restarted",
+            Action::Reapply => "reapplied",
+        }
+    }
+}
+
+struct App {
+    /// Names of the services with their states, `None` if not installed.
+    services: Vec<(String, Option<ServiceState>)>,
+
+    table_state: TableState,
+
+    /// Outcome of the last action or refresh.
+    message: String,
+}
+
+impl App {
+    fn selected_name(&self) -> Option<String> {
+        self.table_state
+            .selected()
+            .and_then(|index| self.services.get(index))
+            .map(|service| service.0.clone())
+    }
+
+    fn select_offset(&mut self, offset: isize) {
+        if self.services.is_empty() {
+            return;
+        }
+
+        let last = self.services.len() as isize - 1;
+        let index = self.table_state.selected().unwrap_or(0) as isize + offset;
+        self.table_state.select(Some(index.max(0).min(last) as usize));
+    }
+
+    /// Queries the states of the services again, keeping the selection within the services.
+    fn refresh<F>(&mut self, load: &F)
+    where
+        F: Fn() -> Result<FileConfig>,
+    {
+        match query(load) {
+            Ok(services) => {
+                self.services = services;
+                self.select_offset(0);
+            }
+
+            Err(e) => self.message = chain_message("Unable to query the services", &e),
+        }
+    }
+}
+
+/// Joins the description and every cause of the error into a single line.
+fn chain_message(description: &str, e: &Error) -> String {
+    e.iter().fold(description.to_owned(), |message, cause| {
+        format!("{}: {}", message, cause)
+    })
+}
+
+fn query<F>(load: &F) -> Result<Vec<(String, Option<ServiceState>)>>
+where
+    F: Fn() -> Result<FileConfig>,
+{
+    let file_config = load()?.for_host(None);
+
+    Ok(nssm_exec_status(&file_config)?
+        .iter()
+        .map(|status| (status.name.to_owned(), status.state))
+        .collect())
+}
+
+fn perform<F>(action: Action, name: &str, load: &F) -> Result<()>
+where
+    F: Fn() -> Result<FileConfig>,
+{
+    let mut file_config = load()?.for_host(None);
+    file_config.retain_services(&[name.to_owned()])?;
+    file_config.run_report = Some(RunReport::default());
+
+    let res = match action {
+        Action::Start => nssm_exec_start(
+            &file_config,
+            &file_config.pending_start_poll_interval(),
+            file_config.pending_start_poll_count(),
+        ),
+
+        Action::Stop => nssm_exec_stop(
+            &file_config,
+            &file_config.pending_stop_poll_interval(),
+            file_config.pending_stop_poll_count(),
+        ),
+
+        Action::Restart => nssm_exec_restart(
+            &file_config,
+            &file_config.pending_stop_poll_interval(),
+            file_config.pending_stop_poll_count(),
+            &file_config.pending_start_poll_interval(),
+            file_config.pending_start_poll_count(),
+        ),
+
+        Action::Reapply => nssm_exec_update(
+            &file_config,
+            &file_config.pending_stop_poll_interval(),
+            file_config.pending_stop_poll_count(),
+            &file_config.pending_start_poll_interval(),
+            file_config.pending_start_poll_count(),
+        ),
+    };
+
+    // the error of the run only counts the failed services, while the report holds why
+    let errors: Vec<String> = file_config
+        .run_report
+        .map(|run_report| run_report.services())
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|service| service.errors)
+        .collect();
+
+    match res {
+        Err(_) if !errors.is_empty() => bail!("{}", errors.join("; ")),
+        res => res,
+    }
+}
+
+fn state_cell(state: Option<ServiceState>) -> Cell<'static> {
+    let (label, color) = match state {
+        Some(ServiceState::Running) => ("Running".to_owned(), Color::Green),
+        Some(ServiceState::Stopped) => ("Stopped".to_owned(), Color::Red),
+        Some(ServiceState::Paused) => ("Paused".to_owned(), Color::Yellow),
+        Some(state) => (format!("{:?}", state), Color::Yellow),
+        None => ("Not installed".to_owned(), Color::DarkGray),
+    };
+
+    Cell::from(label).style(Style::default().fg(color))
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)])
+        .split(frame.area());
+
+    let rows = app.services
+        .iter()
+        .map(|&(ref name, state)| Row::new(vec![Cell::from(name.clone()), state_cell(state)]));
+
+    let table = Table::new(rows, [Constraint::Percentage(60), Constraint::Percentage(40)])
+        .header(Row::new(vec!["Service", "State"])
+            .style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(" nssm_exec services "))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    frame.render_stateful_widget(table, chunks[0], &mut app.table_state);
+
+    let footer = Paragraph::new(vec![Line::from(app.message.clone()), Line::from(HELP)])
+        .block(Block::default().borders(Borders::TOP));
+
+    frame.render_widget(footer, chunks[1]);
+}
+
+/// Restores the terminal when dropped, even if the interface fails midway.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = io::stdout().execute(LeaveAlternateScreen);
+    }
+}
+
+/// Runs the interface with the configuration loaded by the given function until quit.
+pub fn run<F>(load: F) -> Result<()>
+where
+    F: Fn() -> Result<FileConfig>,
+{
+    let mut app = App {
+        services: Vec::new(),
+        table_state: TableState::default(),
+        message: "Loading...".to_owned(),
+    };
+
+    terminal::enable_raw_mode().chain_err(|| "Unable to enable raw mode of the terminal")?;
+    let _guard = TerminalGuard;
+
+    io::stdout()
+        .execute(EnterAlternateScreen)
+        .chain_err(|| "Unable to enter the alternate screen of the terminal")?;
+
+    let mut terminal: Terminal<CrosstermBackend<Stdout>> =
+        Terminal::new(CrosstermBackend::new(io::stdout()))
+            .chain_err(|| "Unable to create the terminal interface")?;
+
+    let refresh_interval = Duration::from_millis(REFRESH_INTERVAL_MS);
+
+    terminal.draw(|frame| draw(frame, &mut app))?;
+    app.message = String::new();
+    app.refresh(&load);
+    let mut last_refresh = Instant::now();
+
+    loop {
+        terminal.draw(|frame| draw(frame, &mut app))?;
+
+        let timeout = refresh_interval
+            .checked_sub(last_refresh.elapsed())
+            .unwrap_or_else(|| Duration::from_secs(0));
+
+        let mut action = None;
+
+        if event::poll(timeout)? {
+            match event::read()? {
+                // only the presses, since the releases are reported too on Windows
+                Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Up | KeyCode::Char('k') => app.select_offset(-1),
+                    KeyCode::Down | KeyCode::Char('j') => app.select_offset(1),
+                    KeyCode::Char('s') => action = Some(Action::Start),
+                    KeyCode::Char('x') => action = Some(Action::Stop),
+                    KeyCode::Char('r') => action = Some(Action::Restart),
+                    KeyCode::Char('a') => action = Some(Action::Reapply),
+                    _ => (),
+                },
+
+                _ => (),
+            }
+        }
+
+        if let (Some(action), Some(name)) = (action, app.selected_name()) {
+            app.message = format!("{} '{}'...", action.progress(), name);
+            terminal.draw(|frame| draw(frame, &mut app))?;
+
+            app.message = match perform(action, &name, &load) {
+                Ok(_) => format!("Service '{}' {}", name, action.done()),
+                Err(e) => chain_message(&format!("Unable to process '{}'", name), &e),
+            };
+
+            app.refresh(&load);
+            last_refresh = Instant::now();
+
+            // repaints over any log lines printed to the console during the action
+            terminal.clear()?;
+        } else if last_refresh.elapsed() >= refresh_interval {
+            app.refresh(&load);
+            last_refresh = Instant::now();
+        }
+    }
+}
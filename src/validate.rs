@@ -0,0 +1,388 @@
+//! Checks the configuration for problems that would otherwise only surface when running the
+//! nssm commands, without touching the Service Control Manager.
+
+use config::{Backend, FileConfig, Service};
+use notify;
+use params;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use super::{green_service, service_deps};
+use shawl;
+use systemd;
+use version;
+use winsw;
+
+/// Returns true if the nssm parameter can be set with a backend other than nssm.
+type IsSupported = fn(&str) -> bool;
+
+/// Holds the problems found in the configuration.
+#[derive(Debug, Default)]
+pub struct Validation {
+    /// Problems that would cause the nssm operations to fail.
+    pub errors: Vec<String>,
+
+    /// Problems that may be intended, e.g. depending on services outside of the
+    /// configuration.
+    pub warnings: Vec<String>,
+}
+
+impl Validation {
+    /// Returns true if there is no error, regardless of any warning.
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Returns the path of the service executable, resolving any relative path from the
+/// directory containing the nssm executable, like nssm does.
+pub fn resolve_service_path(service: &Service, file_config: &FileConfig) -> PathBuf {
+    if service.path.is_absolute() {
+        return service.path.clone();
+    }
+
+    match file_config.nssm_path.parent() {
+        Some(nssm_dir) => nssm_dir.join(&service.path),
+        None => service.path.clone(),
+    }
+}
+
+/// Checks the presence and version of the nssm executable, the presence of the service
+/// executables, the uniqueness of the service names, and the dependencies between the
+/// services. The executables of the services on remote hosts are not checked.
+pub fn validate(file_config: &FileConfig) -> Validation {
+    let mut validation = Validation::default();
+
+    if !file_config.for_host(None).uses_backend(Backend::Nssm) {
+        // nssm is not needed locally when all the local services use other backends
+    } else if !file_config.nssm_path.is_file() {
+        validation.errors.push(format!(
+            "nssm executable at '{}' does not exist",
+            file_config.nssm_path.to_string_lossy()
+        ));
+    } else {
+        match version::detect(file_config) {
+            Ok(nssm_version) => {
+                validation
+                    .errors
+                    .extend(version::unsupported(file_config, nssm_version));
+            }
+
+            Err(e) => {
+                validation.warnings.push(format!(
+                    "Unable to check the configured parameters against the nssm version: {}",
+                    e
+                ));
+            }
+        }
+    }
+
+    if file_config.uses_backend(Backend::Winsw) {
+        match file_config.winsw_path {
+            Some(ref winsw_path) if !winsw_path.is_file() => {
+                validation.errors.push(format!(
+                    "WinSW executable at '{}' does not exist",
+                    winsw_path.to_string_lossy()
+                ));
+            }
+
+            Some(_) => (),
+
+            None => {
+                validation
+                    .errors
+                    .push("winsw_path is required for services using the WinSW backend".to_owned());
+            }
+        }
+    }
+
+    if file_config.uses_backend(Backend::Shawl) {
+        match file_config.shawl_path {
+            Some(ref shawl_path) if !shawl_path.is_file() => {
+                validation.errors.push(format!(
+                    "shawl executable at '{}' does not exist",
+                    shawl_path.to_string_lossy()
+                ));
+            }
+
+            Some(_) => (),
+
+            None => {
+                validation
+                    .errors
+                    .push("shawl_path is required for services using the shawl backend".to_owned());
+            }
+        }
+    }
+
+    if let Some(email) = file_config.notify.as_ref().and_then(|notify| notify.email.as_ref()) {
+        if email.to.is_empty() {
+            validation
+                .errors
+                .push("[notify.email] has no recipient in 'to'".to_owned());
+        }
+
+        if email.password.is_some() && email.password_env.is_some() {
+            validation
+                .errors
+                .push("[notify.email] has both password and password_env".to_owned());
+        }
+
+        if email.password.is_some() {
+            validation.warnings.push(
+                "[notify.email] has a plain text password, consider password_env".to_owned(),
+            );
+        }
+    }
+
+    if let Some(webhook) = file_config.notify.as_ref().and_then(|notify| notify.webhook.as_ref()) {
+        match (webhook.url.is_some(), webhook.url_env.is_some()) {
+            (true, true) => validation
+                .errors
+                .push("[notify.webhook] has both url and url_env".to_owned()),
+            (false, false) => validation
+                .errors
+                .push("[notify.webhook] has neither url nor url_env".to_owned()),
+            _ => (),
+        }
+
+        if let Some(ref template) = webhook.template {
+            let values = notify::WEBHOOK_VARIABLES
+                .iter()
+                .map(|name| (name.to_string(), String::new()))
+                .collect();
+
+            if let Err(e) = notify::render_webhook_template(template, &values) {
+                validation.errors.push(format!(
+                    "[notify.webhook] template is invalid: {}",
+                    e.iter().last().map(|cause| cause.to_string()).unwrap_or_default()
+                ));
+            }
+        }
+    }
+
+    let mut names = HashSet::new();
+
+    for service in &file_config.services {
+        if !names.insert(&service.name) {
+            validation.errors.push(format!(
+                "Service '{}' is configured more than once",
+                service.name
+            ));
+        }
+
+        // only the services that already exist are recreated blue-green
+        if file_config.is_blue_green() {
+            if let Err(e) = green_service(service) {
+                validation.warnings.push(e.to_string());
+            }
+        }
+
+        let is_supported: Option<(IsSupported, &str)> =
+            match file_config.backend(&service.name) {
+                Backend::Nssm | Backend::Mock => None,
+                Backend::Winsw => Some((winsw::is_supported, "WinSW")),
+                Backend::Shawl => Some((shawl::is_supported, "shawl")),
+                Backend::Systemd => Some((systemd::is_supported, "systemd")),
+            };
+
+        if let Some((is_supported, backend_name)) = is_supported {
+            for param in params::desired_params(service, file_config) {
+                if !is_supported(param.name) {
+                    validation.errors.push(format!(
+                        "Service '{}' uses '{}' which is not supported by the {} backend",
+                        service.name,
+                        param.key(),
+                        backend_name
+                    ));
+                }
+            }
+        }
+
+        if file_config.backend(&service.name) == Backend::Systemd
+            && (service.recovery.is_some() || service.firewall.is_some())
+        {
+            validation.warnings.push(format!(
+                "Service '{}' recovery and firewall are not applied by the systemd backend",
+                service.name
+            ));
+        }
+
+        if service.verify_process == Some(true)
+            && (file_config.backend(&service.name) != Backend::Nssm || !service.is_on_host(None))
+        {
+            validation.warnings.push(format!(
+                "Service '{}' application process is only verified for the nssm backend on the \
+                 local machine",
+                service.name
+            ));
+        }
+
+        if let Some(ref health) = service.health {
+            if health.is_empty() {
+                validation.errors.push(format!(
+                    "Service '{}' health has no tcp_port, http_url or command to check",
+                    service.name
+                ));
+            }
+
+            if health.http_status.is_some() && health.http_url.is_none() {
+                validation.errors.push(format!(
+                    "Service '{}' health has http_status without http_url",
+                    service.name
+                ));
+            }
+        }
+
+        if let Some(ref firewall) = service.firewall {
+            if firewall.ports.is_empty() {
+                validation.errors.push(format!(
+                    "Service '{}' firewall has no ports to allow",
+                    service.name
+                ));
+            }
+        }
+
+        if let Some(ref artifact) = service.artifact {
+            if service.hosts.is_some() {
+                validation.errors.push(format!(
+                    "Service '{}' artifact can only be fetched on the local machine",
+                    service.name
+                ));
+            }
+
+            if artifact.url.is_some() && artifact.sha256.is_none() {
+                validation.errors.push(format!(
+                    "Service '{}' artifact has url without sha256",
+                    service.name
+                ));
+            }
+
+            if artifact.url.is_none() && artifact.extract_dir.is_none() {
+                validation.errors.push(format!(
+                    "Service '{}' artifact has neither url nor extract_dir",
+                    service.name
+                ));
+            }
+
+            if artifact.junction.is_some() && artifact.extract_dir.is_none() {
+                validation.errors.push(format!(
+                    "Service '{}' artifact has junction without extract_dir",
+                    service.name
+                ));
+            }
+
+            if let Some(ref sha256) = artifact.sha256 {
+                if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                    validation.errors.push(format!(
+                        "Service '{}' artifact sha256 is not a hex SHA-256 checksum",
+                        service.name
+                    ));
+                }
+            }
+
+            if artifact.url.is_none() && !artifact.dest.is_file() {
+                validation.errors.push(format!(
+                    "Service '{}' artifact at '{}' does not exist",
+                    service.name,
+                    artifact.dest.to_string_lossy()
+                ));
+            }
+        }
+
+        // the files of remote services are on the remote hosts,
+        // and the artifact is only fetched when installing
+        if service.is_on_host(None) {
+            let service_path = resolve_service_path(service, file_config);
+
+            if !service_path.is_file() && service.artifact.is_none() {
+                validation.errors.push(format!(
+                    "Service '{}' executable at '{}' does not exist",
+                    service.name,
+                    service_path.to_string_lossy()
+                ));
+            }
+
+            if let Some(ref startup_dir) = service.startup_dir {
+                if !startup_dir.is_dir() {
+                    validation.warnings.push(format!(
+                        "Service '{}' startup directory at '{}' does not exist",
+                        service.name,
+                        startup_dir.to_string_lossy()
+                    ));
+                }
+            }
+        }
+
+        for dep in service_deps(service, file_config) {
+            if dep == service.name {
+                validation.errors.push(format!(
+                    "Service '{}' depends on itself",
+                    service.name
+                ));
+            } else if !file_config.services.iter().any(|other| other.name == dep) {
+                validation.warnings.push(format!(
+                    "Service '{}' depends on service '{}' outside of the configuration, \
+                     which must already be installed",
+                    service.name,
+                    dep
+                ));
+            }
+        }
+    }
+
+    for cycle in dependency_cycles(file_config) {
+        validation.errors.push(format!(
+            "Services have circular dependencies: {}",
+            cycle.join(" -> ")
+        ));
+    }
+
+    validation
+}
+
+/// Returns the cycles of dependencies between the configured services, each starting and
+/// ending with the same service name. Services depending on themselves are excluded.
+fn dependency_cycles(file_config: &FileConfig) -> Vec<Vec<&str>> {
+    let mut cycles = Vec::new();
+    let mut visited = HashSet::new();
+
+    for service in &file_config.services {
+        let mut path = Vec::new();
+        find_cycles(service, file_config, &mut path, &mut visited, &mut cycles);
+    }
+
+    cycles
+}
+
+fn find_cycles<'a>(
+    service: &'a Service,
+    file_config: &'a FileConfig,
+    path: &mut Vec<&'a str>,
+    visited: &mut HashSet<&'a str>,
+    cycles: &mut Vec<Vec<&'a str>>,
+) {
+    if let Some(start) = path.iter().position(|name| *name == service.name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(&service.name);
+        cycles.push(cycle);
+        return;
+    }
+
+    if !visited.insert(&service.name) {
+        return;
+    }
+
+    path.push(&service.name);
+
+    for dep in service_deps(service, file_config) {
+        if dep == service.name {
+            continue;
+        }
+
+        if let Some(dep_service) = file_config.services.iter().find(|other| other.name == dep) {
+            find_cycles(dep_service, file_config, path, visited, cycles);
+        }
+    }
+
+    path.pop();
+}
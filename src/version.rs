@@ -0,0 +1,136 @@
+//! Detects the version of the configured nssm executable, and checks the configured
+//! parameters against the parameters supported by that version.
+
+use config::{Backend, FileConfig};
+use errors::*;
+use output;
+use params;
+use std::fmt;
+use std::process::Command;
+
+/// Version of an nssm release, e.g. 2.24.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct NssmVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl NssmVersion {
+    pub fn new(major: u32, minor: u32) -> NssmVersion {
+        NssmVersion {
+            major,
+            minor,
+        }
+    }
+
+    /// Returns the first version that supports the given nssm parameter.
+    pub fn required_for(param_name: &str) -> NssmVersion {
+        match param_name {
+            "AppEvents" | "AppKillProcessTree" => NssmVersion::new(2, 25),
+            "AppRotateOnline" => NssmVersion::new(2, 24),
+            _ => MIN_VERSION,
+        }
+    }
+}
+
+impl fmt::Display for NssmVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// First version with the get, set and service control commands that nssm_exec relies on.
+pub const MIN_VERSION: NssmVersion = NssmVersion {
+    major: 2,
+    minor: 22,
+};
+
+/// Runs `nssm version` and parses the version from the banner,
+/// e.g. `Version 2.24 64-bit, 2014-08-31`.
+pub fn detect(file_config: &FileConfig) -> Result<NssmVersion> {
+    let nssm_path = file_config.nssm_path.to_string_lossy();
+
+    let output = Command::new(&file_config.nssm_path)
+        .arg("version")
+        .output()
+        .chain_err(|| ErrorKind::CommandUnavailable {
+            cmd: format!("{} version", nssm_path),
+        })?;
+
+    // older releases without the version command still print the banner with their usage,
+    // so the exit code is ignored
+    let stdout = output::decode(&output.stdout);
+    let stderr = output::decode(&output.stderr);
+
+    parse(&stdout)
+        .or_else(|| parse(&stderr))
+        .ok_or_else(|| {
+            format!(
+                "Unable to find version in output of '{} version': {}",
+                nssm_path,
+                stdout.trim()
+            ).into()
+        })
+}
+
+fn parse(output: &str) -> Option<NssmVersion> {
+    let version_index = output.find("Version ")?;
+
+    let number: String = output[version_index + "Version ".len()..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+
+    let mut parts = number.split('.').map(|part| part.parse::<u32>());
+
+    match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Some(NssmVersion::new(major, minor)),
+        _ => None,
+    }
+}
+
+/// Returns a message for every configured parameter that the given nssm version does not
+/// support, or a single message if the version is too old for nssm_exec altogether.
+pub fn unsupported(file_config: &FileConfig, version: NssmVersion) -> Vec<String> {
+    if version < MIN_VERSION {
+        return vec![
+            format!(
+                "nssm {} is not supported, at least nssm {} is required",
+                version,
+                MIN_VERSION
+            ),
+        ];
+    }
+
+    let mut messages = Vec::new();
+
+    for service in &file_config.services {
+        if file_config.backend(&service.name) != Backend::Nssm {
+            continue;
+        }
+
+        let mut names: Vec<&str> = Vec::new();
+
+        for param in params::desired_params(service, file_config) {
+            if !names.contains(&param.name) {
+                names.push(param.name);
+            }
+        }
+
+        for name in names {
+            let required = NssmVersion::required_for(name);
+
+            if version < required {
+                messages.push(format!(
+                    "Service '{}' uses '{}' which requires nssm {}, but nssm {} is configured",
+                    service.name,
+                    name,
+                    required,
+                    version
+                ));
+            }
+        }
+    }
+
+    messages
+}
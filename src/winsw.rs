@@ -0,0 +1,490 @@
+//! Manages services with WinSW, where every service has its own copy of the WinSW executable
+//! next to an XML configuration of the same name. The XML configuration is generated from
+//! the same parameters as used for nssm, and is overwritten whenever a parameter is set.
+
+use backend::ServiceBackend;
+use config::FileConfig;
+use errors::*;
+use file;
+use params::{self, ServiceParam};
+use scm;
+use state::ServiceState;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use super::{audit_cmd, log_dry_run, log_dry_run_cmd, record_cmd,
+            run_cmd_with_timeout};
+
+/// nssm parameters that map to a single WinSW XML element, in the order they are written.
+const ELEMENTS: &[(&str, &str)] = &[
+    (params::APPLICATION, "executable"),
+    ("AppParameters", "arguments"),
+    ("AppDirectory", "workingdirectory"),
+    ("DisplayName", "name"),
+    ("Description", "description"),
+    ("AppPriority", "priority"),
+];
+
+/// nssm priority class names with the equivalent WinSW priority names.
+const PRIORITIES: &[(&str, &str)] = &[
+    ("IDLE_PRIORITY_CLASS", "Idle"),
+    ("BELOW_NORMAL_PRIORITY_CLASS", "BelowNormal"),
+    ("NORMAL_PRIORITY_CLASS", "Normal"),
+    ("ABOVE_NORMAL_PRIORITY_CLASS", "AboveNormal"),
+    ("HIGH_PRIORITY_CLASS", "High"),
+    ("REALTIME_PRIORITY_CLASS", "RealTime"),
+];
+
+/// Returns true if the nssm parameter can be set for services using the WinSW backend.
+pub fn is_supported(param_name: &str) -> bool {
+    ELEMENTS.iter().any(|&(name, _)| name == param_name)
+        || matches!(
+            param_name,
+            "DependOnService" | "AppEnvironmentExtra" | "AppRestartDelay" | "ObjectName"
+        )
+}
+
+/// Manages the services with a copy of the configured WinSW executable per service.
+pub struct Winsw;
+
+impl ServiceBackend for Winsw {
+    fn install(&self, service_name: &str, path: &Path, file_config: &FileConfig) -> Result<()> {
+        let service_dir = service_dir(service_name, file_config)?;
+
+        if file_config.is_dry_run() {
+            log_dry_run(
+                file_config,
+                &format!(
+                    "Install service '{}' with WinSW in '{}'",
+                    service_name,
+                    service_dir.to_string_lossy(),
+                ),
+            );
+
+            return Ok(());
+        }
+
+        fs::create_dir_all(&service_dir).chain_err(|| {
+            format!(
+                "Unable to create directory at '{}'",
+                service_dir.to_string_lossy()
+            )
+        })?;
+
+        let winsw_path = winsw_path(file_config)?;
+        let wrapper_path = wrapper_path(service_name, file_config)?;
+
+        fs::copy(winsw_path, &wrapper_path).chain_err(|| {
+            format!(
+                "Unable to copy WinSW executable from '{}' to '{}'",
+                winsw_path.to_string_lossy(),
+                wrapper_path.to_string_lossy()
+            )
+        })?;
+
+        // WinSW requires the name and description, which are set properly afterwards
+        let mut winsw_xml = WinswXml::new(service_name);
+        winsw_xml.set(param(params::APPLICATION, vec![path.to_string_lossy().into_owned()]));
+        winsw_xml.set(param("DisplayName", vec![service_name.to_owned()]));
+        winsw_xml.set(param("Description", vec![service_name.to_owned()]));
+        write_xml(service_name, &winsw_xml, file_config)?;
+
+        run_wrapper(service_name, "install", file_config)?;
+        Ok(())
+    }
+
+    fn set_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<()> {
+        if !is_supported(param.name) {
+            bail!("Parameter '{}' is not supported by the WinSW backend", param.key());
+        }
+
+        if file_config.is_dry_run() {
+            log_dry_run(
+                file_config,
+                &format!(
+                    "Set '{}' of service '{}' to '{}' in its WinSW configuration",
+                    param.key(),
+                    service_name,
+                    param.value(),
+                ),
+            );
+
+            return Ok(());
+        }
+
+        let mut winsw_xml = read_xml(service_name, file_config)?;
+
+        if param.name == "ObjectName" {
+            winsw_xml.password = param.secret.clone();
+        }
+
+        winsw_xml.set(ServiceParam {
+            secret: None,
+            ..param.clone()
+        });
+
+        write_xml(service_name, &winsw_xml, file_config)?;
+
+        // applies the changes that only take effect on installation, e.g. the account
+        run_wrapper(service_name, "refresh", file_config)?;
+        Ok(())
+    }
+
+    fn get_param(
+        &self,
+        service_name: &str,
+        param: &ServiceParam,
+        file_config: &FileConfig,
+    ) -> Result<String> {
+        if !is_supported(param.name) {
+            bail!("Parameter '{}' is not supported by the WinSW backend", param.key());
+        }
+
+        let winsw_xml = read_xml(service_name, file_config)?;
+
+        // unset parameters are empty like in nssm
+        let value = winsw_xml
+            .params
+            .iter()
+            .find(|installed| installed.key() == param.key())
+            .map(|installed| installed.values.join("\n"))
+            .unwrap_or_default();
+
+        Ok(value)
+    }
+
+    fn status(&self, service_name: &str, file_config: &FileConfig) -> Result<ServiceState> {
+        if !wrapper_path(service_name, file_config)?.is_file() {
+            bail!("Service '{}' is not installed with WinSW", service_name);
+        }
+
+        let output = run_wrapper(service_name, "status", file_config)?;
+        let status = String::from_utf8_lossy(&output.stdout);
+
+        match status.trim() {
+            "Started" => Ok(ServiceState::Running),
+            "Stopped" => Ok(ServiceState::Stopped),
+            "NonExistent" => bail!("Service '{}' is not installed with WinSW", service_name),
+            status => bail!("Unable to obtain valid state from WinSW status '{}'", status),
+        }
+    }
+
+    fn start(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_wrapper_modify(service_name, "start", file_config)
+    }
+
+    fn stop(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_wrapper_modify(service_name, "stop", file_config)
+    }
+
+    fn kill_tree(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        scm::kill_tree(service_name, file_config)
+    }
+
+    fn remove(&self, service_name: &str, file_config: &FileConfig) -> Result<()> {
+        run_wrapper_modify(service_name, "uninstall", file_config)?;
+
+        if file_config.is_dry_run() {
+            return Ok(());
+        }
+
+        let service_dir = service_dir(service_name, file_config)?;
+
+        if let Err(e) = fs::remove_dir_all(&service_dir) {
+            warn!(
+                "Unable to remove WinSW directory of service '{}' at '{}': {}",
+                service_name,
+                service_dir.to_string_lossy(),
+                e
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn winsw_path(file_config: &FileConfig) -> Result<&Path> {
+    file_config
+        .winsw_path.as_deref()
+        .ok_or_else(|| {
+            "Unable to use the WinSW backend without winsw_path in the configuration".into()
+        })
+}
+
+fn service_dir(service_name: &str, file_config: &FileConfig) -> Result<PathBuf> {
+    let winsw_dir = match file_config.winsw_dir {
+        Some(ref winsw_dir) => winsw_dir.clone(),
+        None => winsw_path(file_config)?
+            .parent()
+            .map(|winsw_parent| winsw_parent.join("services"))
+            .unwrap_or_else(|| PathBuf::from("services")),
+    };
+
+    Ok(winsw_dir.join(service_name))
+}
+
+fn wrapper_path(service_name: &str, file_config: &FileConfig) -> Result<PathBuf> {
+    Ok(service_dir(service_name, file_config)?.join(format!("{}.exe", service_name)))
+}
+
+fn xml_path(service_name: &str, file_config: &FileConfig) -> Result<PathBuf> {
+    Ok(service_dir(service_name, file_config)?.join(format!("{}.xml", service_name)))
+}
+
+fn run_wrapper(service_name: &str, cmd: &str, file_config: &FileConfig) -> Result<Output> {
+    let timeout = file_config.command_timeout(Some(service_name));
+    let wrapper_path = wrapper_path(service_name, file_config)?;
+    let res = run_cmd_with_timeout(&wrapper_path, &[cmd], timeout);
+    audit_cmd(file_config, &wrapper_path, &[cmd], &res);
+    record_cmd(file_config, &wrapper_path, &[cmd], &res);
+    res
+}
+
+fn run_wrapper_modify(service_name: &str, cmd: &str, file_config: &FileConfig) -> Result<()> {
+    if file_config.is_dry_run() {
+        log_dry_run_cmd(
+            file_config,
+            &wrapper_path(service_name, file_config)?.to_string_lossy(),
+            &[cmd],
+        );
+
+        return Ok(());
+    }
+
+    run_wrapper(service_name, cmd, file_config).map(|_| ())
+}
+
+fn param(name: &'static str, values: Vec<String>) -> ServiceParam {
+    ServiceParam {
+        name,
+        sub: None,
+        values,
+        secret: None,
+    }
+}
+
+/// Holds the content of the WinSW XML configuration of a service, with the values in the
+/// same format as the nssm parameters.
+struct WinswXml {
+    id: String,
+    params: Vec<ServiceParam>,
+    password: Option<String>,
+}
+
+impl WinswXml {
+    fn new(id: &str) -> WinswXml {
+        WinswXml {
+            id: id.to_owned(),
+            params: Vec::new(),
+            password: None,
+        }
+    }
+
+    fn set(&mut self, param: ServiceParam) {
+        match self.params
+            .iter()
+            .position(|existing| existing.key() == param.key())
+        {
+            Some(index) => self.params[index] = param,
+            None => self.params.push(param),
+        }
+    }
+
+    fn push_value(&mut self, name: &'static str, value: String) {
+        match self.params.iter().position(|existing| existing.name == name) {
+            Some(index) => self.params[index].values.push(value),
+            None => self.params.push(param(name, vec![value])),
+        }
+    }
+
+    fn values(&self, name: &str) -> &[String] {
+        self.params
+            .iter()
+            .find(|param| param.name == name)
+            .map(|param| param.values.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn to_xml(&self) -> String {
+        let mut lines = vec![
+            "<service>".to_owned(),
+            format!("  <id>{}</id>", escape(&self.id)),
+        ];
+
+        for &(name, tag) in ELEMENTS {
+            if let Some(value) = self.values(name).first() {
+                let value = if name == "AppPriority" {
+                    PRIORITIES
+                        .iter()
+                        .find(|&&(nssm_value, _)| nssm_value == value)
+                        .map(|&(_, winsw_value)| winsw_value)
+                        .unwrap_or(value)
+                } else {
+                    value
+                };
+
+                lines.push(format!("  <{0}>{1}</{0}>", tag, escape(value)));
+            }
+        }
+
+        for dep in self.values("DependOnService") {
+            lines.push(format!("  <depend>{}</depend>", escape(dep)));
+        }
+
+        for env in self.values("AppEnvironmentExtra") {
+            let mut parts = env.splitn(2, '=');
+            let env_name = parts.next().unwrap_or("");
+            let env_value = parts.next().unwrap_or("");
+
+            lines.push(format!(
+                r#"  <env name="{}" value="{}"/>"#,
+                escape(env_name),
+                escape(env_value)
+            ));
+        }
+
+        if let Some(delay_ms) = self.values("AppRestartDelay").first() {
+            lines.push(format!(
+                r#"  <onfailure action="restart" delay="{} ms"/>"#,
+                escape(delay_ms)
+            ));
+        }
+
+        if let Some(user) = self.values("ObjectName").first() {
+            lines.push("  <serviceaccount>".to_owned());
+            lines.push(format!("    <username>{}</username>", escape(user)));
+
+            if let Some(ref password) = self.password {
+                lines.push(format!("    <password>{}</password>", escape(password)));
+            }
+
+            lines.push("    <allowservicelogon>true</allowservicelogon>".to_owned());
+            lines.push("  </serviceaccount>".to_owned());
+        }
+
+        lines.push("</service>".to_owned());
+        lines.join("\r\n") + "\r\n"
+    }
+
+    /// Parses the XML configuration as written by `to_xml`, ignoring unknown lines.
+    fn from_xml(content: &str) -> WinswXml {
+        let mut winsw_xml = WinswXml::new("");
+
+        for line in content.lines().map(|line| line.trim()) {
+            if let Some((tag, value)) = simple_element(line) {
+                match tag {
+                    "id" => winsw_xml.id = value,
+                    "depend" => winsw_xml.push_value("DependOnService", value),
+                    "username" => winsw_xml.set(param("ObjectName", vec![value])),
+                    "password" => winsw_xml.password = Some(value),
+                    "priority" => {
+                        let value = PRIORITIES
+                            .iter()
+                            .find(|&&(_, winsw_value)| winsw_value == value)
+                            .map(|&(nssm_value, _)| nssm_value.to_owned())
+                            .unwrap_or(value);
+
+                        winsw_xml.set(param("AppPriority", vec![value]));
+                    }
+                    tag => if let Some(&(name, _)) =
+                        ELEMENTS.iter().find(|&&(_, element)| element == tag)
+                    {
+                        winsw_xml.set(param(name, vec![value]));
+                    },
+                }
+            } else if line.starts_with("<env ") {
+                let env_name = attribute(line, "name").unwrap_or_default();
+                let env_value = attribute(line, "value").unwrap_or_default();
+                winsw_xml.push_value("AppEnvironmentExtra", format!("{}={}", env_name, env_value));
+            } else if line.starts_with("<onfailure ") {
+                if let Some(delay) = attribute(line, "delay") {
+                    let delay_ms = match delay.rfind(" ms") {
+                        Some(unit_index) => delay[..unit_index].to_owned(),
+                        None => delay,
+                    };
+
+                    winsw_xml.set(param("AppRestartDelay", vec![delay_ms]));
+                }
+            }
+        }
+
+        winsw_xml
+    }
+}
+
+fn read_xml(service_name: &str, file_config: &FileConfig) -> Result<WinswXml> {
+    let xml_path = xml_path(service_name, file_config)?;
+
+    let content = file::get_text(&xml_path).chain_err(|| {
+        format!(
+            "Unable to read WinSW configuration at '{}'",
+            xml_path.to_string_lossy()
+        )
+    })?;
+
+    Ok(WinswXml::from_xml(&content))
+}
+
+fn write_xml(service_name: &str, winsw_xml: &WinswXml, file_config: &FileConfig) -> Result<()> {
+    let xml_path = xml_path(service_name, file_config)?;
+
+    file::put_text(&xml_path, winsw_xml.to_xml()).chain_err(|| {
+        format!(
+            "Unable to write WinSW configuration at '{}'",
+            xml_path.to_string_lossy()
+        )
+    })
+}
+
+/// Returns the tag and unescaped text of a single line element, e.g. `<id>name</id>`.
+fn simple_element(line: &str) -> Option<(&str, String)> {
+    if !line.starts_with('<') || line.starts_with("</") {
+        return None;
+    }
+
+    let tag_end = line.find('>')?;
+    let tag = &line[1..tag_end];
+
+    if tag.contains(' ') || tag.contains('/') {
+        return None;
+    }
+
+    let closing = format!("</{}>", tag);
+
+    if !line.ends_with(&closing) || line.len() < tag_end + 1 + closing.len() {
+        return None;
+    }
+
+    Some((tag, unescape(&line[tag_end + 1..line.len() - closing.len()])))
+}
+
+/// Returns the unescaped value of the attribute in the element line.
+fn attribute(line: &str, name: &str) -> Option<String> {
+    let prefix = format!(r#" {}=""#, name);
+    let value_start = line.find(&prefix)? + prefix.len();
+    let value_len = line[value_start..].find('"')?;
+    Some(unescape(&line[value_start..value_start + value_len]))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,972 @@
+//! Exercises the whole flow of processing the services against the mock backend, which
+//! simulates the services in memory. The mock backend is shared by all the tests, so the
+//! tests run one at a time, each starting from an empty mock backend.
+
+#[macro_use]
+extern crate lazy_static;
+extern crate nssm_exec;
+
+use nssm_exec::errors::ErrorCategory;
+use nssm_exec::{explain, mock, FileConfig, ServiceState};
+use std::env;
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard};
+
+lazy_static! {
+    static ref MOCK_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Waits for the running test to finish and resets the mock backend, returning the guard that
+/// lets the next test run once dropped.
+fn isolate() -> MutexGuard<'static, ()> {
+    // a failed test poisons the lock, which must not fail the remaining tests
+    let guard = MOCK_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    mock::reset();
+    guard
+}
+
+/// Returns the configuration of the services given in TOML, managed by the mock backend with
+/// the shortest poll interval.
+fn mock_config(services: &str) -> FileConfig {
+    let content = format!(
+        r#"
+nssm_path = "nssm.exe"
+backend = "mock"
+pending_stop_poll_ms = 1
+pending_stop_poll_count = 5
+pending_start_poll_ms = 1
+pending_start_poll_count = 5
+
+{}
+"#,
+        services
+    );
+
+    FileConfig::from_str(&content).unwrap()
+}
+
+fn apply(file_config: &FileConfig) -> nssm_exec::errors::Result<()> {
+    nssm_exec::nssm_exec(
+        file_config,
+        &file_config.pending_stop_poll_interval(),
+        file_config.pending_stop_poll_count(),
+        &file_config.pending_start_poll_interval(),
+        file_config.pending_start_poll_count(),
+    )
+}
+
+fn update(file_config: &FileConfig) -> nssm_exec::errors::Result<()> {
+    nssm_exec::nssm_exec_update(
+        file_config,
+        &file_config.pending_stop_poll_interval(),
+        file_config.pending_stop_poll_count(),
+        &file_config.pending_start_poll_interval(),
+        file_config.pending_start_poll_count(),
+    )
+}
+
+#[test]
+fn installs_sets_and_starts_new_service() {
+    let _mock = isolate();
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "create_app"
+path = "app.exe"
+startup_dir = 'C:\app'
+args = ["--port", "80"]
+
+[services.other]
+start_on_create = true
+"#,
+    );
+
+    apply(&file_config).unwrap();
+
+    assert_eq!(
+        mock::operations("create_app"),
+        vec![
+            "install app.exe",
+            "set AppDirectory C:\\app",
+            "set AppParameters --port 80",
+            "start",
+        ]
+    );
+
+    assert_eq!(mock::state("create_app"), Some(ServiceState::Running));
+    assert_eq!(mock::param_value("create_app", "AppDirectory").unwrap(), "C:\\app");
+}
+
+#[test]
+fn stops_and_removes_existing_service_before_install() {
+    let _mock = isolate();
+
+    mock::add_service("replace_app", "old.exe", ServiceState::Running);
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "replace_app"
+path = "new.exe"
+"#,
+    );
+
+    apply(&file_config).unwrap();
+
+    assert_eq!(
+        mock::operations("replace_app"),
+        vec!["stop", "remove", "install new.exe"]
+    );
+
+    assert_eq!(mock::state("replace_app"), Some(ServiceState::Stopped));
+    assert_eq!(mock::param_value("replace_app", "Application").unwrap(), "new.exe");
+}
+
+#[test]
+fn rolls_back_to_previous_service_when_create_fails() {
+    let _mock = isolate();
+
+    mock::add_service("rollback_app", "old.exe", ServiceState::Running);
+    mock::fail_next("rollback_app", "set AppDirectory");
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "rollback_app"
+path = "new.exe"
+startup_dir = 'C:\new'
+"#,
+    );
+
+    assert!(apply(&file_config).is_err());
+
+    // the failed operation is never recorded
+    assert_eq!(
+        mock::operations("rollback_app"),
+        vec![
+            "stop",
+            "remove",
+            "install new.exe",
+            "remove",
+            "install old.exe",
+            "start",
+        ]
+    );
+
+    assert_eq!(mock::state("rollback_app"), Some(ServiceState::Running));
+    assert_eq!(mock::param_value("rollback_app", "Application").unwrap(), "old.exe");
+}
+
+#[test]
+fn rollback_leaves_service_stopped_when_account_password_is_unknown() {
+    let _mock = isolate();
+
+    let old_config = mock_config(
+        r#"
+[[services]]
+name = "rollback_account_app"
+path = "old.exe"
+
+[services.other]
+start_on_create = true
+account = { user = "old_user", password = "old_password" }
+"#,
+    );
+
+    apply(&old_config).unwrap();
+    assert_eq!(mock::state("rollback_account_app"), Some(ServiceState::Running));
+
+    mock::fail_next("rollback_account_app", "set AppDirectory");
+
+    let new_config = mock_config(
+        r#"
+[[services]]
+name = "rollback_account_app"
+path = "new.exe"
+startup_dir = 'C:\new'
+
+[services.other]
+account = { user = "new_user", password = "new_password" }
+"#,
+    );
+
+    assert!(apply(&new_config).is_err());
+
+    // the old account cannot be restored without its password, so it never runs as
+    // LocalSystem instead
+    let operations = mock::operations("rollback_account_app");
+    assert_eq!(operations.last().unwrap(), "install old.exe");
+    assert_eq!(mock::state("rollback_account_app"), Some(ServiceState::Stopped));
+    assert_eq!(mock::param_value("rollback_account_app", "ObjectName"), None);
+}
+
+#[test]
+fn update_only_sets_changed_params() {
+    let _mock = isolate();
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "update_app"
+path = "app.exe"
+description = "App"
+"#,
+    );
+
+    apply(&file_config).unwrap();
+
+    let changed_config = mock_config(
+        r#"
+[[services]]
+name = "update_app"
+path = "app.exe"
+description = "Changed app"
+"#,
+    );
+
+    update(&changed_config).unwrap();
+
+    assert_eq!(
+        mock::operations("update_app"),
+        vec!["install app.exe", "set Description App", "set Description Changed app"]
+    );
+
+    assert_eq!(mock::param_value("update_app", "Description").unwrap(), "Changed app");
+}
+
+#[test]
+fn update_resets_params_removed_from_config() {
+    let _mock = isolate();
+
+    let service = |description: &str| {
+        format!(
+            r#"
+[[services]]
+name = "reset_app"
+path = "app.exe"
+{}
+"#,
+            description
+        )
+    };
+
+    apply(&mock_config(&service(r#"description = "App""#))).unwrap();
+    update(&mock_config(&service(""))).unwrap();
+    update(&mock_config(&service(""))).unwrap();
+
+    assert_eq!(
+        mock::operations("reset_app"),
+        vec!["install app.exe", "set Description App", "reset Description"]
+    );
+
+    assert_eq!(mock::param_value("reset_app", "Description"), None);
+}
+
+#[test]
+fn failed_artifact_download_keeps_existing_service_running() {
+    let _mock = isolate();
+
+    mock::add_service("artifact_app", "app.exe", ServiceState::Running);
+
+    let dest = env::temp_dir().join("nssm_exec_missing_artifact.zip");
+
+    let file_config = mock_config(&format!(
+        r#"
+[[services]]
+name = "artifact_app"
+path = "app.exe"
+
+[services.artifact]
+url = "file:///nssm_exec/missing_artifact.zip"
+sha256 = "0000000000000000000000000000000000000000000000000000000000000000"
+dest = '{}'
+"#,
+        dest.to_string_lossy()
+    ));
+
+    assert!(apply(&file_config).is_err());
+    assert!(mock::operations("artifact_app").is_empty());
+    assert_eq!(mock::state("artifact_app"), Some(ServiceState::Running));
+}
+
+#[test]
+fn start_polls_until_running() {
+    let _mock = isolate();
+
+    mock::add_service("start_app", "app.exe", ServiceState::Stopped);
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "start_app"
+path = "app.exe"
+"#,
+    );
+
+    nssm_exec::nssm_exec_start(
+        &file_config,
+        &file_config.pending_start_poll_interval(),
+        file_config.pending_start_poll_count(),
+    ).unwrap();
+
+    assert_eq!(mock::operations("start_app"), vec!["start"]);
+    assert_eq!(mock::state("start_app"), Some(ServiceState::Running));
+}
+
+#[test]
+fn start_times_out_while_pending() {
+    let _mock = isolate();
+
+    mock::add_service("timeout_app", "app.exe", ServiceState::Stopped);
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "timeout_app"
+path = "app.exe"
+"#,
+    );
+
+    // the only poll sees the pending state
+    let res =
+        nssm_exec::nssm_exec_start(&file_config, &file_config.pending_start_poll_interval(), 1);
+
+    assert!(res.is_err());
+}
+
+#[test]
+fn poll_timeout_is_categorized() {
+    let _mock = isolate();
+
+    mock::add_service("category_app", "app.exe", ServiceState::Stopped);
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "category_app"
+path = "app.exe"
+"#,
+    );
+
+    let res = nssm_exec::poll_service_state_until(
+        "category_app",
+        &file_config,
+        &file_config.pending_start_poll_interval(),
+        2,
+        ServiceState::Running,
+    );
+
+    assert_eq!(res.unwrap_err().category(), ErrorCategory::ServiceTimeout);
+}
+
+#[test]
+fn failure_of_some_services_is_partial() {
+    let _mock = isolate();
+
+    mock::add_service("partial_app", "app.exe", ServiceState::Stopped);
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "partial_app"
+path = "app.exe"
+
+[[services]]
+name = "partial_missing_app"
+path = "app.exe"
+"#,
+    );
+
+    let res = nssm_exec::nssm_exec_start(
+        &file_config,
+        &file_config.pending_start_poll_interval(),
+        file_config.pending_start_poll_count(),
+    );
+
+    assert_eq!(res.unwrap_err().category(), ErrorCategory::PartialFailure);
+    assert_eq!(mock::state("partial_app"), Some(ServiceState::Running));
+}
+
+#[test]
+fn failure_before_skipping_the_rest_is_total() {
+    let _mock = isolate();
+
+    mock::add_service("fail_fast_app", "app.exe", ServiceState::Stopped);
+
+    let file_config = mock_config(
+        r#"
+fail_fast = true
+
+[[services]]
+name = "fail_fast_missing_app"
+path = "app.exe"
+
+[[services]]
+name = "fail_fast_app"
+path = "app.exe"
+"#,
+    );
+
+    let res = nssm_exec::nssm_exec_start(
+        &file_config,
+        &file_config.pending_start_poll_interval(),
+        file_config.pending_start_poll_count(),
+    );
+
+    assert_eq!(res.unwrap_err().category(), ErrorCategory::TotalFailure);
+    assert_eq!(mock::state("fail_fast_app"), Some(ServiceState::Stopped));
+}
+
+#[test]
+fn remove_stops_and_removes_service() {
+    let _mock = isolate();
+
+    mock::add_service("remove_app", "app.exe", ServiceState::Running);
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "remove_app"
+path = "app.exe"
+"#,
+    );
+
+    nssm_exec::nssm_exec_remove(
+        &file_config,
+        &file_config.pending_stop_poll_interval(),
+        file_config.pending_stop_poll_count(),
+    ).unwrap();
+
+    assert_eq!(mock::operations("remove_app"), vec!["stop", "remove"]);
+    assert_eq!(mock::state("remove_app"), None);
+}
+
+#[test]
+fn dry_run_leaves_services_untouched() {
+    let _mock = isolate();
+
+    mock::add_service("dry_run_app", "old.exe", ServiceState::Running);
+
+    let mut file_config = mock_config(
+        r#"
+[[services]]
+name = "dry_run_app"
+path = "new.exe"
+"#,
+    );
+
+    file_config.dry_run = Some(true);
+    apply(&file_config).unwrap();
+
+    assert!(mock::operations("dry_run_app").is_empty());
+    assert_eq!(mock::state("dry_run_app"), Some(ServiceState::Running));
+    assert_eq!(mock::param_value("dry_run_app", "Application").unwrap(), "old.exe");
+}
+
+#[test]
+fn strict_config_rejects_misspelt_key() {
+    let _mock = isolate();
+
+    let services = r#"
+[[services]]
+name = "strict_app"
+path = "app.exe"
+
+[services.other]
+start_on_creat = true
+
+[services.other.stop_method]
+nam = "strict_app"
+"#;
+
+    // the misspelt key is only ignored by default
+    assert!(FileConfig::from_str(&format!("nssm_path = 'nssm.exe'\n{}", services)).is_ok());
+
+    let strict_content = format!("nssm_path = 'nssm.exe'\nstrict = true\n{}", services);
+
+    let e = match FileConfig::from_str(&strict_content) {
+        Ok(_) => panic!("misspelt key is accepted in strict mode"),
+        Err(e) => e,
+    };
+
+    assert_eq!(e.category(), ErrorCategory::ConfigParse);
+    assert!(e.to_string().contains("did you mean 'start_on_create'?"));
+
+    // only the settings of the section of the key are suggested
+    assert!(e.to_string().contains("Unknown key 'services.0.other.stop_method.nam'"));
+    assert!(!e.to_string().contains("did you mean 'name'?"));
+}
+
+#[test]
+fn strict_config_rejects_misspelt_key_of_nested_section() {
+    let _mock = isolate();
+
+    let services = r#"
+[[services]]
+name = "strict_app"
+path = "app.exe"
+
+[services.green.health]
+tcp_prot = 8080
+"#;
+
+    // the misspelt key is only ignored by default
+    assert!(FileConfig::from_str(&format!("nssm_path = 'nssm.exe'\n{}", services)).is_ok());
+
+    let strict_content = format!("nssm_path = 'nssm.exe'\nstrict = true\n{}", services);
+
+    let e = match FileConfig::from_str(&strict_content) {
+        Ok(_) => panic!("misspelt key of nested section is accepted in strict mode"),
+        Err(e) => e,
+    };
+
+    assert_eq!(e.category(), ErrorCategory::ConfigParse);
+    assert!(e.to_string().contains("Unknown key 'services.0.green.health.tcp_prot'"));
+    assert!(e.to_string().contains("did you mean 'tcp_port'?"));
+}
+
+#[test]
+fn explain_shows_merged_settings_with_sources() {
+    let _mock = isolate();
+
+    let content = r#"
+nssm_path = "nssm.exe"
+
+[global]
+deps = ["Tcpip"]
+start_on_create = true
+
+[global.account]
+user = "svc"
+password = "hunter2"
+
+[[services]]
+name = "explained_app"
+path = "app.exe"
+
+[services.other]
+start_on_create = false
+backend = "nssm"
+"#;
+
+    let file_config = FileConfig::from_str(content).unwrap();
+    let explanation = explain::explain(&file_config, "explained_app").unwrap();
+
+    let setting = |name: &str| {
+        let setting = explanation
+            .settings
+            .iter()
+            .find(|setting| setting.name == name)
+            .unwrap();
+
+        (setting.value.clone(), setting.source)
+    };
+
+    assert_eq!(setting("deps"), (Some(r#"["Tcpip"]"#.to_owned()), "global"));
+    assert_eq!(setting("start_on_create"), (Some("false".to_owned()), "service"));
+    assert_eq!(setting("throttle_ms"), (None, "default"));
+
+    let account = setting("account").0.unwrap();
+    assert!(account.contains("<secret>") && !account.contains("hunter2"));
+
+    assert!(
+        explanation
+            .nssm_cmds
+            .iter()
+            .any(|cmd| cmd == "nssm.exe set explained_app ObjectName svc <secret>")
+    );
+}
+
+#[test]
+fn global_defaults_fill_unset_service_fields() {
+    let _mock = isolate();
+
+    let content = r#"
+nssm_path = "nssm.exe"
+
+[global]
+startup_dir = 'D:\apps\{{ service_name }}'
+description = "Managed {{ service_name }}"
+env = { APP_ENV = "production", RUST_LOG = "info" }
+
+[[services]]
+name = "defaulted_app"
+path = "app.exe"
+description = "Own description"
+env = { RUST_LOG = "debug" }
+"#;
+
+    let file_config = FileConfig::from_str(content).unwrap();
+    let service = &file_config.services[0];
+
+    assert_eq!(
+        service.startup_dir.as_ref().unwrap().to_string_lossy(),
+        r"D:\apps\defaulted_app"
+    );
+
+    assert_eq!(service.description.as_ref().unwrap(), "Own description");
+
+    let env = service.env.as_ref().unwrap();
+    assert_eq!(env["APP_ENV"], "production");
+    assert_eq!(env["RUST_LOG"], "debug");
+
+    assert_eq!(service.from_global, vec!["startup_dir", "env.APP_ENV"]);
+}
+
+#[test]
+fn env_var_values_are_not_rendered_again() {
+    let _mock = isolate();
+
+    env::set_var("NSSM_EXEC_TEST_TEMPLATE", "{{ root }}");
+
+    let content = r#"
+nssm_path = "nssm.exe"
+
+[variables]
+root = 'D:\apps'
+
+[[services]]
+name = "interpolated_app"
+path = '{{ root }}\app.exe'
+args = ["--template", "${NSSM_EXEC_TEST_TEMPLATE}"]
+"#;
+
+    let file_config = FileConfig::from_str(content).unwrap();
+    let service = &file_config.services[0];
+
+    assert_eq!(service.path.to_string_lossy(), r"D:\apps\app.exe");
+    assert_eq!(
+        service.args.as_ref().unwrap().to_line(),
+        r#"--template "{{ root }}""#
+    );
+}
+
+#[test]
+fn variables_are_not_rendered_in_password() {
+    let _mock = isolate();
+
+    let content = r#"
+nssm_path = "nssm.exe"
+
+[variables]
+domain = 'CORP'
+
+[[services]]
+name = "password_app"
+path = 'app.exe'
+
+[services.other.account]
+user = '{{ domain }}\app'
+password = 'pa{{ domain }}ss'
+"#;
+
+    let file_config = FileConfig::from_str(content).unwrap();
+    let account = file_config.services[0]
+        .other
+        .as_ref()
+        .unwrap()
+        .account
+        .as_ref()
+        .unwrap();
+
+    assert_eq!(account.user, r"CORP\app");
+    assert_eq!(account.password, "pa{{ domain }}ss");
+}
+
+#[test]
+fn name_prefix_applies_to_names_and_deps() {
+    let _mock = isolate();
+
+    let content = r#"
+nssm_path = "nssm.exe"
+name_prefix = "blue-"
+
+[[services]]
+name = "prefixed_db"
+path = "db.exe"
+description = "Database of {name}"
+
+[[services]]
+name = "prefixed_web"
+path = "web.exe"
+other = { deps = ["Tcpip", "prefixed_db"] }
+"#;
+
+    let mut file_config = FileConfig::from_str(content).unwrap();
+
+    assert_eq!(file_config.services[0].name, "blue-prefixed_db");
+
+    assert_eq!(
+        file_config.services[0].description.as_ref().unwrap(),
+        "Database of blue-prefixed_db"
+    );
+
+    let web = &file_config.services[1];
+
+    assert_eq!(
+        file_config.merged_other(web).deps.unwrap().names(),
+        vec!["Tcpip", "blue-prefixed_db"]
+    );
+
+    // the services may still be selected by their configured names
+    file_config
+        .retain_services(&["prefixed_web".to_owned()])
+        .unwrap();
+
+    assert_eq!(file_config.services.len(), 1);
+}
+
+#[test]
+fn skip_existing_leaves_installed_service_untouched() {
+    let _mock = isolate();
+
+    mock::add_service("skipped_app", "old.exe", ServiceState::Running);
+
+    let mut file_config = mock_config(
+        r#"
+[[services]]
+name = "skipped_app"
+path = "new.exe"
+
+[[services]]
+name = "unskipped_app"
+path = "app.exe"
+"#,
+    );
+
+    file_config.skip_existing = Some(true);
+    apply(&file_config).unwrap();
+
+    assert!(mock::operations("skipped_app").is_empty());
+    assert_eq!(mock::state("skipped_app"), Some(ServiceState::Running));
+    assert_eq!(mock::param_value("skipped_app", "Application").unwrap(), "old.exe");
+    assert_eq!(mock::operations("unskipped_app"), vec!["install app.exe"]);
+}
+
+#[test]
+fn update_keeps_running_service_when_only_metadata_changed() {
+    let _mock = isolate();
+
+    let service = |description: &str, args: &str| {
+        format!(
+            r#"
+[[services]]
+name = "metadata_app"
+path = "app.exe"
+description = "{}"
+args = "{}"
+
+[services.other]
+start_on_create = true
+"#,
+            description, args
+        )
+    };
+
+    apply(&mock_config(&service("App", "--port 80"))).unwrap();
+    update(&mock_config(&service("Changed app", "--port 80"))).unwrap();
+    update(&mock_config(&service("Changed app", "--port 81"))).unwrap();
+
+    assert_eq!(
+        mock::operations("metadata_app"),
+        vec![
+            "install app.exe",
+            "set AppParameters --port 80",
+            "set Description App",
+            "start",
+            "set Description Changed app",
+            "stop",
+            "set AppParameters --port 81",
+            "start",
+        ]
+    );
+
+    assert_eq!(mock::state("metadata_app"), Some(ServiceState::Running));
+}
+
+#[test]
+fn failing_drain_check_keeps_service_running() {
+    let _mock = isolate();
+
+    mock::add_service("drain_app", "app.exe", ServiceState::Running);
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "drain_app"
+path = "app.exe"
+
+[services.drain_check]
+command = "nssm_exec_missing_drain_check"
+poll_ms = 1
+poll_count = 2
+"#,
+    );
+
+    let res = nssm_exec::nssm_exec_stop(
+        &file_config,
+        &file_config.pending_stop_poll_interval(),
+        file_config.pending_stop_poll_count(),
+    );
+
+    assert!(res.is_err());
+
+    assert!(mock::operations("drain_app").is_empty());
+    assert_eq!(mock::state("drain_app"), Some(ServiceState::Running));
+}
+
+#[test]
+fn kill_tree_escalates_when_service_does_not_stop() {
+    let _mock = isolate();
+
+    mock::add_service("hung_app", "app.exe", ServiceState::Running);
+
+    // the stop control is lost, so the service keeps running
+    mock::fail_next("hung_app", "stop");
+
+    let file_config = mock_config(
+        r#"
+[[services]]
+name = "hung_app"
+path = "app.exe"
+
+[services.other]
+stop_method = { kill_tree = true }
+"#,
+    );
+
+    nssm_exec::nssm_exec_stop(
+        &file_config,
+        &file_config.pending_stop_poll_interval(),
+        file_config.pending_stop_poll_count(),
+    ).unwrap();
+
+    assert_eq!(mock::operations("hung_app"), vec!["kill_tree"]);
+    assert_eq!(mock::state("hung_app"), Some(ServiceState::Stopped));
+}
+
+#[test]
+fn stop_method_settings_fall_back_to_global_individually() {
+    let _mock = isolate();
+
+    let file_config = mock_config(
+        r#"
+[global.stop_method]
+console_ms = 30000
+
+[[services]]
+name = "stop_method_app"
+path = "app.exe"
+
+[services.other.stop_method]
+window_ms = 5000
+"#,
+    );
+
+    apply(&file_config).unwrap();
+
+    assert_eq!(
+        mock::param_value("stop_method_app", "AppStopMethodConsole").unwrap(),
+        "30000"
+    );
+
+    assert_eq!(
+        mock::param_value("stop_method_app", "AppStopMethodWindow").unwrap(),
+        "5000"
+    );
+}
+
+#[test]
+fn start_creates_missing_log_directories() {
+    let _mock = isolate();
+
+    let log_dir = std::env::temp_dir().join("nssm_exec_log_dir_app");
+    let _ = std::fs::remove_dir_all(&log_dir);
+
+    let file_config = mock_config(&format!(
+        r#"
+[[services]]
+name = "log_dir_app"
+path = "app.exe"
+stdout_log = '{}'
+
+[services.other]
+start_on_create = true
+"#,
+        log_dir.join("nested").join("out.log").to_string_lossy()
+    ));
+
+    apply(&file_config).unwrap();
+
+    assert!(log_dir.join("nested").is_dir());
+    assert_eq!(mock::state("log_dir_app"), Some(ServiceState::Running));
+
+    std::fs::remove_dir_all(&log_dir).unwrap();
+}
+
+#[test]
+fn blue_green_runs_copy_with_its_own_settings() {
+    let _mock = isolate();
+
+    mock::add_service("green_app", "old.exe", ServiceState::Running);
+
+    let mut file_config = mock_config(
+        r#"
+[[services]]
+name = "green_app"
+path = "new.exe"
+args = ["--port", "80"]
+stdout_log = 'C:\logs\{name}.log'
+
+[services.green]
+args = ["--port", "8080"]
+stdout_log = 'C:\logs\{name}.log'
+
+[services.other]
+start_on_create = true
+"#,
+    );
+
+    file_config.blue_green = Some(true);
+    apply(&file_config).unwrap();
+
+    let green_operations = mock::operations("green_app-green");
+    assert!(green_operations.contains(&"set AppParameters --port 8080".to_owned()));
+    assert!(
+        green_operations.contains(&"set AppStdout C:\\logs\\green_app-green.log".to_owned())
+    );
+    assert_eq!(green_operations.last().unwrap(), "remove");
+
+    assert_eq!(mock::state("green_app"), Some(ServiceState::Running));
+    assert_eq!(mock::param_value("green_app", "AppParameters").unwrap(), "--port 80");
+}
+
+#[test]
+fn blue_green_rejects_copy_sharing_logs_of_service() {
+    let _mock = isolate();
+
+    mock::add_service("shared_log_app", "old.exe", ServiceState::Running);
+
+    let mut file_config = mock_config(
+        r#"
+[[services]]
+name = "shared_log_app"
+path = "new.exe"
+stdout_log = 'C:\logs\app.log'
+
+[services.green]
+args = ["--port", "8080"]
+"#,
+    );
+
+    file_config.blue_green = Some(true);
+    assert!(apply(&file_config).is_err());
+
+    assert!(mock::operations("shared_log_app").is_empty());
+    assert!(mock::operations("shared_log_app-green").is_empty());
+    assert_eq!(mock::state("shared_log_app"), Some(ServiceState::Running));
+}